@@ -364,6 +364,18 @@ pub enum EthereumChain {
     Other(primitive_types::U256),
 }
 
+impl EthereumChain {
+    /// The chain with the given chain ID, as returned by `eth_chainId`.
+    pub fn from_chain_id(id: primitive_types::U256) -> Self {
+        match id {
+            x if x == primitive_types::U256::from(1u32) => Self::Mainnet,
+            x if x == primitive_types::U256::from(5u32) => Self::Goerli,
+            x if x == primitive_types::U256::from(11155111u32) => Self::Sepolia,
+            x => Self::Other(x),
+        }
+    }
+}
+
 /// Starknet chain.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Chain {
@@ -480,6 +492,7 @@ macros::felt_newtypes!(
         L1ToL2MessagePayloadElem,
         L2ToL1MessagePayloadElem,
         PaymasterDataElem,
+        PublicKey,
         SequencerAddress,
         StateCommitment,
         StateDiffCommitment,
@@ -1,9 +1,62 @@
 use fake::Dummy;
+use pathfinder_crypto::hash::poseidon_hash_many;
+use pathfinder_crypto::signature::{ecdsa_verify_partial, SignatureError};
 
-use crate::BlockCommitmentSignatureElem;
+use crate::{BlockCommitmentSignatureElem, BlockHash, PublicKey, StateDiffCommitment};
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Dummy)]
 pub struct BlockCommitmentSignature {
     pub r: BlockCommitmentSignatureElem,
     pub s: BlockCommitmentSignatureElem,
 }
+
+impl BlockCommitmentSignature {
+    /// Verifies that this is the signature of the sequencer with `public_key` over the
+    /// given block hash and state diff commitment.
+    pub fn verify(
+        &self,
+        public_key: PublicKey,
+        block_hash: BlockHash,
+        state_diff_commitment: StateDiffCommitment,
+    ) -> Result<(), SignatureError> {
+        let message = poseidon_hash_many(&[block_hash.0.into(), state_diff_commitment.0.into()]);
+
+        ecdsa_verify_partial(public_key.0, message.into(), self.r.0, self.s.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macro_prelude::*;
+
+    #[test]
+    fn verify() {
+        // Mainnet block 350000.
+        let public_key =
+            public_key!("0x48253ff2c3bed7af18bde0b611b083b39445959102d4947c51c4db6aa4f4e58");
+        let block_hash =
+            block_hash!("0x6f7342a680d7f99bdfdd859f587c75299e7ffabe62c071ded3a6d8a34cb132c");
+        let state_diff_commitment = state_diff_commitment!(
+            "0x432e8e2ad833548e1c1077fc298991b055ba1e6f7a17dd332db98f4f428c56c"
+        );
+        let signature = BlockCommitmentSignature {
+            r: block_commitment_signature_elem!(
+                "0x95e98f5b91d39ae2b1bf77447a4fc01725352ae8b0b2c0a3fe09d43d1d9e57"
+            ),
+            s: block_commitment_signature_elem!(
+                "0x541b2db8dae6d5ae24b34e427d251edc2e94dcffddd85f207e1b51f2f4bb1ef"
+            ),
+        };
+
+        signature
+            .verify(public_key, block_hash, state_diff_commitment)
+            .unwrap();
+
+        let tampered = state_diff_commitment!("0x1234");
+        assert_eq!(
+            signature.verify(public_key, block_hash, tampered),
+            Err(SignatureError::Signature)
+        );
+    }
+}
@@ -18,7 +18,8 @@ where
 /// Metrics related test aids
 pub mod metrics {
     use metrics::{
-        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Label, Recorder, SharedString, Unit,
+        Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Label, Recorder,
+        SharedString, Unit,
     };
     use std::borrow::Cow;
     use std::collections::HashMap;
@@ -60,16 +61,27 @@ pub mod metrics {
     #[derive(Debug, Default)]
     pub struct FakeRecorder(FakeRecorderHandle);
 
-    /// Handle to the [`FakeRecorder`], which allows to get the current value of counters.
+    /// Handle to the [`FakeRecorder`], which allows to get the current value of counters, gauges
+    /// and histograms.
     #[derive(Clone, Debug, Default)]
     pub struct FakeRecorderHandle {
         counters: Arc<RwLock<HashMap<Key, Arc<FakeCounterFn>>>>,
+        gauges: Arc<RwLock<HashMap<Key, Arc<FakeGaugeFn>>>>,
+        histograms: Arc<RwLock<HashMap<Key, Arc<FakeHistogramFn>>>>,
         methods: Option<&'static [&'static str]>,
     }
 
     #[derive(Debug, Default)]
     struct FakeCounterFn(AtomicU64);
 
+    /// Holds the bits of the gauge's `f64` value.
+    #[derive(Debug, Default)]
+    struct FakeGaugeFn(AtomicU64);
+
+    /// Only counts the recorded values.
+    #[derive(Debug, Default)]
+    struct FakeHistogramFn(AtomicU64);
+
     impl Recorder for FakeRecorder {
         fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
         fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
@@ -103,12 +115,44 @@ pub mod metrics {
             }
         }
 
-        fn register_gauge(&self, _: &Key) -> Gauge {
-            unimplemented!()
+        /// Registers a gauge if the method is on the `self::methods` list and returns it.
+        ///
+        /// # Warning
+        ///
+        /// Returns `Gauge::noop()` in other cases.
+        fn register_gauge(&self, key: &Key) -> Gauge {
+            if self.is_key_used(key) {
+                Gauge::from_arc(register(&self.0.gauges, key))
+            } else {
+                Gauge::noop()
+            }
         }
-        fn register_histogram(&self, _: &Key) -> Histogram {
-            unimplemented!()
+
+        /// Registers a histogram if the method is on the `self::methods` list and returns it.
+        ///
+        /// # Warning
+        ///
+        /// Returns `Histogram::noop()` in other cases.
+        fn register_histogram(&self, key: &Key) -> Histogram {
+            if self.is_key_used(key) {
+                Histogram::from_arc(register(&self.0.histograms, key))
+            } else {
+                Histogram::noop()
+            }
+        }
+    }
+
+    /// Returns the metric registered for `key`, registering it first if necessary.
+    fn register<T: Default>(metrics: &RwLock<HashMap<Key, Arc<T>>>, key: &Key) -> Arc<T> {
+        if let Some(metric) = metrics.read().unwrap().get(key) {
+            return metric.clone();
         }
+        metrics
+            .write()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone()
     }
 
     impl FakeRecorder {
@@ -117,8 +161,8 @@ pub mod metrics {
         /// All other methods use the [no-op counters](`https://docs.rs/metrics/latest/metrics/struct.Counter.html#method.noop`)
         pub fn new_for(methods: &'static [&'static str]) -> Self {
             Self(FakeRecorderHandle {
-                counters: Arc::default(),
                 methods: Some(methods),
+                ..Default::default()
             })
         }
 
@@ -184,6 +228,74 @@ pub mod metrics {
         }
     }
 
+    impl FakeRecorderHandle {
+        /// Returns the current value of the gauge with the given name and
+        /// [label](https://docs.rs/metrics/latest/metrics/struct.Label.html#)-s, or `None` if it
+        /// has not been registered via [`metrics::register_gauge`] yet.
+        pub fn get_gauge_value_by_label<const N: usize>(
+            &self,
+            gauge_name: &'static str,
+            labels: [(&'static str, &'static str); N],
+        ) -> Option<f64> {
+            let read_guard = self.gauges.read().unwrap();
+            read_guard
+                .get(&key(gauge_name, labels))
+                .map(|gauge| f64::from_bits(gauge.0.load(Ordering::Relaxed)))
+        }
+
+        /// Returns the number of values recorded by the histogram with the given name and
+        /// [label](https://docs.rs/metrics/latest/metrics/struct.Label.html#)-s, or `None` if it
+        /// has not been registered via [`metrics::register_histogram`] yet.
+        pub fn get_histogram_count_by_label<const N: usize>(
+            &self,
+            histogram_name: &'static str,
+            labels: [(&'static str, &'static str); N],
+        ) -> Option<u64> {
+            let read_guard = self.histograms.read().unwrap();
+            read_guard
+                .get(&key(histogram_name, labels))
+                .map(|histogram| histogram.0.load(Ordering::Relaxed))
+        }
+    }
+
+    fn key<const N: usize>(name: &'static str, labels: [(&'static str, &'static str); N]) -> Key {
+        Key::from_parts(
+            name,
+            labels
+                .iter()
+                .map(|&(key, val)| Label::new(key, val))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    impl GaugeFn for FakeGaugeFn {
+        fn increment(&self, val: f64) {
+            self.update(|x| x + val);
+        }
+        fn decrement(&self, val: f64) {
+            self.update(|x| x - val);
+        }
+        fn set(&self, val: f64) {
+            self.0.store(val.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    impl FakeGaugeFn {
+        fn update(&self, f: impl Fn(f64) -> f64) {
+            let _ = self
+                .0
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                    Some(f(f64::from_bits(bits)).to_bits())
+                });
+        }
+    }
+
+    impl HistogramFn for FakeHistogramFn {
+        fn record(&self, _: f64) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     impl CounterFn for FakeCounterFn {
         fn increment(&self, val: u64) {
             self.0.fetch_add(val, Ordering::Relaxed);
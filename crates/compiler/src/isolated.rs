@@ -0,0 +1,268 @@
+//! Sierra to CASM compilation in a child process.
+//!
+//! `catch_unwind` protects against compiler panics, but not against a class which makes
+//! the compiler exhaust memory or never terminate. Compiling in a child process of the
+//! current executable contains both: the child caps its own address space, and the parent
+//! kills it once the time limit is exceeded.
+//!
+//! The child is started with [CHILD_ARG], the Starknet version and the memory limit as its
+//! arguments. It reads the Sierra definition from stdin and writes a single [Reply] to stdout.
+
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use pathfinder_common::StarknetVersion;
+use serde_json::value::RawValue;
+
+const CHILD_ARG: &str = "__compile-sierra";
+/// Passed instead of a Starknet version to select the latest compiler.
+const LATEST: &str = "latest";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Matches the stack size of the node's worker threads, which some classes need.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Resource limits of a compiler child process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum address space of the child process in bytes.
+    pub max_memory: u64,
+    /// Time after which the child process is killed.
+    pub timeout: Duration,
+}
+
+/// The result of a compilation, as written by the child process.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    Compiled(Box<RawValue>),
+    Failed(String),
+}
+
+/// Turns this process into a compiler child process if it was started as one.
+///
+/// Must be called at the very start of `main` by executables which use
+/// [Compiler::Isolated](crate::Compiler::Isolated). Does not return in a child process.
+pub fn run_if_child() {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().as_deref() != Some(OsStr::new(CHILD_ARG)) {
+        return;
+    }
+
+    let code = match child(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e:#}");
+            1
+        }
+    };
+
+    std::process::exit(code);
+}
+
+fn child(mut args: impl Iterator<Item = std::ffi::OsString>) -> anyhow::Result<()> {
+    let version = args
+        .next()
+        .context("Missing Starknet version")?
+        .into_string()
+        .map_err(|_| anyhow::anyhow!("Starknet version is not valid UTF-8"))?;
+    let max_memory = args
+        .next()
+        .context("Missing memory limit")?
+        .to_str()
+        .and_then(|s| s.parse::<u64>().ok())
+        .context("Parsing memory limit")?;
+
+    limit_memory(max_memory)?;
+
+    let mut definition = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut definition)
+        .context("Reading Sierra class")?;
+
+    let reply = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || serve(&version, &definition))
+        .context("Spawning compiler thread")?
+        .join()
+        .map_err(|_| anyhow::anyhow!("Compiler thread panicked"))?;
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &reply).context("Writing reply")?;
+    stdout.flush().context("Flushing reply")
+}
+
+fn serve(version: &str, definition: &[u8]) -> Reply {
+    let result = match version {
+        LATEST => crate::compile_to_casm_with_latest_compiler(definition),
+        version => crate::compile_to_casm(definition, &StarknetVersion::from(version.to_owned())),
+    };
+
+    let casm = result.and_then(|casm| {
+        let casm = String::from_utf8(casm).context("CASM is not valid UTF-8")?;
+        RawValue::from_string(casm).context("CASM is not valid JSON")
+    });
+
+    match casm {
+        Ok(casm) => Reply::Compiled(casm),
+        Err(e) => Reply::Failed(format!("{e:#}")),
+    }
+}
+
+#[cfg(unix)]
+fn limit_memory(bytes: u64) -> anyhow::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: bytes as libc::rlim_t,
+        rlim_max: bytes as libc::rlim_t,
+    };
+    // Safety: `setrlimit` only reads the limit passed to it.
+    if unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Setting memory limit");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn limit_memory(_bytes: u64) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Compiles a Sierra class in a child process of the current executable.
+///
+/// Uses the latest compiler if `version` is `None`.
+pub(crate) fn compile(
+    sierra_definition: &[u8],
+    version: Option<&StarknetVersion>,
+    limits: &Limits,
+) -> anyhow::Result<Vec<u8>> {
+    let executable = std::env::current_exe().context("Locating current executable")?;
+
+    let mut command = Command::new(executable);
+    command
+        .arg(CHILD_ARG)
+        .arg(version.map(StarknetVersion::as_str).unwrap_or(LATEST))
+        .arg(limits.max_memory.to_string());
+
+    run(command, sierra_definition, limits.timeout)
+}
+
+fn run(mut command: Command, input: &[u8], timeout: Duration) -> anyhow::Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Spawning compiler process")?;
+
+    // Pipes are serviced by their own threads so that the child can't block on a full pipe
+    // while we wait for it to exit.
+    let mut stdin = child.stdin.take().context("Missing stdin")?;
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let stdout = read_in_background(child.stdout.take().context("Missing stdout")?);
+    let stderr = read_in_background(child.stderr.take().context("Missing stderr")?);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().context("Waiting for compiler process")? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            // The process may have exited in the meantime, in which case killing it fails.
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("Compilation timed out after {:?}", timeout);
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    // Writing fails if the child exits without reading all of its input, which is
+    // reported through its exit status or reply instead.
+    let _ = writer.join();
+    let stdout = stdout.join().expect("Reader thread does not panic")?;
+    let stderr = stderr.join().expect("Reader thread does not panic")?;
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        anyhow::bail!("Compiler process failed with {}: {}", status, stderr.trim());
+    }
+
+    match serde_json::from_slice(&stdout).context("Parsing compiler process reply")? {
+        Reply::Compiled(casm) => Ok(casm.get().as_bytes().to_vec()),
+        Reply::Failed(error) => Err(anyhow::anyhow!(error)),
+    }
+}
+
+fn read_in_background(
+    mut reader: impl Read + Send + 'static,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).map(|_| buffer)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serve_compiles() {
+        use starknet_gateway_test_fixtures::class_definitions::CAIRO_1_1_0_RC0_SIERRA;
+
+        let reply = serve("0.11.2", CAIRO_1_1_0_RC0_SIERRA);
+        assert_matches::assert_matches!(reply, Reply::Compiled(_));
+
+        let reply = serve(LATEST, CAIRO_1_1_0_RC0_SIERRA);
+        assert_matches::assert_matches!(reply, Reply::Compiled(_));
+    }
+
+    #[test]
+    fn serve_reports_errors() {
+        let reply = serve(LATEST, b"not a class");
+        assert_matches::assert_matches!(reply, Reply::Failed(e) => assert!(e.contains("Parsing Sierra class")));
+    }
+
+    #[cfg(unix)]
+    mod process {
+        use super::*;
+
+        fn shell(script: &str) -> Command {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        }
+
+        #[test]
+        fn reply_is_returned() {
+            let command = shell(r#"cat > /dev/null; echo '{"compiled":{"program":[1,2]}}'"#);
+            let casm = run(command, b"input", Duration::from_secs(10)).unwrap();
+            assert_eq!(casm, br#"{"program":[1,2]}"#);
+
+            let command = shell(r#"echo '{"failed":"Validating Sierra class"}'"#);
+            let error = run(command, b"input", Duration::from_secs(10)).unwrap_err();
+            assert_eq!(error.to_string(), "Validating Sierra class");
+        }
+
+        #[test]
+        fn crash_is_reported() {
+            let command = shell("echo 'memory allocation failed' >&2; exit 3");
+            let error = run(command, b"input", Duration::from_secs(10)).unwrap_err();
+            let error = error.to_string();
+            assert!(error.contains("memory allocation failed"), "{error}");
+        }
+
+        #[test]
+        fn process_is_killed_after_timeout() {
+            let started = Instant::now();
+            let error = run(shell("sleep 30"), b"input", Duration::from_millis(100)).unwrap_err();
+            assert!(error.to_string().contains("timed out"));
+            assert!(started.elapsed() < Duration::from_secs(10));
+        }
+    }
+}
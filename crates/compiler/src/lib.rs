@@ -1,7 +1,49 @@
 use std::borrow::Cow;
 
 use anyhow::Context;
-use pathfinder_common::StarknetVersion;
+use pathfinder_common::{CasmHash, StarknetVersion};
+use pathfinder_crypto::Felt;
+
+mod isolated;
+
+pub use isolated::{run_if_child, Limits};
+
+/// How Sierra classes are compiled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Compiler {
+    /// Compile on the calling thread, relying on `catch_unwind` to survive compiler panics.
+    #[default]
+    InProcess,
+    /// Compile in a child process with resource limits, see [run_if_child].
+    Isolated(Limits),
+}
+
+impl Compiler {
+    /// Like [compile_to_casm], but using this compiler.
+    pub fn compile_to_casm(
+        &self,
+        sierra_definition: &[u8],
+        version: &StarknetVersion,
+    ) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compiler::InProcess => compile_to_casm(sierra_definition, version),
+            Compiler::Isolated(limits) => {
+                isolated::compile(sierra_definition, Some(version), limits)
+            }
+        }
+    }
+
+    /// Like [compile_to_casm_with_latest_compiler], but using this compiler.
+    pub fn compile_to_casm_with_latest_compiler(
+        &self,
+        sierra_definition: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compiler::InProcess => compile_to_casm_with_latest_compiler(sierra_definition),
+            Compiler::Isolated(limits) => isolated::compile(sierra_definition, None, limits),
+        }
+    }
+}
 
 /// Compile a Sierra class definition into CASM.
 ///
@@ -46,6 +88,23 @@ pub fn compile_to_casm_with_latest_compiler(sierra_definition: &[u8]) -> anyhow:
     result.unwrap_or_else(|e| Err(panic_error(e)))
 }
 
+/// Computes the compiled class hash of a CASM definition.
+///
+/// This is the hash a declare transaction commits to for the class it declares.
+pub fn casm_class_hash(casm_definition: &[u8]) -> anyhow::Result<CasmHash> {
+    use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+
+    let casm_class = serde_json::from_slice::<CasmContractClass>(casm_definition)
+        .context("Parsing CASM class")?;
+
+    let result = std::panic::catch_unwind(|| casm_class.compiled_class_hash());
+    let hash = result.map_err(|e| panic_error(e))?;
+
+    Felt::from_be_bytes(hash.to_be_bytes())
+        .map(CasmHash)
+        .context("Converting CASM class hash")
+}
+
 fn panic_error(e: Box<dyn std::any::Any>) -> anyhow::Error {
     match e.downcast_ref::<&str>() {
         Some(e) => anyhow::anyhow!("Compiler panicked: {}", e),
@@ -301,6 +360,27 @@ mod tests {
         }
     }
 
+    mod casm_class_hash {
+        use super::super::casm_class_hash;
+        use starknet_gateway_test_fixtures::class_definitions::CAIRO_1_1_0_BALANCE_CASM_JSON;
+
+        #[test]
+        fn is_deterministic() {
+            let hash = casm_class_hash(CAIRO_1_1_0_BALANCE_CASM_JSON).unwrap();
+
+            // The hash does not depend on the JSON formatting.
+            let value =
+                serde_json::from_slice::<serde_json::Value>(CAIRO_1_1_0_BALANCE_CASM_JSON).unwrap();
+            let reformatted = serde_json::to_vec_pretty(&value).unwrap();
+            assert_eq!(casm_class_hash(&reformatted).unwrap(), hash);
+        }
+
+        #[test]
+        fn invalid_casm() {
+            casm_class_hash(b"{}").unwrap_err();
+        }
+    }
+
     mod starknet_v0_11_2_onwards {
         use super::*;
         use starknet_gateway_test_fixtures::class_definitions::{
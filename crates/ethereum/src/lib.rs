@@ -2,6 +2,8 @@ use pathfinder_common::{BlockHash, BlockNumber, EthereumChain, StateCommitment};
 use pathfinder_crypto::Felt;
 use primitive_types::{H160, H256, U256};
 
+pub mod starknet;
+
 pub mod core_addr {
     use const_decoder::Decoder;
 
@@ -29,6 +31,30 @@ pub trait EthereumApi {
     async fn get_chain(&self) -> anyhow::Result<EthereumChain>;
 }
 
+/// The layer a Starknet chain settles on.
+#[derive(Clone, Debug)]
+pub enum SettlementClient {
+    Ethereum(EthereumClient),
+    Starknet(starknet::StarknetCoreContract),
+}
+
+#[async_trait::async_trait]
+impl EthereumApi for SettlementClient {
+    async fn get_starknet_state(&self, address: &H160) -> anyhow::Result<EthereumStateUpdate> {
+        match self {
+            Self::Ethereum(client) => client.get_starknet_state(address).await,
+            Self::Starknet(client) => client.get_starknet_state(address).await,
+        }
+    }
+
+    async fn get_chain(&self) -> anyhow::Result<EthereumChain> {
+        match self {
+            Self::Ethereum(client) => client.get_chain().await,
+            Self::Starknet(client) => client.get_chain().await,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct EthereumClient {
     http: reqwest::Client,
@@ -140,12 +166,7 @@ impl EthereumApi for EthereumClient {
             }))
             .await
             .and_then(|value| get_u256(&value))?;
-        Ok(match id {
-            x if x == U256::from(1u32) => EthereumChain::Mainnet,
-            x if x == U256::from(5u32) => EthereumChain::Goerli,
-            x if x == U256::from(11155111u32) => EthereumChain::Sepolia,
-            x => EthereumChain::Other(x),
-        })
+        Ok(EthereumChain::from_chain_id(id))
     }
 }
 
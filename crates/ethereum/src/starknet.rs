@@ -0,0 +1,178 @@
+//! Settlement on a Starknet network instead of Ethereum, as used by appchains.
+//!
+//! The appchain's core contract is deployed on the settlement network and its latest
+//! state is read using the settlement network's JSON-RPC API.
+
+use anyhow::Context;
+use pathfinder_common::{
+    BlockHash, BlockNumber, ContractAddress, EntryPoint, EthereumChain, StateCommitment,
+};
+use pathfinder_crypto::Felt;
+use primitive_types::{H160, U256};
+
+use crate::{EthereumApi, EthereumStateUpdate};
+
+/// Reads the state of an appchain's core contract deployed on a Starknet network.
+///
+/// The core contract must expose `get_state`, which returns the settled state root,
+/// block number and block hash in that order.
+#[derive(Clone, Debug)]
+pub struct StarknetCoreContract {
+    http: reqwest::Client,
+    url: reqwest::Url,
+    address: ContractAddress,
+}
+
+impl StarknetCoreContract {
+    pub fn new(url: reqwest::Url, address: ContractAddress) -> anyhow::Result<Self> {
+        Ok(Self {
+            http: reqwest::ClientBuilder::new().build()?,
+            url,
+            address,
+        })
+    }
+
+    async fn call_starknet(&self, value: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let res = self.http.post(self.url.clone()).json(&value).send().await?;
+
+        let status = res.status();
+        if !status.is_success() {
+            tracing::error!(code=%status.as_u16(), "Starknet call failed");
+            anyhow::bail!(status.as_u16());
+        }
+
+        let mut response: serde_json::Value = res.json().await?;
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("Starknet call failed: {error}");
+        }
+
+        Ok(response["result"].take())
+    }
+}
+
+#[async_trait::async_trait]
+impl EthereumApi for StarknetCoreContract {
+    /// The `address` is ignored, the core contract is part of the client's configuration.
+    async fn get_starknet_state(&self, _address: &H160) -> anyhow::Result<EthereumStateUpdate> {
+        // Blocks accepted on a Starknet network are not reverted in practice, so there is
+        // no need to wait for the settlement network to finalize them on Ethereum.
+        let result = self
+            .call_starknet(serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "starknet_call",
+                "params": {
+                    "request": {
+                        "contract_address": self.address.0.to_string(),
+                        "entry_point_selector": EntryPoint::hashed(b"get_state").0.to_string(),
+                        "calldata": []
+                    },
+                    "block_id": "latest"
+                },
+                "id": 0
+            }))
+            .await?;
+
+        let felts = result
+            .as_array()
+            .context("Expected an array of felts")?
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .context("Expected a hex string")
+                    .and_then(|value| Felt::from_hex_str(value).map_err(Into::into))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let [state_root, block_number, block_hash] = felts[..] else {
+            anyhow::bail!("Expected 3 felts but got {}", felts.len());
+        };
+
+        let block_number: Option<u64> = block_number.try_into().ok();
+        let block_number = block_number
+            .and_then(BlockNumber::new)
+            .context("Block number out of range")?;
+
+        Ok(EthereumStateUpdate {
+            state_root: StateCommitment(state_root),
+            block_number,
+            block_hash: BlockHash(block_hash),
+        })
+    }
+
+    /// Returns the settlement network's Starknet chain ID.
+    async fn get_chain(&self) -> anyhow::Result<EthereumChain> {
+        let id = self
+            .call_starknet(serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "starknet_chainId",
+                "params": [],
+                "id": 0
+            }))
+            .await?;
+        let id = id.as_str().context("Expected a hex string")?;
+        let id = Felt::from_hex_str(id)?;
+
+        Ok(EthereumChain::Other(U256::from_big_endian(
+            id.as_be_bytes(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use httpmock::prelude::*;
+    use pathfinder_common::macro_prelude::*;
+    use reqwest::Url;
+
+    #[tokio::test]
+    async fn get_starknet_state() -> anyhow::Result<()> {
+        let server = MockServer::start_async().await;
+
+        let mock = server.mock(|when, then| {
+            when.path("/")
+                .method(POST)
+                .json_body_partial(r#"{"method":"starknet_call","params":{"block_id":"latest"}}"#);
+            then.status(200)
+                .header("Content-type", "application/json")
+                .body(r#"{"jsonrpc":"2.0","id":0,"result":["0x1234","0x7eeb","0x5678"]}"#);
+        });
+
+        let url = Url::parse(&server.url("/"))?;
+        let client = StarknetCoreContract::new(url, contract_address!("0xabc"))?;
+
+        let state = client.get_starknet_state(&H160::zero()).await?;
+
+        mock.assert();
+        assert_eq!(
+            state,
+            EthereumStateUpdate {
+                state_root: state_commitment!("0x1234"),
+                block_number: BlockNumber::new_or_panic(0x7eeb),
+                block_hash: block_hash!("0x5678"),
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_starknet_state_error() -> anyhow::Result<()> {
+        let server = MockServer::start_async().await;
+
+        server.mock(|when, then| {
+            when.path("/").method(POST);
+            then.status(200)
+                .header("Content-type", "application/json")
+                .body(r#"{"jsonrpc":"2.0","id":0,"error":{"code":20,"message":"Contract not found"}}"#);
+        });
+
+        let url = Url::parse(&server.url("/"))?;
+        let client = StarknetCoreContract::new(url, contract_address!("0xabc"))?;
+
+        let error = client.get_starknet_state(&H160::zero()).await.unwrap_err();
+        assert!(error.to_string().contains("Contract not found"));
+        Ok(())
+    }
+}
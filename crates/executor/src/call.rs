@@ -4,7 +4,6 @@ use blockifier::{
     context::TransactionContext,
     execution::entry_point::{CallEntryPoint, EntryPointExecutionContext},
     transaction::objects::{DeprecatedTransactionInfo, TransactionInfo},
-    versioned_constants::VersionedConstants,
 };
 use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
 use pathfinder_common::{CallParam, CallResultValue, ContractAddress, EntryPoint};
@@ -34,12 +33,15 @@ pub fn call(
         .map(|param| param.0.into_starkfelt())
         .collect();
 
+    let initial_gas = block_context
+        .versioned_constants()
+        .gas_cost("initial_gas_cost");
     let call_entry_point = CallEntryPoint {
         storage_address: contract_address,
         entry_point_type: starknet_api::deprecated_contract_class::EntryPointType::External,
         entry_point_selector,
         calldata: starknet_api::transaction::Calldata(Arc::new(calldata)),
-        initial_gas: VersionedConstants::latest_constants().gas_cost("initial_gas_cost"),
+        initial_gas,
         call_type: blockifier::execution::entry_point::CallType::Call,
         ..Default::default()
     };
@@ -13,15 +13,43 @@ use blockifier::{
 use pathfinder_common::{contract_address, BlockHeader, ChainId, ContractAddress, StateUpdate};
 use starknet_api::core::PatriciaKey;
 
-// NOTE: these are the same for _all_ networks
+// NOTE: these are the same for all public Starknet networks
 pub const ETH_FEE_TOKEN_ADDRESS: ContractAddress =
     contract_address!("0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7");
 pub const STRK_FEE_TOKEN_ADDRESS: ContractAddress =
     contract_address!("0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
 
+/// Execution parameters which custom networks may change.
+#[derive(Clone, Debug)]
+pub struct ChainConfig {
+    pub eth_fee_token_address: ContractAddress,
+    pub strk_fee_token_address: ContractAddress,
+    /// Used instead of the latest versioned constants of blockifier if set.
+    pub versioned_constants: Option<Arc<VersionedConstants>>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            eth_fee_token_address: ETH_FEE_TOKEN_ADDRESS,
+            strk_fee_token_address: STRK_FEE_TOKEN_ADDRESS,
+            versioned_constants: None,
+        }
+    }
+}
+
+impl ChainConfig {
+    fn versioned_constants(&self) -> &VersionedConstants {
+        self.versioned_constants
+            .as_deref()
+            .unwrap_or_else(VersionedConstants::latest_constants)
+    }
+}
+
 pub struct ExecutionState<'tx> {
     transaction: &'tx pathfinder_storage::Transaction<'tx>,
     pub chain_id: ChainId,
+    chain: ChainConfig,
     pub header: BlockHeader,
     execute_on_parent_state: bool,
     pending_state: Option<Arc<StateUpdate>>,
@@ -80,7 +108,7 @@ impl<'tx> ExecutionState<'tx> {
             old_block_number_and_hash,
             block_info,
             chain_info,
-            VersionedConstants::latest_constants().to_owned(),
+            self.chain.versioned_constants().to_owned(),
         )?;
 
         Ok((cached_state, block_context))
@@ -88,11 +116,11 @@ impl<'tx> ExecutionState<'tx> {
 
     fn chain_info(&self) -> anyhow::Result<ChainInfo> {
         let eth_fee_token_address = starknet_api::core::ContractAddress(
-            PatriciaKey::try_from(ETH_FEE_TOKEN_ADDRESS.0.into_starkfelt())
+            PatriciaKey::try_from(self.chain.eth_fee_token_address.0.into_starkfelt())
                 .expect("ETH fee token address overflow"),
         );
         let strk_fee_token_address = starknet_api::core::ContractAddress(
-            PatriciaKey::try_from(STRK_FEE_TOKEN_ADDRESS.0.into_starkfelt())
+            PatriciaKey::try_from(self.chain.strk_fee_token_address.0.into_starkfelt())
                 .expect("STRK fee token address overflow"),
         );
 
@@ -163,12 +191,14 @@ impl<'tx> ExecutionState<'tx> {
     pub fn trace(
         transaction: &'tx pathfinder_storage::Transaction<'tx>,
         chain_id: ChainId,
+        chain: ChainConfig,
         header: BlockHeader,
         pending_state: Option<Arc<StateUpdate>>,
     ) -> Self {
         Self {
             transaction,
             chain_id,
+            chain,
             header,
             pending_state,
             execute_on_parent_state: true,
@@ -178,12 +208,14 @@ impl<'tx> ExecutionState<'tx> {
     pub fn simulation(
         transaction: &'tx pathfinder_storage::Transaction<'tx>,
         chain_id: ChainId,
+        chain: ChainConfig,
         header: BlockHeader,
         pending_state: Option<Arc<StateUpdate>>,
     ) -> Self {
         Self {
             transaction,
             chain_id,
+            chain,
             header,
             pending_state,
             execute_on_parent_state: false,
@@ -15,7 +15,7 @@ pub use call::call;
 pub use class::{parse_casm_definition, parse_deprecated_class_definition};
 pub use error::{CallError, TransactionExecutionError};
 pub use estimate::estimate;
-pub use execution_state::{ExecutionState, ETH_FEE_TOKEN_ADDRESS};
+pub use execution_state::{ChainConfig, ExecutionState, ETH_FEE_TOKEN_ADDRESS};
 pub use felt::{IntoFelt, IntoStarkFelt};
 pub use simulate::{simulate, trace, TraceCache};
 
@@ -23,4 +23,5 @@ pub use simulate::{simulate, trace, TraceCache};
 pub use blockifier::transaction::account_transaction::AccountTransaction;
 pub use blockifier::transaction::transaction_execution::Transaction;
 pub use blockifier::transaction::transactions::ClassInfo;
+pub use blockifier::versioned_constants::VersionedConstants;
 pub use transaction::transaction_hash;
@@ -0,0 +1,433 @@
+//! Serves feeder gateway replies from a local archive instead of over HTTP.
+//!
+//! An archive is a directory, or an uncompressed tarball, containing the following files:
+//!
+//! | File                               | Feeder gateway reply                                |
+//! | ---------------------------------- | --------------------------------------------------- |
+//! | `block_<number>.json`              | `get_state_update?blockNumber=..&includeBlock=true` |
+//! | `signature_<number>.json`          | `get_signature?blockNumber=..`                      |
+//! | `class_<class hash>.json`          | `get_class_by_hash?classHash=..`                    |
+//! | `compiled_class_<class hash>.json` | `get_compiled_class_by_class_hash?classHash=..`     |
+//!
+//! Block numbers are decimal and class hashes are hex encoded. Files may be nested in
+//! sub-directories, and files with other names are ignored. Compiled classes are optional
+//! as they are only requested if compiling a Sierra class locally fails.
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use pathfinder_common::{BlockHash, BlockId, BlockNumber, ClassHash, StateUpdate};
+use pathfinder_crypto::Felt;
+use starknet_gateway_types::error::{KnownStarknetErrorCode, SequencerError, StarknetError};
+use starknet_gateway_types::reply::{self, PendingBlock};
+
+use crate::GatewayApi;
+
+/// A [GatewayApi] backed by a local archive of feeder gateway replies.
+#[derive(Clone, Debug)]
+pub struct Archive(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    /// Set if the entries are located within this tarball.
+    tarball: Option<PathBuf>,
+    entries: HashMap<Key, Entry>,
+    head: Option<BlockNumber>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Block(BlockNumber),
+    Signature(BlockNumber),
+    Class(ClassHash),
+    CompiledClass(ClassHash),
+}
+
+impl Key {
+    fn parse(file_name: &str) -> Option<Self> {
+        let stem = file_name.strip_suffix(".json")?;
+
+        let number = |s: &str| s.parse::<u64>().ok().and_then(BlockNumber::new);
+        let class_hash = |s: &str| Felt::from_hex_str(s).ok().map(ClassHash);
+
+        if let Some(hash) = stem.strip_prefix("compiled_class_") {
+            class_hash(hash).map(Key::CompiledClass)
+        } else if let Some(hash) = stem.strip_prefix("class_") {
+            class_hash(hash).map(Key::Class)
+        } else if let Some(n) = stem.strip_prefix("block_") {
+            number(n).map(Key::Block)
+        } else if let Some(n) = stem.strip_prefix("signature_") {
+            number(n).map(Key::Signature)
+        } else {
+            None
+        }
+    }
+
+    fn not_found(&self) -> SequencerError {
+        let code = match self {
+            Key::Block(_) | Key::Signature(_) => KnownStarknetErrorCode::BlockNotFound,
+            Key::Class(_) | Key::CompiledClass(_) => KnownStarknetErrorCode::UndeclaredClass,
+        };
+
+        SequencerError::StarknetError(StarknetError {
+            code: code.into(),
+            message: format!("{self:?} is not part of the archive"),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Entry {
+    File(PathBuf),
+    /// Location of the file's data within the tarball.
+    Tarball {
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl Archive {
+    /// Indexes the archive at `path`, which is either a directory or a tarball.
+    ///
+    /// Fails if the blocks in the archive are not consecutive, or if a block's
+    /// signature is missing.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let (tarball, entries) = if path.is_dir() {
+            let mut entries = HashMap::new();
+            index_directory(path, &mut entries)?;
+            (None, entries)
+        } else {
+            (Some(path.to_owned()), index_tarball(path)?)
+        };
+
+        let mut blocks = entries
+            .keys()
+            .filter_map(|key| match key {
+                Key::Block(number) => Some(*number),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        blocks.sort();
+
+        // A gap would look like a reorg to sync, once it runs into it.
+        if let Some(gap) = blocks.windows(2).find(|w| w[1] != w[0] + 1) {
+            anyhow::bail!("Archive is missing blocks {} to {}", gap[0] + 1, gap[1] - 1);
+        }
+
+        if let Some(number) = blocks
+            .iter()
+            .find(|number| !entries.contains_key(&Key::Signature(**number)))
+        {
+            anyhow::bail!("Archive is missing the signature of block {number}");
+        }
+
+        tracing::debug!(path=%path.display(), first=?blocks.first(), last=?blocks.last(), "Archive indexed");
+
+        Ok(Self(Arc::new(Inner {
+            tarball,
+            entries,
+            head: blocks.last().copied(),
+        })))
+    }
+
+    async fn read(&self, key: Key) -> Result<Vec<u8>, SequencerError> {
+        let inner = self.0.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<_, SequencerError> {
+            let entry = inner.entries.get(&key).ok_or_else(|| key.not_found())?;
+
+            let data = match (entry, &inner.tarball) {
+                (Entry::File(path), _) => std::fs::read(path)?,
+                (Entry::Tarball { offset, size }, Some(tarball)) => {
+                    let mut file = std::fs::File::open(tarball)?;
+                    file.seek(SeekFrom::Start(*offset))?;
+
+                    let mut data = Vec::with_capacity(*size as usize);
+                    file.take(*size).read_to_end(&mut data)?;
+                    data
+                }
+                (Entry::Tarball { .. }, None) => unreachable!("Only tarballs have tarball entries"),
+            };
+
+            Ok(data)
+        })
+        .await
+        .map_err(std::io::Error::from)?
+    }
+
+    async fn read_json<T: serde::de::DeserializeOwned>(
+        &self,
+        key: Key,
+    ) -> Result<T, SequencerError> {
+        let data = self.read(key).await?;
+        Ok(serde_json::from_slice(&data).map_err(std::io::Error::from)?)
+    }
+}
+
+fn index_directory(directory: &Path, entries: &mut HashMap<Key, Entry>) -> anyhow::Result<()> {
+    let dir_entries = std::fs::read_dir(directory)
+        .with_context(|| format!("Reading directory {}", directory.display()))?;
+
+    for entry in dir_entries {
+        let path = entry.context("Reading directory entry")?.path();
+
+        if path.is_dir() {
+            index_directory(&path, entries)?;
+        } else if let Some(key) = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(Key::parse)
+        {
+            entries.insert(key, Entry::File(path));
+        }
+    }
+
+    Ok(())
+}
+
+fn index_tarball(path: &Path) -> anyhow::Result<HashMap<Key, Entry>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Opening tarball {}", path.display()))?;
+    let mut archive = tar::Archive::new(file);
+
+    let mut entries = HashMap::new();
+    for entry in archive.entries_with_seek().context("Reading tarball")? {
+        let entry = entry.context("Reading tarball entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path().context("Reading tarball entry path")?;
+        if let Some(key) = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(Key::parse)
+        {
+            entries.insert(
+                key,
+                Entry::Tarball {
+                    offset: entry.raw_file_position(),
+                    size: entry.size(),
+                },
+            );
+        }
+    }
+
+    Ok(entries)
+}
+
+#[async_trait::async_trait]
+impl GatewayApi for Archive {
+    /// Archives only contain accepted blocks.
+    async fn pending_block(&self) -> Result<(PendingBlock, StateUpdate), SequencerError> {
+        Err(Key::Block(BlockNumber::MAX).not_found())
+    }
+
+    async fn block_header(
+        &self,
+        block: BlockId,
+    ) -> Result<(BlockNumber, BlockHash), SequencerError> {
+        #[derive(serde::Deserialize)]
+        struct Dto {
+            block: Header,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Header {
+            block_number: BlockNumber,
+            block_hash: BlockHash,
+        }
+
+        let number = match block {
+            BlockId::Number(number) => number,
+            BlockId::Latest => self
+                .0
+                .head
+                .ok_or_else(|| Key::Block(BlockNumber::GENESIS).not_found())?,
+            // Finding a block by its hash would require reading the entire archive.
+            BlockId::Hash(_) | BlockId::Pending => {
+                return Err(Key::Block(BlockNumber::MAX).not_found())
+            }
+        };
+
+        let Dto { block } = self.read_json(Key::Block(number)).await?;
+        Ok((block.block_number, block.block_hash))
+    }
+
+    async fn pending_class_by_hash(
+        &self,
+        class_hash: ClassHash,
+    ) -> Result<bytes::Bytes, SequencerError> {
+        self.read(Key::Class(class_hash)).await.map(Into::into)
+    }
+
+    async fn pending_casm_by_hash(
+        &self,
+        class_hash: ClassHash,
+    ) -> Result<bytes::Bytes, SequencerError> {
+        self.read(Key::CompiledClass(class_hash))
+            .await
+            .map(Into::into)
+    }
+
+    async fn state_update_with_block(
+        &self,
+        block: BlockNumber,
+    ) -> Result<(reply::Block, StateUpdate), SequencerError> {
+        #[derive(serde::Deserialize)]
+        struct Dto {
+            block: reply::Block,
+            state_update: reply::StateUpdate,
+        }
+
+        let result: Dto = self.read_json(Key::Block(block)).await?;
+        Ok((result.block, result.state_update.into()))
+    }
+
+    async fn signature(&self, block: BlockId) -> Result<reply::BlockSignature, SequencerError> {
+        let number = match block {
+            BlockId::Number(number) => number,
+            BlockId::Latest => self
+                .0
+                .head
+                .ok_or_else(|| Key::Block(BlockNumber::GENESIS).not_found())?,
+            BlockId::Hash(_) | BlockId::Pending => {
+                return Err(Key::Signature(BlockNumber::MAX).not_found())
+            }
+        };
+
+        self.read_json(Key::Signature(number)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::macro_prelude::*;
+
+    fn block(number: u64) -> String {
+        let block = starknet_gateway_test_fixtures::v0_13_1::state_update_with_block::SEPOLIA_INTEGRATION_NUMBER_9703;
+        let mut block: serde_json::Value = serde_json::from_str(block).unwrap();
+        block["block"]["block_number"] = number.into();
+        block.to_string()
+    }
+
+    fn signature(number: u64) -> String {
+        let signature = starknet_gateway_test_fixtures::v0_12_2::signature::BLOCK_350000;
+        let mut signature: serde_json::Value = serde_json::from_str(signature).unwrap();
+        signature["block_number"] = number.into();
+        signature.to_string()
+    }
+
+    /// Returns the files of an archive containing blocks 0 and 1, and a class.
+    fn files() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("block_0.json", block(0).into_bytes()),
+            ("signature_0.json", signature(0).into_bytes()),
+            ("blocks/block_1.json", block(1).into_bytes()),
+            ("blocks/signature_1.json", signature(1).into_bytes()),
+            ("class_0x1234.json", b"class definition".to_vec()),
+            ("README.md", b"ignored".to_vec()),
+        ]
+    }
+
+    async fn check(archive: Archive) {
+        let head = archive.head().await.unwrap();
+        assert_eq!(head.0, BlockNumber::new_or_panic(1));
+
+        let (block, _) = archive
+            .state_update_with_block(BlockNumber::GENESIS)
+            .await
+            .unwrap();
+        assert_eq!(block.block_number, BlockNumber::GENESIS);
+
+        let signature = archive
+            .signature(BlockNumber::GENESIS.into())
+            .await
+            .unwrap();
+        assert_eq!(signature.block_number, BlockNumber::GENESIS);
+
+        let class = archive
+            .pending_class_by_hash(class_hash!("0x1234"))
+            .await
+            .unwrap();
+        assert_eq!(class.as_ref(), b"class definition");
+
+        let error = archive
+            .state_update_with_block(BlockNumber::new_or_panic(2))
+            .await
+            .unwrap_err();
+        assert_matches::assert_matches!(
+            error,
+            SequencerError::StarknetError(e) if e.code == KnownStarknetErrorCode::BlockNotFound.into()
+        );
+
+        let error = archive
+            .pending_casm_by_hash(class_hash!("0x1234"))
+            .await
+            .unwrap_err();
+        assert_matches::assert_matches!(
+            error,
+            SequencerError::StarknetError(e) if e.code == KnownStarknetErrorCode::UndeclaredClass.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn directory() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, data) in files() {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+
+        check(Archive::open(dir.path()).unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn tarball() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+        for (name, data) in files() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        check(Archive::open(&path).unwrap()).await;
+    }
+
+    #[test]
+    fn missing_block() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("block_0.json"), block(0)).unwrap();
+        std::fs::write(dir.path().join("signature_0.json"), signature(0)).unwrap();
+        std::fs::write(dir.path().join("block_2.json"), block(2)).unwrap();
+        std::fs::write(dir.path().join("signature_2.json"), signature(2)).unwrap();
+
+        let error = Archive::open(dir.path()).unwrap_err();
+        assert_eq!(error.to_string(), "Archive is missing blocks 1 to 1");
+    }
+
+    #[test]
+    fn missing_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("block_0.json"), block(0)).unwrap();
+
+        let error = Archive::open(dir.path()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Archive is missing the signature of block 0"
+        );
+    }
+}
@@ -140,6 +140,7 @@ impl<'a> Request<'a, stage::Method> {
         get_block_traces,
         get_transaction_trace,
         get_signature,
+        get_public_key,
     );
 
     /// Appends the given method to the request url.
@@ -442,6 +443,14 @@ fn retry_condition(e: &SequencerError) -> bool {
             error!(reason=%e, "Request failed, retrying");
             true
         }
+        SequencerError::InconsistentSources(_) => {
+            warn!(reason=%e, "Request failed, retrying");
+            true
+        }
+        SequencerError::Io(_) => {
+            error!(reason=%e, "Request failed, retrying");
+            true
+        }
     }
 }
 
@@ -0,0 +1,570 @@
+//! Health-aware failover and hedging across multiple feeder gateway sources.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::Future;
+use pathfinder_common::{BlockHash, BlockId, BlockNumber};
+use reqwest::Url;
+use starknet_gateway_types::error::{KnownStarknetErrorCode, SequencerError};
+
+/// The number of consecutive failures after which a source is considered unhealthy.
+const UNHEALTHY_THRESHOLD: u32 = 3;
+/// How long an unhealthy source is tried only as a last resort.
+const UNHEALTHY_PERIOD: Duration = Duration::from_secs(30);
+/// Backoff limit when all sources fail.
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A feeder gateway and its observed health.
+#[derive(Debug)]
+pub(crate) struct Source {
+    pub url: Url,
+    consecutive_failures: AtomicU32,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Source {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            consecutive_failures: AtomicU32::new(0),
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .map_or(true, |until| Instant::now() >= until)
+    }
+
+    fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        *self.unhealthy_until.lock().unwrap() = None;
+    }
+
+    fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= UNHEALTHY_THRESHOLD {
+            tracing::debug!(url=%self.url, %failures, "Feeder gateway marked unhealthy");
+            *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_PERIOD);
+        }
+    }
+
+    /// Updates the source's health based on the outcome of a request.
+    fn record<T>(&self, result: &Result<T, SequencerError>) {
+        match result {
+            Err(e) if is_source_failure(e) => self.record_failure(),
+            _ => self.record_success(),
+        }
+    }
+}
+
+/// Returns the sources in the order they should be tried: healthy sources first,
+/// each group in the configured order.
+fn ordered(sources: &[Source]) -> Vec<&Source> {
+    let (mut healthy, unhealthy): (Vec<_>, Vec<_>) =
+        sources.iter().partition(|source| source.is_healthy());
+    healthy.extend(unhealthy);
+    healthy
+}
+
+/// Starknet errors are valid answers, anything else reflects on the source itself.
+fn is_source_failure(e: &SequencerError) -> bool {
+    !matches!(e, SequencerError::StarknetError(_))
+}
+
+/// A source lagging behind the others answers with block not found.
+fn is_block_not_found(e: &SequencerError) -> bool {
+    matches!(e, SequencerError::StarknetError(e) if e.code == KnownStarknetErrorCode::BlockNotFound.into())
+}
+
+async fn attempt<Fut: Future>(source: &Source, request: Fut) -> (&Source, Fut::Output) {
+    (source, request.await)
+}
+
+/// Sends a read request to the sources, starting with the most preferred one.
+///
+/// The next source is tried as soon as a request fails, or in parallel once a request
+/// has not completed within `hedge_delay`. The first successful response is returned.
+/// If all sources fail the request is repeated with an exponential backoff, unless
+/// `retry` is disabled.
+pub(crate) async fn read<'a, T, F, Fut>(
+    sources: &'a [Source],
+    hedge_delay: Duration,
+    retry: bool,
+    request: F,
+) -> Result<T, SequencerError>
+where
+    F: Fn(&'a Source) -> Fut,
+    Fut: Future<Output = Result<T, SequencerError>>,
+{
+    let mut backoff = Duration::from_secs(2);
+
+    loop {
+        match read_once(sources, hedge_delay, &request).await {
+            Err(e) if retry && is_source_failure(&e) => {
+                tracing::debug!(reason=%e, "All feeder gateways failed, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn read_once<'a, T, F, Fut>(
+    sources: &'a [Source],
+    hedge_delay: Duration,
+    request: &F,
+) -> Result<T, SequencerError>
+where
+    F: Fn(&'a Source) -> Fut,
+    Fut: Future<Output = Result<T, SequencerError>>,
+{
+    let mut remaining = ordered(sources).into_iter();
+    let mut in_flight = FuturesUnordered::new();
+
+    let first = remaining
+        .next()
+        .expect("At least one feeder gateway is configured");
+    in_flight.push(attempt(first, request(first)));
+
+    loop {
+        tokio::select! {
+            Some((source, result)) = in_flight.next() => {
+                source.record(&result);
+
+                let error = match result {
+                    Ok(data) => return Ok(data),
+                    // Only failures and lagging sources warrant asking another source.
+                    Err(e) if !is_source_failure(&e) && !is_block_not_found(&e) => return Err(e),
+                    Err(e) => e,
+                };
+
+                tracing::trace!(url=%source.url, reason=%error, "Feeder gateway request failed");
+
+                match remaining.next() {
+                    Some(next) => in_flight.push(attempt(next, request(next))),
+                    None if in_flight.is_empty() => return Err(error),
+                    None => {}
+                }
+            }
+            _ = tokio::time::sleep(hedge_delay), if !remaining.as_slice().is_empty() => {
+                let next = remaining.next().expect("Checked by the branch condition");
+                tracing::trace!(url=%next.url, "Hedging feeder gateway request");
+                in_flight.push(attempt(next, request(next)));
+            }
+        }
+    }
+}
+
+/// Returns the chain head which a majority of the responding sources agree on.
+///
+/// This is the block at the lowest height reported. Sources which are further ahead are asked
+/// for the block hash at that height, and the block hash reported by more than half of the
+/// sources which answered wins. Sources disagreeing with the majority are logged. Without a
+/// majority the sources are inconsistent and an error is returned. Failing sources are left
+/// out. If no source responds or the sources are inconsistent, the request is repeated with an
+/// exponential backoff, unless `retry` is disabled.
+pub(crate) async fn consistent_head<'a, F, Fut>(
+    sources: &'a [Source],
+    retry: bool,
+    request: F,
+) -> Result<(BlockNumber, BlockHash), SequencerError>
+where
+    F: Fn(&'a Source, BlockId) -> Fut,
+    Fut: Future<Output = Result<(BlockNumber, BlockHash), SequencerError>>,
+{
+    let mut backoff = Duration::from_secs(2);
+
+    loop {
+        match consistent_head_once(sources, &request).await {
+            Err(e) if retry && is_source_failure(&e) => {
+                tracing::debug!(reason=%e, "Feeder gateways failed or disagree, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn consistent_head_once<'a, F, Fut>(
+    sources: &'a [Source],
+    request: &F,
+) -> Result<(BlockNumber, BlockHash), SequencerError>
+where
+    F: Fn(&'a Source, BlockId) -> Fut,
+    Fut: Future<Output = Result<(BlockNumber, BlockHash), SequencerError>>,
+{
+    let results = futures::future::join_all(
+        sources
+            .iter()
+            .map(|source| attempt(source, request(source, BlockId::Latest))),
+    )
+    .await;
+
+    let mut heads = Vec::with_capacity(results.len());
+    let mut last_error = None;
+    for (source, result) in results {
+        source.record(&result);
+        match result {
+            Ok(head) => heads.push((source, head)),
+            Err(e) => {
+                tracing::trace!(url=%source.url, reason=%e, "Fetching head failed");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    let Some(&(_, lowest)) = heads.iter().min_by_key(|(_, (number, _))| *number) else {
+        return Err(last_error.expect("At least one feeder gateway is configured"));
+    };
+
+    let hashes = futures::future::join_all(heads.into_iter().map(|(source, (number, hash))| {
+        let request = (number != lowest.0).then(|| request(source, lowest.0.into()));
+        async move {
+            let hash = match request {
+                Some(request) => {
+                    let result = request.await;
+                    source.record(&result);
+                    result.map(|(_, hash)| hash)
+                }
+                None => Ok(hash),
+            };
+            (source, hash)
+        }
+    }))
+    .await;
+
+    let mut votes = HashMap::<BlockHash, Vec<&Url>>::new();
+    let mut answered = 0;
+    for (source, hash) in hashes {
+        match hash {
+            Ok(hash) => {
+                votes.entry(hash).or_default().push(&source.url);
+                answered += 1;
+            }
+            Err(e) => {
+                tracing::trace!(url=%source.url, block=%lowest.0, reason=%e, "Fetching block hash failed");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    let describe = |votes: &HashMap<BlockHash, Vec<&Url>>, skip: Option<BlockHash>| {
+        votes
+            .iter()
+            .filter(|(hash, _)| Some(**hash) != skip)
+            .flat_map(|(hash, urls)| {
+                urls.iter()
+                    .map(move |url| format!("{url} reports block hash {hash}"))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let Some((&hash, urls)) = votes.iter().max_by_key(|(_, urls)| urls.len()) else {
+        return Err(last_error.expect("A source which fails to answer has failed"));
+    };
+    if urls.len() * 2 <= answered {
+        return Err(SequencerError::InconsistentSources(format!(
+            "No majority for block {}: {}",
+            lowest.0,
+            describe(&votes, None)
+        )));
+    }
+
+    if urls.len() < answered {
+        tracing::warn!(block=%lowest.0, %hash, sources=%describe(&votes, Some(hash)), "Feeder gateways disagree with the majority");
+    }
+
+    Ok((lowest.0, hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::macro_prelude::*;
+    use starknet_gateway_types::error::StarknetError;
+
+    fn sources(n: usize) -> Vec<Source> {
+        (0..n)
+            .map(|i| Source::new(Url::parse(&format!("http://source{i}")).unwrap()))
+            .collect()
+    }
+
+    fn transport_error() -> SequencerError {
+        SequencerError::InvalidStarknetErrorVariant
+    }
+
+    fn block_not_found() -> SequencerError {
+        SequencerError::StarknetError(StarknetError {
+            code: KnownStarknetErrorCode::BlockNotFound.into(),
+            message: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_next_source() {
+        let sources = sources(3);
+
+        let result = read(&sources, Duration::from_secs(60), false, |source| {
+            let host = source.url.host_str().unwrap().to_owned();
+            async move {
+                match host.as_str() {
+                    "source0" => Err(transport_error()),
+                    _ => Ok(host),
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(result, "source1");
+        assert_eq!(sources[0].consecutive_failures.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn block_not_found_is_confirmed_by_other_sources() {
+        let sources = sources(2);
+
+        let result = read(&sources, Duration::from_secs(60), false, |source| {
+            let host = source.url.host_str().unwrap().to_owned();
+            async move {
+                match host.as_str() {
+                    "source0" => Err(block_not_found()),
+                    _ => Ok(host),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(result, "source1");
+
+        let result = read(&sources, Duration::from_secs(60), false, |_| async {
+            Err::<(), _>(block_not_found())
+        })
+        .await;
+        assert!(is_block_not_found(&result.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn hedges_slow_source() {
+        let sources = sources(2);
+
+        let result = read(&sources, Duration::from_millis(10), false, |source| {
+            let host = source.url.host_str().unwrap().to_owned();
+            async move {
+                if host == "source0" {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+                Ok::<_, SequencerError>(host)
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(result, "source1");
+    }
+
+    #[tokio::test]
+    async fn unhealthy_sources_are_tried_last() {
+        let sources = sources(2);
+        for _ in 0..UNHEALTHY_THRESHOLD {
+            sources[0].record_failure();
+        }
+
+        let order = ordered(&sources)
+            .into_iter()
+            .map(|source| source.url.host_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["source1", "source0"]);
+
+        sources[0].record_success();
+        assert!(sources[0].is_healthy());
+    }
+
+    mod consistent_head {
+        use super::*;
+
+        #[tokio::test]
+        async fn lowest_agreed_head() {
+            let sources = sources(2);
+
+            let head = consistent_head(&sources, false, |source, block| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match (host.as_str(), block) {
+                        ("source0", BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(10), block_hash!("0x10")))
+                        }
+                        ("source1", BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(11), block_hash!("0x11")))
+                        }
+                        ("source1", BlockId::Number(n)) if n.get() == 10 => {
+                            Ok((n, block_hash!("0x10")))
+                        }
+                        _ => Err(block_not_found()),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test]
+        async fn inconsistent() {
+            let sources = sources(2);
+
+            let result = consistent_head(&sources, false, |source, _| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match host.as_str() {
+                        "source0" => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                        _ => Ok((BlockNumber::new_or_panic(10), block_hash!("0xbad"))),
+                    }
+                }
+            })
+            .await;
+
+            assert!(matches!(
+                result,
+                Err(SequencerError::InconsistentSources(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn majority_wins() {
+            let sources = sources(3);
+
+            let head = consistent_head(&sources, false, |source, _| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match host.as_str() {
+                        "source2" => Ok((BlockNumber::new_or_panic(10), block_hash!("0xbad"))),
+                        _ => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test]
+        async fn forked_lowest_source_is_outvoted() {
+            let sources = sources(3);
+
+            let head = consistent_head(&sources, false, |source, block| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match (host.as_str(), block) {
+                        ("source0", BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(10), block_hash!("0xbad")))
+                        }
+                        (_, BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(11), block_hash!("0x11")))
+                        }
+                        (_, _) => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn retries_when_sources_disagree() {
+            let sources = sources(2);
+            let attempts = AtomicU32::new(0);
+
+            let head = consistent_head(&sources, true, |_, _| {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    match attempt {
+                        0 => Ok((BlockNumber::new_or_panic(10), block_hash!("0xbad"))),
+                        _ => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test]
+        async fn source_failing_to_confirm_hash_is_ignored() {
+            let sources = sources(3);
+
+            let head = consistent_head(&sources, false, |source, block| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match (host.as_str(), block) {
+                        ("source0", BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(10), block_hash!("0x10")))
+                        }
+                        (_, BlockId::Latest) => {
+                            Ok((BlockNumber::new_or_panic(11), block_hash!("0x11")))
+                        }
+                        ("source1", _) => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                        _ => Err(transport_error()),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn retries_when_all_sources_fail() {
+            let sources = sources(2);
+            let attempts = AtomicU32::new(0);
+
+            let head = consistent_head(&sources, true, |_, _| {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    match attempt {
+                        0 | 1 => Err(transport_error()),
+                        _ => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+
+        #[tokio::test]
+        async fn failed_sources_are_ignored() {
+            let sources = sources(2);
+
+            let head = consistent_head(&sources, false, |source, _| {
+                let host = source.url.host_str().unwrap().to_owned();
+                async move {
+                    match host.as_str() {
+                        "source0" => Err(transport_error()),
+                        _ => Ok((BlockNumber::new_or_panic(10), block_hash!("0x10"))),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(head, (BlockNumber::new_or_panic(10), block_hash!("0x10")));
+        }
+    }
+}
@@ -1,14 +1,20 @@
 //! Starknet L2 sequencer client.
-use pathfinder_common::{BlockHash, BlockId, BlockNumber, ClassHash, StateUpdate, TransactionHash};
+use pathfinder_common::{
+    BlockHash, BlockId, BlockNumber, ClassHash, PublicKey, StateUpdate, TransactionHash,
+};
 use reqwest::Url;
 use starknet_gateway_types::reply::PendingBlock;
 use starknet_gateway_types::trace::{BlockTrace, TransactionTrace};
 use starknet_gateway_types::{error::SequencerError, reply, request};
-use std::{fmt::Debug, result::Result, time::Duration};
+use std::{fmt::Debug, result::Result, sync::Arc, time::Duration};
 
+mod archive;
 mod builder;
+mod failover;
 mod metrics;
 
+pub use archive::Archive;
+
 #[allow(unused_variables)]
 #[mockall::automock]
 #[async_trait::async_trait]
@@ -99,6 +105,10 @@ pub trait GatewayApi: Sync {
     async fn signature(&self, block: BlockId) -> Result<reply::BlockSignature, SequencerError> {
         unimplemented!();
     }
+
+    async fn public_key(&self) -> Result<PublicKey, SequencerError> {
+        unimplemented!();
+    }
 }
 
 #[async_trait::async_trait]
@@ -182,6 +192,14 @@ impl<T: GatewayApi + Sync + Send> GatewayApi for std::sync::Arc<T> {
     async fn signature(&self, block: BlockId) -> Result<reply::BlockSignature, SequencerError> {
         self.as_ref().signature(block).await
     }
+
+    async fn public_key(&self) -> Result<PublicKey, SequencerError> {
+        self.as_ref().public_key().await
+    }
+
+    async fn head(&self) -> Result<(BlockNumber, BlockHash), SequencerError> {
+        self.as_ref().head().await
+    }
 }
 
 /// Starknet sequencer client using REST API.
@@ -194,14 +212,21 @@ impl<T: GatewayApi + Sync + Send> GatewayApi for std::sync::Arc<T> {
 /// `backoff [secs] = min((2 ^ N) * 15, 600) [secs]`
 ///
 /// where `N` is the consecutive retry iteration number `{1, 2, ...}`.
+///
+/// Multiple feeder gateways can be configured using
+/// [with_feeder_gateway_fallbacks](Client::with_feeder_gateway_fallbacks), in which case
+/// read requests fail over between them and [head](GatewayApi::head) only returns a
+/// head which a majority of them agree on.
 #[derive(Debug, Clone)]
 pub struct Client {
     /// This client is internally refcounted
     inner: reqwest::Client,
     /// Starknet gateway URL.
     gateway: Url,
-    /// Starknet feeder gateway URL.
-    feeder_gateway: Url,
+    /// Starknet feeder gateway URLs, in order of preference.
+    feeder_gateways: Arc<[failover::Source]>,
+    /// Delay after which a slow read request is also sent to the next feeder gateway.
+    hedge_delay: Duration,
     /// Whether __read only__ requests should be retried, defaults to __true__ for production.
     /// Use [disable_retry_for_tests](Client::disable_retry_for_tests) to disable retry logic for all __read only__ requests when testing.
     retry: bool,
@@ -254,7 +279,8 @@ impl Client {
                 .user_agent(pathfinder_common::consts::USER_AGENT)
                 .build()?,
             gateway,
-            feeder_gateway,
+            feeder_gateways: Arc::new([failover::Source::new(feeder_gateway)]),
+            hedge_delay: Duration::from_secs(2),
             retry: true,
             api_key: None,
         })
@@ -266,6 +292,25 @@ impl Client {
         self
     }
 
+    /// Adds feeder gateways to fail over to, in order of preference. These can be any source
+    /// serving the feeder gateway API, such as a proxy or another pathfinder node.
+    pub fn with_feeder_gateway_fallbacks(mut self, fallbacks: Vec<Url>) -> Self {
+        self.feeder_gateways = self
+            .feeder_gateways
+            .iter()
+            .map(|source| source.url.clone())
+            .chain(fallbacks)
+            .map(failover::Source::new)
+            .collect();
+        self
+    }
+
+    /// Sets the delay after which a slow read request is also sent to the next feeder gateway.
+    pub fn with_hedge_delay(mut self, hedge_delay: Duration) -> Self {
+        self.hedge_delay = hedge_delay;
+        self
+    }
+
     /// Use this method to disable retry logic for all __non write__ requests when testing.
     pub fn disable_retry_for_tests(self) -> Self {
         Self {
@@ -279,14 +324,59 @@ impl Client {
     }
 
     fn feeder_gateway_request(&self) -> builder::Request<'_, builder::stage::Method> {
-        builder::Request::builder(
-            &self.inner,
-            self.feeder_gateway.clone(),
-            self.api_key.clone(),
-        )
+        self.source_request(&self.feeder_gateways[0])
+    }
+
+    fn source_request(
+        &self,
+        source: &failover::Source,
+    ) -> builder::Request<'_, builder::stage::Method> {
+        builder::Request::builder(&self.inner, source.url.clone(), self.api_key.clone())
+    }
+
+    /// Sends a read request to the feeder gateway, failing over to the other feeder
+    /// gateways if more than one is configured.
+    async fn feeder_gateway_read<'a, T, F, Fut>(&'a self, request: F) -> Result<T, SequencerError>
+    where
+        F: Fn(builder::Request<'a, builder::stage::Method>, bool) -> Fut,
+        Fut: futures::Future<Output = Result<T, SequencerError>>,
+    {
+        match &*self.feeder_gateways {
+            [_] => request(self.feeder_gateway_request(), self.retry).await,
+            sources => {
+                failover::read(sources, self.hedge_delay, self.retry, |source| {
+                    // Retries are performed across all sources instead.
+                    request(self.source_request(source), false)
+                })
+                .await
+            }
+        }
     }
 }
 
+async fn block_header(
+    request: builder::Request<'_, builder::stage::Method>,
+    block: BlockId,
+    retry: bool,
+) -> Result<(BlockNumber, BlockHash), SequencerError> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct BlockHeader {
+        pub block_hash: BlockHash,
+        pub block_number: BlockNumber,
+    }
+
+    let header: BlockHeader = request
+        .get_block()
+        .with_block(block)
+        .add_param("headerOnly", "true")
+        .with_retry(retry)
+        .get()
+        .await?;
+
+    Ok((header.block_number, header.block_hash))
+}
+
 #[async_trait::async_trait]
 impl GatewayApi for Client {
     #[tracing::instrument(skip(self))]
@@ -298,12 +388,14 @@ impl GatewayApi for Client {
         }
 
         let result: Dto = self
-            .feeder_gateway_request()
-            .get_state_update()
-            .with_block(BlockId::Pending)
-            .add_param("includeBlock", "true")
-            .with_retry(self.retry)
-            .get()
+            .feeder_gateway_read(|request, retry| {
+                request
+                    .get_state_update()
+                    .with_block(BlockId::Pending)
+                    .add_param("includeBlock", "true")
+                    .with_retry(retry)
+                    .get()
+            })
             .await?;
 
         Ok((result.block, result.state_update.into()))
@@ -313,23 +405,22 @@ impl GatewayApi for Client {
         &self,
         block: BlockId,
     ) -> Result<(BlockNumber, BlockHash), SequencerError> {
-        #[derive(serde::Deserialize)]
-        #[serde(deny_unknown_fields)]
-        pub struct BlockHeader {
-            pub block_hash: BlockHash,
-            pub block_number: BlockNumber,
-        }
-
-        let header: BlockHeader = self
-            .feeder_gateway_request()
-            .get_block()
-            .with_block(block)
-            .add_param("headerOnly", "true")
-            .with_retry(self.retry)
-            .get()
-            .await?;
+        self.feeder_gateway_read(|request, retry| block_header(request, block, retry))
+            .await
+    }
 
-        Ok((header.block_number, header.block_hash))
+    /// Returns the chain head. With multiple feeder gateways, this is the latest block
+    /// a majority of them agree on.
+    async fn head(&self) -> Result<(BlockNumber, BlockHash), SequencerError> {
+        match &*self.feeder_gateways {
+            [_] => self.block_header(BlockId::Latest).await,
+            sources => {
+                failover::consistent_head(sources, self.retry, |source, block| {
+                    block_header(self.source_request(source), block, false)
+                })
+                .await
+            }
+        }
     }
 
     /// Gets class for a particular class hash.
@@ -338,13 +429,15 @@ impl GatewayApi for Client {
         &self,
         class_hash: ClassHash,
     ) -> Result<bytes::Bytes, SequencerError> {
-        self.feeder_gateway_request()
-            .get_class_by_hash()
-            .with_class_hash(class_hash)
-            .with_block(BlockId::Pending)
-            .with_retry(self.retry)
-            .get_as_bytes()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_class_by_hash()
+                .with_class_hash(class_hash)
+                .with_block(BlockId::Pending)
+                .with_retry(retry)
+                .get_as_bytes()
+        })
+        .await
     }
 
     /// Gets CASM for a particular class hash.
@@ -353,13 +446,15 @@ impl GatewayApi for Client {
         &self,
         class_hash: ClassHash,
     ) -> Result<bytes::Bytes, SequencerError> {
-        self.feeder_gateway_request()
-            .get_compiled_class_by_class_hash()
-            .with_class_hash(class_hash)
-            .with_block(BlockId::Pending)
-            .with_retry(self.retry)
-            .get_as_bytes()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_compiled_class_by_class_hash()
+                .with_class_hash(class_hash)
+                .with_block(BlockId::Pending)
+                .with_retry(retry)
+                .get_as_bytes()
+        })
+        .await
     }
 
     /// Gets transaction by hash.
@@ -368,12 +463,14 @@ impl GatewayApi for Client {
         &self,
         transaction_hash: TransactionHash,
     ) -> Result<reply::TransactionStatus, SequencerError> {
-        self.feeder_gateway_request()
-            .get_transaction()
-            .with_transaction_hash(transaction_hash)
-            .with_retry(self.retry)
-            .get()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_transaction()
+                .with_transaction_hash(transaction_hash)
+                .with_retry(retry)
+                .get()
+        })
+        .await
     }
 
     /// Gets a _block_ and the corresponding _state update_.
@@ -394,12 +491,14 @@ impl GatewayApi for Client {
         }
 
         let result: Dto = self
-            .feeder_gateway_request()
-            .get_state_update()
-            .with_block(block)
-            .add_param("includeBlock", "true")
-            .with_retry(self.retry)
-            .get()
+            .feeder_gateway_read(|request, retry| {
+                request
+                    .get_state_update()
+                    .with_block(block)
+                    .add_param("includeBlock", "true")
+                    .with_retry(retry)
+                    .get()
+            })
             .await?;
         Ok((result.block, result.state_update.into()))
     }
@@ -407,11 +506,10 @@ impl GatewayApi for Client {
     /// Gets addresses of the Ethereum contracts crucial to Starknet operation.
     #[tracing::instrument(skip(self))]
     async fn eth_contract_addresses(&self) -> Result<reply::EthContractAddresses, SequencerError> {
-        self.feeder_gateway_request()
-            .get_contract_addresses()
-            .with_retry(self.retry)
-            .get()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request.get_contract_addresses().with_retry(retry).get()
+        })
+        .await
     }
 
     /// Adds a transaction invoking a contract.
@@ -471,12 +569,14 @@ impl GatewayApi for Client {
 
     #[tracing::instrument(skip(self))]
     async fn block_traces(&self, block: BlockId) -> Result<BlockTrace, SequencerError> {
-        self.feeder_gateway_request()
-            .get_block_traces()
-            .with_block(block)
-            .with_retry(self.retry)
-            .get()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_block_traces()
+                .with_block(block)
+                .with_retry(retry)
+                .get()
+        })
+        .await
     }
 
     #[tracing::instrument(skip(self))]
@@ -484,21 +584,32 @@ impl GatewayApi for Client {
         &self,
         transaction: TransactionHash,
     ) -> Result<TransactionTrace, SequencerError> {
-        self.feeder_gateway_request()
-            .get_transaction_trace()
-            .with_transaction_hash(transaction)
-            .with_retry(self.retry)
-            .get()
-            .await
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_transaction_trace()
+                .with_transaction_hash(transaction)
+                .with_retry(retry)
+                .get()
+        })
+        .await
     }
 
     #[tracing::instrument(skip(self))]
     async fn signature(&self, block: BlockId) -> Result<reply::BlockSignature, SequencerError> {
-        self.feeder_gateway_request()
-            .get_signature()
-            .with_block(block)
-            .with_retry(self.retry)
-            .get()
+        self.feeder_gateway_read(|request, retry| {
+            request
+                .get_signature()
+                .with_block(block)
+                .with_retry(retry)
+                .get()
+        })
+        .await
+    }
+
+    /// Returns the key with which the sequencer signs blocks.
+    #[tracing::instrument(skip(self))]
+    async fn public_key(&self) -> Result<PublicKey, SequencerError> {
+        self.feeder_gateway_read(|request, retry| request.get_public_key().with_retry(retry).get())
             .await
     }
 }
@@ -1378,4 +1489,26 @@ mod tests {
                 .unwrap();
         }
     }
+
+    mod public_key {
+        use super::*;
+        use pathfinder_common::macro_prelude::*;
+
+        #[tokio::test]
+        async fn success() {
+            let (_jh, client) = setup([(
+                "/feeder_gateway/get_public_key",
+                (
+                    r#""0x48253ff2c3bed7af18bde0b611b083b39445959102d4947c51c4db6aa4f4e58""#,
+                    200,
+                ),
+            )]);
+
+            let public_key = client.public_key().await.unwrap();
+            assert_eq!(
+                public_key,
+                public_key!("0x48253ff2c3bed7af18bde0b611b083b39445959102d4947c51c4db6aa4f4e58")
+            );
+        }
+    }
 }
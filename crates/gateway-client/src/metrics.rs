@@ -163,7 +163,9 @@ pub async fn with_metrics<T>(
             {
                 increment_failed(meta, REASON_RATE_LIMITING);
             }
-            SequencerError::ReqwestError(_) => {}
+            SequencerError::ReqwestError(_)
+            | SequencerError::InconsistentSources(_)
+            | SequencerError::Io(_) => {}
         }
 
         e
@@ -14,6 +14,12 @@ pub enum SequencerError {
     /// not informative enough or bloated
     #[error("error decoding response body: invalid error variant")]
     InvalidStarknetErrorVariant,
+    /// Multiple feeder gateways returned conflicting data.
+    #[error("inconsistent feeder gateways: {0}")]
+    InconsistentSources(String),
+    /// Reading a reply from a local archive failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
 /// Used for deserializing specific Starknet sequencer error data.
@@ -89,8 +89,7 @@ impl Block {
 }
 
 #[serde_as]
-#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
-#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq, serde::Serialize)]
 pub struct PendingBlock {
     /// Excluded in blocks prior to Starknet 0.9.
     ///
@@ -2108,6 +2107,84 @@ impl From<StateUpdate> for pathfinder_common::StateUpdate {
     }
 }
 
+impl From<pathfinder_common::StateUpdate> for StateUpdate {
+    fn from(update: pathfinder_common::StateUpdate) -> Self {
+        use pathfinder_common::state_update::ContractClassUpdate;
+        use std::collections::HashMap;
+
+        let mut storage_diffs = HashMap::new();
+        let mut deployed_contracts = Vec::new();
+        let mut nonces = HashMap::new();
+        let mut replaced_classes = Vec::new();
+
+        for (address, contract) in update.contract_updates {
+            if let Some(nonce) = contract.nonce {
+                nonces.insert(address, nonce);
+            }
+
+            match contract.class {
+                Some(ContractClassUpdate::Deploy(class_hash)) => {
+                    deployed_contracts.push(state_update::DeployedContract {
+                        address,
+                        class_hash,
+                    })
+                }
+                Some(ContractClassUpdate::Replace(class_hash)) => {
+                    replaced_classes.push(state_update::ReplacedClass {
+                        address,
+                        class_hash,
+                    })
+                }
+                None => {}
+            }
+
+            let storage = contract
+                .storage
+                .into_iter()
+                .map(|(key, value)| state_update::StorageDiff { key, value })
+                .collect();
+
+            storage_diffs.insert(address, storage);
+        }
+
+        // System contracts are embedded in the normal storage diffs.
+        for (address, system) in update.system_contract_updates {
+            let storage = system
+                .storage
+                .into_iter()
+                .map(|(key, value)| state_update::StorageDiff { key, value })
+                .collect();
+
+            storage_diffs.insert(address, storage);
+        }
+
+        let declared_classes = update
+            .declared_sierra_classes
+            .into_iter()
+            .map(
+                |(class_hash, compiled_class_hash)| state_update::DeclaredSierraClass {
+                    class_hash,
+                    compiled_class_hash,
+                },
+            )
+            .collect();
+
+        Self {
+            block_hash: update.block_hash,
+            new_root: update.state_commitment,
+            old_root: update.parent_state_commitment,
+            state_diff: state_update::StateDiff {
+                storage_diffs,
+                deployed_contracts,
+                old_declared_contracts: update.declared_cairo_classes,
+                declared_classes,
+                nonces,
+                replaced_classes,
+            },
+        }
+    }
+}
+
 /// Types used when deserializing state update related data.
 pub mod state_update {
     use pathfinder_common::{
@@ -7,7 +7,7 @@ use pathfinder_common::{
 use pathfinder_crypto::Felt;
 use pathfinder_storage::{Node, Transaction};
 
-use crate::tree::MerkleTree;
+use crate::tree::{LeafRange, MerkleTree};
 use pathfinder_common::hash::PoseidonHash;
 
 /// A [Patricia Merkle tree](MerkleTree) used to calculate commitments to Starknet's Sierra classes.
@@ -45,6 +45,23 @@ impl<'tx> ClassCommitmentTree<'tx> {
         Ok(Self { tree, storage })
     }
 
+    /// Loads the tree with the given root, whose leaves are part of the state of `block`.
+    ///
+    /// Unlike [Self::load], the root does not have to be the one recorded for the block, which
+    /// allows building the tree of a block in several steps.
+    pub fn load_root(tx: &'tx Transaction<'tx>, block: BlockNumber, root: Option<u64>) -> Self {
+        let storage = ClassStorage {
+            tx,
+            block: Some(block),
+        };
+        let tree = match root {
+            Some(root) => MerkleTree::new(root),
+            None => MerkleTree::empty(),
+        };
+
+        Self { tree, storage }
+    }
+
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
         self.tree = self.tree.with_verify_hashes(verify_hashes);
         self
@@ -68,6 +85,37 @@ impl<'tx> ClassCommitmentTree<'tx> {
         let commitment = ClassCommitment(update.root);
         Ok((commitment, update.nodes))
     }
+
+    /// Returns up to `limit` classes in `start..=end` with their range proof. See
+    /// [`MerkleTree::get_range`].
+    pub fn get_range(
+        tx: &'tx Transaction<'tx>,
+        block: BlockNumber,
+        start: SierraHash,
+        end: SierraHash,
+        limit: usize,
+    ) -> anyhow::Result<LeafRange> {
+        let root = tx
+            .class_root_index(block)
+            .context("Querying class root index")?;
+
+        let Some(root) = root else {
+            return Ok(LeafRange::default());
+        };
+
+        let storage = ClassStorage {
+            tx,
+            block: Some(block),
+        };
+
+        MerkleTree::<PoseidonHash, 251>::get_range(
+            root,
+            &storage,
+            start.view_bits(),
+            end.view_bits(),
+            limit,
+        )
+    }
 }
 
 struct ClassStorage<'tx> {
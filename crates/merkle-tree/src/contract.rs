@@ -5,7 +5,7 @@
 
 use crate::{
     merkle_node::InternalNode,
-    tree::{MerkleTree, Visit},
+    tree::{LeafRange, MerkleTree, Visit},
 };
 use anyhow::Context;
 use bitvec::{prelude::Msb0, slice::BitSlice};
@@ -64,6 +64,30 @@ impl<'tx> ContractsStorageTree<'tx> {
         Ok(Self { tree, storage })
     }
 
+    /// Loads the contract's tree with the given root, whose leaves are part of the state of
+    /// `block`.
+    ///
+    /// Unlike [Self::load], the root does not have to be the one recorded for the block, which
+    /// allows building the tree of a block in several steps.
+    pub fn load_root(
+        tx: &'tx Transaction<'tx>,
+        contract: ContractAddress,
+        block: BlockNumber,
+        root: Option<u64>,
+    ) -> Self {
+        let storage = ContractStorage {
+            tx,
+            block: Some(block),
+            contract,
+        };
+        let tree = match root {
+            Some(root) => MerkleTree::new(root),
+            None => MerkleTree::empty(),
+        };
+
+        Self { tree, storage }
+    }
+
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
         self.tree = self.tree.with_verify_hashes(verify_hashes);
         self
@@ -93,6 +117,39 @@ impl<'tx> ContractsStorageTree<'tx> {
         MerkleTree::<PedersenHash, 251>::get_proof(root, &storage, key)
     }
 
+    /// Returns up to `limit` storage slots in `start..=end` with their range proof. See
+    /// [`MerkleTree::get_range`].
+    pub fn get_range(
+        tx: &'tx Transaction<'tx>,
+        contract: ContractAddress,
+        block: BlockNumber,
+        start: StorageAddress,
+        end: StorageAddress,
+        limit: usize,
+    ) -> anyhow::Result<LeafRange> {
+        let root = tx
+            .contract_root_index(block, contract)
+            .context("Querying contract root index")?;
+
+        let Some(root) = root else {
+            return Ok(LeafRange::default());
+        };
+
+        let storage = ContractStorage {
+            tx,
+            block: Some(block),
+            contract,
+        };
+
+        MerkleTree::<PedersenHash, 251>::get_range(
+            root,
+            &storage,
+            start.view_bits(),
+            end.view_bits(),
+            limit,
+        )
+    }
+
     pub fn set(&mut self, address: StorageAddress, value: StorageValue) -> anyhow::Result<()> {
         let key = address.view_bits().to_owned();
         self.tree.set(&self.storage, key, value.0)
@@ -151,6 +208,23 @@ impl<'tx> StorageCommitmentTree<'tx> {
         Ok(Self { tree, storage })
     }
 
+    /// Loads the tree with the given root, whose leaves are part of the state of `block`.
+    ///
+    /// Unlike [Self::load], the root does not have to be the one recorded for the block, which
+    /// allows building the tree of a block in several steps.
+    pub fn load_root(tx: &'tx Transaction<'tx>, block: BlockNumber, root: Option<u64>) -> Self {
+        let storage = StorageTrieStorage {
+            tx,
+            block: Some(block),
+        };
+        let tree = match root {
+            Some(root) => MerkleTree::new(root),
+            None => MerkleTree::empty(),
+        };
+
+        Self { tree, storage }
+    }
+
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
         self.tree = self.tree.with_verify_hashes(verify_hashes);
         self
@@ -195,6 +269,37 @@ impl<'tx> StorageCommitmentTree<'tx> {
         MerkleTree::<PedersenHash, 251>::get_proof(root, &storage, address.view_bits())
     }
 
+    /// Returns up to `limit` contracts in `start..=end` with their range proof. See
+    /// [`MerkleTree::get_range`].
+    pub fn get_range(
+        tx: &'tx Transaction<'tx>,
+        block: BlockNumber,
+        start: ContractAddress,
+        end: ContractAddress,
+        limit: usize,
+    ) -> anyhow::Result<LeafRange> {
+        let root = tx
+            .storage_root_index(block)
+            .context("Querying storage root index")?;
+
+        let Some(root) = root else {
+            return Ok(LeafRange::default());
+        };
+
+        let storage = StorageTrieStorage {
+            tx,
+            block: Some(block),
+        };
+
+        MerkleTree::<PedersenHash, 251>::get_range(
+            root,
+            &storage,
+            start.view_bits(),
+            end.view_bits(),
+            limit,
+        )
+    }
+
     /// See [`MerkleTree::dfs`]
     pub fn dfs<B, F: FnMut(&InternalNode, &BitSlice<u8, Msb0>) -> ControlFlow<B, Visit>>(
         &mut self,
@@ -0,0 +1,194 @@
+//! Verification of the [range proofs](crate::tree::MerkleTree::get_range_proof) used to sync the
+//! state tries leaf range by leaf range.
+//!
+//! A range proof consists of the nodes on the paths from the root towards both ends of a range.
+//! Together with the leaves within the range this is enough to recompute the root: subtrees which
+//! lie within the range are hashed from the leaves, subtrees which lie outside of the range are
+//! represented by their hash in the proof, and the nodes on the boundary paths connect the two.
+//! Any missing, additional or altered leaf within the range therefore results in a different root.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use bitvec::prelude::{BitSlice, BitVec, Msb0};
+use pathfinder_common::hash::FeltHash;
+use pathfinder_common::trie::TrieNode;
+use pathfinder_crypto::Felt;
+
+use crate::merkle_node::Direction;
+
+/// Verifies that `leaves` are exactly the leaves with keys in `start..=end` of the tree with the
+/// given `root`.
+///
+/// The leaves must be sorted by key, and `proof` must contain the nodes on the paths from the
+/// root towards `start` and `end`, as generated by
+/// [get_range_proof](crate::tree::MerkleTree::get_range_proof).
+pub fn verify_range<H: FeltHash, const HEIGHT: usize>(
+    root: Felt,
+    start: &BitSlice<u8, Msb0>,
+    end: &BitSlice<u8, Msb0>,
+    leaves: &[(BitVec<u8, Msb0>, Felt)],
+    proof: &[TrieNode],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        start.len() == HEIGHT && end.len() == HEIGHT,
+        "Range bounds must be {HEIGHT} bits long"
+    );
+    anyhow::ensure!(start <= end, "Range starts after its end");
+    anyhow::ensure!(
+        leaves.iter().all(|(key, _)| key.len() == HEIGHT),
+        "Leaf keys must be {HEIGHT} bits long"
+    );
+    anyhow::ensure!(
+        leaves.windows(2).all(|pair| pair[0].0 < pair[1].0),
+        "Leaves are not sorted by key"
+    );
+    anyhow::ensure!(
+        leaves
+            .iter()
+            .all(|(key, _)| key.as_bitslice() >= start && key.as_bitslice() <= end),
+        "Leaves lie outside of the range"
+    );
+
+    if root == Felt::ZERO {
+        anyhow::ensure!(leaves.is_empty(), "Empty tree has no leaves");
+        return Ok(());
+    }
+
+    let nodes = proof
+        .iter()
+        .map(|node| (node.hash::<H>(), node))
+        .collect::<HashMap<_, _>>();
+
+    Verifier::<H> {
+        start,
+        end,
+        nodes,
+        _hasher: std::marker::PhantomData,
+    }
+    .verify(root, &BitVec::new(), leaves)
+}
+
+struct Verifier<'a, H: FeltHash> {
+    start: &'a BitSlice<u8, Msb0>,
+    end: &'a BitSlice<u8, Msb0>,
+    /// Proof nodes by their hash.
+    nodes: HashMap<Felt, &'a TrieNode>,
+    _hasher: std::marker::PhantomData<H>,
+}
+
+/// How the keys of a subtree relate to the range being verified.
+enum Coverage {
+    /// None of the subtree's keys lie in the range.
+    Outside,
+    /// All of the subtree's keys lie in the range.
+    Inside,
+    /// The range starts or ends within the subtree.
+    Partial,
+}
+
+impl<H: FeltHash> Verifier<'_, H> {
+    fn coverage(&self, prefix: &BitSlice<u8, Msb0>) -> Coverage {
+        let height = prefix.len();
+        let start = &self.start[..height];
+        let end = &self.end[..height];
+
+        if prefix < start || prefix > end {
+            return Coverage::Outside;
+        }
+
+        let after_start = prefix > start || self.start[height..].not_any();
+        let before_end = prefix < end || self.end[height..].all();
+        if after_start && before_end {
+            Coverage::Inside
+        } else {
+            Coverage::Partial
+        }
+    }
+
+    /// Verifies the subtree with the given `hash` at `prefix`, given all of the leaves of the
+    /// range which lie within the subtree.
+    fn verify(
+        &self,
+        hash: Felt,
+        prefix: &BitSlice<u8, Msb0>,
+        leaves: &[(BitVec<u8, Msb0>, Felt)],
+    ) -> anyhow::Result<()> {
+        match self.coverage(prefix) {
+            Coverage::Outside => Ok(()),
+            Coverage::Inside => {
+                anyhow::ensure!(!leaves.is_empty(), "Leaves are missing from the range");
+                anyhow::ensure!(
+                    subtree_hash::<H>(leaves, prefix.len()) == hash,
+                    "Leaves do not match the proof"
+                );
+                Ok(())
+            }
+            Coverage::Partial => {
+                let node = self.nodes.get(&hash).with_context(|| {
+                    format!("Proof is missing a node at height {}", prefix.len())
+                })?;
+
+                match node {
+                    TrieNode::Binary { left, right } => {
+                        let split = leaves.partition_point(|(key, _)| !key[prefix.len()]);
+                        let (left_leaves, right_leaves) = leaves.split_at(split);
+
+                        let mut child = prefix.to_bitvec();
+                        child.push(Direction::Left.into());
+                        self.verify(*left, &child, left_leaves)?;
+
+                        child.pop();
+                        child.push(Direction::Right.into());
+                        self.verify(*right, &child, right_leaves)
+                    }
+                    TrieNode::Edge { child, path } => {
+                        let mut child_prefix = prefix.to_bitvec();
+                        child_prefix.extend_from_bitslice(path);
+                        anyhow::ensure!(
+                            child_prefix.len() <= self.start.len(),
+                            "Edge path exceeds the tree height"
+                        );
+                        anyhow::ensure!(
+                            leaves.iter().all(|(key, _)| key.starts_with(&child_prefix)),
+                            "Leaves are not part of the tree"
+                        );
+
+                        self.verify(*child, &child_prefix, leaves)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Computes the hash of the subtree at `height` which contains exactly the given `leaves`.
+fn subtree_hash<H: FeltHash>(leaves: &[(BitVec<u8, Msb0>, Felt)], height: usize) -> Felt {
+    let (first, value) = &leaves[0];
+    let last = &leaves[leaves.len() - 1].0;
+
+    if first.len() == height {
+        // The subtree is a single leaf.
+        return *value;
+    }
+
+    let common = first[height..]
+        .iter()
+        .zip(last[height..].iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    if common > 0 {
+        let child = subtree_hash::<H>(leaves, height + common);
+        TrieNode::Edge {
+            child,
+            path: first[height..height + common].to_bitvec(),
+        }
+        .hash::<H>()
+    } else {
+        let split = leaves.partition_point(|(key, _)| !key[height]);
+        let left = subtree_hash::<H>(&leaves[..split], height + 1);
+        let right = subtree_hash::<H>(&leaves[split..], height + 1);
+        TrieNode::Binary { left, right }.hash::<H>()
+    }
+}
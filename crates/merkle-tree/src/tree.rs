@@ -76,6 +76,14 @@ pub struct TrieUpdate {
     pub nodes: HashMap<Felt, Node>,
 }
 
+/// Consecutive leaves of a tree, in key order, together with the proof that there are no other
+/// leaves in their range. See [MerkleTree::get_range].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LeafRange {
+    pub leaves: Vec<(BitVec<u8, Msb0>, Felt)>,
+    pub proof: Vec<TrieNode>,
+}
+
 impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
     pub fn new(root: u64) -> Self {
         let root = Some(Rc::new(RefCell::new(InternalNode::Unresolved(root))));
@@ -560,6 +568,132 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
         Ok(nodes)
     }
 
+    /// Returns up to `limit` leaves whose keys lie within `start..=end`, in key order.
+    pub fn get_leaves(
+        root: u64,
+        storage: &impl Storage,
+        start: &BitSlice<u8, Msb0>,
+        end: &BitSlice<u8, Msb0>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(BitVec<u8, Msb0>, Felt)>> {
+        enum Pending {
+            Node(u64),
+            Leaf,
+        }
+
+        anyhow::ensure!(
+            start.len() == HEIGHT && end.len() == HEIGHT,
+            "Range bounds must be {HEIGHT} bits long"
+        );
+
+        // Whether any key with the given prefix lies within the range.
+        let overlaps = |prefix: &BitSlice<u8, Msb0>| {
+            prefix >= &start[..prefix.len()] && prefix <= &end[..prefix.len()]
+        };
+
+        let mut leaves = Vec::new();
+        // Right children are pushed first, so that the leaves are visited in key order.
+        let mut stack = vec![(Pending::Node(root), BitVec::<u8, Msb0>::new())];
+        while let Some((pending, path)) = stack.pop() {
+            if leaves.len() >= limit {
+                break;
+            }
+            if !overlaps(&path) {
+                continue;
+            }
+
+            let index = match pending {
+                Pending::Node(index) => index,
+                Pending::Leaf => {
+                    let value = storage
+                        .leaf(&path)
+                        .context("Querying leaf")?
+                        .context("Leaf is missing")?;
+                    leaves.push((path, value));
+                    continue;
+                }
+            };
+
+            let node = storage
+                .get(index)
+                .context("Resolving node")?
+                .context("Node is missing from storage")?;
+
+            let child_path = |direction: Direction| {
+                let mut child = path.clone();
+                child.push(direction.into());
+                child
+            };
+            let edge_path = |edge: &BitSlice<u8, Msb0>| {
+                let mut child = path.clone();
+                child.extend_from_bitslice(edge);
+                child
+            };
+
+            match node {
+                StoredNode::Binary { left, right } => {
+                    stack.push((Pending::Node(right), child_path(Direction::Right)));
+                    stack.push((Pending::Node(left), child_path(Direction::Left)));
+                }
+                StoredNode::Edge { child, path: edge } => {
+                    stack.push((Pending::Node(child), edge_path(&edge)));
+                }
+                StoredNode::LeafBinary => {
+                    stack.push((Pending::Leaf, child_path(Direction::Right)));
+                    stack.push((Pending::Leaf, child_path(Direction::Left)));
+                }
+                StoredNode::LeafEdge { path: edge } => {
+                    stack.push((Pending::Leaf, edge_path(&edge)));
+                }
+            }
+        }
+
+        Ok(leaves)
+    }
+
+    /// Generates a proof for the range of leaves with keys in `start..=end`.
+    ///
+    /// The proof consists of the [proofs](Self::get_proof) for both ends of the range, which is
+    /// enough to prove that a set of leaves are all of the leaves in the range. See
+    /// [verify_range](crate::range::verify_range).
+    pub fn get_range_proof(
+        root: u64,
+        storage: &impl Storage,
+        start: &BitSlice<u8, Msb0>,
+        end: &BitSlice<u8, Msb0>,
+    ) -> anyhow::Result<Vec<TrieNode>> {
+        let mut proof = Self::get_proof(root, storage, start).context("Proving range start")?;
+        for node in Self::get_proof(root, storage, end).context("Proving range end")? {
+            if !proof.contains(&node) {
+                proof.push(node);
+            }
+        }
+
+        Ok(proof)
+    }
+
+    /// Returns up to `limit` leaves with keys in `start..=end` together with their range proof.
+    ///
+    /// If any leaves are returned the proof covers the range up to the last leaf, so that the
+    /// next range can be requested from there. Otherwise the proof covers the whole range.
+    pub fn get_range(
+        root: u64,
+        storage: &impl Storage,
+        start: &BitSlice<u8, Msb0>,
+        end: &BitSlice<u8, Msb0>,
+        limit: usize,
+    ) -> anyhow::Result<LeafRange> {
+        let leaves =
+            Self::get_leaves(root, storage, start, end, limit).context("Collecting leaves")?;
+        let last = leaves
+            .last()
+            .map(|(key, _)| key.as_bitslice())
+            .unwrap_or(end);
+        let proof = Self::get_range_proof(root, storage, start, last)?;
+
+        Ok(LeafRange { leaves, proof })
+    }
+
     /// Traverses from the current root towards destination node.
     /// Returns the list of nodes along the path.
     ///
@@ -2158,4 +2292,173 @@ mod tests {
             assert!(verified.is_none());
         }
     }
+
+    mod ranges {
+        use super::*;
+        use crate::range::verify_range;
+
+        /// All keys of the tree.
+        const FULL: (Felt, Felt) = (
+            Felt::ZERO,
+            felt!("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+        );
+
+        /// Creates a tree with `len` random leaves, and returns its root and leaves in key order.
+        fn random_tree(
+            len: usize,
+            storage: &mut TestStorage,
+        ) -> (Felt, u64, Vec<(BitVec<u8, Msb0>, Felt)>) {
+            let mut rng = rand::rngs::ThreadRng::default();
+            let mut leaves = std::collections::BTreeMap::new();
+            while leaves.len() < len {
+                let key = Felt::random(&mut rng);
+                if !key.has_more_than_251_bits() {
+                    leaves.insert(key, Felt::random(&mut rng));
+                }
+            }
+
+            let mut uut = TestTree::empty();
+            for (key, value) in &leaves {
+                uut.set(&*storage, key.view_bits().to_owned(), *value)
+                    .unwrap();
+            }
+            let (root, root_idx) = commit_and_persist(uut, storage);
+
+            let leaves = leaves
+                .into_iter()
+                .map(|(key, value)| (key.view_bits().to_owned(), value))
+                .collect();
+
+            (root, root_idx, leaves)
+        }
+
+        #[test]
+        fn full_range() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, leaves) = random_tree(100, &mut storage);
+            let (start, end) = (FULL.0.view_bits(), FULL.1.view_bits());
+
+            let range = TestTree::get_range(root_idx, &storage, start, end, usize::MAX).unwrap();
+            assert_eq!(range.leaves, leaves);
+
+            verify_range::<PedersenHash, 251>(root, start, end, &range.leaves, &range.proof)
+                .unwrap();
+        }
+
+        #[test]
+        fn chunks() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, leaves) = random_tree(100, &mut storage);
+
+            let mut start = FULL.0;
+            let end = FULL.1;
+            let mut synced = Vec::new();
+            loop {
+                let range =
+                    TestTree::get_range(root_idx, &storage, start.view_bits(), end.view_bits(), 7)
+                        .unwrap();
+                assert!(range.leaves.len() <= 7);
+
+                let Some((last, _)) = range.leaves.last() else {
+                    verify_range::<PedersenHash, 251>(
+                        root,
+                        start.view_bits(),
+                        end.view_bits(),
+                        &range.leaves,
+                        &range.proof,
+                    )
+                    .unwrap();
+                    break;
+                };
+
+                verify_range::<PedersenHash, 251>(
+                    root,
+                    start.view_bits(),
+                    last,
+                    &range.leaves,
+                    &range.proof,
+                )
+                .unwrap();
+
+                start = Felt::from_bits(last).unwrap() + Felt::from_u64(1);
+                synced.extend(range.leaves);
+            }
+
+            assert_eq!(synced, leaves);
+        }
+
+        #[test]
+        fn partial_range() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, leaves) = random_tree(100, &mut storage);
+
+            // Bounds which are not keys of the tree.
+            let start = Felt::from_bits(&leaves[10].0).unwrap() + Felt::from_u64(1);
+            let end = Felt::from_bits(&leaves[50].0).unwrap() - Felt::from_u64(1);
+            let (start, end) = (start.view_bits(), end.view_bits());
+
+            let range = TestTree::get_range(root_idx, &storage, start, end, usize::MAX).unwrap();
+            assert_eq!(range.leaves, leaves[11..50]);
+
+            verify_range::<PedersenHash, 251>(root, start, end, &range.leaves, &range.proof)
+                .unwrap();
+        }
+
+        #[test]
+        fn empty_range() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, leaves) = random_tree(100, &mut storage);
+
+            let key = Felt::from_bits(&leaves[10].0).unwrap() + Felt::from_u64(1);
+            let key = key.view_bits();
+
+            let range = TestTree::get_range(root_idx, &storage, key, key, usize::MAX).unwrap();
+            assert!(range.leaves.is_empty());
+
+            verify_range::<PedersenHash, 251>(root, key, key, &range.leaves, &range.proof).unwrap();
+        }
+
+        #[test]
+        fn missing_leaf() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, _) = random_tree(100, &mut storage);
+            let (start, end) = (FULL.0.view_bits(), FULL.1.view_bits());
+
+            let mut range =
+                TestTree::get_range(root_idx, &storage, start, end, usize::MAX).unwrap();
+            range.leaves.remove(42);
+
+            verify_range::<PedersenHash, 251>(root, start, end, &range.leaves, &range.proof)
+                .unwrap_err();
+        }
+
+        #[test]
+        fn modified_leaf() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, _) = random_tree(100, &mut storage);
+            let (start, end) = (FULL.0.view_bits(), FULL.1.view_bits());
+
+            let mut range =
+                TestTree::get_range(root_idx, &storage, start, end, usize::MAX).unwrap();
+            range.leaves[42].1 = range.leaves[42].1 + Felt::from_u64(1);
+
+            verify_range::<PedersenHash, 251>(root, start, end, &range.leaves, &range.proof)
+                .unwrap_err();
+        }
+
+        #[test]
+        fn missing_proof_node() {
+            let mut storage = TestStorage::default();
+            let (root, root_idx, leaves) = random_tree(100, &mut storage);
+            let start = leaves[10].0.as_bitslice();
+            let end = leaves[50].0.as_bitslice();
+
+            let mut range =
+                TestTree::get_range(root_idx, &storage, start, end, usize::MAX).unwrap();
+            range.proof.pop();
+
+            verify_range::<PedersenHash, 251>(root, start, end, &range.leaves, &range.proof)
+                .unwrap_err();
+        }
+    }
 }
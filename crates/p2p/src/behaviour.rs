@@ -1,12 +1,15 @@
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::net::IpAddr;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::peers::{Connectivity, Direction, KeyedNetworkGroup, Peer};
+use crate::peer_store::{self, PeerStore};
+use crate::peers::{Connectivity, Direction, KeyedNetworkGroup, Peer, PeerInfo};
+use crate::reputation::{Misbehaviour, PeerScore, Reputation};
 use crate::secret::Secret;
 use crate::sync::codec;
+use crate::sync::protocol::SyncProtocol;
 use crate::{peers::PeerSet, Config};
 use libp2p::core::Endpoint;
 use libp2p::dcutr;
@@ -28,6 +31,10 @@ use p2p_proto::class::{ClassesRequest, ClassesResponse};
 use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse};
 use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRangeRequest, ContractRangeResponse, ContractStorageRequest, ContractStorageResponse,
+};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
 use pathfinder_common::ChainId;
@@ -42,6 +49,11 @@ pub fn kademlia_protocol_name(chain_id: ChainId) -> String {
 pub struct Behaviour {
     cfg: Config,
     peers: PeerSet,
+    /// Peers we have seen before, including those from previous runs.
+    peer_store: PeerStore,
+    reputation: Reputation,
+    /// The save of the known peers which is in flight, if any, see [Self::save_peers].
+    saving_peers: Option<tokio::task::JoinHandle<()>>,
     swarm: crate::Client,
     secret: Secret,
     inner: Inner,
@@ -63,6 +75,10 @@ pub struct Inner {
     transactions_sync: p2p_stream::Behaviour<codec::Transactions>,
     receipts_sync: p2p_stream::Behaviour<codec::Receipts>,
     events_sync: p2p_stream::Behaviour<codec::Events>,
+    contract_range_sync: p2p_stream::Behaviour<codec::ContractRange>,
+    contract_storage_sync: p2p_stream::Behaviour<codec::ContractStorage>,
+    class_range_sync: p2p_stream::Behaviour<codec::ClassRange>,
+    classes_by_hash_sync: p2p_stream::Behaviour<codec::ClassesByHash>,
 }
 
 impl NetworkBehaviour for Behaviour {
@@ -81,6 +97,7 @@ impl NetworkBehaviour for Behaviour {
 
         self.check_duplicate_connection(peer)?;
         self.prevent_evicted_peer_reconnections(peer)?;
+        self.prevent_banned_peer_connections(peer)?;
 
         // Is the peer connecting over a relay?
         let is_relayed = remote_addr.iter().any(|p| p == Protocol::P2pCircuit);
@@ -123,6 +140,7 @@ impl NetworkBehaviour for Behaviour {
 
         self.check_duplicate_connection(peer)?;
         self.prevent_evicted_peer_reconnections(peer)?;
+        self.prevent_banned_peer_connections(peer)?;
 
         self.inner
             .handle_established_outbound_connection(connection_id, peer, addr, role_override)
@@ -170,6 +188,15 @@ impl NetworkBehaviour for Behaviour {
                     return;
                 };
 
+                self.peer_store.seen(peer_id, SystemTime::now());
+                if endpoint.is_dialer() {
+                    self.peer_store.add_addresses(
+                        peer_id,
+                        [endpoint.get_remote_address().clone()],
+                        SystemTime::now(),
+                    );
+                }
+
                 self.peers.upsert(
                     peer_id,
                     |peer| {
@@ -231,6 +258,7 @@ impl NetworkBehaviour for Behaviour {
                 ..
             }) => {
                 if remaining_established == 0 {
+                    self.peer_store.seen(peer_id, SystemTime::now());
                     self.peers.update(peer_id, |peer| {
                         peer.connectivity = Connectivity::Disconnected {
                             connected_at: peer.connected_at(),
@@ -341,9 +369,11 @@ impl NetworkBehaviour for Behaviour {
             _ => None,
         });
 
-        // If we can extract the peer ID, prevent evicted peers from reconnecting too quickly.
+        // If we can extract the peer ID, prevent evicted peers from reconnecting too quickly
+        // and banned peers from reconnecting at all.
         if let Some(peer_id) = peer_id {
             self.prevent_evicted_peer_reconnections(peer_id)?;
+            self.prevent_banned_peer_connections(peer_id)?;
         }
 
         drop(recent_peers);
@@ -390,6 +420,7 @@ impl NetworkBehaviour for Behaviour {
                 // hole-punching.
 
                 self.prevent_evicted_peer_reconnections(peer_id)?;
+                self.prevent_banned_peer_connections(peer_id)?;
 
                 if self.outbound_peers().count() >= self.cfg.max_outbound_peers {
                     self.evict_outbound_peer()?;
@@ -468,18 +499,45 @@ impl Behaviour {
         )
         .expect("valid gossipsub params");
 
-        let headers_sync = request_response_behavior::<codec::Headers>();
-        let classes_sync = request_response_behavior::<codec::Classes>();
-        let state_diffs_sync = request_response_behavior::<codec::StateDiffs>();
-        let transactions_sync = request_response_behavior::<codec::Transactions>();
-        let receipts_sync = request_response_behavior::<codec::Receipts>();
-        let events_sync = request_response_behavior::<codec::Events>();
+        let headers_sync =
+            request_response_behavior(codec::Headers::new(cfg.response_meter.clone()), &cfg);
+        let classes_sync =
+            request_response_behavior(codec::Classes::new(cfg.response_meter.clone()), &cfg);
+        let state_diffs_sync =
+            request_response_behavior(codec::StateDiffs::new(cfg.response_meter.clone()), &cfg);
+        let transactions_sync =
+            request_response_behavior(codec::Transactions::new(cfg.response_meter.clone()), &cfg);
+        let receipts_sync =
+            request_response_behavior(codec::Receipts::new(cfg.response_meter.clone()), &cfg);
+        let events_sync =
+            request_response_behavior(codec::Events::new(cfg.response_meter.clone()), &cfg);
+        let contract_range_sync =
+            request_response_behavior(codec::ContractRange::new(cfg.response_meter.clone()), &cfg);
+        let contract_storage_sync = request_response_behavior(
+            codec::ContractStorage::new(cfg.response_meter.clone()),
+            &cfg,
+        );
+        let class_range_sync =
+            request_response_behavior(codec::ClassRange::new(cfg.response_meter.clone()), &cfg);
+        let classes_by_hash_sync =
+            request_response_behavior(codec::ClassesByHash::new(cfg.response_meter.clone()), &cfg);
 
         let (relay_transport, relay) = relay::client::new(peer_id);
 
+        let (peer_store, reputation) = match &cfg.peers_file {
+            Some(path) => peer_store::load(path).unwrap_or_else(|error| {
+                tracing::warn!(?path, %error, "Failed to load known peers, starting afresh");
+                Default::default()
+            }),
+            None => Default::default(),
+        };
+
         (
             Self {
                 peers: PeerSet::new(cfg.eviction_timeout),
+                peer_store,
+                reputation,
+                saving_peers: None,
                 cfg,
                 swarm,
                 secret: Secret::new(identity),
@@ -503,6 +561,10 @@ impl Behaviour {
                     transactions_sync,
                     receipts_sync,
                     events_sync,
+                    contract_range_sync,
+                    contract_storage_sync,
+                    class_range_sync,
+                    classes_by_hash_sync,
                 },
             },
             relay_transport,
@@ -758,12 +820,24 @@ impl Behaviour {
         }
     }
 
+    /// Prevent banned peers from connecting until their ban expires.
+    fn prevent_banned_peer_connections(&self, peer_id: PeerId) -> Result<(), ConnectionDenied> {
+        if self.reputation.is_banned(peer_id, SystemTime::now()) {
+            tracing::debug!(%peer_id, "Banned peer attempting to connect, disconnecting");
+            return Err(ConnectionDenied::new("peer is banned"));
+        }
+        Ok(())
+    }
+
     /// Get the IP address from a multiaddr, or disconnect the peer if it doesn't have one.
     fn get_ip(addr: &Multiaddr) -> Result<IpAddr, ConnectionDenied> {
         addr.iter()
             .find_map(|p| match p {
                 Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
                 Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+                // In-process connections are local.
+                #[cfg(feature = "memory-transport")]
+                Protocol::Memory(_) => Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)),
                 _ => None,
             })
             .ok_or_else(|| {
@@ -778,6 +852,184 @@ impl Behaviour {
         });
     }
 
+    /// Lower the score of a misbehaving peer, disconnecting it if it gets banned as a result.
+    pub fn penalize(&mut self, peer_id: PeerId, misbehaviour: Misbehaviour) {
+        metrics::increment_counter!("p2p_peer_penalties_total", "reason" => misbehaviour.as_str());
+
+        if !self
+            .reputation
+            .penalize(peer_id, misbehaviour, SystemTime::now())
+        {
+            tracing::debug!(%peer_id, ?misbehaviour, "Peer penalized");
+            return;
+        }
+
+        tracing::info!(%peer_id, ?misbehaviour, "Peer banned");
+        metrics::increment_counter!("p2p_peer_bans_total");
+
+        if self
+            .peers
+            .get(peer_id)
+            .map_or(false, |peer| peer.is_connected())
+        {
+            self.peers.update(peer_id, |peer| {
+                peer.connectivity = Connectivity::Disconnecting {
+                    connected_at: peer.connected_at(),
+                };
+            });
+            tokio::spawn({
+                let swarm = self.swarm.clone();
+                async move {
+                    if let Err(e) = swarm.disconnect(peer_id).await {
+                        tracing::debug!(%peer_id, %e, "Failed to disconnect banned peer");
+                    }
+                }
+            });
+        }
+    }
+
+    pub fn peer_scores(&mut self) -> HashMap<PeerId, PeerScore> {
+        self.reputation.scores(SystemTime::now())
+    }
+
+    /// Persist the known peers and their scores in the background, if a file is configured.
+    ///
+    /// Saves never overlap, so this is skipped while the previous save is still in flight.
+    pub fn save_peers(&mut self) {
+        if self
+            .saving_peers
+            .as_ref()
+            .map_or(false, |saving| !saving.is_finished())
+        {
+            tracing::debug!("Previous save of known peers still in progress, skipping");
+            return;
+        }
+
+        let Some(path) = self.cfg.peers_file.clone() else {
+            return;
+        };
+        self.peer_store.prune(SystemTime::now());
+        let peer_store = self.peer_store.clone();
+        let reputation = self.reputation.clone();
+        self.saving_peers = Some(tokio::task::spawn_blocking(move || {
+            if let Err(error) = peer_store::save(&path, &peer_store, &reputation) {
+                tracing::warn!(?path, %error, "Failed to persist known peers");
+            }
+        }));
+    }
+
+    /// Persists the known peers and their scores one last time, after waiting for a save in
+    /// flight to finish.
+    pub async fn flush_peers(&mut self) {
+        if let Some(saving) = self.saving_peers.take() {
+            _ = saving.await;
+        }
+        self.save_peers();
+        if let Some(saving) = self.saving_peers.take() {
+            _ = saving.await;
+        }
+    }
+
+    /// Records the addresses a peer reported to be listening on, leaving out the ones which
+    /// could not be dialed from elsewhere, see [peer_store::is_routable].
+    pub fn add_peer_addresses(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        let addresses = addresses
+            .into_iter()
+            .filter(|address| peer_store::is_routable(address, &self.cfg.ip_whitelist));
+        self.peer_store
+            .add_addresses(peer_id, addresses, SystemTime::now());
+    }
+
+    /// Records peers found to provide a capability.
+    pub fn add_capability_providers(&mut self, capability: &str, providers: &HashSet<PeerId>) {
+        let now = SystemTime::now();
+        for peer_id in providers {
+            self.peer_store.add_capability(*peer_id, capability, now);
+        }
+    }
+
+    /// Connected peers which are known to provide a capability.
+    pub fn connected_capability_providers(&self, capability: &str) -> HashSet<PeerId> {
+        let mut providers = self.peer_store.providers(capability, SystemTime::now());
+        providers.retain(|peer_id| {
+            self.peers
+                .get(*peer_id)
+                .map_or(false, |peer| peer.is_connected())
+        });
+        providers
+    }
+
+    /// Known peers to dial on startup, along with their addresses.
+    pub fn reconnect_candidates(&mut self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let scores = self.peer_scores();
+        self.peer_store
+            .reconnect_candidates(&scores, self.cfg.max_outbound_peers)
+    }
+
+    /// Update the peer score metrics.
+    pub fn report_peer_scores(&mut self) {
+        let scores = self.peer_scores();
+
+        let banned = scores.values().filter(|score| score.banned).count();
+        metrics::gauge!("p2p_banned_peers", banned as f64);
+
+        // Scores are aggregated, as a label per peer would grow without bound. Peers which
+        // have recovered are no longer part of the scores.
+        metrics::gauge!("p2p_penalized_peers", scores.len() as f64);
+        let lowest = scores
+            .values()
+            .map(|score| score.score)
+            .min()
+            .unwrap_or_default();
+        metrics::gauge!("p2p_lowest_peer_score", lowest as f64);
+    }
+
+    /// Update the connected peer metrics.
+    pub fn report_peers(&mut self) {
+        let mut counts = HashMap::<_, usize>::new();
+        for (_, peer) in self.peers.iter().filter(|(_, peer)| peer.is_connected()) {
+            *counts
+                .entry((peer.is_outbound(), peer.is_relayed()))
+                .or_default() += 1;
+        }
+
+        for outbound in [false, true] {
+            for relayed in [false, true] {
+                let count = counts
+                    .get(&(outbound, relayed))
+                    .copied()
+                    .unwrap_or_default();
+                let direction = if outbound { "outbound" } else { "inbound" };
+                let relayed = if relayed { "true" } else { "false" };
+                metrics::gauge!("p2p_connected_peers", count as f64, "direction" => direction, "relayed" => relayed);
+            }
+        }
+
+        let dht_peers = self
+            .kademlia_mut()
+            .kbuckets()
+            .map(|bucket| bucket.num_entries())
+            .sum::<usize>();
+        metrics::gauge!("p2p_dht_peers", dht_peers as f64);
+    }
+
+    /// The known peers along with their scores, including recently disconnected ones.
+    pub fn peer_infos(&mut self) -> Vec<PeerInfo> {
+        let scores = self.peer_scores();
+        self.peers
+            .iter()
+            .map(|(peer_id, peer)| PeerInfo {
+                peer_id,
+                addr: peer.addr.clone(),
+                connected: peer.is_connected(),
+                outbound: peer.is_outbound(),
+                relayed: peer.is_relayed(),
+                min_ping: peer.min_ping,
+                score: scores.get(&peer_id).copied().unwrap_or_default(),
+            })
+            .collect()
+    }
+
     pub fn kademlia_mut(&mut self) -> &mut kad::Behaviour<MemoryStore> {
         &mut self.inner.kademlia
     }
@@ -810,6 +1062,24 @@ impl Behaviour {
         &mut self.inner.events_sync
     }
 
+    pub fn contract_range_sync_mut(&mut self) -> &mut p2p_stream::Behaviour<codec::ContractRange> {
+        &mut self.inner.contract_range_sync
+    }
+
+    pub fn contract_storage_sync_mut(
+        &mut self,
+    ) -> &mut p2p_stream::Behaviour<codec::ContractStorage> {
+        &mut self.inner.contract_storage_sync
+    }
+
+    pub fn class_range_sync_mut(&mut self) -> &mut p2p_stream::Behaviour<codec::ClassRange> {
+        &mut self.inner.class_range_sync
+    }
+
+    pub fn classes_by_hash_sync_mut(&mut self) -> &mut p2p_stream::Behaviour<codec::ClassesByHash> {
+        &mut self.inner.classes_by_hash_sync
+    }
+
     pub fn peers(&self) -> impl Iterator<Item = (PeerId, &Peer)> {
         self.peers.iter()
     }
@@ -836,12 +1106,18 @@ impl Behaviour {
     }
 }
 
-fn request_response_behavior<C>() -> p2p_stream::Behaviour<C>
+/// Serves and requests every supported version of the protocol, preferring the newest one.
+fn request_response_behavior<C>(codec: C, cfg: &Config) -> p2p_stream::Behaviour<C>
 where
-    C: Default + p2p_stream::Codec + Clone + Send,
-    C::Protocol: Default,
+    C: p2p_stream::Codec + Clone + Send,
+    C::Protocol: SyncProtocol,
 {
-    p2p_stream::Behaviour::new(std::iter::once(C::Protocol::default()), Default::default())
+    p2p_stream::Behaviour::with_codec(
+        codec,
+        C::Protocol::VERSIONS.iter().copied(),
+        p2p_stream::Config::default()
+            .with_max_concurrent_inbound_streams(cfg.max_concurrent_inbound_streams),
+    )
 }
 
 #[derive(Debug)]
@@ -859,6 +1135,10 @@ pub enum Event {
     TransactionsSync(p2p_stream::Event<TransactionsRequest, TransactionsResponse>),
     ReceiptsSync(p2p_stream::Event<ReceiptsRequest, ReceiptsResponse>),
     EventsSync(p2p_stream::Event<EventsRequest, EventsResponse>),
+    ContractRangeSync(p2p_stream::Event<ContractRangeRequest, ContractRangeResponse>),
+    ContractStorageSync(p2p_stream::Event<ContractStorageRequest, ContractStorageResponse>),
+    ClassRangeSync(p2p_stream::Event<ClassRangeRequest, ClassRangeResponse>),
+    ClassesByHashSync(p2p_stream::Event<ClassesByHashRequest, ClassesByHashResponse>),
 }
 
 impl From<relay::client::Event> for Event {
@@ -939,6 +1219,30 @@ impl From<p2p_stream::Event<EventsRequest, EventsResponse>> for Event {
     }
 }
 
+impl From<p2p_stream::Event<ContractRangeRequest, ContractRangeResponse>> for Event {
+    fn from(event: p2p_stream::Event<ContractRangeRequest, ContractRangeResponse>) -> Self {
+        Event::ContractRangeSync(event)
+    }
+}
+
+impl From<p2p_stream::Event<ContractStorageRequest, ContractStorageResponse>> for Event {
+    fn from(event: p2p_stream::Event<ContractStorageRequest, ContractStorageResponse>) -> Self {
+        Event::ContractStorageSync(event)
+    }
+}
+
+impl From<p2p_stream::Event<ClassRangeRequest, ClassRangeResponse>> for Event {
+    fn from(event: p2p_stream::Event<ClassRangeRequest, ClassRangeResponse>) -> Self {
+        Event::ClassRangeSync(event)
+    }
+}
+
+impl From<p2p_stream::Event<ClassesByHashRequest, ClassesByHashResponse>> for Event {
+    fn from(event: p2p_stream::Event<ClassesByHashRequest, ClassesByHashResponse>) -> Self {
+        Event::ClassesByHashSync(event)
+    }
+}
+
 fn string_to_key(input: &str) -> kad::RecordKey {
     use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
@@ -2,27 +2,42 @@
 //! Frees the caller from managing peers manually.
 use std::{
     collections::{HashMap, HashSet},
-    sync::Arc,
-    time::Duration,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use anyhow::Context;
 use futures::StreamExt;
 use libp2p::PeerId;
-use p2p_proto::common::{Direction, Iteration};
+use p2p_proto::class::{Class, ClassesRequest, ClassesResponse};
+use p2p_proto::common::{Address, Direction, Hash, Iteration};
+use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse};
+use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRange, ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRange, ContractRangeRequest, ContractRangeResponse, ContractStorage,
+    ContractStorageRequest, ContractStorageResponse,
+};
+use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
+use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
+use pathfinder_common::event::Event;
+use pathfinder_common::transaction::Transaction;
 use pathfinder_common::{
-    transaction::{DeployAccountTransactionV0V1, DeployAccountTransactionV3, TransactionVariant},
-    BlockNumber,
+    BlockNumber, ClassHash, ContractAddress, StateUpdate, StorageAddress, TransactionHash,
 };
-use pathfinder_common::{BlockHash, ContractAddress};
-
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use tokio::sync::RwLock;
 
 use crate::client::peer_aware;
-use crate::client::types::{RawDeployAccountTransaction, SignedBlockHeader};
-use crate::sync::protocol;
+use crate::client::types::{RawTransactionVariant, Receipt, SignedBlockHeader, TryFromDto};
+use crate::sync::protocol::{self, SyncProtocol};
+use crate::Misbehaviour;
+
+/// The number of peers which must agree on the receipts of a block, see [Client::block_receipts].
+const RECEIPT_QUORUM: usize = 2;
+
+/// How long peers which rate limited our requests are only tried after all other peers.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(10);
 
 /// Data received from a specific peer.
 #[derive(Debug)]
@@ -42,6 +57,8 @@ pub struct Client {
     inner: peer_aware::Client,
     block_propagation_topic: String,
     peers_with_capability: Arc<RwLock<PeersWithCapability>>,
+    /// Peers which rate limited our requests, along with when we can try them again.
+    backoff: Arc<Mutex<HashMap<PeerId, Instant>>>,
 }
 
 // TODO Rework the API!
@@ -53,9 +70,15 @@ impl Client {
             inner,
             block_propagation_topic,
             peers_with_capability: Default::default(),
+            backoff: Default::default(),
         }
     }
 
+    /// The underlying client, for managing peers directly.
+    pub fn peer_aware(&self) -> &peer_aware::Client {
+        &self.inner
+    }
+
     // Propagate new L2 head head
     pub async fn propagate_new_head(
         &self,
@@ -73,35 +96,90 @@ impl Client {
             .await
     }
 
-    async fn get_update_peers_with_sync_capability(&self, capability: &str) -> Vec<PeerId> {
-        use rand::seq::SliceRandom;
+    /// Lower the score of a peer which served invalid data.
+    pub async fn penalize(&self, peer: PeerId, misbehaviour: Misbehaviour) {
+        self.inner.penalize(peer, misbehaviour).await
+    }
 
-        let r = self.peers_with_capability.read().await;
-        let mut peers = if let Some(peers) = r.get(capability) {
-            peers.iter().copied().collect::<Vec<_>>()
-        } else {
-            // Avoid deadlock
-            drop(r);
+    /// Backs off from a peer which rate limited our request, see [RATE_LIMIT_BACKOFF].
+    fn back_off(&self, peer: PeerId) {
+        self.backoff
+            .lock()
+            .unwrap()
+            .insert(peer, Instant::now() + RATE_LIMIT_BACKOFF);
+    }
 
-            let mut peers = self
-                .inner
-                .get_capability_providers(capability)
-                .await
-                .unwrap_or_default();
+    /// Handles a failed request to `peer`. Peers which rate limited the request are backed off
+    /// from, and misbehaving peers are penalized.
+    async fn request_failed(&self, peer: PeerId, error: &anyhow::Error) {
+        if error.downcast_ref::<RateLimitedResponse>().is_some() {
+            self.back_off(peer);
+        } else if let Some(misbehaviour) = misbehaviour(error) {
+            self.penalize(peer, misbehaviour).await;
+        }
+    }
 
-            let _i_should_have_the_capability_too = peers.remove(self.inner.peer_id());
-            debug_assert!(_i_should_have_the_capability_too);
+    /// Returns the peers with any of the capabilities, best scoring peers first. Peers with
+    /// equal scores are shuffled, and banned peers are left out. Peers which recently rate
+    /// limited us come last.
+    ///
+    /// The capabilities are the versioned names of a protocol, so peers running a different
+    /// release are included as long as they support one of its versions.
+    async fn get_update_peers_with_sync_capability(&self, capabilities: &[&str]) -> Vec<PeerId> {
+        use rand::seq::SliceRandom;
+
+        let mut peers = HashSet::new();
+        for capability in capabilities {
+            peers.extend(self.get_update_capability_providers(capability).await);
+        }
+        let mut peers = peers.into_iter().collect::<Vec<_>>();
+        peers.shuffle(&mut rand::thread_rng());
 
-            let peers_vec = peers.iter().copied().collect::<Vec<_>>();
+        let scores = self.inner.peer_scores().await;
+        peers.retain(|peer| !scores.get(peer).map_or(false, |score| score.banned));
 
-            let mut w = self.peers_with_capability.write().await;
-            w.update(capability, peers);
-            peers_vec
+        let backing_off = {
+            let mut backoff = self.backoff.lock().unwrap();
+            let now = Instant::now();
+            backoff.retain(|_, until| *until > now);
+            backoff.keys().copied().collect::<HashSet<_>>()
         };
-        peers.shuffle(&mut rand::thread_rng());
+
+        // Sorting is stable, so peers with equal scores stay shuffled.
+        peers.sort_by_key(|peer| {
+            (
+                backing_off.contains(peer),
+                std::cmp::Reverse(scores.get(peer).copied().unwrap_or_default().score),
+            )
+        });
+        peers
+    }
+
+    /// Returns the peers providing the capability, from the cache if it is still fresh.
+    async fn get_update_capability_providers(&self, capability: &str) -> HashSet<PeerId> {
+        let r = self.peers_with_capability.read().await;
+        if let Some(peers) = r.get(capability) {
+            return peers.clone();
+        }
+        // Avoid deadlock
+        drop(r);
+
+        let mut peers = self
+            .inner
+            .get_capability_providers(capability)
+            .await
+            .unwrap_or_default();
+
+        let _i_should_have_the_capability_too = peers.remove(self.inner.peer_id());
+        debug_assert!(_i_should_have_the_capability_too);
+
+        let mut w = self.peers_with_capability.write().await;
+        w.update(capability, peers.clone());
         peers
     }
 
+    /// Streams the headers from `start` to `stop`, switching peers whenever one stops serving
+    /// them. Ends early once none of the peers serves any more headers.
     pub fn header_stream(
         self,
         start: BlockNumber,
@@ -117,12 +195,14 @@ impl Client {
             // Loop which refreshes peer set once we exhaust it.
             loop {
                 let peers = self
-                    .get_update_peers_with_sync_capability(protocol::Headers::NAME)
+                    .get_update_peers_with_sync_capability(protocol::Headers::NAMES)
                     .await;
+                let mut progress = false;
 
                 // Attempt each peer.
                 'next_peer: for peer in peers {
-                    let limit = start.get().max(stop.get()) - start.get().min(stop.get());
+                    // Both start and stop are inclusive.
+                    let limit = start.get().max(stop.get()) - start.get().min(stop.get()) + 1;
 
                     let request = BlockHeadersRequest {
                         iteration: Iteration {
@@ -139,6 +219,7 @@ impl Client {
                         Err(error) => {
                             // Failed to establish connection, try next peer.
                             tracing::debug!(%peer, reason=%error, "Headers request failed");
+                            self.penalize(peer, Misbehaviour::Timeout).await;
                             continue 'next_peer;
                         }
                     };
@@ -149,6 +230,7 @@ impl Client {
                                 Ok(hdr) => hdr,
                                 Err(error) => {
                                     tracing::debug!(%peer, %error, "Header stream failed");
+                                    self.penalize(peer, Misbehaviour::MalformedMessage).await;
                                     continue 'next_peer;
                                 },
                             },
@@ -156,6 +238,11 @@ impl Client {
                                 tracing::debug!(%peer, "Header stream Fin");
                                 continue 'next_peer;
                             }
+                            BlockHeadersResponse::RateLimited => {
+                                tracing::debug!(%peer, "Header stream rate limited");
+                                self.back_off(peer);
+                                continue 'next_peer;
+                            }
                         };
 
                         start = match direction {
@@ -165,79 +252,613 @@ impl Client {
                             Direction::Backward => start.parent().unwrap_or_default(),
                         };
 
+                        progress = true;
+                        let done = signed_header.header.number == stop;
                         yield PeerData::new(peer, signed_header);
+                        if done {
+                            return;
+                        }
                     }
 
                     // TODO: track how much and how fast this peer responded with i.e. don't let them drip feed us etc.
                 }
+
+                if !progress {
+                    return;
+                }
             }
         }
     }
-}
 
-// TODO
-/// Does not block the current thread.
-async fn _compute_contract_addresses(
-    deploy_account: HashMap<BlockHash, Vec<super::types::RawDeployAccountTransaction>>,
-) -> anyhow::Result<Vec<(BlockHash, Vec<TransactionVariant>)>> {
-    let jh = tokio::task::spawn_blocking(move || {
-        // Now we can compute the missing addresses
-        let computed: Vec<_> = deploy_account
-            .into_par_iter()
-            .map(|(block_hash, transactions)| {
-                (
-                    block_hash,
-                    transactions
-                        .into_par_iter()
-                        .map(|t| match t {
-                            RawDeployAccountTransaction::DeployAccountV0V1(x) => {
-                                let contract_address = ContractAddress::deployed_contract_address(
-                                    x.constructor_calldata.iter().copied(),
-                                    &x.contract_address_salt,
-                                    &x.class_hash,
-                                );
-                                TransactionVariant::DeployAccountV0V1(
-                                    DeployAccountTransactionV0V1 {
-                                        contract_address,
-                                        max_fee: x.max_fee,
-                                        version: x.version,
-                                        signature: x.signature,
-                                        nonce: x.nonce,
-                                        contract_address_salt: x.contract_address_salt,
-                                        constructor_calldata: x.constructor_calldata,
-                                        class_hash: x.class_hash,
-                                    },
-                                )
-                            }
-                            RawDeployAccountTransaction::DeployAccountV3(x) => {
-                                let contract_address = ContractAddress::deployed_contract_address(
-                                    x.constructor_calldata.iter().copied(),
-                                    &x.contract_address_salt,
-                                    &x.class_hash,
-                                );
-                                TransactionVariant::DeployAccountV3(DeployAccountTransactionV3 {
-                                    contract_address,
-                                    signature: x.signature,
-                                    nonce: x.nonce,
-                                    nonce_data_availability_mode: x.nonce_data_availability_mode,
-                                    fee_data_availability_mode: x.fee_data_availability_mode,
-                                    resource_bounds: x.resource_bounds,
-                                    tip: x.tip,
-                                    paymaster_data: x.paymaster_data,
-                                    contract_address_salt: x.contract_address_salt,
-                                    constructor_calldata: x.constructor_calldata,
-                                    class_hash: x.class_hash,
-                                })
-                            }
-                        })
-                        .collect::<Vec<_>>(),
+    /// Fetches the header of a single block from the given peer. Returns `None` if the peer
+    /// does not have the block.
+    pub async fn block_header_from(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+    ) -> anyhow::Result<Option<SignedBlockHeader>> {
+        let request = BlockHeadersRequest {
+            iteration: single_block(block),
+        };
+
+        let mut responses = self.inner.send_headers_sync_request(peer, request).await?;
+        match responses.next().await {
+            Some(BlockHeadersResponse::Header(header)) => SignedBlockHeader::try_from(*header)
+                .map(Some)
+                .context(MalformedResponse),
+            Some(BlockHeadersResponse::Fin) | None => Ok(None),
+            Some(BlockHeadersResponse::RateLimited) => {
+                self.back_off(peer);
+                Err(rate_limited())
+            }
+        }
+    }
+
+    /// Fetches up to `limit` consecutive headers starting at `start` from the first peer which
+    /// has the `start` block. Returns `None` if no peer has it.
+    ///
+    /// The headers are not verified, the caller must check that they extend its chain.
+    pub async fn block_headers_from_any_peer(
+        &self,
+        start: BlockNumber,
+        limit: u64,
+    ) -> anyhow::Result<Option<PeerData<Vec<SignedBlockHeader>>>> {
+        let request = BlockHeadersRequest {
+            iteration: Iteration {
+                start: start.get().into(),
+                direction: Direction::Forward,
+                limit,
+                step: 1.into(),
+            },
+        };
+
+        let peers = self
+            .get_update_peers_with_sync_capability(protocol::Headers::NAMES)
+            .await;
+
+        for peer in peers {
+            let result = async {
+                let mut responses = self.inner.send_headers_sync_request(peer, request).await?;
+
+                let mut headers = Vec::new();
+                while let Some(response) = responses.next().await {
+                    match response {
+                        BlockHeadersResponse::Header(header) => headers
+                            .push(SignedBlockHeader::try_from(*header).context(MalformedResponse)?),
+                        BlockHeadersResponse::Fin => break,
+                        BlockHeadersResponse::RateLimited => return Err(rate_limited()),
+                    }
+                }
+
+                Ok::<_, anyhow::Error>(headers)
+            }
+            .await;
+
+            match result {
+                // The peer does not have the block, which is not its fault.
+                Ok(headers) if headers.is_empty() => {}
+                Ok(headers) => return Ok(Some(PeerData::new(peer, headers))),
+                Err(error) => {
+                    tracing::debug!(%peer, %start, reason=%error, "Headers request failed");
+                    self.request_failed(peer, &error).await;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches the transactions of a single block, with deployed contract addresses computed.
+    ///
+    /// Peers are tried until one of them responds with exactly `count` transactions.
+    pub async fn block_transactions(
+        &self,
+        block: BlockNumber,
+        count: usize,
+    ) -> anyhow::Result<PeerData<Vec<Transaction>>> {
+        let request = TransactionsRequest {
+            iteration: single_block(block),
+        };
+
+        self.request_from_any_peer(protocol::Transactions::NAMES, block, |peer| async move {
+            let mut responses = self
+                .inner
+                .send_transactions_sync_request(peer, request)
+                .await?;
+
+            let mut transactions = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    TransactionsResponse::Transaction(transaction) => {
+                        let variant = RawTransactionVariant::try_from_dto(transaction.variant)
+                            .context(MalformedResponse)?;
+                        transactions.push(Transaction {
+                            hash: TransactionHash(transaction.hash.0),
+                            variant: variant.into_variant(),
+                        });
+                    }
+                    TransactionsResponse::Fin => break,
+                    TransactionsResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(transactions.len() == count, || {
+                format!(
+                    "Expected {count} transactions but got {}",
+                    transactions.len()
                 )
-            })
-            .collect();
-        computed
-    });
-    let computed = jh.await.context("task ended unexpectedly")?;
-    Ok(computed)
+            })?;
+
+            Ok::<_, anyhow::Error>(transactions)
+        })
+        .await
+    }
+
+    /// Fetches the receipts of a single block, excluding events.
+    ///
+    /// Receipts are not committed to by the block header, so they are preferably only accepted
+    /// once [RECEIPT_QUORUM] peers responded with exactly `count` identical receipts. Peers which
+    /// responded with other receipts are penalized. If too few peers respond for a quorum, the
+    /// receipts are accepted from a single peer as long as no other peer contradicts them, in
+    /// which case the caller must verify the rest of the block against the header's commitments
+    /// before accepting them.
+    pub async fn block_receipts(
+        &self,
+        block: BlockNumber,
+        count: usize,
+    ) -> anyhow::Result<PeerData<Vec<Receipt>>> {
+        let request = ReceiptsRequest {
+            iteration: single_block(block),
+        };
+
+        let peers = self
+            .get_update_peers_with_sync_capability(protocol::Receipts::NAMES)
+            .await;
+
+        // The distinct responses so far, along with the peers which served them.
+        let mut responses: Vec<(Vec<Receipt>, Vec<PeerId>)> = Vec::new();
+        for peer in peers {
+            let result = async {
+                let mut stream = self.inner.send_receipts_sync_request(peer, request).await?;
+
+                let mut receipts = Vec::new();
+                while let Some(response) = stream.next().await {
+                    match response {
+                        ReceiptsResponse::Receipt(receipt) => {
+                            receipts.push(Receipt::try_from(receipt).context(MalformedResponse)?)
+                        }
+                        ReceiptsResponse::Fin => break,
+                        ReceiptsResponse::RateLimited => return Err(rate_limited()),
+                    }
+                }
+
+                ensure_complete(receipts.len() == count, || {
+                    format!("Expected {count} receipts but got {}", receipts.len())
+                })?;
+
+                Ok::<_, anyhow::Error>(receipts)
+            }
+            .await;
+
+            let receipts = match result {
+                Ok(receipts) => receipts,
+                Err(error) => {
+                    tracing::debug!(%peer, %block, reason=%error, "Receipts request failed");
+                    self.request_failed(peer, &error).await;
+                    continue;
+                }
+            };
+
+            let agreeing = match responses.iter().position(|(x, _)| *x == receipts) {
+                Some(i) => i,
+                None => {
+                    responses.push((receipts, Vec::new()));
+                    responses.len() - 1
+                }
+            };
+            responses[agreeing].1.push(peer);
+
+            if responses[agreeing].1.len() >= RECEIPT_QUORUM {
+                let (receipts, peers) = responses.swap_remove(agreeing);
+                for (_, disagreeing) in responses {
+                    for peer in disagreeing {
+                        self.penalize(peer, Misbehaviour::CommitmentMismatch).await;
+                    }
+                }
+                return Ok(PeerData::new(peers[0], receipts));
+            }
+        }
+
+        if let [(_, peers)] = responses.as_slice() {
+            tracing::debug!(%block, peer=%peers[0], "Too few peers for a quorum, accepting receipts from a single peer");
+            let (receipts, peers) = responses.swap_remove(0);
+            return Ok(PeerData::new(peers[0], receipts));
+        }
+
+        anyhow::bail!("Receipts of block {block} were not confirmed by {RECEIPT_QUORUM} peers")
+    }
+
+    /// Fetches the events of a single block along with the hash of the emitting transaction.
+    ///
+    /// Peers are tried until one of them responds with exactly `count` events.
+    pub async fn block_events(
+        &self,
+        block: BlockNumber,
+        count: usize,
+    ) -> anyhow::Result<PeerData<Vec<(TransactionHash, Event)>>> {
+        let request = EventsRequest {
+            iteration: single_block(block),
+        };
+
+        self.request_from_any_peer(protocol::Events::NAMES, block, |peer| async move {
+            let mut responses = self.inner.send_events_sync_request(peer, request).await?;
+
+            let mut events = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    EventsResponse::Event(event) => {
+                        let transaction_hash = TransactionHash(event.transaction_hash.0);
+                        let event = Event::try_from_dto(event).context(MalformedResponse)?;
+                        events.push((transaction_hash, event));
+                    }
+                    EventsResponse::Fin => break,
+                    EventsResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(events.len() == count, || {
+                format!("Expected {count} events but got {}", events.len())
+            })?;
+
+            Ok::<_, anyhow::Error>(events)
+        })
+        .await
+    }
+
+    /// Fetches the state diff of a single block. Declared classes are not part of the
+    /// state diff, see [Self::block_classes].
+    ///
+    /// A peer which does not have the block responds with an empty state diff. Unless the
+    /// state diff `may_be_empty`, such a response is treated as incomplete and the next peer
+    /// is tried. Either way the caller must verify the result against the block's state
+    /// commitment.
+    pub async fn block_state_diff(
+        &self,
+        block: BlockNumber,
+        may_be_empty: bool,
+    ) -> anyhow::Result<PeerData<StateUpdate>> {
+        let request = StateDiffsRequest {
+            iteration: single_block(block),
+        };
+
+        self.request_from_any_peer(protocol::StateDiffs::NAMES, block, |peer| async move {
+            let mut responses = self
+                .inner
+                .send_state_diffs_sync_request(peer, request)
+                .await?;
+
+            let mut diffs = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    StateDiffsResponse::ContractDiff(diff) => diffs.push(diff),
+                    StateDiffsResponse::Fin => break,
+                    StateDiffsResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            let state_update = StateUpdate::try_from_dto(diffs).context(MalformedResponse)?;
+            ensure_complete(may_be_empty || state_update.change_count() > 0, || {
+                "Expected a state diff but got none".to_owned()
+            })?;
+
+            Ok::<_, anyhow::Error>(state_update)
+        })
+        .await
+    }
+
+    /// Fetches the classes declared in a single block.
+    ///
+    /// As with [Self::block_state_diff], an empty response is only accepted if the classes
+    /// `may_be_empty`, and the caller must verify that the result is complete.
+    pub async fn block_classes(
+        &self,
+        block: BlockNumber,
+        may_be_empty: bool,
+    ) -> anyhow::Result<PeerData<Vec<Class>>> {
+        let request = ClassesRequest {
+            iteration: single_block(block),
+        };
+
+        self.request_from_any_peer(protocol::Classes::NAMES, block, |peer| async move {
+            let mut responses = self.inner.send_classes_sync_request(peer, request).await?;
+
+            let mut classes = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    ClassesResponse::Class(class) => classes.push(class),
+                    ClassesResponse::Fin => break,
+                    ClassesResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(may_be_empty || !classes.is_empty(), || {
+                "Expected classes but got none".to_owned()
+            })?;
+
+            Ok::<_, anyhow::Error>(classes)
+        })
+        .await
+    }
+
+    /// Fetches chunks of the contracts with addresses in `start..=end` at `block`, along with
+    /// their range proofs.
+    ///
+    /// A peer may stop before it reaches `end`, so the caller must verify each chunk and continue
+    /// from where the last chunk stopped. A peer which does not have the state at `block` responds
+    /// without any chunks, in which case the next peer is tried.
+    pub async fn contract_range(
+        &self,
+        block: BlockNumber,
+        start: ContractAddress,
+        end: ContractAddress,
+    ) -> anyhow::Result<PeerData<Vec<ContractRange>>> {
+        let request = ContractRangeRequest {
+            domain: 0,
+            block_number: block.get(),
+            start: Address(start.0),
+            end: Address(end.0),
+        };
+
+        self.request_from_any_peer(protocol::ContractRange::NAMES, block, |peer| async move {
+            let mut responses = self
+                .inner
+                .send_contract_range_sync_request(peer, request)
+                .await?;
+
+            let mut chunks = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    ContractRangeResponse::Range(range) => chunks.push(range),
+                    ContractRangeResponse::Fin => break,
+                    ContractRangeResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(!chunks.is_empty(), || {
+                "Peer does not have the state".to_owned()
+            })?;
+
+            Ok::<_, anyhow::Error>(chunks)
+        })
+        .await
+    }
+
+    /// Fetches chunks of the storage of `contract` with keys in `start..=end` at `block`, along
+    /// with their range proofs.
+    ///
+    /// As with [Self::contract_range], the caller must verify the chunks and continue from where
+    /// the last chunk stopped.
+    pub async fn contract_storage(
+        &self,
+        block: BlockNumber,
+        contract: ContractAddress,
+        start: StorageAddress,
+        end: StorageAddress,
+    ) -> anyhow::Result<PeerData<Vec<ContractStorage>>> {
+        let request = ContractStorageRequest {
+            domain: 0,
+            block_number: block.get(),
+            address: Address(contract.0),
+            start: start.0,
+            end: end.0,
+        };
+
+        self.request_from_any_peer(protocol::ContractStorage::NAMES, block, |peer| async move {
+            let mut responses = self
+                .inner
+                .send_contract_storage_sync_request(peer, request)
+                .await?;
+
+            let mut chunks = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    ContractStorageResponse::Storage(storage) => chunks.push(storage),
+                    ContractStorageResponse::Fin => break,
+                    ContractStorageResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(!chunks.is_empty(), || {
+                "Peer does not have the state".to_owned()
+            })?;
+
+            Ok::<_, anyhow::Error>(chunks)
+        })
+        .await
+    }
+
+    /// Fetches chunks of the Sierra classes with hashes in `start..=end` at `block`, along with
+    /// their range proofs.
+    ///
+    /// As with [Self::contract_range], the caller must verify the chunks and continue from where
+    /// the last chunk stopped.
+    pub async fn class_range(
+        &self,
+        block: BlockNumber,
+        start: ClassHash,
+        end: ClassHash,
+    ) -> anyhow::Result<PeerData<Vec<ClassRange>>> {
+        let request = ClassRangeRequest {
+            block_number: block.get(),
+            start: Hash(start.0),
+            end: Hash(end.0),
+        };
+
+        self.request_from_any_peer(protocol::ClassRange::NAMES, block, |peer| async move {
+            let mut responses = self
+                .inner
+                .send_class_range_sync_request(peer, request)
+                .await?;
+
+            let mut chunks = Vec::new();
+            while let Some(response) = responses.next().await {
+                match response {
+                    ClassRangeResponse::Range(range) => chunks.push(range),
+                    ClassRangeResponse::Fin => break,
+                    ClassRangeResponse::RateLimited => return Err(rate_limited()),
+                }
+            }
+
+            ensure_complete(!chunks.is_empty(), || {
+                "Peer does not have the state".to_owned()
+            })?;
+
+            Ok::<_, anyhow::Error>(chunks)
+        })
+        .await
+    }
+
+    /// Fetches the definitions of the Cairo 0 classes with `hashes`, which are referenced by the
+    /// contracts of the state at `block` but are not part of its tries.
+    ///
+    /// Peers may serve only some of the classes. The caller must verify that the classes hash to
+    /// the requested hashes, and request the remaining ones again.
+    pub async fn classes_by_hash(
+        &self,
+        block: BlockNumber,
+        hashes: &[ClassHash],
+    ) -> anyhow::Result<PeerData<Vec<Class>>> {
+        let request = ClassesByHashRequest {
+            class_hashes: hashes.iter().map(|hash| Hash(hash.0)).collect(),
+        };
+
+        self.request_from_any_peer(protocol::ClassesByHash::NAMES, block, |peer| {
+            let request = request.clone();
+            async move {
+                let mut responses = self
+                    .inner
+                    .send_classes_by_hash_sync_request(peer, request)
+                    .await?;
+
+                let mut classes = Vec::new();
+                while let Some(response) = responses.next().await {
+                    match response {
+                        ClassesByHashResponse::Class(class) => classes.push(class),
+                        ClassesByHashResponse::Fin => break,
+                        ClassesByHashResponse::RateLimited => return Err(rate_limited()),
+                    }
+                }
+
+                ensure_complete(!classes.is_empty(), || {
+                    "Peer has none of the classes".to_owned()
+                })?;
+
+                Ok::<_, anyhow::Error>(classes)
+            }
+        })
+        .await
+    }
+
+    /// Sends a request to each peer with any of the capabilities in turn, until one of them
+    /// responds successfully.
+    ///
+    /// Peers which fail to respond are penalized, see [misbehaviour]. Incomplete responses
+    /// are not, as the peer might simply not have the block yet, and peers which rate limited
+    /// the request are backed off from instead.
+    async fn request_from_any_peer<T, F, Fut>(
+        &self,
+        capabilities: &[&str],
+        block: BlockNumber,
+        request: F,
+    ) -> anyhow::Result<PeerData<T>>
+    where
+        F: Fn(PeerId) -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<T>>,
+    {
+        let peers = self
+            .get_update_peers_with_sync_capability(capabilities)
+            .await;
+
+        for peer in peers {
+            match request(peer).await {
+                Ok(data) => return Ok(PeerData::new(peer, data)),
+                Err(error) => {
+                    tracing::debug!(%peer, %block, ?capabilities, reason=%error, "Block data request failed");
+                    self.request_failed(peer, &error).await;
+                }
+            }
+        }
+
+        anyhow::bail!("No peer responded to {capabilities:?} request for block {block}")
+    }
+}
+
+/// Context marking errors caused by responses which don't conform to `p2p_proto`.
+#[derive(Debug)]
+struct MalformedResponse;
+
+impl std::fmt::Display for MalformedResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Malformed response")
+    }
+}
+
+/// Context marking errors caused by a peer which does not have (all of) the requested data.
+#[derive(Debug)]
+struct IncompleteResponse;
+
+impl std::fmt::Display for IncompleteResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Incomplete response")
+    }
+}
+
+/// Context marking errors caused by a peer which rejected the request because we are over our
+/// budget with it. This is not misbehaviour, instead the peer is backed off from.
+#[derive(Debug)]
+struct RateLimitedResponse;
+
+impl std::fmt::Display for RateLimitedResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Rate limited")
+    }
+}
+
+fn rate_limited() -> anyhow::Error {
+    anyhow::anyhow!("Peer rejected the request").context(RateLimitedResponse)
+}
+
+/// Fails with [IncompleteResponse] context unless the response is `complete`.
+fn ensure_complete(complete: bool, description: impl FnOnce() -> String) -> anyhow::Result<()> {
+    match complete {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!(description()).context(IncompleteResponse)),
+    }
+}
+
+/// How a peer whose request failed with `error` misbehaved, if at all. Errors with
+/// [MalformedResponse] context are malformed messages, and errors with [IncompleteResponse] or
+/// [RateLimitedResponse] context are not misbehaviour. Anything else is treated like a timeout.
+fn misbehaviour(error: &anyhow::Error) -> Option<Misbehaviour> {
+    if error.downcast_ref::<MalformedResponse>().is_some() {
+        Some(Misbehaviour::MalformedMessage)
+    } else if error.downcast_ref::<IncompleteResponse>().is_some()
+        || error.downcast_ref::<RateLimitedResponse>().is_some()
+    {
+        None
+    } else {
+        Some(Misbehaviour::Timeout)
+    }
+}
+
+/// An [Iteration] covering only the given block.
+fn single_block(block: BlockNumber) -> Iteration {
+    Iteration {
+        start: block.get().into(),
+        direction: Direction::Forward,
+        limit: 1,
+        step: 1.into(),
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -276,3 +897,32 @@ impl Default for PeersWithCapability {
         Self::new(Duration::from_secs(60))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incomplete_responses_are_not_misbehaviour() {
+        let incomplete = ensure_complete(false, || "Expected 3 events but got 0".to_owned())
+            .context("Fetching events")
+            .unwrap_err();
+        assert_eq!(misbehaviour(&incomplete), None);
+
+        let malformed = Err::<(), _>(anyhow::anyhow!("Invalid variant"))
+            .context(MalformedResponse)
+            .unwrap_err();
+        assert_eq!(
+            misbehaviour(&malformed),
+            Some(Misbehaviour::MalformedMessage)
+        );
+
+        let rate_limited = Err::<(), _>(rate_limited())
+            .context("Fetching events")
+            .unwrap_err();
+        assert_eq!(misbehaviour(&rate_limited), None);
+
+        let timeout = anyhow::anyhow!("Connection closed");
+        assert_eq!(misbehaviour(&timeout), Some(Misbehaviour::Timeout));
+    }
+}
@@ -1,6 +1,6 @@
 //! _Low level_ client for p2p interaction. Caller has to manage peers manually.
 //! For syncing use [`crate::client::peer_agnostic::Client`] instead, which manages peers "under the hood".
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Context;
 use futures::channel::mpsc::Receiver as ResponseReceiver;
@@ -11,12 +11,16 @@ use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, NewBlock};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 
 use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRangeRequest, ContractRangeResponse, ContractStorageRequest, ContractStorageResponse,
+};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
 use tokio::sync::{mpsc, oneshot};
 
 #[cfg(test)]
 use crate::test_utils;
-use crate::Command;
+use crate::{Command, Misbehaviour, PeerInfo, PeerScore};
 
 #[derive(Clone, Debug)]
 pub struct Client {
@@ -173,6 +177,34 @@ impl Client {
         EventsResponse
     );
 
+    impl_send!(
+        send_contract_range_sync_request,
+        SendContractRangeSyncRequest,
+        ContractRangeRequest,
+        ContractRangeResponse
+    );
+
+    impl_send!(
+        send_contract_storage_sync_request,
+        SendContractStorageSyncRequest,
+        ContractStorageRequest,
+        ContractStorageResponse
+    );
+
+    impl_send!(
+        send_class_range_sync_request,
+        SendClassRangeSyncRequest,
+        ClassRangeRequest,
+        ClassRangeResponse
+    );
+
+    impl_send!(
+        send_classes_by_hash_sync_request,
+        SendClassesByHashSyncRequest,
+        ClassesByHashRequest,
+        ClassesByHashResponse
+    );
+
     pub async fn publish(&self, topic: &str, new_block: NewBlock) -> anyhow::Result<()> {
         let (sender, receiver) = oneshot::channel();
         let topic = IdentTopic::new(topic);
@@ -199,6 +231,43 @@ impl Client {
         receiver.await.expect("Sender not to be dropped")
     }
 
+    /// Lower the score of a misbehaving peer.
+    ///
+    /// Peers whose score drops too low are disconnected and banned.
+    pub async fn penalize(&self, peer_id: PeerId, misbehaviour: Misbehaviour) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::Penalize {
+                peer_id,
+                misbehaviour,
+                sender,
+            })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")
+    }
+
+    /// Scores of the peers which misbehaved recently. Peers which are not included have a
+    /// neutral score.
+    pub async fn peer_scores(&self) -> HashMap<PeerId, PeerScore> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::GetPeerScores { sender })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")
+    }
+
+    /// The known peers, including recently disconnected ones.
+    pub async fn peers(&self) -> Vec<PeerInfo> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::GetPeers { sender })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")
+    }
+
     #[cfg(test)]
     pub(crate) fn for_test(&self) -> test_utils::Client {
         test_utils::Client::new(self.sender.clone())
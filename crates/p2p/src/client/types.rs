@@ -5,19 +5,22 @@ use pathfinder_common::event::Event;
 use pathfinder_common::receipt::{
     BuiltinCounters, ExecutionDataAvailability, ExecutionResources, ExecutionStatus, L2ToL1Message,
 };
+use pathfinder_common::state_update::ContractClassUpdate;
 use pathfinder_common::transaction::{
     DataAvailabilityMode, DeclareTransactionV0V1, DeclareTransactionV2, DeclareTransactionV3,
     DeployAccountTransactionV0V1, DeployAccountTransactionV3, DeployTransaction,
     InvokeTransactionV0, InvokeTransactionV1, InvokeTransactionV3, L1HandlerTransaction,
     ResourceBound, ResourceBounds, TransactionVariant,
 };
+use pathfinder_common::trie::TrieNode;
 use pathfinder_common::{
     AccountDeploymentDataElem, BlockCommitmentSignature, BlockCommitmentSignatureElem, BlockHash,
     BlockNumber, BlockTimestamp, CallParam, CasmHash, ClassHash, ConstructorParam, ContractAddress,
-    ContractAddressSalt, EntryPoint, EthereumAddress, EventCommitment, EventData, EventKey, Fee,
-    GasPrice, L1DataAvailabilityMode, L2ToL1MessagePayloadElem, PaymasterDataElem,
-    SequencerAddress, StarknetVersion, StateCommitment, Tip, TransactionCommitment,
-    TransactionHash, TransactionNonce, TransactionSignatureElem, TransactionVersion,
+    ContractAddressSalt, ContractNonce, EntryPoint, EthereumAddress, EventCommitment, EventData,
+    EventKey, Fee, GasPrice, L1DataAvailabilityMode, L2ToL1MessagePayloadElem, PaymasterDataElem,
+    SequencerAddress, StarknetVersion, StateCommitment, StateUpdate, StorageAddress, StorageValue,
+    Tip, TransactionCommitment, TransactionHash, TransactionNonce, TransactionSignatureElem,
+    TransactionVersion,
 };
 
 /// We don't want to introduce circular dependencies between crates
@@ -303,6 +306,59 @@ impl NonDeployAccountTransaction {
     }
 }
 
+impl RawDeployAccountTransaction {
+    /// Computes the deployed contract address, which is expensive.
+    pub fn into_variant(self) -> TransactionVariant {
+        match self {
+            Self::DeployAccountV0V1(x) => {
+                TransactionVariant::DeployAccountV0V1(DeployAccountTransactionV0V1 {
+                    contract_address: ContractAddress::deployed_contract_address(
+                        x.constructor_calldata.iter().copied(),
+                        &x.contract_address_salt,
+                        &x.class_hash,
+                    ),
+                    max_fee: x.max_fee,
+                    version: x.version,
+                    signature: x.signature,
+                    nonce: x.nonce,
+                    contract_address_salt: x.contract_address_salt,
+                    constructor_calldata: x.constructor_calldata,
+                    class_hash: x.class_hash,
+                })
+            }
+            Self::DeployAccountV3(x) => {
+                TransactionVariant::DeployAccountV3(DeployAccountTransactionV3 {
+                    contract_address: ContractAddress::deployed_contract_address(
+                        x.constructor_calldata.iter().copied(),
+                        &x.contract_address_salt,
+                        &x.class_hash,
+                    ),
+                    signature: x.signature,
+                    nonce: x.nonce,
+                    nonce_data_availability_mode: x.nonce_data_availability_mode,
+                    fee_data_availability_mode: x.fee_data_availability_mode,
+                    resource_bounds: x.resource_bounds,
+                    tip: x.tip,
+                    paymaster_data: x.paymaster_data,
+                    contract_address_salt: x.contract_address_salt,
+                    constructor_calldata: x.constructor_calldata,
+                    class_hash: x.class_hash,
+                })
+            }
+        }
+    }
+}
+
+impl RawTransactionVariant {
+    /// Computes the deployed contract address of deploy account transactions.
+    pub fn into_variant(self) -> TransactionVariant {
+        match self {
+            Self::DeployAccount(x) => x.into_variant(),
+            Self::NonDeployAccount(x) => x.into_variant(),
+        }
+    }
+}
+
 impl From<TransactionVariant> for RawTransactionVariant {
     fn from(x: TransactionVariant) -> Self {
         use TransactionVariant::*;
@@ -439,7 +495,11 @@ impl TryFromDto<p2p_proto::transaction::TransactionVariant> for RawTransactionVa
             ),
             Deploy(x) => RawTransactionVariant::NonDeployAccount(
                 NonDeployAccountTransaction::Deploy(DeployTransaction {
-                    contract_address: ContractAddress::ZERO, // FIXME: compute deployed contract address
+                    contract_address: ContractAddress::deployed_contract_address(
+                        x.calldata.iter().copied().map(CallParam),
+                        &ContractAddressSalt(x.address_salt),
+                        &ClassHash(x.class_hash.0),
+                    ),
                     contract_address_salt: ContractAddressSalt(x.address_salt),
                     class_hash: ClassHash(x.class_hash.0),
                     constructor_calldata: x.calldata.into_iter().map(ConstructorParam).collect(),
@@ -586,6 +646,81 @@ impl TryFromDto<p2p_proto::event::Event> for Event {
     }
 }
 
+impl TryFromDto<Vec<p2p_proto::state::ContractDiff>> for StateUpdate {
+    /// Declared classes are not part of the state diff and are left empty.
+    fn try_from_dto(dto: Vec<p2p_proto::state::ContractDiff>) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut state_update = StateUpdate::default();
+
+        for diff in dto {
+            let address = ContractAddress(diff.address.0);
+
+            if address == ContractAddress::ONE {
+                anyhow::ensure!(
+                    diff.nonce.is_none() && diff.class_hash.is_none(),
+                    "System contract {address} cannot have a nonce or class"
+                );
+                for value in diff.values {
+                    state_update = state_update.with_system_storage_update(
+                        address,
+                        StorageAddress(value.key),
+                        StorageValue(value.value),
+                    );
+                }
+                continue;
+            }
+
+            let update = state_update.contract_updates.entry(address).or_default();
+            update.nonce = diff.nonce.map(ContractNonce);
+            update.class = diff.class_hash.map(|class_hash| {
+                if diff.is_replaced.unwrap_or_default() {
+                    ContractClassUpdate::Replace(ClassHash(class_hash))
+                } else {
+                    ContractClassUpdate::Deploy(ClassHash(class_hash))
+                }
+            });
+            update.storage = diff
+                .values
+                .into_iter()
+                .map(|x| (StorageAddress(x.key), StorageValue(x.value)))
+                .collect();
+        }
+
+        Ok(state_update)
+    }
+}
+
+impl TryFromDto<p2p_proto::snapshot::PatriciaNode> for TrieNode {
+    fn try_from_dto(dto: p2p_proto::snapshot::PatriciaNode) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        use p2p_proto::snapshot::PatriciaNode;
+        Ok(match dto {
+            PatriciaNode::Binary { left, right } => Self::Binary { left, right },
+            PatriciaNode::Edge {
+                length,
+                path,
+                value,
+            } => {
+                let length = usize::try_from(length)?;
+                anyhow::ensure!(length <= 251, "Edge path is too long");
+                let bits = path.view_bits();
+                anyhow::ensure!(
+                    bits[..251 - length].not_any(),
+                    "Edge path is longer than its length"
+                );
+                Self::Edge {
+                    child: value,
+                    path: bits[251 - length..].to_bitvec(),
+                }
+            }
+        })
+    }
+}
+
 impl TryFromDto<String> for DataAvailabilityMode {
     fn try_from_dto(dto: String) -> anyhow::Result<Self>
     where
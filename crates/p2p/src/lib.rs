@@ -1,6 +1,8 @@
 #![deny(rust_2018_idioms)]
 
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::channel::mpsc::{Receiver as ResponseReceiver, Sender as ResponseSender};
@@ -14,6 +16,10 @@ use p2p_proto::class::{ClassesRequest, ClassesResponse};
 use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, NewBlock};
 use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRangeRequest, ContractRangeResponse, ContractStorageRequest, ContractStorageResponse,
+};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
 use pathfinder_common::{BlockHash, BlockNumber, ChainId};
@@ -23,7 +29,9 @@ use tokio::sync::{mpsc, oneshot};
 mod behaviour;
 pub mod client;
 mod main_loop;
+mod peer_store;
 mod peers;
+mod reputation;
 mod secret;
 mod sync;
 #[cfg(test)]
@@ -34,6 +42,8 @@ mod transport;
 
 pub use client::peer_agnostic::PeerData;
 pub use libp2p;
+pub use peers::PeerInfo;
+pub use reputation::{Misbehaviour, PeerScore};
 pub use sync::protocol::PROTOCOLS;
 
 use client::peer_aware::Client;
@@ -93,11 +103,40 @@ pub struct Config {
     pub low_watermark: usize,
     /// How long to prevent evicted peers from reconnecting.
     pub eviction_timeout: Duration,
+    /// File in which known peers, their addresses, capabilities and scores are persisted
+    /// across restarts. If not set, they are only kept in memory.
+    pub peers_file: Option<PathBuf>,
+    /// Maximum number of sync requests a peer can have in flight at the same time, per
+    /// connection and protocol.
+    pub max_concurrent_inbound_streams: usize,
+    /// Called with the size of each sync response sent to a peer.
+    pub response_meter: Option<ResponseMeter>,
     pub ip_whitelist: Vec<IpNet>,
     pub bootstrap: BootstrapConfig,
     pub inbound_connections_rate_limit: RateLimit,
 }
 
+/// Accounts for the bytes of the sync responses sent to each peer. Responses are measured once
+/// they are encoded, and charged after they are written.
+#[derive(Clone)]
+pub struct ResponseMeter(Arc<dyn Fn(PeerId, usize) + Send + Sync>);
+
+impl ResponseMeter {
+    pub fn new(charge: impl Fn(PeerId, usize) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(charge))
+    }
+
+    pub(crate) fn charge(&self, peer: PeerId, bytes: usize) {
+        (self.0)(peer, bytes)
+    }
+}
+
+impl std::fmt::Debug for ResponseMeter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResponseMeter")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RateLimit {
     pub max: usize,
@@ -119,8 +158,17 @@ impl Default for BootstrapConfig {
     }
 }
 
-pub type HeadTx = tokio::sync::watch::Sender<Option<(BlockNumber, BlockHash)>>;
-pub type HeadRx = tokio::sync::watch::Receiver<Option<(BlockNumber, BlockHash)>>;
+/// A chain head announced on the block propagation topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Head {
+    /// The peer which announced the head.
+    pub from: PeerId,
+    pub number: BlockNumber,
+    pub hash: BlockHash,
+}
+
+pub type HeadTx = tokio::sync::watch::Sender<Option<Head>>;
+pub type HeadRx = tokio::sync::watch::Receiver<Option<Head>>;
 
 type EmptyResultSender = oneshot::Sender<anyhow::Result<()>>;
 
@@ -181,6 +229,26 @@ enum Command {
         request: EventsRequest,
         sender: oneshot::Sender<anyhow::Result<ResponseReceiver<EventsResponse>>>,
     },
+    SendContractRangeSyncRequest {
+        peer_id: PeerId,
+        request: ContractRangeRequest,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<ContractRangeResponse>>>,
+    },
+    SendContractStorageSyncRequest {
+        peer_id: PeerId,
+        request: ContractStorageRequest,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<ContractStorageResponse>>>,
+    },
+    SendClassRangeSyncRequest {
+        peer_id: PeerId,
+        request: ClassRangeRequest,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<ClassRangeResponse>>>,
+    },
+    SendClassesByHashSyncRequest {
+        peer_id: PeerId,
+        request: ClassesByHashRequest,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<ClassesByHashResponse>>>,
+    },
     PublishPropagationMessage {
         topic: IdentTopic,
         new_block: NewBlock,
@@ -190,6 +258,17 @@ enum Command {
         peer_id: PeerId,
         sender: oneshot::Sender<()>,
     },
+    Penalize {
+        peer_id: PeerId,
+        misbehaviour: Misbehaviour,
+        sender: oneshot::Sender<()>,
+    },
+    GetPeerScores {
+        sender: oneshot::Sender<HashMap<PeerId, PeerScore>>,
+    },
+    GetPeers {
+        sender: oneshot::Sender<Vec<PeerInfo>>,
+    },
     /// For testing purposes only
     _Test(TestCommand),
 }
@@ -235,6 +314,26 @@ pub enum Event {
         request: EventsRequest,
         channel: ResponseSender<EventsResponse>,
     },
+    InboundContractRangeSyncRequest {
+        from: PeerId,
+        request: ContractRangeRequest,
+        channel: ResponseSender<ContractRangeResponse>,
+    },
+    InboundContractStorageSyncRequest {
+        from: PeerId,
+        request: ContractStorageRequest,
+        channel: ResponseSender<ContractStorageResponse>,
+    },
+    InboundClassRangeSyncRequest {
+        from: PeerId,
+        request: ClassRangeRequest,
+        channel: ResponseSender<ClassRangeResponse>,
+    },
+    InboundClassesByHashSyncRequest {
+        from: PeerId,
+        request: ClassesByHashRequest,
+        channel: ResponseSender<ClassesByHashResponse>,
+    },
     BlockPropagation {
         from: PeerId,
         new_block: NewBlock,
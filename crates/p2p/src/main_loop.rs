@@ -15,15 +15,19 @@ use p2p_proto::class::ClassesResponse;
 use p2p_proto::event::EventsResponse;
 use p2p_proto::header::BlockHeadersResponse;
 use p2p_proto::receipt::ReceiptsResponse;
+use p2p_proto::snapshot::{
+    ClassRangeResponse, ClassesByHashResponse, ContractRangeResponse, ContractStorageResponse,
+};
 use p2p_proto::state::StateDiffsResponse;
 use p2p_proto::transaction::TransactionsResponse;
 use p2p_proto::{ToProtobuf, TryFromProtobuf};
 use p2p_stream::{self, OutboundRequestId};
 use pathfinder_common::ChainId;
 use tokio::sync::{mpsc, oneshot};
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 
 use crate::behaviour;
+use crate::sync::protocol::{self, SyncProtocol};
 #[cfg(test)]
 use crate::test_utils;
 use crate::Config;
@@ -38,6 +42,9 @@ pub struct MainLoop {
     /// succeeds or fails.
     pending_dials: HashMap<PeerId, EmptyResultSender>,
     pending_sync_requests: PendingRequests,
+    /// When each of the ongoing outbound sync requests was sent, by protocol, as request IDs
+    /// are only unique per protocol.
+    outbound_request_times: HashMap<(&'static str, OutboundRequestId), Instant>,
     // TODO there's no sync status message anymore so we have to:
     // 1. set the idle connection timeout to maximum value to keep connections open (earlier: keep alive::Behavior)
     // 2. update the sync head info of our peers using a different mechanism
@@ -75,11 +82,29 @@ struct PendingRequests {
         OutboundRequestId,
         oneshot::Sender<anyhow::Result<ResponseReceiver<EventsResponse>>>,
     >,
+    pub contract_range: HashMap<
+        OutboundRequestId,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<ContractRangeResponse>>>,
+    >,
+    pub contract_storage: HashMap<
+        OutboundRequestId,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<ContractStorageResponse>>>,
+    >,
+    pub class_range: HashMap<
+        OutboundRequestId,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<ClassRangeResponse>>>,
+    >,
+    pub classes_by_hash: HashMap<
+        OutboundRequestId,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<ClassesByHashResponse>>>,
+    >,
 }
 
 #[derive(Debug, Default)]
 struct PendingQueries {
     pub get_providers: HashMap<QueryId, mpsc::Sender<anyhow::Result<HashSet<PeerId>>>>,
+    /// The capability each of the `get_providers` queries is for.
+    pub provider_capabilities: HashMap<QueryId, String>,
 }
 
 impl MainLoop {
@@ -97,6 +122,7 @@ impl MainLoop {
             event_sender,
             pending_dials: Default::default(),
             pending_sync_requests: Default::default(),
+            outbound_request_times: Default::default(),
             pending_queries: Default::default(),
             chain_id,
             ongoing_bootstrap: None,
@@ -114,6 +140,17 @@ impl MainLoop {
         let mut peer_status_interval = tokio::time::interval(Duration::from_secs(30));
         let me = *self.swarm.local_peer_id();
 
+        // Rejoin the network through the peers we knew before the restart.
+        for (peer_id, addresses) in self.swarm.behaviour_mut().reconnect_candidates() {
+            tracing::debug!(%peer_id, ?addresses, "Reconnecting to known peer");
+            if let Err(error) = self
+                .swarm
+                .dial(DialOpts::peer_id(peer_id).addresses(addresses).build())
+            {
+                tracing::debug!(%peer_id, %error, "Failed to reconnect to known peer");
+            }
+        }
+
         loop {
             let bootstrap_interval_tick = bootstrap_interval.tick();
             tokio::pin!(bootstrap_interval_tick);
@@ -131,7 +168,9 @@ impl MainLoop {
                     let connection_counters = network_info.connection_counters();
                     let num_established_connections = connection_counters.num_established();
                     let num_pending_connections = connection_counters.num_pending();
-                    tracing::info!(%num_peers, %num_established_connections, %num_pending_connections, "Network status")
+                    tracing::info!(%num_peers, %num_established_connections, %num_pending_connections, "Network status");
+
+                    self.swarm.behaviour_mut().report_peers();
                 }
                 _ = peer_status_interval_tick => {
                     let dht = self.swarm.behaviour_mut().kademlia_mut()
@@ -163,6 +202,9 @@ impl MainLoop {
                         connected,
                         dht,
                     );
+
+                    self.swarm.behaviour_mut().report_peer_scores();
+                    self.swarm.behaviour_mut().save_peers();
                 }
                 _ = bootstrap_interval_tick => {
                     tracing::debug!("Checking low watermark");
@@ -192,7 +234,10 @@ impl MainLoop {
                 command = self.command_receiver.recv() => {
                     match command {
                         Some(c) => self.handle_command(c).await,
-                        None => return,
+                        None => {
+                            self.swarm.behaviour_mut().flush_peers().await;
+                            return;
+                        }
                     }
                 }
                 Some(event) = self.swarm.next() => self.handle_event(event).await,
@@ -201,6 +246,8 @@ impl MainLoop {
     }
 
     async fn handle_event(&mut self, event: SwarmEvent<behaviour::Event>) {
+        self.record_request_metrics(&event);
+
         match event {
             // ===========================
             // Connection management
@@ -289,6 +336,10 @@ impl MainLoop {
 
                     self.swarm.add_external_address(observed_addr);
 
+                    self.swarm
+                        .behaviour_mut()
+                        .add_peer_addresses(peer_id, listen_addrs.clone());
+
                     if protocols
                         .iter()
                         .any(|p| p.as_ref() == behaviour::kademlia_protocol_name(self.chain_id))
@@ -324,6 +375,8 @@ impl MainLoop {
             })) => {
                 use prost::Message;
 
+                metrics::increment_counter!("p2p_gossip_messages_received_total");
+
                 match p2p_proto::proto::header::NewBlock::decode(message.data.as_ref()) {
                     Ok(new_block) => {
                         match p2p_proto::header::NewBlock::try_from_protobuf(new_block, "message") {
@@ -344,11 +397,13 @@ impl MainLoop {
                                     .expect("Event receiver not to be dropped");
                             }
                             Err(error) => {
+                                metrics::increment_counter!("p2p_gossip_messages_invalid_total");
                                 tracing::error!(from=%peer_id, %error, "Gossipsub Message")
                             }
                         }
                     }
                     Err(error) => {
+                        metrics::increment_counter!("p2p_gossip_messages_invalid_total");
                         tracing::error!(from=%peer_id, %error, "Gossipsub Message");
                     }
                 };
@@ -398,6 +453,17 @@ impl MainLoop {
                                     Err(e) => Err(e.into()),
                                 };
 
+                                let capability = self
+                                    .pending_queries
+                                    .provider_capabilities
+                                    .remove(&id)
+                                    .expect("Query to be pending");
+                                if let Ok(providers) = &result {
+                                    self.swarm
+                                        .behaviour_mut()
+                                        .add_capability_providers(&capability, providers);
+                                }
+
                                 let sender = self
                                     .pending_queries
                                     .get_providers
@@ -424,6 +490,14 @@ impl MainLoop {
                             }
                         };
 
+                        if let (Ok(providers), Some(capability)) =
+                            (&result, self.pending_queries.provider_capabilities.get(&id))
+                        {
+                            self.swarm
+                                .behaviour_mut()
+                                .add_capability_providers(capability, providers);
+                        }
+
                         let sender = self
                             .pending_queries
                             .get_providers
@@ -664,6 +738,146 @@ impl MainLoop {
                     .expect("Block sync request still to be pending")
                     .send(Ok(channel));
             }
+            SwarmEvent::Behaviour(behaviour::Event::ContractRangeSync(
+                p2p_stream::Event::InboundRequest {
+                    request_id,
+                    request,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(?request, %peer, %request_id, "Received sync request");
+
+                self.event_sender
+                    .send(Event::InboundContractRangeSyncRequest {
+                        from: peer,
+                        request,
+                        channel,
+                    })
+                    .await
+                    .expect("Event receiver not to be dropped");
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ContractRangeSync(
+                p2p_stream::Event::OutboundRequestSentAwaitingResponses {
+                    request_id,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(%peer, %request_id, "Sync request sent");
+
+                let _ = self
+                    .pending_sync_requests
+                    .contract_range
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Ok(channel));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ContractStorageSync(
+                p2p_stream::Event::InboundRequest {
+                    request_id,
+                    request,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(?request, %peer, %request_id, "Received sync request");
+
+                self.event_sender
+                    .send(Event::InboundContractStorageSyncRequest {
+                        from: peer,
+                        request,
+                        channel,
+                    })
+                    .await
+                    .expect("Event receiver not to be dropped");
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ContractStorageSync(
+                p2p_stream::Event::OutboundRequestSentAwaitingResponses {
+                    request_id,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(%peer, %request_id, "Sync request sent");
+
+                let _ = self
+                    .pending_sync_requests
+                    .contract_storage
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Ok(channel));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassRangeSync(
+                p2p_stream::Event::InboundRequest {
+                    request_id,
+                    request,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(?request, %peer, %request_id, "Received sync request");
+
+                self.event_sender
+                    .send(Event::InboundClassRangeSyncRequest {
+                        from: peer,
+                        request,
+                        channel,
+                    })
+                    .await
+                    .expect("Event receiver not to be dropped");
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassRangeSync(
+                p2p_stream::Event::OutboundRequestSentAwaitingResponses {
+                    request_id,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(%peer, %request_id, "Sync request sent");
+
+                let _ = self
+                    .pending_sync_requests
+                    .class_range
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Ok(channel));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassesByHashSync(
+                p2p_stream::Event::InboundRequest {
+                    request_id,
+                    request,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(?request, %peer, %request_id, "Received sync request");
+
+                self.event_sender
+                    .send(Event::InboundClassesByHashSyncRequest {
+                        from: peer,
+                        request,
+                        channel,
+                    })
+                    .await
+                    .expect("Event receiver not to be dropped");
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassesByHashSync(
+                p2p_stream::Event::OutboundRequestSentAwaitingResponses {
+                    request_id,
+                    peer,
+                    channel,
+                },
+            )) => {
+                tracing::debug!(%peer, %request_id, "Sync request sent");
+
+                let _ = self
+                    .pending_sync_requests
+                    .classes_by_hash
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Ok(channel));
+            }
             SwarmEvent::Behaviour(behaviour::Event::HeadersSync(
                 p2p_stream::Event::OutboundFailure {
                     request_id, error, ..
@@ -742,6 +956,58 @@ impl MainLoop {
                     .expect("Block sync request still to be pending")
                     .send(Err(error.into()));
             }
+            SwarmEvent::Behaviour(behaviour::Event::ContractRangeSync(
+                p2p_stream::Event::OutboundFailure {
+                    request_id, error, ..
+                },
+            )) => {
+                tracing::warn!(?request_id, ?error, "Outbound request failed");
+                let _ = self
+                    .pending_sync_requests
+                    .contract_range
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Err(error.into()));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ContractStorageSync(
+                p2p_stream::Event::OutboundFailure {
+                    request_id, error, ..
+                },
+            )) => {
+                tracing::warn!(?request_id, ?error, "Outbound request failed");
+                let _ = self
+                    .pending_sync_requests
+                    .contract_storage
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Err(error.into()));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassRangeSync(
+                p2p_stream::Event::OutboundFailure {
+                    request_id, error, ..
+                },
+            )) => {
+                tracing::warn!(?request_id, ?error, "Outbound request failed");
+                let _ = self
+                    .pending_sync_requests
+                    .class_range
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Err(error.into()));
+            }
+            SwarmEvent::Behaviour(behaviour::Event::ClassesByHashSync(
+                p2p_stream::Event::OutboundFailure {
+                    request_id, error, ..
+                },
+            )) => {
+                tracing::warn!(?request_id, ?error, "Outbound request failed");
+                let _ = self
+                    .pending_sync_requests
+                    .classes_by_hash
+                    .remove(&request_id)
+                    .expect("Block sync request still to be pending")
+                    .send(Err(error.into()));
+            }
             // ===========================
             // NAT hole punching
             // ===========================
@@ -822,11 +1088,24 @@ impl MainLoop {
                 };
             }
             Command::GetCapabilityProviders { capability, sender } => {
+                // Connected peers which are known to provide the capability are part of the
+                // result too, the DHT query does not necessarily find them.
+                let known = self
+                    .swarm
+                    .behaviour()
+                    .connected_capability_providers(&capability);
+                if !known.is_empty() {
+                    let _ = sender.try_send(Ok(known));
+                }
+
                 let query_id = self
                     .swarm
                     .behaviour_mut()
                     .get_capability_providers(&capability);
                 self.pending_queries.get_providers.insert(query_id, sender);
+                self.pending_queries
+                    .provider_capabilities
+                    .insert(query_id, capability);
             }
             Command::SubscribeTopic { topic, sender } => {
                 let _ = match self.swarm.behaviour_mut().subscribe_topic(&topic) {
@@ -849,6 +1128,7 @@ impl MainLoop {
                     .behaviour_mut()
                     .headers_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::Headers::NAME, request_id);
                 self.pending_sync_requests
                     .headers
                     .insert(request_id, sender);
@@ -865,6 +1145,7 @@ impl MainLoop {
                     .behaviour_mut()
                     .classes_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::Classes::NAME, request_id);
                 self.pending_sync_requests
                     .classes
                     .insert(request_id, sender);
@@ -881,6 +1162,7 @@ impl MainLoop {
                     .behaviour_mut()
                     .state_diffs_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::StateDiffs::NAME, request_id);
                 self.pending_sync_requests
                     .state_diffs
                     .insert(request_id, sender);
@@ -897,6 +1179,7 @@ impl MainLoop {
                     .behaviour_mut()
                     .transactions_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::Transactions::NAME, request_id);
                 self.pending_sync_requests
                     .transactions
                     .insert(request_id, sender);
@@ -913,6 +1196,7 @@ impl MainLoop {
                     .behaviour_mut()
                     .receipts_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::Receipts::NAME, request_id);
                 self.pending_sync_requests
                     .receipts
                     .insert(request_id, sender);
@@ -929,8 +1213,77 @@ impl MainLoop {
                     .behaviour_mut()
                     .events_sync_mut()
                     .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::Events::NAME, request_id);
                 self.pending_sync_requests.events.insert(request_id, sender);
             }
+            Command::SendContractRangeSyncRequest {
+                peer_id,
+                request,
+                sender,
+            } => {
+                tracing::debug!(?request, "Sending sync request");
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .contract_range_sync_mut()
+                    .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::ContractRange::NAME, request_id);
+                self.pending_sync_requests
+                    .contract_range
+                    .insert(request_id, sender);
+            }
+            Command::SendContractStorageSyncRequest {
+                peer_id,
+                request,
+                sender,
+            } => {
+                tracing::debug!(?request, "Sending sync request");
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .contract_storage_sync_mut()
+                    .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::ContractStorage::NAME, request_id);
+                self.pending_sync_requests
+                    .contract_storage
+                    .insert(request_id, sender);
+            }
+            Command::SendClassRangeSyncRequest {
+                peer_id,
+                request,
+                sender,
+            } => {
+                tracing::debug!(?request, "Sending sync request");
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .class_range_sync_mut()
+                    .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::ClassRange::NAME, request_id);
+                self.pending_sync_requests
+                    .class_range
+                    .insert(request_id, sender);
+            }
+            Command::SendClassesByHashSyncRequest {
+                peer_id,
+                request,
+                sender,
+            } => {
+                tracing::debug!(?request, "Sending sync request");
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .classes_by_hash_sync_mut()
+                    .send_request(&peer_id, request);
+                self.outbound_request_sent(protocol::ClassesByHash::NAME, request_id);
+                self.pending_sync_requests
+                    .classes_by_hash
+                    .insert(request_id, sender);
+            }
             Command::PublishPropagationMessage {
                 topic,
                 new_block,
@@ -945,10 +1298,81 @@ impl MainLoop {
                 self.swarm.behaviour_mut().not_useful(peer_id);
                 let _ = sender.send(());
             }
+            Command::Penalize {
+                peer_id,
+                misbehaviour,
+                sender,
+            } => {
+                self.swarm.behaviour_mut().penalize(peer_id, misbehaviour);
+                let _ = sender.send(());
+            }
+            Command::GetPeerScores { sender } => {
+                let _ = sender.send(self.swarm.behaviour_mut().peer_scores());
+            }
+            Command::GetPeers { sender } => {
+                let _ = sender.send(self.swarm.behaviour_mut().peer_infos());
+            }
             Command::_Test(command) => self.handle_test_command(command).await,
         };
     }
 
+    fn outbound_request_sent(&mut self, protocol: &'static str, request_id: OutboundRequestId) {
+        metrics::increment_counter!("p2p_requests_total", "protocol" => protocol, "direction" => "outbound");
+        self.outbound_request_times
+            .insert((protocol, request_id), Instant::now());
+    }
+
+    /// Updates the sync request metrics, which are recorded the same way for all protocols.
+    fn record_request_metrics(&mut self, event: &SwarmEvent<behaviour::Event>) {
+        let SwarmEvent::Behaviour(event) = event else {
+            return;
+        };
+        let (protocol, event) = match event {
+            behaviour::Event::HeadersSync(e) => (protocol::Headers::NAME, RequestEvent::new(e)),
+            behaviour::Event::ClassesSync(e) => (protocol::Classes::NAME, RequestEvent::new(e)),
+            behaviour::Event::StateDiffsSync(e) => {
+                (protocol::StateDiffs::NAME, RequestEvent::new(e))
+            }
+            behaviour::Event::TransactionsSync(e) => {
+                (protocol::Transactions::NAME, RequestEvent::new(e))
+            }
+            behaviour::Event::ReceiptsSync(e) => (protocol::Receipts::NAME, RequestEvent::new(e)),
+            behaviour::Event::EventsSync(e) => (protocol::Events::NAME, RequestEvent::new(e)),
+            behaviour::Event::ContractRangeSync(e) => {
+                (protocol::ContractRange::NAME, RequestEvent::new(e))
+            }
+            behaviour::Event::ContractStorageSync(e) => {
+                (protocol::ContractStorage::NAME, RequestEvent::new(e))
+            }
+            behaviour::Event::ClassRangeSync(e) => {
+                (protocol::ClassRange::NAME, RequestEvent::new(e))
+            }
+            behaviour::Event::ClassesByHashSync(e) => {
+                (protocol::ClassesByHash::NAME, RequestEvent::new(e))
+            }
+            _ => return,
+        };
+
+        match event {
+            RequestEvent::Inbound => {
+                metrics::increment_counter!("p2p_requests_total", "protocol" => protocol, "direction" => "inbound");
+            }
+            RequestEvent::InboundFailure(reason) => {
+                metrics::increment_counter!("p2p_request_errors_total", "protocol" => protocol, "direction" => "inbound", "reason" => reason);
+            }
+            RequestEvent::OutboundFailure(request_id, reason) => {
+                self.outbound_request_times.remove(&(protocol, request_id));
+                metrics::increment_counter!("p2p_request_errors_total", "protocol" => protocol, "direction" => "outbound", "reason" => reason);
+            }
+            RequestEvent::OutboundCompleted(request_id) => {
+                if let Some(sent) = self.outbound_request_times.remove(&(protocol, request_id)) {
+                    metrics::histogram!("p2p_request_duration_seconds", sent.elapsed().as_secs_f64(), "protocol" => protocol);
+                }
+            }
+            RequestEvent::Other => {}
+        }
+    }
+
     fn publish_data(&mut self, topic: IdentTopic, data: &[u8]) -> anyhow::Result<()> {
         let message_id = self
             .swarm
@@ -956,6 +1380,7 @@ impl MainLoop {
             .gossipsub_mut()
             .publish(topic, data)
             .map_err(|e| anyhow::anyhow!("Gossipsub publish failed: {}", e))?;
+        metrics::increment_counter!("p2p_gossip_messages_published_total");
         tracing::debug!(?message_id, "Data published");
         Ok(())
     }
@@ -1007,6 +1432,49 @@ impl MainLoop {
     }
 }
 
+/// The parts of a sync protocol event which are relevant to metrics, independent of the
+/// protocol's message types.
+enum RequestEvent {
+    Inbound,
+    InboundFailure(&'static str),
+    OutboundFailure(OutboundRequestId, &'static str),
+    /// All responses to an outbound request have been received.
+    OutboundCompleted(OutboundRequestId),
+    Other,
+}
+
+impl RequestEvent {
+    fn new<Req, Resp, ChannelResp>(event: &p2p_stream::Event<Req, Resp, ChannelResp>) -> Self {
+        use p2p_stream::{InboundFailure, OutboundFailure};
+
+        match event {
+            p2p_stream::Event::InboundRequest { .. } => Self::Inbound,
+            p2p_stream::Event::InboundFailure { error, .. } => Self::InboundFailure(match error {
+                InboundFailure::Timeout => "timeout",
+                InboundFailure::ConnectionClosed => "connection_closed",
+                InboundFailure::Io(_) => "io",
+            }),
+            p2p_stream::Event::OutboundFailure {
+                request_id, error, ..
+            } => Self::OutboundFailure(
+                *request_id,
+                match error {
+                    OutboundFailure::DialFailure => "dial_failure",
+                    OutboundFailure::Timeout => "timeout",
+                    OutboundFailure::ConnectionClosed => "connection_closed",
+                    OutboundFailure::UnsupportedProtocols => "unsupported_protocols",
+                    OutboundFailure::Io(_) => "io",
+                },
+            ),
+            p2p_stream::Event::InboundResponseStreamClosed { request_id, .. } => {
+                Self::OutboundCompleted(*request_id)
+            }
+            p2p_stream::Event::OutboundRequestSentAwaitingResponses { .. }
+            | p2p_stream::Event::OutboundResponseStreamClosed { .. } => Self::Other,
+        }
+    }
+}
+
 /// No-op outside tests
 async fn send_test_event(_event_sender: &mpsc::Sender<Event>, _event: TestEvent) {
     #[cfg(test)]
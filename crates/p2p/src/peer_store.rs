@@ -0,0 +1,440 @@
+//! Peers we have seen before, persisted together with their [reputation](Reputation) so that
+//! the node can reconnect to good peers after a restart instead of going through bootstrapping
+//! and DHT discovery again.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use ipnet::IpNet;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::reputation::{PeerScore, Reputation};
+
+/// Peers which have not been seen for this long are forgotten.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Upper bound on the number of peers kept, the least recently seen ones are forgotten first.
+const MAX_PEERS: usize = 1000;
+/// Upper bound on the number of addresses kept per peer, the oldest ones are forgotten first.
+const MAX_ADDRESSES: usize = 8;
+/// Capabilities which have not been confirmed for this long are forgotten, the peer may have
+/// stopped providing them. Provider records in the DHT are republished well within this period.
+const CAPABILITY_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KnownPeer {
+    /// Addresses the peer can be dialed on, most recently learned last.
+    addresses: Vec<Multiaddr>,
+    /// When each capability was last confirmed.
+    capabilities: BTreeMap<String, SystemTime>,
+    last_seen: SystemTime,
+}
+
+impl KnownPeer {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            addresses: Vec::new(),
+            capabilities: BTreeMap::new(),
+            last_seen: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct PeerStore {
+    peers: HashMap<PeerId, KnownPeer>,
+}
+
+impl PeerStore {
+    /// Records that we were connected to the peer at `now`.
+    pub fn seen(&mut self, peer_id: PeerId, now: SystemTime) {
+        self.peers
+            .entry(peer_id)
+            .or_insert_with(|| KnownPeer::new(now))
+            .last_seen = now;
+    }
+
+    pub fn add_addresses(
+        &mut self,
+        peer_id: PeerId,
+        addresses: impl IntoIterator<Item = Multiaddr>,
+        now: SystemTime,
+    ) {
+        let peer = self
+            .peers
+            .entry(peer_id)
+            .or_insert_with(|| KnownPeer::new(now));
+        for address in addresses {
+            peer.addresses.retain(|known| known != &address);
+            peer.addresses.push(address);
+        }
+        let excess = peer.addresses.len().saturating_sub(MAX_ADDRESSES);
+        peer.addresses.drain(..excess);
+    }
+
+    pub fn add_capability(&mut self, peer_id: PeerId, capability: &str, now: SystemTime) {
+        self.peers
+            .entry(peer_id)
+            .or_insert_with(|| KnownPeer::new(now))
+            .capabilities
+            .insert(capability.to_owned(), now);
+    }
+
+    /// Peers which were confirmed to provide the capability recently.
+    pub fn providers(&self, capability: &str, now: SystemTime) -> HashSet<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.capabilities
+                    .get(capability)
+                    .map_or(false, |confirmed| {
+                        !expired(*confirmed, CAPABILITY_MAX_AGE, now)
+                    })
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Up to `count` peers to dial on startup. Peers with better scores are preferred, followed
+    /// by the most recently seen ones. Banned peers and peers without known addresses are left
+    /// out.
+    pub fn reconnect_candidates(
+        &self,
+        scores: &HashMap<PeerId, PeerScore>,
+        count: usize,
+    ) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let mut candidates = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| !peer.addresses.is_empty())
+            .map(|(peer_id, peer)| {
+                (
+                    peer_id,
+                    peer,
+                    scores.get(peer_id).copied().unwrap_or_default(),
+                )
+            })
+            .filter(|(_, _, score)| !score.banned)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|(_, a, a_score), (_, b, b_score)| {
+            b_score
+                .score
+                .cmp(&a_score.score)
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        candidates
+            .into_iter()
+            .take(count)
+            .map(|(peer_id, peer, _)| (*peer_id, peer.addresses.clone()))
+            .collect()
+    }
+
+    /// Forgets peers which have not been seen for a long time, and the least recently seen
+    /// peers if there are too many. Capabilities which have not been confirmed for a long time
+    /// are forgotten as well.
+    pub fn prune(&mut self, now: SystemTime) {
+        self.peers
+            .retain(|_, peer| !expired(peer.last_seen, MAX_AGE, now));
+        for peer in self.peers.values_mut() {
+            peer.capabilities
+                .retain(|_, confirmed| !expired(*confirmed, CAPABILITY_MAX_AGE, now));
+        }
+
+        if self.peers.len() > MAX_PEERS {
+            let mut last_seen = self
+                .peers
+                .values()
+                .map(|peer| peer.last_seen)
+                .collect::<Vec<_>>();
+            last_seen.sort_unstable_by(|a, b| b.cmp(a));
+            let oldest_kept = last_seen[MAX_PEERS - 1];
+            self.peers.retain(|_, peer| peer.last_seen >= oldest_kept);
+        }
+    }
+}
+
+fn expired(time: SystemTime, max_age: Duration, now: SystemTime) -> bool {
+    now.duration_since(time).map_or(false, |age| age >= max_age)
+}
+
+/// Whether other peers could dial the address. Peers report all of their listen addresses,
+/// including loopback and private network ones which are useless to anyone outside of their
+/// host or network.
+///
+/// Such addresses are only routable if `ip_whitelist` names their range explicitly, i.e. with
+/// anything narrower than `0.0.0.0/0` or `::/0`, as is the case for private or local networks.
+pub(crate) fn is_routable(address: &Multiaddr, ip_whitelist: &[IpNet]) -> bool {
+    let ip = match address.iter().next() {
+        Some(Protocol::Ip4(ip)) => IpAddr::V4(ip),
+        Some(Protocol::Ip6(ip)) => IpAddr::V6(ip),
+        Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)) => {
+            return true
+        }
+        _ => return false,
+    };
+
+    let whitelisted = ip_whitelist
+        .iter()
+        .any(|net| net.prefix_len() > 0 && net.contains(&ip));
+
+    whitelisted
+        || match ip {
+            IpAddr::V4(ip) => is_routable_ipv4(ip),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => is_routable_ipv4(ip),
+                None => is_routable_ipv6(ip),
+            },
+        }
+}
+
+fn is_routable_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    // Shared address space used by carrier-grade NATs, 100.64.0.0/10.
+    let shared = a == 100 && (b & 0b1100_0000) == 64;
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || shared)
+}
+
+fn is_routable_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    // Unique local addresses, fc00::/7.
+    let unique_local = (first & 0xfe00) == 0xfc00;
+    // Link local addresses, fe80::/10.
+    let link_local = (first & 0xffc0) == 0xfe80;
+
+    !(ip.is_unspecified() || ip.is_loopback() || unique_local || link_local)
+}
+
+#[derive(Serialize)]
+struct PersistedRef<'a> {
+    peers: &'a PeerStore,
+    scores: &'a Reputation,
+}
+
+#[derive(Deserialize)]
+struct Persisted {
+    #[serde(default)]
+    peers: PeerStore,
+    #[serde(default)]
+    scores: Reputation,
+}
+
+/// Loads the peers and scores persisted by [save]. A missing file results in no known peers.
+pub(crate) fn load(path: &Path) -> anyhow::Result<(PeerStore, Reputation)> {
+    let json = match std::fs::read(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(e).context("Reading known peers"),
+    };
+    let persisted: Persisted = serde_json::from_slice(&json).context("Parsing known peers")?;
+    Ok((persisted.peers, persisted.scores))
+}
+
+pub(crate) fn save(path: &Path, peers: &PeerStore, scores: &Reputation) -> anyhow::Result<()> {
+    let json =
+        serde_json::to_vec(&PersistedRef { peers, scores }).context("Serializing known peers")?;
+    // Write to a temporary file first so that a crash does not leave a truncated file behind.
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json).context("Writing known peers")?;
+    std::fs::rename(&tmp, path).context("Replacing known peers")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reputation::Misbehaviour;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()
+    }
+
+    #[test]
+    fn addresses_are_bounded() {
+        let mut store = PeerStore::default();
+        let peer = PeerId::random();
+        let now = SystemTime::UNIX_EPOCH;
+
+        store.add_addresses(peer, (0..10).map(addr), now);
+        // Re-learning an address makes it the most recent one.
+        store.add_addresses(peer, [addr(2)], now);
+
+        let expected = (3..10).chain([2]).map(addr).collect::<Vec<_>>();
+        assert_eq!(store.peers[&peer].addresses, expected);
+    }
+
+    #[test]
+    fn reconnect_candidates() {
+        let mut store = PeerStore::default();
+        let now = SystemTime::UNIX_EPOCH + MAX_AGE;
+        let [old, recent, penalized, banned, no_address] = [(); 5].map(|_| PeerId::random());
+
+        store.add_addresses(old, [addr(1)], now - MINUTE);
+        store.add_addresses(recent, [addr(2)], now);
+        store.add_addresses(penalized, [addr(3)], now);
+        store.add_addresses(banned, [addr(4)], now);
+        store.seen(no_address, now);
+
+        let scores = HashMap::from([
+            (
+                penalized,
+                PeerScore {
+                    score: -10,
+                    banned: false,
+                },
+            ),
+            (
+                banned,
+                PeerScore {
+                    score: -50,
+                    banned: true,
+                },
+            ),
+        ]);
+
+        let candidates = store.reconnect_candidates(&scores, 10);
+        assert_eq!(
+            candidates,
+            vec![
+                (recent, vec![addr(2)]),
+                (old, vec![addr(1)]),
+                (penalized, vec![addr(3)]),
+            ]
+        );
+        assert_eq!(store.reconnect_candidates(&scores, 1).len(), 1);
+
+        store.prune(now + MAX_AGE - MINUTE / 2);
+        assert!(!store.peers.contains_key(&old));
+        assert!(store.peers.contains_key(&recent));
+    }
+
+    #[test]
+    fn capabilities_expire() {
+        let mut store = PeerStore::default();
+        let now = SystemTime::UNIX_EPOCH;
+        let [stale, confirmed] = [(); 2].map(|_| PeerId::random());
+
+        store.add_capability(stale, "core/headers-sync/1", now);
+        store.add_capability(confirmed, "core/headers-sync/1", now);
+        store.add_capability(
+            confirmed,
+            "core/headers-sync/1",
+            now + CAPABILITY_MAX_AGE / 2,
+        );
+
+        let later = now + CAPABILITY_MAX_AGE;
+        assert_eq!(
+            store.providers("core/headers-sync/1", later),
+            HashSet::from([confirmed])
+        );
+
+        store.prune(later);
+        assert!(store.peers[&stale].capabilities.is_empty());
+        assert!(!store.peers[&confirmed].capabilities.is_empty());
+    }
+
+    #[test]
+    fn routable_addresses() {
+        let routable = [
+            "/ip4/1.2.3.4/tcp/1",
+            "/ip6/2001:4860::1/tcp/1",
+            "/ip6/::ffff:1.2.3.4/tcp/1",
+            "/dns4/example.com/tcp/1",
+        ];
+        let unroutable = [
+            "/ip4/127.0.0.1/tcp/1",
+            "/ip4/0.0.0.0/tcp/1",
+            "/ip4/10.0.0.1/tcp/1",
+            "/ip4/172.16.0.1/tcp/1",
+            "/ip4/192.168.1.1/tcp/1",
+            "/ip4/169.254.0.1/tcp/1",
+            "/ip4/100.64.0.1/tcp/1",
+            "/ip6/::1/tcp/1",
+            "/ip6/fd00::1/tcp/1",
+            "/ip6/fe80::1/tcp/1",
+            "/ip6/::ffff:192.168.1.1/tcp/1",
+            "/memory/1",
+        ];
+
+        let whitelist = ["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()];
+        for address in routable {
+            assert!(
+                is_routable(&address.parse().unwrap(), &whitelist),
+                "{address}"
+            );
+        }
+        for address in unroutable {
+            assert!(
+                !is_routable(&address.parse().unwrap(), &whitelist),
+                "{address}"
+            );
+        }
+    }
+
+    #[test]
+    fn whitelisted_private_addresses_are_routable() {
+        let whitelist = [
+            "0.0.0.0/0".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+            "::1/128".parse().unwrap(),
+        ];
+
+        for address in ["/ip4/10.0.0.1/tcp/1", "/ip6/::1/tcp/1"] {
+            assert!(
+                is_routable(&address.parse().unwrap(), &whitelist),
+                "{address}"
+            );
+        }
+        for address in ["/ip4/192.168.1.1/tcp/1", "/ip4/127.0.0.1/tcp/1"] {
+            assert!(
+                !is_routable(&address.parse().unwrap(), &whitelist),
+                "{address}"
+            );
+        }
+    }
+
+    #[test]
+    fn persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.json");
+        let now = SystemTime::now();
+        let peer = PeerId::random();
+
+        let (store, mut reputation) = load(&path).unwrap();
+        assert!(store.peers.is_empty());
+        assert!(reputation.scores(now).is_empty());
+
+        let mut store = PeerStore::default();
+        store.add_addresses(peer, [addr(1)], now);
+        store.add_capability(peer, "core/headers-sync/1", now);
+        let mut reputation = Reputation::default();
+        reputation.penalize(peer, Misbehaviour::InvalidSignature, now);
+        save(&path, &store, &reputation).unwrap();
+
+        let (store, mut reputation) = load(&path).unwrap();
+        assert_eq!(
+            store.providers("core/headers-sync/1", now),
+            HashSet::from([peer])
+        );
+        assert_eq!(store.peers[&peer].addresses, vec![addr(1)]);
+        assert!(reputation.is_banned(peer, now));
+        assert_eq!(
+            reputation.scores(now)[&peer],
+            PeerScore {
+                score: -50,
+                banned: true
+            }
+        );
+    }
+}
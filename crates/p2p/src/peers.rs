@@ -7,6 +7,7 @@ use std::{
 use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use sha3::{Digest, Sha3_256};
 
+use crate::reputation::PeerScore;
 use crate::secret::Secret;
 
 #[derive(Debug, Clone)]
@@ -48,6 +49,8 @@ impl Peer {
             addr.iter().find_map(|p| match p {
                 Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
                 Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+                #[cfg(feature = "memory-transport")]
+                Protocol::Memory(_) => Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)),
                 _ => None,
             })
         })
@@ -64,6 +67,19 @@ impl Peer {
     }
 }
 
+/// What is known about a peer, as reported to the node's operator.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub peer_id: PeerId,
+    pub addr: Option<Multiaddr>,
+    pub connected: bool,
+    pub outbound: bool,
+    pub relayed: bool,
+    /// The smallest ping time to the peer, see [Peer::min_ping].
+    pub min_ping: Option<Duration>,
+    pub score: PeerScore,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Connectivity {
     Dialing,
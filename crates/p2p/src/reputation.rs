@@ -0,0 +1,222 @@
+//! Peer reputation, used to disconnect and ban peers which misbehave.
+//!
+//! Every peer starts with a score of zero and loses points for each [Misbehaviour]. Lost points
+//! are slowly regained over time. Once the score drops to [BAN_THRESHOLD] the peer is banned,
+//! and repeat offenders are banned for exponentially longer periods. The offence count is
+//! forgotten once the peer's score has fully recovered.
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// The lowest score a peer can have.
+const MIN_SCORE: i32 = -100;
+/// Peers whose score drops to this value are banned.
+const BAN_THRESHOLD: i32 = -50;
+/// How long it takes for a peer to regain a single point.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a peer is banned for on its first offence. The ban duration doubles with
+/// every consecutive offence.
+const BAN_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_BAN_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Misbehaviour which lowers a peer's score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// The peer served a block header with an invalid signature.
+    InvalidSignature,
+    /// The peer served data which does not match the commitments or hashes it was
+    /// verified against.
+    CommitmentMismatch,
+    /// The peer sent a message which could not be parsed.
+    MalformedMessage,
+    /// The peer announced a block which is invalid, or which no one could serve.
+    InvalidAnnouncement,
+    /// The peer did not respond, or its response was incomplete.
+    Timeout,
+}
+
+impl Misbehaviour {
+    fn penalty(self) -> i32 {
+        match self {
+            Misbehaviour::InvalidSignature => 50,
+            Misbehaviour::CommitmentMismatch => 30,
+            Misbehaviour::MalformedMessage => 20,
+            Misbehaviour::InvalidAnnouncement => 20,
+            Misbehaviour::Timeout => 5,
+        }
+    }
+
+    /// Label used in metrics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Misbehaviour::InvalidSignature => "invalid_signature",
+            Misbehaviour::CommitmentMismatch => "commitment_mismatch",
+            Misbehaviour::MalformedMessage => "malformed_message",
+            Misbehaviour::InvalidAnnouncement => "invalid_announcement",
+            Misbehaviour::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerScore {
+    pub score: i32,
+    pub banned: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Record {
+    score: i32,
+    /// When the score was last brought up to date.
+    updated_at: SystemTime,
+    /// Number of consecutive bans.
+    bans: u32,
+    banned_until: Option<SystemTime>,
+}
+
+impl Record {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            score: 0,
+            updated_at: now,
+            bans: 0,
+            banned_until: None,
+        }
+    }
+
+    /// Applies the recovery since the last update.
+    fn refresh(&mut self, now: SystemTime) {
+        let elapsed = now.duration_since(self.updated_at).unwrap_or_default();
+        let recovered = (elapsed.as_secs() / RECOVERY_INTERVAL.as_secs()).min(i32::MAX as u64);
+        self.score = self.score.saturating_add(recovered as i32).min(0);
+        self.updated_at += RECOVERY_INTERVAL * recovered as u32;
+
+        if self.banned_until.map_or(false, |until| until <= now) {
+            self.banned_until = None;
+        }
+        if self.score == 0 && self.banned_until.is_none() {
+            self.bans = 0;
+        }
+    }
+
+    fn is_banned(&self, now: SystemTime) -> bool {
+        self.banned_until.map_or(false, |until| until > now)
+    }
+
+    /// A record which is no different from that of an unknown peer.
+    fn is_clean(&self) -> bool {
+        self.score == 0 && self.bans == 0 && self.banned_until.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Reputation {
+    records: HashMap<PeerId, Record>,
+}
+
+impl Reputation {
+    /// Lowers the score of a peer. Returns `true` if the peer got banned as a result.
+    pub fn penalize(
+        &mut self,
+        peer_id: PeerId,
+        misbehaviour: Misbehaviour,
+        now: SystemTime,
+    ) -> bool {
+        let record = self
+            .records
+            .entry(peer_id)
+            .or_insert_with(|| Record::new(now));
+        record.refresh(now);
+        record.score = (record.score - misbehaviour.penalty()).max(MIN_SCORE);
+
+        if record.score > BAN_THRESHOLD || record.is_banned(now) {
+            return false;
+        }
+
+        let duration = BAN_DURATION
+            .saturating_mul(1 << record.bans.min(16))
+            .min(MAX_BAN_DURATION);
+        record.bans += 1;
+        record.banned_until = Some(now + duration);
+        true
+    }
+
+    pub fn is_banned(&self, peer_id: PeerId, now: SystemTime) -> bool {
+        self.records
+            .get(&peer_id)
+            .map_or(false, |record| record.is_banned(now))
+    }
+
+    /// The current scores of all peers which have misbehaved recently.
+    pub fn scores(&mut self, now: SystemTime) -> HashMap<PeerId, PeerScore> {
+        self.records
+            .values_mut()
+            .for_each(|record| record.refresh(now));
+        // Forget peers which have fully recovered, they are no different from unknown peers.
+        self.records.retain(|_, record| !record.is_clean());
+        self.records
+            .iter()
+            .map(|(peer_id, record)| {
+                (
+                    *peer_id,
+                    PeerScore {
+                        score: record.score,
+                        banned: record.is_banned(now),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn score_recovers() {
+        let mut reputation = Reputation::default();
+        let peer = PeerId::random();
+        let now = SystemTime::UNIX_EPOCH;
+
+        assert!(!reputation.penalize(peer, Misbehaviour::MalformedMessage, now));
+        assert_eq!(reputation.scores(now)[&peer].score, -20);
+
+        // Partial intervals are not lost.
+        let later = now + MINUTE * 5 + MINUTE / 2;
+        assert_eq!(reputation.scores(later)[&peer].score, -15);
+        let later = later + MINUTE / 2;
+        assert_eq!(reputation.scores(later)[&peer].score, -14);
+
+        // Fully recovered peers are forgotten.
+        assert!(!reputation.scores(now + MINUTE * 20).contains_key(&peer));
+    }
+
+    #[test]
+    fn ban_duration_grows_with_repeat_offences() {
+        let mut reputation = Reputation::default();
+        let peer = PeerId::random();
+        let now = SystemTime::UNIX_EPOCH;
+
+        assert!(reputation.penalize(peer, Misbehaviour::InvalidSignature, now));
+        assert!(reputation.is_banned(peer, now + BAN_DURATION - MINUTE));
+        assert!(!reputation.is_banned(peer, now + BAN_DURATION));
+
+        // The score has not recovered yet, so the next offence results in a longer ban.
+        let now = now + BAN_DURATION;
+        assert!(reputation.penalize(peer, Misbehaviour::CommitmentMismatch, now));
+        assert!(reputation.is_banned(peer, now + BAN_DURATION * 2 - MINUTE));
+        assert!(!reputation.is_banned(peer, now + BAN_DURATION * 2));
+
+        // Once the score has fully recovered the offences are forgotten.
+        let now = now + MINUTE * 100;
+        assert!(reputation.scores(now).is_empty());
+        assert!(reputation.penalize(peer, Misbehaviour::InvalidSignature, now));
+        assert!(!reputation.is_banned(peer, now + BAN_DURATION));
+    }
+}
@@ -1,46 +1,117 @@
 //! request/streaming-response protocol and codec definitions for sync
 
 pub mod protocol {
+    use p2p_proto::version::Version;
+
+    /// A sync protocol which is served under one name per supported [Version].
+    pub trait SyncProtocol: AsRef<str> + Copy + Send + 'static {
+        /// The name of the protocol without the version.
+        const NAME: &'static str;
+        /// The versioned names of the protocol, in the order of [VERSIONS](Self::VERSIONS).
+        const NAMES: &'static [&'static str];
+        /// The supported versions, most preferred first. Outbound streams negotiate them in this
+        /// order, and inbound streams are accepted for any of them.
+        const VERSIONS: &'static [Self];
+
+        fn version(&self) -> Version;
+    }
+
     macro_rules! define_protocol {
-        ($type_name:ident, $name:literal) => {
-            #[derive(Debug, Clone, Default)]
-            pub struct $type_name;
+        ($type_name:ident, $name:literal, [$($version:ident = $number:literal),+]) => {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum $type_name {
+                $($version),+
+            }
 
-            impl $type_name {
-                pub const NAME: &'static str = $name;
+            impl SyncProtocol for $type_name {
+                const NAME: &'static str = $name;
+                const NAMES: &'static [&'static str] = &[$(concat!($name, "/", $number)),+];
+                const VERSIONS: &'static [Self] = &[$(Self::$version),+];
+
+                fn version(&self) -> Version {
+                    match self {
+                        $(Self::$version => Version::$version),+
+                    }
+                }
             }
 
             impl AsRef<str> for $type_name {
                 fn as_ref(&self) -> &str {
-                    Self::NAME
+                    match self {
+                        $(Self::$version => concat!($name, "/", $number)),+
+                    }
                 }
             }
         };
     }
 
-    define_protocol!(Headers, "/starknet/headers/1");
-    define_protocol!(StateDiffs, "/starknet/state_diffs/1");
-    define_protocol!(Classes, "/starknet/classes/1");
-    define_protocol!(Transactions, "/starknet/transactions/1");
-    define_protocol!(Receipts, "/starknet/receipts/1");
-    define_protocol!(Events, "/starknet/events/1");
-
-    pub const PROTOCOLS: &[&str] = &[
-        Headers::NAME,
-        StateDiffs::NAME,
-        Classes::NAME,
-        Transactions::NAME,
-        Receipts::NAME,
-        Events::NAME,
+    define_protocol!(Headers, "/starknet/headers", [V1 = "1"]);
+    define_protocol!(StateDiffs, "/starknet/state_diffs", [V1 = "1"]);
+    define_protocol!(Classes, "/starknet/classes", [V1 = "1"]);
+    define_protocol!(Transactions, "/starknet/transactions", [V1 = "1"]);
+    define_protocol!(Receipts, "/starknet/receipts", [V1 = "1"]);
+    define_protocol!(Events, "/starknet/events", [V1 = "1"]);
+    define_protocol!(ContractRange, "/starknet/contract_range", [V1 = "1"]);
+    define_protocol!(ContractStorage, "/starknet/contract_storage", [V1 = "1"]);
+    define_protocol!(ClassRange, "/starknet/class_range", [V1 = "1"]);
+    define_protocol!(ClassesByHash, "/starknet/classes_by_hash", [V1 = "1"]);
+    /// Only exists to test version negotiation, see [TestRange](p2p_proto::version::TestRange).
+    #[cfg(test)]
+    define_protocol!(Test, "/starknet/test", [V2 = "2", V1 = "1"]);
+
+    /// The versioned names of every sync protocol.
+    pub const PROTOCOLS: &[&[&str]] = &[
+        Headers::NAMES,
+        StateDiffs::NAMES,
+        Classes::NAMES,
+        Transactions::NAMES,
+        Receipts::NAMES,
+        Events::NAMES,
+        ContractRange::NAMES,
+        ContractStorage::NAMES,
+        ClassRange::NAMES,
+        ClassesByHash::NAMES,
     ];
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Checks that the versioned names are the family name suffixed with the version.
+        fn check<P: SyncProtocol>() {
+            let expected = P::VERSIONS
+                .iter()
+                .map(|p| format!("{}/{}", P::NAME, p.version()))
+                .collect::<Vec<_>>();
+            let actual: Vec<&str> = P::VERSIONS.iter().map(|p| p.as_ref()).collect();
+            assert_eq!(actual, expected);
+            assert_eq!(P::NAMES, expected);
+        }
+
+        #[test]
+        fn names_match_versions() {
+            check::<Headers>();
+            check::<StateDiffs>();
+            check::<Classes>();
+            check::<Transactions>();
+            check::<Receipts>();
+            check::<Events>();
+            check::<ContractRange>();
+            check::<ContractStorage>();
+            check::<ClassRange>();
+            check::<ClassesByHash>();
+        }
+    }
 }
 
 pub(crate) mod codec {
-    use super::protocol;
+    use super::protocol::{self, SyncProtocol};
+    use crate::ResponseMeter;
     use async_trait::async_trait;
     use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-    use p2p_proto::{class, event, header, proto, receipt, state, transaction};
-    use p2p_proto::{ToProtobuf, TryFromProtobuf};
+    use libp2p::PeerId;
+    use p2p_proto::version::Versioned;
+    use p2p_proto::{class, event, header, receipt, snapshot, state, transaction};
     use p2p_stream::Codec;
     use std::marker::PhantomData;
 
@@ -51,8 +122,6 @@ pub(crate) mod codec {
         protocol::Headers,
         header::BlockHeadersRequest,
         header::BlockHeadersResponse,
-        proto::header::BlockHeadersRequest,
-        proto::header::BlockHeadersResponse,
         ONE_MIB,
     >;
 
@@ -60,67 +129,95 @@ pub(crate) mod codec {
         protocol::StateDiffs,
         state::StateDiffsRequest,
         state::StateDiffsResponse,
-        proto::state::StateDiffsRequest,
-        proto::state::StateDiffsResponse,
         ONE_MIB,
     >;
 
-    pub type Classes = SyncCodec<
-        protocol::Classes,
-        class::ClassesRequest,
-        class::ClassesResponse,
-        proto::class::ClassesRequest,
-        proto::class::ClassesResponse,
-        FOUR_MIB,
-    >;
+    pub type Classes =
+        SyncCodec<protocol::Classes, class::ClassesRequest, class::ClassesResponse, FOUR_MIB>;
 
     pub type Transactions = SyncCodec<
         protocol::Transactions,
         transaction::TransactionsRequest,
         transaction::TransactionsResponse,
-        proto::transaction::TransactionsRequest,
-        proto::transaction::TransactionsResponse,
         ONE_MIB,
     >;
 
-    pub type Receipts = SyncCodec<
-        protocol::Receipts,
-        receipt::ReceiptsRequest,
-        receipt::ReceiptsResponse,
-        proto::receipt::ReceiptsRequest,
-        proto::receipt::ReceiptsResponse,
+    pub type Receipts =
+        SyncCodec<protocol::Receipts, receipt::ReceiptsRequest, receipt::ReceiptsResponse, ONE_MIB>;
+
+    pub type Events =
+        SyncCodec<protocol::Events, event::EventsRequest, event::EventsResponse, ONE_MIB>;
+
+    pub type ContractRange = SyncCodec<
+        protocol::ContractRange,
+        snapshot::ContractRangeRequest,
+        snapshot::ContractRangeResponse,
+        ONE_MIB,
+    >;
+
+    pub type ContractStorage = SyncCodec<
+        protocol::ContractStorage,
+        snapshot::ContractStorageRequest,
+        snapshot::ContractStorageResponse,
         ONE_MIB,
     >;
 
-    pub type Events = SyncCodec<
-        protocol::Events,
-        event::EventsRequest,
-        event::EventsResponse,
-        proto::event::EventsRequest,
-        proto::event::EventsResponse,
+    pub type ClassRange = SyncCodec<
+        protocol::ClassRange,
+        snapshot::ClassRangeRequest,
+        snapshot::ClassRangeResponse,
+        FOUR_MIB,
+    >;
+
+    pub type ClassesByHash = SyncCodec<
+        protocol::ClassesByHash,
+        snapshot::ClassesByHashRequest,
+        snapshot::ClassesByHashResponse,
+        FOUR_MIB,
+    >;
+
+    #[cfg(test)]
+    pub type Test = SyncCodec<
+        protocol::Test,
+        p2p_proto::version::TestRange,
+        p2p_proto::version::TestRange,
         ONE_MIB,
     >;
 
+    /// Encodes the messages according to the version of the negotiated protocol.
+    ///
+    /// The size of each response sent to a peer is reported to the [ResponseMeter], if any.
     #[derive(Clone, Debug)]
-    pub struct SyncCodec<Protocol, Req, Resp, ProstReq, ProstResp, const RESPONSE_SIZE_LIMIT: usize>(
-        PhantomData<(Protocol, Req, Resp, ProstReq, ProstResp)>,
-    );
+    pub struct SyncCodec<Protocol, Req, Resp, const RESPONSE_SIZE_LIMIT: usize> {
+        meter: Option<ResponseMeter>,
+        /// The peer of the connection, see [Codec::for_peer].
+        peer: Option<PeerId>,
+        _marker: PhantomData<(Protocol, Req, Resp)>,
+    }
+
+    impl<A, B, C, const D: usize> SyncCodec<A, B, C, D> {
+        pub fn new(meter: Option<ResponseMeter>) -> Self {
+            Self {
+                meter,
+                peer: None,
+                _marker: Default::default(),
+            }
+        }
+    }
 
-    impl<A, B, C, D, E, const F: usize> Default for SyncCodec<A, B, C, D, E, F> {
+    impl<A, B, C, const D: usize> Default for SyncCodec<A, B, C, D> {
         fn default() -> Self {
-            Self(Default::default())
+            Self::new(None)
         }
     }
 
     #[async_trait]
-    impl<Protocol, Req, Resp, ProstReq, ProstResp, const RESPONSE_SIZE_LIMIT: usize> Codec
-        for SyncCodec<Protocol, Req, Resp, ProstReq, ProstResp, RESPONSE_SIZE_LIMIT>
+    impl<Protocol, Req, Resp, const RESPONSE_SIZE_LIMIT: usize> Codec
+        for SyncCodec<Protocol, Req, Resp, RESPONSE_SIZE_LIMIT>
     where
-        Protocol: AsRef<str> + Send + Clone,
-        Req: TryFromProtobuf<ProstReq> + ToProtobuf<ProstReq> + Send,
-        Resp: TryFromProtobuf<ProstResp> + ToProtobuf<ProstResp> + Send,
-        ProstReq: prost::Message + Default,
-        ProstResp: prost::Message + Default,
+        Protocol: SyncProtocol,
+        Req: Versioned + Send,
+        Resp: Versioned + Send,
     {
         type Protocol = Protocol;
         type Request = Req;
@@ -128,7 +225,7 @@ pub(crate) mod codec {
 
         async fn read_request<T>(
             &mut self,
-            _: &Self::Protocol,
+            protocol: &Self::Protocol,
             io: &mut T,
         ) -> std::io::Result<Self::Request>
         where
@@ -137,16 +234,14 @@ pub(crate) mod codec {
             let mut buf = Vec::new();
 
             io.take(ONE_MIB as u64).read_to_end(&mut buf).await?;
+            count_bytes(protocol, "received", buf.len());
 
-            let prost_dto = ProstReq::decode(buf.as_ref())?;
-            let dto = Req::try_from_protobuf(prost_dto, std::any::type_name::<ProstReq>())?;
-
-            Ok(dto)
+            Req::decode(protocol.version(), &buf)
         }
 
         async fn read_response<T>(
             &mut self,
-            _: &Self::Protocol,
+            protocol: &Self::Protocol,
             mut io: &mut T,
         ) -> std::io::Result<Self::Response>
         where
@@ -168,39 +263,194 @@ pub(crate) mod codec {
 
             let mut buf = vec![0u8; encoded_len];
             io.read_exact(&mut buf).await?;
+            count_bytes(protocol, "received", encoded_len);
 
-            let prost_dto = ProstResp::decode(buf.as_ref())?;
-            let dto = Resp::try_from_protobuf(prost_dto, std::any::type_name::<ProstResp>())?;
-
-            Ok(dto)
+            Resp::decode(protocol.version(), &buf)
         }
 
         async fn write_request<T>(
             &mut self,
-            _: &Self::Protocol,
+            protocol: &Self::Protocol,
             io: &mut T,
             request: Self::Request,
         ) -> std::io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let data = request.to_protobuf().encode_to_vec();
+            let data = request.encode(protocol.version());
             io.write_all(&data).await?;
+            count_bytes(protocol, "sent", data.len());
             Ok(())
         }
 
         async fn write_response<T>(
             &mut self,
-            _: &Self::Protocol,
+            protocol: &Self::Protocol,
             io: &mut T,
             response: Self::Response,
         ) -> std::io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let data = response.to_protobuf().encode_length_delimited_to_vec();
+            let message = response.encode(protocol.version());
+            let mut len_buf = unsigned_varint::encode::usize_buffer();
+            let len = unsigned_varint::encode::usize(message.len(), &mut len_buf);
+
+            let data = [len, &message].concat();
             io.write_all(&data).await?;
+            count_bytes(protocol, "sent", data.len());
+            if let (Some(meter), Some(peer)) = (&self.meter, self.peer) {
+                meter.charge(peer, data.len());
+            }
             Ok(())
         }
+
+        fn for_peer(&self, peer: PeerId) -> Self {
+            Self {
+                meter: self.meter.clone(),
+                peer: Some(peer),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    fn count_bytes<P: SyncProtocol>(protocol: &P, direction: &'static str, bytes: usize) {
+        metrics::counter!(
+            "p2p_sync_bytes_total",
+            bytes as u64,
+            "protocol" => P::NAME,
+            "version" => protocol.version().as_str(),
+            "direction" => direction
+        );
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use futures::{SinkExt, StreamExt};
+        use libp2p::core::transport::MemoryTransport;
+        use libp2p::core::upgrade;
+        use libp2p::identity::Keypair;
+        use libp2p::multiaddr::{Multiaddr, Protocol};
+        use libp2p::swarm::{Swarm, SwarmEvent};
+        use libp2p::{noise, yamux, Transport};
+        use p2p_proto::version::TestRange;
+
+        use super::*;
+        use crate::sync::protocol::Test::{V1, V2};
+
+        const RANGE: TestRange = TestRange {
+            start: 5,
+            stop: 7,
+            step: Some(2),
+        };
+
+        fn swarm(versions: &[protocol::Test]) -> Swarm<p2p_stream::Behaviour<Test>> {
+            let keypair = Keypair::generate_ed25519();
+            let transport = MemoryTransport::default()
+                .upgrade(upgrade::Version::V1)
+                .authenticate(noise::Config::new(&keypair).unwrap())
+                .multiplex(yamux::Config::default())
+                .boxed();
+            let behaviour = p2p_stream::Behaviour::with_codec(
+                Test::default(),
+                versions.iter().copied(),
+                p2p_stream::Config::default(),
+            );
+
+            Swarm::new(
+                transport,
+                behaviour,
+                keypair.public().to_peer_id(),
+                libp2p::swarm::Config::with_tokio_executor()
+                    .with_idle_connection_timeout(Duration::from_secs(10)),
+            )
+        }
+
+        /// Sends `request` from a node supporting the `requester` versions to one supporting the
+        /// `responder` versions, which echoes it back. Returns the request as received by the
+        /// responder and its response as received by the requester.
+        async fn echo(
+            requester: &[protocol::Test],
+            responder: &[protocol::Test],
+            request: TestRange,
+        ) -> (TestRange, TestRange) {
+            let mut requester = swarm(requester);
+            let mut responder = swarm(responder);
+            let responder_id = *responder.local_peer_id();
+
+            let addr = Multiaddr::empty().with(Protocol::Memory(rand::random()));
+            responder.listen_on(addr.clone()).unwrap();
+
+            let (received_tx, mut received_rx) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                loop {
+                    if let SwarmEvent::Behaviour(p2p_stream::Event::InboundRequest {
+                        request,
+                        mut channel,
+                        ..
+                    }) = responder.select_next_some().await
+                    {
+                        received_tx.send(request).unwrap();
+                        channel.send(request).await.unwrap();
+                    }
+                }
+            });
+
+            requester
+                .dial(addr.with(Protocol::P2p(responder_id)))
+                .unwrap();
+            let mut responses = loop {
+                match requester.select_next_some().await {
+                    SwarmEvent::ConnectionEstablished { .. } => {
+                        requester
+                            .behaviour_mut()
+                            .send_request(&responder_id, request);
+                    }
+                    SwarmEvent::Behaviour(
+                        p2p_stream::Event::OutboundRequestSentAwaitingResponses { channel, .. },
+                    ) => break channel,
+                    SwarmEvent::Behaviour(p2p_stream::Event::OutboundFailure { error, .. }) => {
+                        panic!("Request failed: {error}")
+                    }
+                    _ => {}
+                }
+            };
+            tokio::spawn(async move {
+                loop {
+                    requester.select_next_some().await;
+                }
+            });
+
+            let response = responses.next().await.expect("Response");
+            let request = received_rx.recv().await.expect("Request");
+            (request, response)
+        }
+
+        #[tokio::test]
+        async fn newest_version_is_preferred() {
+            let (request, response) = echo(&[V2, V1], &[V2, V1], RANGE).await;
+
+            // Only V2 carries the step.
+            assert_eq!(request, RANGE);
+            assert_eq!(response, RANGE);
+        }
+
+        #[tokio::test]
+        async fn older_version_is_negotiated_with_older_peers() {
+            let expected = TestRange {
+                step: None,
+                ..RANGE
+            };
+
+            let (request, response) = echo(&[V2, V1], &[V1], RANGE).await;
+            assert_eq!(request, expected);
+            assert_eq!(response, expected);
+
+            let (request, response) = echo(&[V1], &[V2, V1], RANGE).await;
+            assert_eq!(request, expected);
+            assert_eq!(response, expected);
+        }
     }
 }
@@ -13,6 +13,10 @@ use p2p_proto::class::{ClassesRequest, ClassesResponse};
 use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, NewBlock};
 use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRangeRequest, ContractRangeResponse, ContractStorageRequest, ContractStorageResponse,
+};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
 use pathfinder_common::ChainId;
@@ -20,7 +24,9 @@ use rstest::rstest;
 use tokio::task::JoinHandle;
 
 use crate::peers::Peer;
-use crate::{BootstrapConfig, Config, Event, EventReceiver, RateLimit, TestEvent};
+use crate::{
+    BootstrapConfig, Config, Event, EventReceiver, Misbehaviour, PeerScore, RateLimit, TestEvent,
+};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -102,6 +108,9 @@ impl Default for TestPeer {
                 ip_whitelist: vec!["::/0".parse().unwrap(), "0.0.0.0/0".parse().unwrap()],
                 bootstrap: Default::default(),
                 eviction_timeout: Duration::from_secs(15 * 60),
+                peers_file: None,
+                max_concurrent_inbound_streams: 100,
+                response_meter: None,
                 inbound_connections_rate_limit: RateLimit {
                     max: 1000,
                     interval: Duration::from_secs(1),
@@ -206,6 +215,31 @@ async fn dial() {
     assert_eq!(peers_of2, vec![peer1.peer_id]);
 }
 
+#[test_log::test(tokio::test)]
+async fn dial_over_quic() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut peer1 = TestPeer::default();
+    let mut peer2 = TestPeer::default();
+
+    let addr2 = peer2
+        .start_listening_on(Multiaddr::from_str("/ip4/127.0.0.1/udp/0/quic-v1").unwrap())
+        .await
+        .unwrap();
+    tracing::info!(%peer2.peer_id, %addr2);
+
+    peer1.client.dial(peer2.peer_id, addr2).await.unwrap();
+
+    exhaust_events(&mut peer1.event_receiver).await;
+
+    let peers_of1 = peer1.connected().await;
+    let peers_of2: Vec<_> = peer2.connected().await.into_keys().collect();
+
+    assert_eq!(peers_of1.len(), 1);
+    let addr = peers_of1[&peer2.peer_id].addr.as_ref().unwrap();
+    assert!(addr.iter().any(|p| p == Protocol::QuicV1));
+    assert_eq!(peers_of2, vec![peer1.peer_id]);
+}
+
 #[test_log::test(tokio::test)]
 async fn disconnect() {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -262,6 +296,9 @@ async fn periodic_bootstrap() {
             start_offset: Duration::from_secs(1),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -408,6 +445,9 @@ async fn reconnect_too_quickly() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -510,6 +550,9 @@ async fn duplicate_connection() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -597,6 +640,9 @@ async fn max_inbound_connections() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -721,6 +767,9 @@ async fn outbound_peer_eviction() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -850,6 +899,9 @@ async fn inbound_peer_eviction() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -935,6 +987,9 @@ async fn evicted_peer_reconnection() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -1009,6 +1064,94 @@ async fn evicted_peer_reconnection() {
     .await;
 }
 
+/// Test that peers get banned once their score drops too low.
+#[test_log::test(tokio::test)]
+async fn banned_peer() {
+    let cfg = Config {
+        direct_connection_timeout: Duration::from_secs(0),
+        relay_connection_timeout: Duration::from_secs(0),
+        ip_whitelist: vec!["::1/0".parse().unwrap(), "0.0.0.0/0".parse().unwrap()],
+        max_inbound_direct_peers: 10,
+        max_inbound_relayed_peers: 10,
+        max_outbound_peers: 10,
+        // Don't open connections automatically.
+        low_watermark: 0,
+        bootstrap: BootstrapConfig {
+            period: Duration::from_millis(500),
+            start_offset: Duration::from_secs(10),
+        },
+        eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
+        inbound_connections_rate_limit: RateLimit {
+            max: 1000,
+            interval: Duration::from_secs(1),
+        },
+    };
+
+    let mut peer1 = TestPeer::new(cfg.clone(), Keypair::generate_ed25519());
+    let mut peer2 = TestPeer::new(cfg, Keypair::generate_ed25519());
+
+    let addr1 = peer1.start_listening().await.unwrap();
+    tracing::info!(%peer1.peer_id, %addr1);
+    let addr2 = peer2.start_listening().await.unwrap();
+    tracing::info!(%peer2.peer_id, %addr2);
+
+    peer1
+        .client
+        .dial(peer2.peer_id, addr2.clone())
+        .await
+        .unwrap();
+
+    // A single timeout is not enough to get banned.
+    peer1
+        .client
+        .penalize(peer2.peer_id, Misbehaviour::Timeout)
+        .await;
+    assert!(peer1.connected().await.contains_key(&peer2.peer_id));
+
+    peer1
+        .client
+        .penalize(peer2.peer_id, Misbehaviour::InvalidSignature)
+        .await;
+
+    // Check that peer2 got disconnected.
+    wait_for_event(&mut peer1.event_receiver, |event| match event {
+        Event::Test(TestEvent::ConnectionClosed { remote, .. }) if remote == peer2.peer_id => {
+            Some(())
+        }
+        _ => None,
+    })
+    .await;
+
+    let scores = peer1.client.peer_scores().await;
+    assert_eq!(
+        scores[&peer2.peer_id],
+        PeerScore {
+            score: -55,
+            banned: true
+        }
+    );
+
+    // peer2 cannot be reconnected.
+    let result = peer1.client.dial(peer2.peer_id, addr2).await;
+    assert!(result.is_err());
+
+    exhaust_events(&mut peer2.event_receiver).await;
+
+    // peer2 cannot connect either. There is no peer ID when connecting, so the connection gets
+    // closed after being established.
+    peer2.client.dial(peer1.peer_id, addr1).await.unwrap();
+    wait_for_event(&mut peer2.event_receiver, |event| match event {
+        Event::Test(TestEvent::ConnectionClosed { remote, .. }) if remote == peer1.peer_id => {
+            Some(())
+        }
+        _ => None,
+    })
+    .await;
+}
+
 /// Test that peers can only connect if they are whitelisted.
 #[test_log::test(tokio::test)]
 async fn ip_whitelist() {
@@ -1026,6 +1169,9 @@ async fn ip_whitelist() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -1059,6 +1205,9 @@ async fn ip_whitelist() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 1000,
             interval: Duration::from_secs(1),
@@ -1093,6 +1242,9 @@ async fn rate_limit() {
             start_offset: Duration::from_secs(10),
         },
         eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
         inbound_connections_rate_limit: RateLimit {
             max: 2,
             interval: RATE_LIMIT_INTERVAL,
@@ -1314,3 +1466,35 @@ define_test!(
     InboundEventsSyncRequest,
     send_events_sync_request
 );
+
+define_test!(
+    sync_contract_range,
+    ContractRangeRequest,
+    ContractRangeResponse,
+    InboundContractRangeSyncRequest,
+    send_contract_range_sync_request
+);
+
+define_test!(
+    sync_contract_storage,
+    ContractStorageRequest,
+    ContractStorageResponse,
+    InboundContractStorageSyncRequest,
+    send_contract_storage_sync_request
+);
+
+define_test!(
+    sync_class_range,
+    ClassRangeRequest,
+    ClassRangeResponse,
+    InboundClassRangeSyncRequest,
+    send_class_range_sync_request
+);
+
+define_test!(
+    sync_classes_by_hash,
+    ClassesByHashRequest,
+    ClassesByHashResponse,
+    InboundClassesByHashSyncRequest,
+    send_classes_by_hash_sync_request
+);
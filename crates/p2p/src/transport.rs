@@ -1,26 +1,43 @@
+use futures::future::Either;
 use libp2p::core::muxing::StreamMuxerBox;
 use libp2p::core::transport::OrTransport;
 use libp2p::core::{upgrade, Transport};
-use libp2p::noise;
-use libp2p::{dns, PeerId};
+use libp2p::{dns, noise, quic, PeerId};
 
 /// Creates a libp2p protocol pathfinder uses.
 ///
-/// TCP with Noise and Yamux on top.
+/// TCP with Noise and Yamux on top, and QUIC, which comes with its own encryption and
+/// multiplexing. The transport used for a connection is picked based on the multiaddress, ie.
+/// `/tcp/<port>` or `/udp/<port>/quic-v1`.
+///
+/// With the `memory-transport` feature, in-process connections over `/memory/<port>` are supported
+/// as well, so that whole networks of nodes can be simulated in tests.
 pub fn create(
     keypair: &libp2p::identity::Keypair,
     relay_transport: libp2p::relay::client::Transport,
 ) -> libp2p::core::transport::Boxed<(PeerId, StreamMuxerBox)> {
-    let transport = libp2p::tcp::tokio::Transport::new(libp2p::tcp::Config::new());
-    let transport = OrTransport::new(transport, relay_transport);
-    let transport = dns::tokio::Transport::system(transport).unwrap();
+    let tcp_transport = libp2p::tcp::tokio::Transport::new(libp2p::tcp::Config::new());
+    let tcp_transport = OrTransport::new(tcp_transport, relay_transport);
+    #[cfg(feature = "memory-transport")]
+    let tcp_transport = OrTransport::new(
+        libp2p::core::transport::MemoryTransport::default(),
+        tcp_transport,
+    );
 
     let noise_config =
         noise::Config::new(keypair).expect("Signing libp2p-noise static DH keypair failed.");
 
-    transport
+    let tcp_transport = tcp_transport
         .upgrade(upgrade::Version::V1)
         .authenticate(noise_config)
-        .multiplex(libp2p::yamux::Config::default())
-        .boxed()
+        .multiplex(libp2p::yamux::Config::default());
+
+    let quic_transport = quic::tokio::Transport::new(quic::Config::new(keypair));
+
+    let transport = OrTransport::new(quic_transport, tcp_transport).map(|output, _| match output {
+        Either::Left((peer_id, connection)) => (peer_id, StreamMuxerBox::new(connection)),
+        Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+    });
+
+    dns::tokio::Transport::system(transport).unwrap().boxed()
 }
@@ -0,0 +1,147 @@
+//! This test was separated because the `metrics` crate uses a singleton recorder, so keeping a test
+//! that relies on metric values in a separate binary makes more sense than using an inter-test
+//! locking mechanism which can cause weird test failures without any obvious clue to what might
+//! have caused those failures in the first place.
+
+use std::time::Duration;
+
+use fake::{Fake, Faker};
+use futures::{SinkExt, StreamExt};
+use p2p::client::peer_aware::Client;
+use p2p::libp2p::identity::Keypair;
+use p2p::libp2p::{Multiaddr, PeerId};
+use p2p::{Config, Event, EventReceiver, RateLimit};
+use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse};
+use pathfinder_common::test_utils::metrics::{FakeRecorder, ScopedRecorderGuard};
+use pathfinder_common::ChainId;
+
+fn start() -> (PeerId, Client, EventReceiver) {
+    let keypair = Keypair::generate_ed25519();
+    let peer_id = keypair.public().to_peer_id();
+    let cfg = Config {
+        direct_connection_timeout: Duration::from_secs(0),
+        relay_connection_timeout: Duration::from_secs(0),
+        max_inbound_direct_peers: 10,
+        max_inbound_relayed_peers: 10,
+        max_outbound_peers: 10,
+        low_watermark: 0,
+        ip_whitelist: vec!["::/0".parse().unwrap(), "0.0.0.0/0".parse().unwrap()],
+        bootstrap: Default::default(),
+        eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
+        inbound_connections_rate_limit: RateLimit {
+            max: 1000,
+            interval: Duration::from_secs(1),
+        },
+    };
+
+    let (client, events, main_loop) = p2p::new(keypair, cfg, ChainId::GOERLI_TESTNET);
+    tokio::spawn(main_loop.run());
+
+    (peer_id, client, events)
+}
+
+/// Polls `f` until it returns `Some`, for at most a few network status intervals.
+async fn wait_for<T>(f: impl Fn() -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(15), async {
+        loop {
+            if let Some(value) = f() {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Metric to be recorded")
+}
+
+#[tokio::test]
+async fn sync_requests_and_connected_peers() {
+    let recorder = FakeRecorder::default();
+    let handle = recorder.handle();
+
+    // Automatically deregister the recorder
+    let _guard = ScopedRecorderGuard::new(recorder);
+
+    // Both nodes report their connected peers every 5 seconds to the same recorder, so their
+    // reports overwrite each other. Starting the client midway between the server's reports
+    // keeps each report visible for a while.
+    let (server_id, server, mut server_events) = start();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let (_, client, mut client_events) = start();
+
+    // Responds to the headers request with two headers, keeping the event loops going.
+    tokio::spawn(async move {
+        while let Some(event) = server_events.recv().await {
+            if let Event::InboundHeadersSyncRequest { mut channel, .. } = event {
+                for _ in 0..2 {
+                    let header = BlockHeadersResponse::Header(Box::new(Faker.fake()));
+                    channel.send(header).await.unwrap();
+                }
+            }
+        }
+    });
+    tokio::spawn(async move { while client_events.recv().await.is_some() {} });
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap();
+    server.start_listening(addr.clone()).await.unwrap();
+    client.dial(server_id, addr).await.unwrap();
+
+    let responses = client
+        .send_headers_sync_request(server_id, Faker.fake::<BlockHeadersRequest>())
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(responses.len(), 2);
+
+    for direction in ["inbound", "outbound"] {
+        let requests = handle.get_counter_value_by_label(
+            "p2p_requests_total",
+            [("protocol", "/starknet/headers"), ("direction", direction)],
+        );
+        assert_eq!(requests, 1, "{direction} requests");
+    }
+
+    // Both the request and the responses are counted once by each side.
+    let sent = handle.get_counter_value_by_label(
+        "p2p_sync_bytes_total",
+        [("protocol", "/starknet/headers/1"), ("direction", "sent")],
+    );
+    let received = handle.get_counter_value_by_label(
+        "p2p_sync_bytes_total",
+        [
+            ("protocol", "/starknet/headers/1"),
+            ("direction", "received"),
+        ],
+    );
+    assert!(sent > 0);
+    assert!(received > 0);
+
+    let durations = wait_for(|| {
+        handle.get_histogram_count_by_label(
+            "p2p_request_duration_seconds",
+            [("protocol", "/starknet/headers")],
+        )
+    })
+    .await;
+    assert_eq!(durations, 1);
+
+    let connected = |direction| {
+        handle
+            .get_gauge_value_by_label(
+                "p2p_connected_peers",
+                [("direction", direction), ("relayed", "false")],
+            )
+            .filter(|count| *count > 0.0)
+    };
+    assert_eq!(wait_for(|| connected("outbound")).await, 1.0);
+    assert_eq!(wait_for(|| connected("inbound")).await, 1.0);
+}
@@ -190,6 +190,8 @@ pub enum ClassesResponse {
     Class(Class),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl ToProtobuf<proto::class::ClassesResponse> for ClassesResponse {
@@ -201,7 +203,12 @@ impl ToProtobuf<proto::class::ClassesResponse> for ClassesResponse {
                 class_message: Some(Class(class.to_protobuf())),
             },
             Self::Fin => ClassesResponse {
-                class_message: Some(Fin(proto::common::Fin {})),
+                class_message: Some(Fin(proto::common::Fin {
+                    rate_limited: false,
+                })),
+            },
+            Self::RateLimited => ClassesResponse {
+                class_message: Some(Fin(proto::common::Fin { rate_limited: true })),
             },
         }
     }
@@ -217,6 +224,7 @@ impl TryFromProtobuf<proto::class::ClassesResponse> for ClassesResponse {
             Class(c) => Ok(Self::Class(TryFromProtobuf::try_from_protobuf(
                 c, field_name,
             )?)),
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
             Fin(_) => Ok(Self::Fin),
         }
     }
@@ -24,6 +24,8 @@ pub enum EventsResponse {
     Event(Event),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl ToProtobuf<proto::event::EventsResponse> for EventsResponse {
@@ -32,7 +34,10 @@ impl ToProtobuf<proto::event::EventsResponse> for EventsResponse {
         proto::event::EventsResponse {
             event_message: Some(match self {
                 Self::Event(event) => Event(event.to_protobuf()),
-                Self::Fin => Fin(proto::common::Fin {}),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
             }),
         }
     }
@@ -46,6 +51,7 @@ impl TryFromProtobuf<proto::event::EventsResponse> for EventsResponse {
         use proto::event::events_response::EventMessage::{Event, Fin};
         Ok(match proto_field(input.event_message, field_name)? {
             Event(events) => Self::Event(TryFromProtobuf::try_from_protobuf(events, field_name)?),
+            Fin(fin) if fin.rate_limited => Self::RateLimited,
             Fin(_) => Self::Fin,
         })
     }
@@ -48,6 +48,8 @@ pub enum BlockHeadersResponse {
     Header(Box<SignedBlockHeader>),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl<T> Dummy<T> for SignedBlockHeader {
@@ -112,7 +114,10 @@ impl ToProtobuf<proto::header::BlockHeadersResponse> for BlockHeadersResponse {
         proto::header::BlockHeadersResponse {
             header_message: Some(match self {
                 Self::Header(header) => Header(header.to_protobuf()),
-                Self::Fin => Fin(proto::common::Fin {}),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
             }),
         }
     }
@@ -128,6 +133,7 @@ impl TryFromProtobuf<proto::header::BlockHeadersResponse> for BlockHeadersRespon
             Header(header) => Self::Header(Box::new(SignedBlockHeader::try_from_protobuf(
                 header, field_name,
             )?)),
+            Fin(fin) if fin.rate_limited => Self::RateLimited,
             Fin(_) => Self::Fin,
         })
     }
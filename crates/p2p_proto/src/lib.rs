@@ -18,6 +18,10 @@ pub mod proto {
     pub mod receipt {
         include!(concat!(env!("OUT_DIR"), "/starknet.receipt.rs"));
     }
+    #[allow(clippy::large_enum_variant)]
+    pub mod snapshot {
+        include!(concat!(env!("OUT_DIR"), "/starknet.snapshot.rs"));
+    }
     pub mod state {
         include!(concat!(env!("OUT_DIR"), "/starknet.state.rs"));
     }
@@ -170,5 +174,7 @@ pub mod common;
 pub mod event;
 pub mod header;
 pub mod receipt;
+pub mod snapshot;
 pub mod state;
 pub mod transaction;
+pub mod version;
@@ -109,6 +109,8 @@ pub enum ReceiptsResponse {
     Receipt(Receipt),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl<T> Dummy<T> for EthereumAddress {
@@ -187,7 +189,10 @@ impl ToProtobuf<proto::receipt::ReceiptsResponse> for ReceiptsResponse {
         proto::receipt::ReceiptsResponse {
             receipt_message: Some(match self {
                 Self::Receipt(r) => Receipt(r.to_protobuf()),
-                Self::Fin => Fin(proto::common::Fin {}),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
             }),
         }
     }
@@ -201,6 +206,7 @@ impl TryFromProtobuf<proto::receipt::ReceiptsResponse> for ReceiptsResponse {
         use proto::receipt::receipts_response::ReceiptMessage::{Fin, Receipt};
         Ok(match proto_field(input.receipt_message, field_name)? {
             Receipt(r) => Self::Receipt(TryFromProtobuf::try_from_protobuf(r, field_name)?),
+            Fin(fin) if fin.rate_limited => Self::RateLimited,
             Fin(_) => Self::Fin,
         })
     }
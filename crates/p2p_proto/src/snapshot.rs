@@ -0,0 +1,292 @@
+use std::fmt::Debug;
+
+use crate::class::Class;
+use crate::common::{Address, Hash};
+use crate::state::ContractStoredValue;
+use crate::{proto, proto_field, ToProtobuf, TryFromProtobuf};
+use fake::Dummy;
+use pathfinder_crypto::Felt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Dummy)]
+pub enum PatriciaNode {
+    Binary {
+        left: Felt,
+        right: Felt,
+    },
+    Edge {
+        length: u32,
+        path: Felt,
+        value: Felt,
+    },
+}
+
+impl ToProtobuf<proto::snapshot::PatriciaNode> for PatriciaNode {
+    fn to_protobuf(self) -> proto::snapshot::PatriciaNode {
+        use proto::snapshot::patricia_node::{Binary, Edge, Node};
+        proto::snapshot::PatriciaNode {
+            node: Some(match self {
+                Self::Binary { left, right } => Node::Binary(Binary {
+                    left: Some(left.to_protobuf()),
+                    right: Some(right.to_protobuf()),
+                }),
+                Self::Edge {
+                    length,
+                    path,
+                    value,
+                } => Node::Edge(Edge {
+                    length,
+                    path: Some(path.to_protobuf()),
+                    value: Some(value.to_protobuf()),
+                }),
+            }),
+        }
+    }
+}
+
+impl TryFromProtobuf<proto::snapshot::PatriciaNode> for PatriciaNode {
+    fn try_from_protobuf(
+        input: proto::snapshot::PatriciaNode,
+        field_name: &'static str,
+    ) -> Result<Self, std::io::Error> {
+        use proto::snapshot::patricia_node::Node;
+        Ok(match proto_field(input.node, field_name)? {
+            Node::Binary(binary) => Self::Binary {
+                left: TryFromProtobuf::try_from_protobuf(binary.left, field_name)?,
+                right: TryFromProtobuf::try_from_protobuf(binary.right, field_name)?,
+            },
+            Node::Edge(edge) => Self::Edge {
+                length: edge.length,
+                path: TryFromProtobuf::try_from_protobuf(edge.path, field_name)?,
+                value: TryFromProtobuf::try_from_protobuf(edge.value, field_name)?,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::PatriciaRangeProof")]
+pub struct PatriciaRangeProof {
+    pub nodes: Vec<PatriciaNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ContractState")]
+pub struct ContractState {
+    pub address: Address,
+    pub class: Hash,
+    pub storage: Hash,
+    pub nonce: Felt,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ContractRangeRequest")]
+pub struct ContractRangeRequest {
+    pub domain: u32,
+    pub block_number: u64,
+    pub start: Address,
+    pub end: Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ContractRange")]
+pub struct ContractRange {
+    pub contracts_root: Hash,
+    pub classes_root: Hash,
+    pub state: Vec<ContractState>,
+    pub proof: PatriciaRangeProof,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Dummy)]
+pub enum ContractRangeResponse {
+    Range(ContractRange),
+    #[default]
+    Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
+}
+
+impl ToProtobuf<proto::snapshot::ContractRangeResponse> for ContractRangeResponse {
+    fn to_protobuf(self) -> proto::snapshot::ContractRangeResponse {
+        use proto::snapshot::contract_range_response::ContractRangeMessage::{Fin, Range};
+        proto::snapshot::ContractRangeResponse {
+            contract_range_message: Some(match self {
+                Self::Range(range) => Range(range.to_protobuf()),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
+            }),
+        }
+    }
+}
+
+impl TryFromProtobuf<proto::snapshot::ContractRangeResponse> for ContractRangeResponse {
+    fn try_from_protobuf(
+        input: proto::snapshot::ContractRangeResponse,
+        field_name: &'static str,
+    ) -> Result<Self, std::io::Error> {
+        use proto::snapshot::contract_range_response::ContractRangeMessage::{Fin, Range};
+        match proto_field(input.contract_range_message, field_name)? {
+            Range(range) => TryFromProtobuf::try_from_protobuf(range, field_name).map(Self::Range),
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
+            Fin(_) => Ok(Self::Fin),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ContractStorageRequest")]
+pub struct ContractStorageRequest {
+    pub domain: u32,
+    pub block_number: u64,
+    pub address: Address,
+    pub start: Felt,
+    pub end: Felt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ContractStorage")]
+pub struct ContractStorage {
+    pub key_value: Vec<ContractStoredValue>,
+    pub proof: PatriciaRangeProof,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Dummy)]
+pub enum ContractStorageResponse {
+    Storage(ContractStorage),
+    #[default]
+    Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
+}
+
+impl ToProtobuf<proto::snapshot::ContractStorageResponse> for ContractStorageResponse {
+    fn to_protobuf(self) -> proto::snapshot::ContractStorageResponse {
+        use proto::snapshot::contract_storage_response::ContractStorageMessage::{Fin, Storage};
+        proto::snapshot::ContractStorageResponse {
+            contract_storage_message: Some(match self {
+                Self::Storage(storage) => Storage(storage.to_protobuf()),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
+            }),
+        }
+    }
+}
+
+impl TryFromProtobuf<proto::snapshot::ContractStorageResponse> for ContractStorageResponse {
+    fn try_from_protobuf(
+        input: proto::snapshot::ContractStorageResponse,
+        field_name: &'static str,
+    ) -> Result<Self, std::io::Error> {
+        use proto::snapshot::contract_storage_response::ContractStorageMessage::{Fin, Storage};
+        match proto_field(input.contract_storage_message, field_name)? {
+            Storage(storage) => {
+                TryFromProtobuf::try_from_protobuf(storage, field_name).map(Self::Storage)
+            }
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
+            Fin(_) => Ok(Self::Fin),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ClassRangeRequest")]
+pub struct ClassRangeRequest {
+    pub block_number: u64,
+    pub start: Hash,
+    pub end: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ClassRange")]
+pub struct ClassRange {
+    pub classes: Vec<Class>,
+    pub compiled_class_hashes: Vec<Hash>,
+    pub proof: PatriciaRangeProof,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Dummy)]
+pub enum ClassRangeResponse {
+    Range(ClassRange),
+    #[default]
+    Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
+}
+
+impl ToProtobuf<proto::snapshot::ClassRangeResponse> for ClassRangeResponse {
+    fn to_protobuf(self) -> proto::snapshot::ClassRangeResponse {
+        use proto::snapshot::class_range_response::ClassRangeMessage::{Fin, Range};
+        proto::snapshot::ClassRangeResponse {
+            class_range_message: Some(match self {
+                Self::Range(range) => Range(range.to_protobuf()),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
+            }),
+        }
+    }
+}
+
+impl TryFromProtobuf<proto::snapshot::ClassRangeResponse> for ClassRangeResponse {
+    fn try_from_protobuf(
+        input: proto::snapshot::ClassRangeResponse,
+        field_name: &'static str,
+    ) -> Result<Self, std::io::Error> {
+        use proto::snapshot::class_range_response::ClassRangeMessage::{Fin, Range};
+        match proto_field(input.class_range_message, field_name)? {
+            Range(range) => TryFromProtobuf::try_from_protobuf(range, field_name).map(Self::Range),
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
+            Fin(_) => Ok(Self::Fin),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
+#[protobuf(name = "crate::proto::snapshot::ClassesByHashRequest")]
+pub struct ClassesByHashRequest {
+    pub class_hashes: Vec<Hash>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Dummy)]
+pub enum ClassesByHashResponse {
+    Class(Class),
+    #[default]
+    Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
+}
+
+impl ToProtobuf<proto::snapshot::ClassesByHashResponse> for ClassesByHashResponse {
+    fn to_protobuf(self) -> proto::snapshot::ClassesByHashResponse {
+        use proto::snapshot::classes_by_hash_response::ClassesByHashMessage::{Class, Fin};
+        proto::snapshot::ClassesByHashResponse {
+            classes_by_hash_message: Some(match self {
+                Self::Class(class) => Class(class.to_protobuf()),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
+            }),
+        }
+    }
+}
+
+impl TryFromProtobuf<proto::snapshot::ClassesByHashResponse> for ClassesByHashResponse {
+    fn try_from_protobuf(
+        input: proto::snapshot::ClassesByHashResponse,
+        field_name: &'static str,
+    ) -> Result<Self, std::io::Error> {
+        use proto::snapshot::classes_by_hash_response::ClassesByHashMessage::{Class, Fin};
+        match proto_field(input.classes_by_hash_message, field_name)? {
+            Class(class) => TryFromProtobuf::try_from_protobuf(class, field_name).map(Self::Class),
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
+            Fin(_) => Ok(Self::Fin),
+        }
+    }
+}
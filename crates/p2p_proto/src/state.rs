@@ -37,6 +37,8 @@ pub enum StateDiffsResponse {
     ContractDiff(ContractDiff),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl ToProtobuf<proto::state::StateDiffsResponse> for StateDiffsResponse {
@@ -45,7 +47,10 @@ impl ToProtobuf<proto::state::StateDiffsResponse> for StateDiffsResponse {
         proto::state::StateDiffsResponse {
             state_diff_message: Some(match self {
                 Self::ContractDiff(contract_diff) => ContractDiff(contract_diff.to_protobuf()),
-                Self::Fin => Fin(proto::common::Fin {}),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
             }),
         }
     }
@@ -61,6 +66,7 @@ impl TryFromProtobuf<proto::state::StateDiffsResponse> for StateDiffsResponse {
             ContractDiff(x) => {
                 TryFromProtobuf::try_from_protobuf(x, field_name).map(Self::ContractDiff)
             }
+            Fin(fin) if fin.rate_limited => Ok(Self::RateLimited),
             Fin(_) => Ok(Self::Fin),
         }
     }
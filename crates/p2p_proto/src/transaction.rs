@@ -194,6 +194,8 @@ pub enum TransactionsResponse {
     Transaction(Transaction),
     #[default]
     Fin,
+    /// The request was rejected because the peer is over its budget, it should retry later.
+    RateLimited,
 }
 
 impl ToProtobuf<proto::transaction::transaction::Txn> for TransactionVariant {
@@ -255,7 +257,10 @@ impl ToProtobuf<proto::transaction::TransactionsResponse> for TransactionsRespon
         proto::transaction::TransactionsResponse {
             transaction_message: Some(match self {
                 Self::Transaction(t) => Transaction(t.to_protobuf()),
-                Self::Fin => Fin(proto::common::Fin {}),
+                Self::Fin => Fin(proto::common::Fin {
+                    rate_limited: false,
+                }),
+                Self::RateLimited => Fin(proto::common::Fin { rate_limited: true }),
             }),
         }
     }
@@ -269,6 +274,7 @@ impl TryFromProtobuf<proto::transaction::TransactionsResponse> for TransactionsR
         use proto::transaction::transactions_response::TransactionMessage::{Fin, Transaction};
         Ok(match proto_field(input.transaction_message, field_name)? {
             Transaction(t) => Self::Transaction(TryFromProtobuf::try_from_protobuf(t, field_name)?),
+            Fin(fin) if fin.rate_limited => Self::RateLimited,
             Fin(_) => Self::Fin,
         })
     }
@@ -0,0 +1,160 @@
+//! Versioned wire encoding of the sync messages.
+//!
+//! Every sync protocol is served under one name per supported [Version], e.g.
+//! `/starknet/headers/1`, and the version negotiated for a stream selects the schema its messages
+//! are encoded with. As long as a message has the same schema in every version it is listed in
+//! the `same_in_all_versions!` invocation below. Once its schema in `proto/*.proto` changes, the
+//! old schema is kept alongside the new one and the entry is replaced by a manual [Versioned]
+//! implementation which converts from and to the old schema for the older versions.
+use crate::{class, event, header, proto, receipt, snapshot, state, transaction};
+use crate::{ToProtobuf, TryFromProtobuf};
+
+/// A version of the sync protocols, ordered from oldest to newest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    V1,
+    /// Only exists to test version negotiation, see [TestRange].
+    #[cfg(feature = "test-version")]
+    V2,
+}
+
+impl Version {
+    /// The suffix of the protocol names of this version.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1 => "1",
+            #[cfg(feature = "test-version")]
+            Self::V2 => "2",
+        }
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A message which is encoded according to the negotiated protocol [Version].
+pub trait Versioned: Sized {
+    fn encode(self, version: Version) -> Vec<u8>;
+
+    fn decode(version: Version, buf: &[u8]) -> Result<Self, std::io::Error>;
+}
+
+macro_rules! same_in_all_versions {
+    ($($dto:ty => $prost:ty),* $(,)?) => {
+        $(
+            impl Versioned for $dto {
+                fn encode(self, _version: Version) -> Vec<u8> {
+                    prost::Message::encode_to_vec(&ToProtobuf::<$prost>::to_protobuf(self))
+                }
+
+                fn decode(_version: Version, buf: &[u8]) -> Result<Self, std::io::Error> {
+                    let prost_dto = <$prost as prost::Message>::decode(buf)?;
+                    TryFromProtobuf::try_from_protobuf(prost_dto, std::any::type_name::<$prost>())
+                }
+            }
+        )*
+    };
+}
+
+same_in_all_versions!(
+    header::BlockHeadersRequest => proto::header::BlockHeadersRequest,
+    header::BlockHeadersResponse => proto::header::BlockHeadersResponse,
+    state::StateDiffsRequest => proto::state::StateDiffsRequest,
+    state::StateDiffsResponse => proto::state::StateDiffsResponse,
+    class::ClassesRequest => proto::class::ClassesRequest,
+    class::ClassesResponse => proto::class::ClassesResponse,
+    transaction::TransactionsRequest => proto::transaction::TransactionsRequest,
+    transaction::TransactionsResponse => proto::transaction::TransactionsResponse,
+    receipt::ReceiptsRequest => proto::receipt::ReceiptsRequest,
+    receipt::ReceiptsResponse => proto::receipt::ReceiptsResponse,
+    event::EventsRequest => proto::event::EventsRequest,
+    event::EventsResponse => proto::event::EventsResponse,
+    snapshot::ContractRangeRequest => proto::snapshot::ContractRangeRequest,
+    snapshot::ContractRangeResponse => proto::snapshot::ContractRangeResponse,
+    snapshot::ContractStorageRequest => proto::snapshot::ContractStorageRequest,
+    snapshot::ContractStorageResponse => proto::snapshot::ContractStorageResponse,
+    snapshot::ClassRangeRequest => proto::snapshot::ClassRangeRequest,
+    snapshot::ClassRangeResponse => proto::snapshot::ClassRangeResponse,
+    snapshot::ClassesByHashRequest => proto::snapshot::ClassesByHashRequest,
+    snapshot::ClassesByHashResponse => proto::snapshot::ClassesByHashResponse,
+);
+
+/// A range of blocks, which only exists to test [Version] negotiation. Its schema changed in
+/// [Version::V2]: the range used to be sent as its start and length, and is sent as its start and
+/// inclusive stop along with a step since.
+#[cfg(feature = "test-version")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestRange {
+    pub start: u64,
+    pub stop: u64,
+    /// Not supported before [Version::V2], which always stepped by one.
+    pub step: Option<u64>,
+}
+
+#[cfg(feature = "test-version")]
+mod test_range {
+    use super::{TestRange, Version, Versioned};
+
+    /// The schema of [TestRange] in [Version::V1].
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct RangeV1 {
+        #[prost(uint64, tag = "1")]
+        start: u64,
+        #[prost(uint64, tag = "2")]
+        limit: u64,
+    }
+
+    /// The schema of [TestRange] in [Version::V2].
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct RangeV2 {
+        #[prost(uint64, tag = "1")]
+        start: u64,
+        #[prost(uint64, tag = "2")]
+        stop: u64,
+        #[prost(uint64, optional, tag = "3")]
+        step: Option<u64>,
+    }
+
+    impl Versioned for TestRange {
+        fn encode(self, version: Version) -> Vec<u8> {
+            match version {
+                Version::V1 => prost::Message::encode_to_vec(&RangeV1 {
+                    start: self.start,
+                    limit: self.stop.saturating_sub(self.start) + 1,
+                }),
+                Version::V2 => prost::Message::encode_to_vec(&RangeV2 {
+                    start: self.start,
+                    stop: self.stop,
+                    step: self.step,
+                }),
+            }
+        }
+
+        fn decode(version: Version, buf: &[u8]) -> Result<Self, std::io::Error> {
+            match version {
+                Version::V1 => {
+                    let range = <RangeV1 as prost::Message>::decode(buf)?;
+                    let stop = (range.start + range.limit).checked_sub(1).ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidData, "Empty range")
+                    })?;
+                    Ok(TestRange {
+                        start: range.start,
+                        stop,
+                        step: None,
+                    })
+                }
+                Version::V2 => {
+                    let range = <RangeV2 as prost::Message>::decode(buf)?;
+                    Ok(TestRange {
+                        start: range.start,
+                        stop: range.stop,
+                        step: range.step,
+                    })
+                }
+            }
+        }
+    }
+}
@@ -24,6 +24,7 @@
 
 use async_trait::async_trait;
 use futures::prelude::*;
+use libp2p::identity::PeerId;
 use std::io;
 
 /// A `Codec` defines the request and response types
@@ -79,4 +80,13 @@ pub trait Codec {
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send;
+
+    /// Returns the codec used for the streams of a connection with `peer`, which allows codecs
+    /// to account for what is exchanged with each peer.
+    fn for_peer(&self, _peer: PeerId) -> Self
+    where
+        Self: Clone,
+    {
+        self.clone()
+    }
 }
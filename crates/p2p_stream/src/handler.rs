@@ -84,6 +84,9 @@ where
     inbound_request_id: Arc<AtomicU64>,
 
     worker_streams: futures_bounded::FuturesMap<RequestId, Result<Event<TCodec>, io::Error>>,
+    /// Number of inbound streams currently held in `worker_streams`.
+    active_inbound_streams: usize,
+    max_concurrent_inbound_streams: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -102,6 +105,7 @@ where
         substream_timeout: Duration,
         inbound_request_id: Arc<AtomicU64>,
         max_concurrent_streams: usize,
+        max_concurrent_inbound_streams: usize,
     ) -> Self {
         let (inbound_sender, inbound_receiver) = mpsc::channel(0);
         let (outbound_sender, outbound_receiver) = mpsc::channel(0);
@@ -120,6 +124,8 @@ where
                 substream_timeout,
                 max_concurrent_streams,
             ),
+            active_inbound_streams: 0,
+            max_concurrent_inbound_streams,
         }
     }
 
@@ -138,6 +144,15 @@ where
             <Self as ConnectionHandler>::InboundOpenInfo,
         >,
     ) {
+        if self.active_inbound_streams >= self.max_concurrent_inbound_streams {
+            // Dropping the stream resets it, which the remote peer observes as a failed request.
+            tracing::debug!(
+                limit=%self.max_concurrent_inbound_streams,
+                "Dropping inbound stream because the peer has too many concurrent requests"
+            );
+            return;
+        }
+
         let mut codec = self.codec.clone();
         let request_id = self.next_inbound_request_id();
         let mut sender = self.inbound_sender.clone();
@@ -174,6 +189,8 @@ where
             .is_err()
         {
             tracing::warn!("Dropping inbound stream because we are at capacity")
+        } else {
+            self.active_inbound_streams += 1;
         }
     }
 
@@ -428,7 +445,12 @@ where
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<ConnectionHandlerEvent<Protocol<TCodec::Protocol>, (), Self::ToBehaviour>> {
-        match self.worker_streams.poll_unpin(cx) {
+        let worker_stream = self.worker_streams.poll_unpin(cx);
+        if let Poll::Ready((RequestId::Inbound(_), _)) = &worker_stream {
+            self.active_inbound_streams -= 1;
+        }
+
+        match worker_stream {
             Poll::Ready((_, Ok(Ok(event)))) => {
                 return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
             }
@@ -231,6 +231,7 @@ impl fmt::Display for OutboundRequestId {
 pub struct Config {
     request_timeout: Duration,
     max_concurrent_streams: usize,
+    max_concurrent_inbound_streams: usize,
 }
 
 impl Default for Config {
@@ -238,6 +239,7 @@ impl Default for Config {
         Self {
             request_timeout: Duration::from_secs(10),
             max_concurrent_streams: 100,
+            max_concurrent_inbound_streams: 100,
         }
     }
 }
@@ -254,6 +256,13 @@ impl Config {
         self.max_concurrent_streams = num_streams;
         self
     }
+
+    /// Sets the upper bound for the number of concurrent inbound streams per connection.
+    /// Inbound streams opened by the remote peer beyond this limit are closed immediately.
+    pub fn with_max_concurrent_inbound_streams(mut self, num_streams: usize) -> Self {
+        self.max_concurrent_inbound_streams = num_streams;
+        self
+    }
 }
 
 /// A request/streaming-response protocol for some message codec.
@@ -567,10 +576,11 @@ where
     ) -> Result<THandler<Self>, ConnectionDenied> {
         let mut handler = Handler::new(
             self.protocols.clone(),
-            self.codec.clone(),
+            self.codec.for_peer(peer),
             self.config.request_timeout,
             self.next_inbound_request_id.clone(),
             self.config.max_concurrent_streams,
+            self.config.max_concurrent_inbound_streams,
         );
 
         self.preload_new_handler(&mut handler, peer, connection_id, None);
@@ -607,10 +617,11 @@ where
     ) -> Result<THandler<Self>, ConnectionDenied> {
         let mut handler = Handler::new(
             self.protocols.clone(),
-            self.codec.clone(),
+            self.codec.for_peer(peer),
             self.config.request_timeout,
             self.next_inbound_request_id.clone(),
             self.config.max_concurrent_streams,
+            self.config.max_concurrent_inbound_streams,
         );
 
         self.preload_new_handler(
@@ -127,7 +127,13 @@ fn execute(storage: &mut Storage, chain_id: ChainId, work: Work) {
 
     let db_tx = connection.transaction().expect("Create transaction");
 
-    let execution_state = ExecutionState::trace(&db_tx, chain_id, work.header.clone(), None);
+    let execution_state = ExecutionState::trace(
+        &db_tx,
+        chain_id,
+        pathfinder_executor::ChainConfig::default(),
+        work.header.clone(),
+        None,
+    );
 
     let transactions = work
         .transactions
@@ -3,7 +3,7 @@ use std::num::NonZeroU32;
 use anyhow::Context;
 use pathfinder_common::{BlockHash, BlockNumber, Chain, ChainId, StarknetVersion};
 use pathfinder_crypto::Felt;
-use pathfinder_lib::state::block_hash::{verify_block_hash, VerifyResult};
+use pathfinder_lib::state::block_hash::{meta, verify_block_hash, VerifyResult};
 use pathfinder_storage::{JournalMode, Storage};
 use starknet_gateway_types::reply::{Block, GasPrices, Status};
 
@@ -22,6 +22,7 @@ fn main() -> anyhow::Result<()> {
         "integration" => (Chain::GoerliIntegration, ChainId::GOERLI_INTEGRATION),
         _ => panic!("Expected chain name: mainnet/goerli/integration"),
     };
+    let meta_info = meta::for_chain(chain);
 
     let database_path = std::env::args().nth(2).unwrap();
     let storage = Storage::migrate(database_path.into(), JournalMode::WAL, 1)?
@@ -81,10 +82,10 @@ fn main() -> anyhow::Result<()> {
         };
         parent_block_hash = block_hash;
 
-        let result = verify_block_hash(&block, chain, chain_id, block_hash)?;
+        let result = verify_block_hash(&block, meta_info, chain_id, block_hash)?;
         match result {
             VerifyResult::Match(_) => {}
-            VerifyResult::NotVerifiable => println!(
+            VerifyResult::NotVerifiable(_) => println!(
                 "Block hash cannot be verified for block number {block_number} hash {block_hash:?}"
             ),
             VerifyResult::Mismatch => {
@@ -0,0 +1,357 @@
+//! Chain specification files for custom networks.
+//!
+//! A chain specification declares the parameters of a network which pathfinder knows
+//! for the public Starknet networks, so that appchains and private deployments can be
+//! synced without patching pathfinder. It is either a JSON file of the form
+//!
+//! ```json
+//! {
+//!     "chain_id": "SN_MY_APPCHAIN",
+//!     "genesis_hash": "0x1234",
+//!     "l1_core_address": "0xc662c410c0ecf747543f5ba90660f6abebd9c8c4",
+//!     "fee_tokens": {
+//!         "eth": "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
+//!         "strk": "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"
+//!     },
+//!     "block_hash": {
+//!         "first_0_7_block": 0,
+//!         "not_verifiable_range": [0, 100],
+//!         "fallback_sequencer_address": "0x5678"
+//!     },
+//!     "versioned_constants": "versioned_constants.json",
+//!     "sequencer_public_key": "0x9abc",
+//!     "settlement_chain_id": "11155111"
+//! }
+//! ```
+//!
+//! or the equivalent TOML file, depending on whether the file name ends in `.json` or `.toml`:
+//!
+//! ```toml
+//! chain_id = "SN_MY_APPCHAIN"
+//! genesis_hash = "0x1234"
+//! l1_core_address = "0xc662c410c0ecf747543f5ba90660f6abebd9c8c4"
+//! versioned_constants = "versioned_constants.json"
+//! sequencer_public_key = "0x9abc"
+//! settlement_chain_id = "11155111"
+//!
+//! [fee_tokens]
+//! eth = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7"
+//! strk = "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"
+//!
+//! [block_hash]
+//! first_0_7_block = 0
+//! not_verifiable_range = [0, 100]
+//! fallback_sequencer_address = "0x5678"
+//! ```
+//!
+//! Only `chain_id` is required. Without `l1_core_address` the address is fetched from the
+//! feeder gateway, and the remaining fields default to the values of the public networks.
+//! `versioned_constants` is the path of a blockifier versioned constants file, relative to
+//! the chain specification. It is always JSON. `settlement_chain_id` is the chain ID of the
+//! settlement layer, which is checked at startup: the decimal or `0x` prefixed hex chain ID of
+//! an Ethereum network, or the chain ID string of a Starknet network such as `SN_SEPOLIA`.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use pathfinder_common::{
+    BlockHash, BlockNumber, ChainId, ContractAddress, EthereumAddress, EthereumChain, PublicKey,
+    SequencerAddress,
+};
+use pathfinder_crypto::Felt;
+use pathfinder_executor::{ChainConfig, VersionedConstants};
+use pathfinder_lib::state::block_hash::BlockHashMetaInfo;
+use pathfinder_serde::EthereumAddressAsHexStr;
+use primitive_types::{H160, U256};
+
+/// A parsed chain specification, see the [module documentation](self).
+pub struct ChainSpec {
+    pub chain_id: ChainId,
+    pub genesis_hash: Option<BlockHash>,
+    pub l1_core_address: Option<H160>,
+    pub block_hash_meta: BlockHashMetaInfo,
+    pub execution: ChainConfig,
+    pub sequencer_public_key: Option<PublicKey>,
+    pub settlement_chain: Option<EthereumChain>,
+}
+
+#[serde_with::serde_as]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Dto {
+    chain_id: String,
+    genesis_hash: Option<BlockHash>,
+    #[serde_as(as = "Option<EthereumAddressAsHexStr>")]
+    #[serde(default)]
+    l1_core_address: Option<EthereumAddress>,
+    fee_tokens: Option<FeeTokens>,
+    #[serde(default)]
+    block_hash: BlockHashDto,
+    versioned_constants: Option<std::path::PathBuf>,
+    sequencer_public_key: Option<PublicKey>,
+    settlement_chain_id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeeTokens {
+    eth: ContractAddress,
+    strk: ContractAddress,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockHashDto {
+    #[serde(default)]
+    first_0_7_block: BlockNumber,
+    not_verifiable_range: Option<[BlockNumber; 2]>,
+    fallback_sequencer_address: Option<SequencerAddress>,
+}
+
+impl ChainSpec {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::read(path).context("Reading file")?;
+        let dto: Dto = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_slice(&file).context("Parsing JSON file")?,
+            Some("toml") => {
+                let file = std::str::from_utf8(&file).context("Reading file as UTF-8")?;
+                toml::from_str(file).context("Parsing TOML file")?
+            }
+            _ => anyhow::bail!("Unknown file format, expected a .json or .toml file"),
+        };
+
+        let versioned_constants = match dto.versioned_constants {
+            Some(constants) => {
+                // Relative to the chain specification, not to the working directory.
+                let constants = path
+                    .parent()
+                    .map(|dir| dir.join(&constants))
+                    .unwrap_or(constants);
+                let file = std::fs::read(&constants).with_context(|| {
+                    format!("Reading versioned constants {}", constants.display())
+                })?;
+                let constants: VersionedConstants =
+                    serde_json::from_slice(&file).with_context(|| {
+                        format!("Parsing versioned constants {}", constants.display())
+                    })?;
+                Some(Arc::new(constants))
+            }
+            None => None,
+        };
+
+        let execution = match dto.fee_tokens {
+            Some(fee_tokens) => ChainConfig {
+                eth_fee_token_address: fee_tokens.eth,
+                strk_fee_token_address: fee_tokens.strk,
+                versioned_constants,
+            },
+            None => ChainConfig {
+                versioned_constants,
+                ..Default::default()
+            },
+        };
+
+        let chain_id =
+            ChainId(Felt::from_be_slice(dto.chain_id.as_bytes()).context("Parsing chain ID")?);
+        let settlement_chain = dto
+            .settlement_chain_id
+            .as_deref()
+            .map(parse_settlement_chain_id)
+            .transpose()
+            .context("Parsing settlement chain ID")?;
+
+        Ok(Self {
+            chain_id,
+            genesis_hash: dto.genesis_hash,
+            l1_core_address: dto.l1_core_address.map(|address| address.0),
+            block_hash_meta: BlockHashMetaInfo {
+                first_0_7_block: dto.block_hash.first_0_7_block,
+                not_verifiable_range: dto
+                    .block_hash
+                    .not_verifiable_range
+                    .map(|[start, end]| start..end),
+                fallback_sequencer_address: dto.block_hash.fallback_sequencer_address,
+            },
+            execution,
+            sequencer_public_key: dto.sequencer_public_key,
+            settlement_chain,
+        })
+    }
+}
+
+/// Parses an Ethereum chain ID given in decimal or as `0x` prefixed hex, or a Starknet chain
+/// ID string, into the chain reported by the settlement client.
+fn parse_settlement_chain_id(id: &str) -> anyhow::Result<EthereumChain> {
+    let id = if let Some(hex) = id.strip_prefix("0x") {
+        U256::from_str_radix(hex, 16).context("Parsing hex chain ID")?
+    } else if id.bytes().all(|b| b.is_ascii_digit()) {
+        U256::from_dec_str(id).context("Parsing decimal chain ID")?
+    } else {
+        let id = Felt::from_be_slice(id.as_bytes()).context("Parsing Starknet chain ID")?;
+        U256::from_big_endian(id.as_be_bytes())
+    };
+
+    Ok(EthereumChain::from_chain_id(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+
+    use super::*;
+
+    fn load(json: &str) -> anyhow::Result<ChainSpec> {
+        load_file("chain.json", json)
+    }
+
+    fn load_file(name: &str, content: &str) -> anyhow::Result<ChainSpec> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        ChainSpec::load(&path)
+    }
+
+    #[test]
+    fn minimal() {
+        let spec = load(r#"{"chain_id": "SN_APPCHAIN"}"#).unwrap();
+
+        assert_eq!(
+            spec.chain_id,
+            ChainId(Felt::from_be_slice(b"SN_APPCHAIN").unwrap())
+        );
+        assert_eq!(spec.genesis_hash, None);
+        assert_eq!(spec.l1_core_address, None);
+        assert_eq!(spec.block_hash_meta.first_0_7_block, BlockNumber::GENESIS);
+        assert_eq!(spec.block_hash_meta.not_verifiable_range, None);
+        assert_eq!(spec.block_hash_meta.fallback_sequencer_address, None);
+        assert_eq!(
+            spec.execution.eth_fee_token_address,
+            pathfinder_executor::ETH_FEE_TOKEN_ADDRESS
+        );
+        assert!(spec.execution.versioned_constants.is_none());
+        assert_eq!(spec.sequencer_public_key, None);
+        assert_eq!(spec.settlement_chain, None);
+    }
+
+    #[test]
+    fn full() {
+        let spec = load(
+            r#"{
+                "chain_id": "SN_APPCHAIN",
+                "genesis_hash": "0x1234",
+                "l1_core_address": "0x0000000000000000000000000000000000001234",
+                "fee_tokens": { "eth": "0x1", "strk": "0x2" },
+                "block_hash": {
+                    "first_0_7_block": 5,
+                    "not_verifiable_range": [10, 20],
+                    "fallback_sequencer_address": "0x5678"
+                },
+                "sequencer_public_key": "0x9abc"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.genesis_hash, Some(block_hash!("0x1234")));
+        assert_eq!(spec.l1_core_address, Some(H160::from_low_u64_be(0x1234)));
+        assert_eq!(
+            spec.execution.eth_fee_token_address,
+            contract_address!("0x1")
+        );
+        assert_eq!(
+            spec.execution.strk_fee_token_address,
+            contract_address!("0x2")
+        );
+        assert_eq!(
+            spec.block_hash_meta.first_0_7_block,
+            BlockNumber::new_or_panic(5)
+        );
+        assert_eq!(
+            spec.block_hash_meta.not_verifiable_range,
+            Some(BlockNumber::new_or_panic(10)..BlockNumber::new_or_panic(20))
+        );
+        assert_eq!(
+            spec.block_hash_meta.fallback_sequencer_address,
+            Some(sequencer_address!("0x5678"))
+        );
+        assert_eq!(spec.sequencer_public_key, Some(public_key!("0x9abc")));
+    }
+
+    #[test]
+    fn toml() {
+        let spec = load_file(
+            "chain.toml",
+            r#"
+                chain_id = "SN_APPCHAIN"
+                genesis_hash = "0x1234"
+                l1_core_address = "0x0000000000000000000000000000000000001234"
+                sequencer_public_key = "0x9abc"
+
+                [fee_tokens]
+                eth = "0x1"
+                strk = "0x2"
+
+                [block_hash]
+                first_0_7_block = 5
+                not_verifiable_range = [10, 20]
+                fallback_sequencer_address = "0x5678"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            spec.chain_id,
+            ChainId(Felt::from_be_slice(b"SN_APPCHAIN").unwrap())
+        );
+        assert_eq!(spec.genesis_hash, Some(block_hash!("0x1234")));
+        assert_eq!(spec.l1_core_address, Some(H160::from_low_u64_be(0x1234)));
+        assert_eq!(
+            spec.execution.strk_fee_token_address,
+            contract_address!("0x2")
+        );
+        assert_eq!(
+            spec.block_hash_meta.not_verifiable_range,
+            Some(BlockNumber::new_or_panic(10)..BlockNumber::new_or_panic(20))
+        );
+        assert_eq!(spec.sequencer_public_key, Some(public_key!("0x9abc")));
+    }
+
+    #[test]
+    fn settlement_chain_id() {
+        let chain = |id: &str| {
+            load(&format!(
+                r#"{{"chain_id": "SN_APPCHAIN", "settlement_chain_id": "{id}"}}"#
+            ))
+            .unwrap()
+            .settlement_chain
+            .unwrap()
+        };
+
+        assert_eq!(chain("1"), EthereumChain::Mainnet);
+        assert_eq!(chain("0xaa36a7"), EthereumChain::Sepolia);
+        assert_eq!(chain("1337"), EthereumChain::Other(U256::from(1337u32)));
+        assert_eq!(
+            chain("SN_SEPOLIA"),
+            EthereumChain::Other(U256::from_big_endian(b"SN_SEPOLIA"))
+        );
+    }
+
+    #[test]
+    fn unknown_extension_is_rejected() {
+        let error = load_file("chain.yaml", r#"{"chain_id": "SN_APPCHAIN"}"#).unwrap_err();
+        assert!(format!("{error:#}").contains("Unknown file format"));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let error = load(r#"{"chain_id": "SN_APPCHAIN", "genesis": "0x1"}"#).unwrap_err();
+        assert!(format!("{error:#}").contains("unknown field"));
+    }
+
+    #[test]
+    fn missing_versioned_constants() {
+        let error = load(r#"{"chain_id": "SN_APPCHAIN", "versioned_constants": "missing.json"}"#)
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("Reading versioned constants"));
+    }
+}
@@ -3,12 +3,12 @@ use clap::{ArgAction, CommandFactory, Parser};
 use ipnet::IpNet;
 #[cfg(feature = "p2p")]
 use p2p::libp2p::Multiaddr;
-use pathfinder_common::AllowedOrigins;
+use pathfinder_common::{AllowedOrigins, BlockNumber, ContractAddress, PublicKey};
 use pathfinder_storage::JournalMode;
 use reqwest::Url;
 use std::collections::HashSet;
 use std::net::SocketAddr;
-use std::num::NonZeroUsize;
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::PathBuf;
 
 use pathfinder_common::consts::VERGEN_GIT_DESCRIBE;
@@ -43,6 +43,8 @@ struct Cli {
         long = "ethereum.url",
         long_help = r"This should point to the HTTP RPC endpoint of your Ethereum entry-point, typically a local Ethereum client or a hosted gateway service such as Infura or Cloudflare.
 
+Required unless `settlement.layer` is set to `starknet` or `none`.
+
 Examples:
     infura: https://goerli.infura.io/v3/<PROJECT_ID>
     geth:   https://localhost:8545",
@@ -50,7 +52,39 @@ Examples:
         value_hint = clap::ValueHint::Url,
         env = "PATHFINDER_ETHEREUM_API_URL", 
     )]
-    ethereum_url: Url,
+    ethereum_url: Option<Url>,
+
+    #[arg(
+        long = "settlement.layer",
+        long_help = r"The layer the Starknet chain settles on, from which L1 state updates are synced.
+
+Possible values:
+    ethereum: the Starknet core contract on Ethereum, see `ethereum.url`
+    starknet: an appchain core contract on another Starknet network, see `settlement.starknet-url`
+    none:     disable L1 sync, e.g. for devnets. Blocks are never reported as accepted on L1",
+        value_name = "LAYER",
+        default_value = "ethereum",
+        env = "PATHFINDER_SETTLEMENT_LAYER"
+    )]
+    settlement_layer: SettlementLayer,
+
+    #[arg(
+        long = "settlement.starknet-url",
+        long_help = "The JSON-RPC endpoint of the Starknet network the chain settles on. Required if `settlement.layer` is `starknet`.",
+        value_name = "HTTP(s) URL",
+        value_hint = clap::ValueHint::Url,
+        env = "PATHFINDER_SETTLEMENT_STARKNET_URL"
+    )]
+    settlement_starknet_url: Option<Url>,
+
+    #[arg(
+        long = "settlement.core-contract",
+        long_help = "The address of the chain's core contract on the Starknet settlement network, as a hex string. Required if `settlement.layer` is `starknet`.",
+        value_name = "ADDRESS",
+        value_parser = parse_contract_address,
+        env = "PATHFINDER_SETTLEMENT_CORE_CONTRACT"
+    )]
+    settlement_core_contract: Option<ContractAddress>,
 
     #[arg(
         long = "http-rpc",
@@ -100,6 +134,14 @@ Examples:
     )]
     monitor_address: Option<SocketAddr>,
 
+    #[arg(
+        long = "feeder-gateway-server.address",
+        long_help = "Enables a feeder gateway compatible REST API at the given address, which other nodes can sync from using `--network custom`",
+        value_name = "IP:PORT",
+        env = "PATHFINDER_FEEDER_GATEWAY_SERVER_ADDRESS"
+    )]
+    feeder_gateway_server_address: Option<SocketAddr>,
+
     #[clap(flatten)]
     network: NetworkCli,
 
@@ -128,6 +170,93 @@ Examples:
     )]
     poll_interval: std::num::NonZeroU64,
 
+    #[arg(
+        long = "sync.look-ahead",
+        long_help = "The number of blocks to download concurrently ahead of the current sync position. \
+            Speeds up catching up with the chain when the feeder gateway latency is high. \
+            Setting this to 0 disables downloading ahead.",
+        value_name = "BLOCKS",
+        default_value = "8",
+        env = "PATHFINDER_SYNC_LOOK_AHEAD"
+    )]
+    sync_look_ahead: usize,
+
+    #[arg(
+        long = "sync.batch-size",
+        long_help = "The maximum number of blocks to store in a single database transaction while \
+            far behind the head of the chain. This reduces disk syncs during the initial sync. \
+            A value of 1 stores every block in its own transaction.",
+        value_name = "BLOCKS",
+        default_value = "1",
+        env = "PATHFINDER_SYNC_BATCH_SIZE"
+    )]
+    sync_batch_size: NonZeroUsize,
+
+    #[arg(
+        long = "sync.batch-timeout",
+        long_help = "The maximum time in seconds to wait for further blocks before storing a batch of blocks. \
+            See also `sync.batch-size`.",
+        value_name = "SECONDS",
+        default_value = "10",
+        env = "PATHFINDER_SYNC_BATCH_TIMEOUT_SECONDS"
+    )]
+    sync_batch_timeout: u64,
+
+    #[arg(
+        long = "sync.target-block",
+        long_help = "Stop syncing once this block has been reached. The node keeps serving RPC \
+            requests from its frozen state but does not follow the chain any further.",
+        value_name = "BLOCK_NUMBER",
+        value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64),
+        env = "PATHFINDER_SYNC_TARGET_BLOCK"
+    )]
+    sync_target_block: Option<u64>,
+
+    #[arg(
+        long = "sync.import-archive",
+        long_help = "Sync blocks from a local archive of feeder gateway replies instead of the \
+            feeder gateway. The archive is either a directory or an uncompressed tarball \
+            containing `block_<number>.json` (`get_state_update` including the block), \
+            `signature_<number>.json`, `class_<class hash>.json` and optionally \
+            `compiled_class_<class hash>.json` files. Sync idles once the archive's last \
+            block has been imported.",
+        value_name = "PATH",
+        env = "PATHFINDER_SYNC_IMPORT_ARCHIVE"
+    )]
+    sync_import_archive: Option<PathBuf>,
+
+    #[arg(
+        long = "compiler.isolated",
+        long_help = "Compile Sierra classes to CASM in a separate process. This protects the node \
+            from classes which make the compiler exhaust memory or run forever. Also enables \
+            compiling classes submitted via `starknet_addDeclareTransaction` before forwarding \
+            them. See also `compiler.max-memory` and `compiler.timeout`.",
+        action = clap::ArgAction::Set,
+        default_value = "false",
+        env = "PATHFINDER_COMPILER_ISOLATED"
+    )]
+    compiler_isolated: bool,
+
+    #[arg(
+        long = "compiler.max-memory",
+        long_help = "The maximum memory in MiB a compiler process may use. Only applies if \
+            `compiler.isolated` is enabled.",
+        value_name = "MiB",
+        default_value = "4096",
+        env = "PATHFINDER_COMPILER_MAX_MEMORY_MIB"
+    )]
+    compiler_max_memory: NonZeroU64,
+
+    #[arg(
+        long = "compiler.timeout",
+        long_help = "The time in seconds after which a compiler process is stopped and the \
+            compilation considered failed. Only applies if `compiler.isolated` is enabled.",
+        value_name = "SECONDS",
+        default_value = "60",
+        env = "PATHFINDER_COMPILER_TIMEOUT_SECONDS"
+    )]
+    compiler_timeout: NonZeroU64,
+
     #[arg(
         long = "color",
         long_help = "This flag controls when to use colors in the output logs.",
@@ -170,6 +299,46 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     verify_tree_node_data: bool,
 
+    #[arg(
+        long = "sync.l1-divergence-policy",
+        long_help = r"Action to take when the state root committed on Ethereum differs from the locally computed state commitment for the same block.
+
+In all cases an error is logged, a metric is emitted and the node is marked as not ready.
+
+Possible values:
+    warn:     keep serving RPC
+    stop-rpc: additionally stop serving RPC",
+        value_name = "POLICY",
+        default_value = "warn",
+        env = "PATHFINDER_SYNC_L1_DIVERGENCE_POLICY"
+    )]
+    l1_divergence_policy: L1DivergencePolicy,
+
+    #[arg(
+        long = "sync.verify-signatures",
+        long_help = r"Verify that each block was signed by the sequencer. This detects blocks which were tampered with, e.g. by a third-party feeder gateway proxy.
+
+The sequencer's public key is taken from `sync.sequencer-public-key` if set. Otherwise the known key is used for mainnet, and other networks require it to be set, since the feeder gateway being verified cannot vouch for its own key.
+
+Possible values:
+    disabled: don't verify signatures
+    warn:     log a warning and emit the `block_signature_invalid_total` metric
+    strict:   stop sync",
+        value_name = "MODE",
+        default_value = "disabled",
+        env = "PATHFINDER_SYNC_VERIFY_SIGNATURES"
+    )]
+    sync_verify_signatures: SignatureVerificationMode,
+
+    #[arg(
+        long = "sync.sequencer-public-key",
+        long_help = "The sequencer's public key used by `sync.verify-signatures`, as a hex string. Defaults to the known key of mainnet, or the key served by the feeder gateway.",
+        value_name = "KEY",
+        value_parser = parse_public_key,
+        env = "PATHFINDER_SYNC_SEQUENCER_PUBLIC_KEY"
+    )]
+    sync_sequencer_public_key: Option<PublicKey>,
+
     #[arg(
         long = "rpc.batch-concurrency-limit",
         long_help = "Sets the concurrency limit for request batch processing. \
@@ -208,6 +377,27 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     gateway_api_key: Option<String>,
 
+    #[arg(
+        long = "feeder-gateway-fallback-urls",
+        value_name = "URL LIST",
+        value_hint = clap::ValueHint::Url,
+        long_help = "Comma separated list of feeder gateway urls to fall back to if the network's feeder gateway is unavailable or slow. \
+            These can be any source serving the feeder gateway API, such as a gateway proxy or another pathfinder node. \
+            The chain head is only followed once all feeder gateways agree on it.",
+        value_delimiter = ',',
+        env = "PATHFINDER_FEEDER_GATEWAY_FALLBACK_URLS"
+    )]
+    feeder_gateway_fallbacks: Vec<Url>,
+
+    #[arg(
+        long = "feeder-gateway-hedge-delay",
+        value_name = "MILLISECONDS",
+        long_help = "The time in milliseconds after which a feeder gateway request is also sent to the next fallback feeder gateway.",
+        default_value = "2000",
+        env = "PATHFINDER_FEEDER_GATEWAY_HEDGE_DELAY_MILLIS"
+    )]
+    feeder_gateway_hedge_delay: u64,
+
     #[arg(
         long = "storage.event-bloom-filter-cache-size",
         long_help = "The number of blocks whose event bloom filters are cached in memory. \
@@ -257,6 +447,38 @@ impl Color {
     }
 }
 
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum L1DivergencePolicy {
+    Warn,
+    StopRpc,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum SettlementLayer {
+    Ethereum,
+    Starknet,
+    None,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum SignatureVerificationMode {
+    Disabled,
+    Warn,
+    Strict,
+}
+
+fn parse_public_key(input: &str) -> Result<PublicKey, String> {
+    pathfinder_crypto::Felt::from_hex_str(input)
+        .map(PublicKey)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_contract_address(input: &str) -> Result<ContractAddress, String> {
+    pathfinder_crypto::Felt::from_hex_str(input)
+        .map(ContractAddress)
+        .map_err(|e| e.to_string())
+}
+
 #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
 pub enum RpcVersion {
     V05,
@@ -270,7 +492,7 @@ struct NetworkCli {
         long = "network",
         long_help = r"Specify the Starknet network for pathfinder to operate on.
 
-Note that 'custom' requires also setting the --gateway-url and --feeder-gateway-url options.",
+Note that 'custom' requires also setting the --gateway-url and --feeder-gateway-url options, and either --chain-id or --chain-spec.",
         value_enum,
         env = "PATHFINDER_NETWORK"
     )]
@@ -281,9 +503,17 @@ Note that 'custom' requires also setting the --gateway-url and --feeder-gateway-
         long_help = "Set a custom Starknet chain ID (e.g. SN_GOERLI)",
         value_name = "CHAIN ID",
         env = "PATHFINDER_CHAIN_ID",
-        required_if_eq("network", Network::Custom)
+        conflicts_with = "chain_spec"
     )]
     chain_id: Option<String>,
+
+    #[arg(
+        long = "chain-spec",
+        long_help = "Path to a JSON or TOML chain specification of a custom Starknet network, selected by the '.json' or '.toml' file extension. Besides the chain ID it can declare the genesis block hash, the L1 core contract address, the fee token addresses, details of the block hash algorithm, the versioned constants, the sequencer's public key and the chain ID of the settlement network, which is then verified at startup. Requires '--network custom'.",
+        value_name = "PATH",
+        env = "PATHFINDER_CHAIN_SPEC"
+    )]
+    chain_spec: Option<PathBuf>,
     #[arg(
         long = "feeder-gateway-url",
         value_name = "URL",
@@ -325,12 +555,16 @@ struct P2PCli {
     identity_config_file: Option<std::path::PathBuf>,
     #[arg(
         long = "p2p.listen-on",
-        long_help = "The multiaddress on which to listen for incoming p2p connections. If not provided, default route on randomly assigned port will be used.",
-        value_name = "MULTIADDRESS",
+        long_help = r#"Comma separated list of multiaddresses on which to listen for incoming p2p connections. Both TCP and QUIC addresses are supported. If not provided, default route on randomly assigned port will be used.
+
+Example:
+    '/ip4/0.0.0.0/tcp/20002,/ip4/0.0.0.0/udp/20002/quic-v1'"#,
+        value_name = "MULTIADDRESS_LIST",
         default_value = "/ip4/0.0.0.0/tcp/0",
+        value_delimiter = ',',
         env = "PATHFINDER_P2P_LISTEN_ON"
     )]
-    listen_on: Multiaddr,
+    listen_on: Vec<Multiaddr>,
     #[arg(
         long = "p2p.bootstrap-addresses",
         long_help = r#"Comma separated list of multiaddresses to use as bootstrap nodes. Each multiaddress must contain a peer ID.
@@ -398,6 +632,78 @@ Example:
         env = "IP_WHITELIST"
     )]
     ip_whitelist: Vec<IpNet>,
+
+    #[arg(
+        long = "p2p.max-blocks-per-request",
+        long_help = "The maximum number of blocks served in response to a single sync request. Requests for more blocks are cut short.",
+        value_name = "MAX_BLOCKS",
+        env = "PATHFINDER_P2P_MAX_BLOCKS_PER_REQUEST",
+        default_value = "1000",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_blocks_per_request: u64,
+
+    #[arg(
+        long = "p2p.peer-requests-per-second",
+        long_help = "The maximum number of sync requests served to a single peer per second. Excess requests are rejected, and the peer is told to back off.",
+        value_name = "REQUESTS",
+        env = "PATHFINDER_P2P_PEER_REQUESTS_PER_SECOND",
+        default_value = "100"
+    )]
+    peer_requests_per_second: usize,
+
+    #[arg(
+        long = "p2p.total-requests-per-second",
+        long_help = "The maximum number of sync requests served to all peers combined per second. Excess requests are rejected, and the peers are told to back off.",
+        value_name = "REQUESTS",
+        env = "PATHFINDER_P2P_TOTAL_REQUESTS_PER_SECOND",
+        default_value = "1000"
+    )]
+    total_requests_per_second: usize,
+
+    #[arg(
+        long = "p2p.peer-bytes-per-second",
+        long_help = "The maximum number of sync response bytes served to a single peer per second. Responses are cut short once this is exceeded.",
+        value_name = "BYTES",
+        env = "PATHFINDER_P2P_PEER_BYTES_PER_SECOND",
+        default_value = "16777216"
+    )]
+    peer_bytes_per_second: usize,
+
+    #[arg(
+        long = "p2p.total-bytes-per-second",
+        long_help = "The maximum number of sync response bytes served to all peers combined per second. Responses are cut short once this is exceeded.",
+        value_name = "BYTES",
+        env = "PATHFINDER_P2P_TOTAL_BYTES_PER_SECOND",
+        default_value = "134217728"
+    )]
+    total_bytes_per_second: usize,
+
+    #[arg(
+        long = "p2p.max-concurrent-inbound-streams",
+        long_help = "The maximum number of sync requests a peer can have in flight at the same time, per protocol. Excess streams are closed immediately.",
+        value_name = "STREAMS",
+        env = "PATHFINDER_P2P_MAX_CONCURRENT_INBOUND_STREAMS",
+        default_value = "8"
+    )]
+    max_concurrent_inbound_streams: usize,
+
+    #[arg(
+        long = "p2p.snapshot-sync",
+        long_help = "When syncing from scratch, sync the state of the latest L1 checkpoint (or of the target block) as a snapshot instead of applying the state diff of every block since genesis. An interrupted snapshot is resumed after a restart. The transactions, receipts and events of blocks before the snapshot are synced once it completes, but not their state diffs, and Cairo 0 class definitions are not synced.",
+        default_value = "false",
+        action = clap::ArgAction::Set,
+        env = "PATHFINDER_P2P_SNAPSHOT_SYNC"
+    )]
+    snapshot_sync: bool,
+
+    #[arg(
+        long = "p2p.admin-rpc-address",
+        long_help = "Serve the pathfinder_p2p_peers, pathfinder_p2p_dial and pathfinder_p2p_disconnect JSON-RPC methods on this address, separately from the HTTP-RPC server. These allow anyone who can reach the address to manage the node's peers, so it must be a loopback address. The methods are not served if this is not set.",
+        value_name = "IP:PORT",
+        env = "PATHFINDER_P2P_ADMIN_RPC_ADDRESS"
+    )]
+    admin_rpc_address: Option<SocketAddr>,
 }
 
 #[cfg(feature = "p2p")]
@@ -518,30 +824,52 @@ enum RpcCorsDomainsParseError {
 
 pub struct Config {
     pub data_directory: PathBuf,
-    pub ethereum: Ethereum,
+    pub settlement: Settlement,
     pub rpc_address: SocketAddr,
     pub rpc_cors_domains: Option<AllowedOrigins>,
     pub rpc_root_version: RpcVersion,
     pub websocket: WebsocketConfig,
     pub monitor_address: Option<SocketAddr>,
+    pub feeder_gateway_server_address: Option<SocketAddr>,
     pub network: Option<NetworkConfig>,
     pub execution_concurrency: Option<std::num::NonZeroU32>,
     pub sqlite_wal: JournalMode,
     pub max_rpc_connections: std::num::NonZeroUsize,
     pub poll_interval: std::time::Duration,
+    pub sync_look_ahead: usize,
+    pub sync_batch_size: NonZeroUsize,
+    pub sync_batch_timeout: std::time::Duration,
+    pub sync_target_block: Option<BlockNumber>,
+    pub sync_import_archive: Option<PathBuf>,
+    pub compiler: pathfinder_compiler::Compiler,
     pub color: Color,
     pub p2p: P2PConfig,
     pub debug: DebugConfig,
     pub verify_tree_hashes: bool,
+    pub l1_divergence_policy: L1DivergencePolicy,
+    pub sync_verify_signatures: SignatureVerificationMode,
+    pub sync_sequencer_public_key: Option<PublicKey>,
     pub rpc_batch_concurrency_limit: NonZeroUsize,
     pub is_sync_enabled: bool,
     pub is_rpc_enabled: bool,
     pub gateway_api_key: Option<String>,
+    pub feeder_gateway_fallbacks: Vec<Url>,
+    pub feeder_gateway_hedge_delay: std::time::Duration,
     pub event_bloom_filter_cache_size: NonZeroUsize,
     pub get_events_max_blocks_to_scan: NonZeroUsize,
     pub get_events_max_uncached_bloom_filters_to_load: NonZeroUsize,
 }
 
+pub enum Settlement {
+    Ethereum(Ethereum),
+    Starknet {
+        url: Url,
+        core_contract: ContractAddress,
+    },
+    /// L1 sync is disabled.
+    None,
+}
+
 pub struct Ethereum {
     pub url: Url,
     pub password: Option<String>,
@@ -556,15 +884,23 @@ pub enum NetworkConfig {
     Custom {
         gateway: Url,
         feeder_gateway: Url,
-        chain_id: String,
+        chain: CustomChain,
     },
 }
 
+pub enum CustomChain {
+    /// All other parameters of the chain are those of the public networks, or are
+    /// fetched from the gateway.
+    Id(String),
+    /// Path to a [chain specification](crate::chain_spec) file.
+    Spec(PathBuf),
+}
+
 #[cfg(feature = "p2p")]
 pub struct P2PConfig {
     pub proxy: bool,
     pub identity_config_file: Option<std::path::PathBuf>,
-    pub listen_on: Multiaddr,
+    pub listen_on: Vec<Multiaddr>,
     pub bootstrap_addresses: Vec<Multiaddr>,
     pub predefined_peers: Vec<Multiaddr>,
     pub max_inbound_direct_connections: usize,
@@ -572,6 +908,14 @@ pub struct P2PConfig {
     pub max_outbound_connections: usize,
     pub ip_whitelist: Vec<IpNet>,
     pub low_watermark: usize,
+    pub max_blocks_per_request: u64,
+    pub peer_requests_per_second: usize,
+    pub total_requests_per_second: usize,
+    pub peer_bytes_per_second: usize,
+    pub total_bytes_per_second: usize,
+    pub max_concurrent_inbound_streams: usize,
+    pub snapshot_sync: bool,
+    pub admin_rpc_address: Option<SocketAddr>,
 }
 
 #[cfg(not(feature = "p2p"))]
@@ -582,30 +926,85 @@ pub struct DebugConfig {
     pub restart_delay: std::time::Duration,
 }
 
+impl Settlement {
+    fn parse_or_exit(
+        layer: SettlementLayer,
+        ethereum_url: Option<Url>,
+        ethereum_password: Option<String>,
+        starknet_url: Option<Url>,
+        core_contract: Option<ContractAddress>,
+    ) -> Self {
+        use clap::error::ErrorKind;
+
+        match (layer, ethereum_url, starknet_url, core_contract) {
+            (SettlementLayer::Ethereum, Some(url), None, None) => Settlement::Ethereum(Ethereum {
+                url,
+                password: ethereum_password,
+            }),
+            (SettlementLayer::Ethereum, None, _, _) => Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--ethereum.url is required when settling on Ethereum",
+                )
+                .exit(),
+            (SettlementLayer::Starknet, _, Some(url), Some(core_contract)) => {
+                Settlement::Starknet { url, core_contract }
+            }
+            (SettlementLayer::Starknet, _, _, _) => Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--settlement.layer starknet requires --settlement.starknet-url and --settlement.core-contract",
+                )
+                .exit(),
+            (SettlementLayer::None, _, None, None) => Settlement::None,
+            _ => Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--settlement.starknet-url and --settlement.core-contract may only be used with --settlement.layer starknet",
+                )
+                .exit(),
+        }
+    }
+}
+
 impl NetworkConfig {
     fn from_components(args: NetworkCli) -> Option<Self> {
+        use clap::error::ErrorKind;
         use Network::*;
+
         let cfg = match (
             args.network,
             args.gateway,
             args.feeder_gateway,
             args.chain_id,
+            args.chain_spec,
         ) {
-            (None, None, None, None) => return None,
-            (Some(Custom), Some(gateway), Some(feeder_gateway), Some(chain_id)) => {
+            (None, None, None, None, None) => return None,
+            (Some(Custom), Some(gateway), Some(feeder_gateway), chain_id, chain_spec) => {
+                let chain = match (chain_id, chain_spec) {
+                    (Some(chain_id), None) => CustomChain::Id(chain_id),
+                    (None, Some(path)) => CustomChain::Spec(path),
+                    _ => Cli::command()
+                        .error(
+                            ErrorKind::MissingRequiredArgument,
+                            "--network custom requires either --chain-id or --chain-spec",
+                        )
+                        .exit(),
+                };
+
                 NetworkConfig::Custom {
                     gateway,
                     feeder_gateway,
-                    chain_id,
+                    chain,
                 }
             }
-            (Some(Custom), _, _, _) => {
+            (Some(Custom), _, _, _, _) => {
                 unreachable!("`--network custom` requirements are handled by clap derive")
             }
             // Handle non-custom variants in an inner match so that the compiler will force
             // us to handle a new network variants explicitly. Otherwise we end up with a
             // catch-all arm that would swallow new variants silently.
-            (Some(non_custom), None, None, None) => match non_custom {
+            (Some(non_custom), None, None, None, None) => match non_custom {
                 Mainnet => NetworkConfig::Mainnet,
                 GoerliTestnet => NetworkConfig::GoerliTestnet,
                 GoerliIntegration => NetworkConfig::GoerliIntegration,
@@ -616,9 +1015,7 @@ impl NetworkConfig {
             // clap does not support disallowing args based on an enum value, so we have check for
             // `--network non-custom` + custom required args manually.
             _ => {
-                use clap::error::ErrorKind;
-
-                Cli::command().error(ErrorKind::ArgumentConflict, "--gateway-url, --feeder-gateway-url and --chain-id may only be used with --network custom").exit()
+                Cli::command().error(ErrorKind::ArgumentConflict, "--gateway-url, --feeder-gateway-url, --chain-id and --chain-spec may only be used with --network custom").exit()
             }
         };
 
@@ -679,6 +1076,17 @@ impl P2PConfig {
                 .exit()
         }
 
+        if let Some(address) = args.admin_rpc_address {
+            if !address.ip().is_loopback() {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "p2p.admin-rpc-address must be a loopback address",
+                    )
+                    .exit()
+            }
+        }
+
         Self {
             max_inbound_direct_connections: args.max_inbound_direct_connections.try_into().unwrap(),
             max_inbound_relayed_connections: args
@@ -693,6 +1101,14 @@ impl P2PConfig {
             predefined_peers: parse_multiaddr_vec(args.predefined_peers),
             ip_whitelist: args.ip_whitelist,
             low_watermark: 0,
+            max_blocks_per_request: args.max_blocks_per_request,
+            peer_requests_per_second: args.peer_requests_per_second,
+            total_requests_per_second: args.total_requests_per_second,
+            peer_bytes_per_second: args.peer_bytes_per_second,
+            total_bytes_per_second: args.total_bytes_per_second,
+            max_concurrent_inbound_streams: args.max_concurrent_inbound_streams,
+            snapshot_sync: args.snapshot_sync,
+            admin_rpc_address: args.admin_rpc_address,
         }
     }
 }
@@ -725,15 +1141,19 @@ impl Config {
 
         Config {
             data_directory: cli.data_directory,
-            ethereum: Ethereum {
-                password: cli.ethereum_password,
-                url: cli.ethereum_url,
-            },
+            settlement: Settlement::parse_or_exit(
+                cli.settlement_layer,
+                cli.ethereum_url,
+                cli.ethereum_password,
+                cli.settlement_starknet_url,
+                cli.settlement_core_contract,
+            ),
             rpc_address: cli.rpc_address,
             rpc_cors_domains: parse_cors_or_exit(cli.rpc_cors_domains),
             rpc_root_version: cli.rpc_root_version,
             websocket: cli.websocket,
             monitor_address: cli.monitor_address,
+            feeder_gateway_server_address: cli.feeder_gateway_server_address,
             network,
             execution_concurrency: cli.execution_concurrency,
             sqlite_wal: match cli.sqlite_wal {
@@ -742,14 +1162,33 @@ impl Config {
             },
             max_rpc_connections: cli.max_rpc_connections,
             poll_interval: std::time::Duration::from_secs(cli.poll_interval.get()),
+            sync_look_ahead: cli.sync_look_ahead,
+            sync_batch_size: cli.sync_batch_size,
+            sync_batch_timeout: std::time::Duration::from_secs(cli.sync_batch_timeout),
+            sync_target_block: cli.sync_target_block.map(BlockNumber::new_or_panic),
+            sync_import_archive: cli.sync_import_archive,
+            compiler: match cli.compiler_isolated {
+                true => pathfinder_compiler::Compiler::Isolated(pathfinder_compiler::Limits {
+                    max_memory: cli.compiler_max_memory.get() * 1024 * 1024,
+                    timeout: std::time::Duration::from_secs(cli.compiler_timeout.get()),
+                }),
+                false => pathfinder_compiler::Compiler::InProcess,
+            },
             color: cli.color,
             p2p: P2PConfig::parse_or_exit(cli.p2p),
             debug: DebugConfig::parse(cli.debug),
             verify_tree_hashes: cli.verify_tree_node_data,
+            l1_divergence_policy: cli.l1_divergence_policy,
+            sync_verify_signatures: cli.sync_verify_signatures,
+            sync_sequencer_public_key: cli.sync_sequencer_public_key,
             rpc_batch_concurrency_limit: cli.rpc_batch_concurrency_limit,
             is_sync_enabled: cli.is_sync_enabled,
             is_rpc_enabled: cli.is_rpc_enabled,
             gateway_api_key: cli.gateway_api_key,
+            feeder_gateway_fallbacks: cli.feeder_gateway_fallbacks,
+            feeder_gateway_hedge_delay: std::time::Duration::from_millis(
+                cli.feeder_gateway_hedge_delay,
+            ),
             event_bloom_filter_cache_size: cli.event_bloom_filter_cache_size,
             get_events_max_blocks_to_scan: cli.get_events_max_blocks_to_scan,
             get_events_max_uncached_bloom_filters_to_load: cli
@@ -4,8 +4,11 @@ use anyhow::Context;
 use metrics_exporter_prometheus::PrometheusBuilder;
 use mimalloc::MiMalloc;
 
-use pathfinder_common::{consts::VERGEN_GIT_DESCRIBE, BlockNumber, Chain, ChainId, EthereumChain};
-use pathfinder_ethereum::{EthereumApi, EthereumClient};
+use pathfinder_common::{
+    consts::VERGEN_GIT_DESCRIBE, BlockHash, BlockNumber, Chain, ChainId, EthereumChain, PublicKey,
+};
+use pathfinder_ethereum::starknet::StarknetCoreContract;
+use pathfinder_ethereum::{EthereumApi, EthereumClient, SettlementClient};
 use pathfinder_lib::monitoring::{self};
 use pathfinder_lib::state;
 use pathfinder_lib::state::SyncContext;
@@ -22,6 +25,7 @@ use tracing::info;
 
 use crate::config::NetworkConfig;
 
+mod chain_spec;
 mod config;
 mod update;
 
@@ -29,6 +33,10 @@ mod update;
 static GLOBAL: MiMalloc = MiMalloc;
 
 fn main() -> anyhow::Result<()> {
+    // Sierra classes may be compiled in a child process of this executable, in which case
+    // this does not return.
+    pathfinder_compiler::run_if_child();
+
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .thread_stack_size(8 * 1024 * 1024)
@@ -65,16 +73,36 @@ async fn async_main() -> anyhow::Result<()> {
     // A readiness flag which is used to indicate that pathfinder is ready via monitoring.
     let readiness = Arc::new(AtomicBool::new(false));
 
-    let ethereum = EthereumContext::setup(config.ethereum.url, config.ethereum.password)
-        .await
-        .context("Creating Ethereum context")?;
+    // Archive imports run without network access, so blocks are neither fetched from nor
+    // checked against the feeder gateway or the settlement layer.
+    let archive = config
+        .sync_import_archive
+        .as_deref()
+        .map(|path| {
+            starknet_gateway_client::Archive::open(path)
+                .with_context(|| format!("Opening archive {}", path.display()))
+        })
+        .transpose()?;
+
+    let ethereum = match &config.settlement {
+        _ if archive.is_some() => None,
+        config::Settlement::Ethereum(ethereum) => Some(
+            EthereumContext::setup(ethereum.url.clone(), ethereum.password.clone())
+                .await
+                .context("Creating Ethereum context")?,
+        ),
+        config::Settlement::Starknet { .. } | config::Settlement::None => None,
+    };
 
     // Use the default starknet network if none was configured.
-    let network = match config.network {
-        Some(network) => network,
-        None => ethereum
+    let network = match (config.network, &ethereum) {
+        (Some(network), _) => network,
+        (None, Some(ethereum)) => ethereum
             .default_network()
             .context("Using default Starknet network based on Ethereum configuration")?,
+        (None, None) => {
+            anyhow::bail!("A Starknet network must be configured when not settling on Ethereum")
+        }
     };
 
     // Spawn monitoring if configured.
@@ -92,15 +120,53 @@ async fn async_main() -> anyhow::Result<()> {
             .context("Starting monitoring task")?;
     }
 
-    let pathfinder_context = PathfinderContext::configure_and_proxy_check(
+    let mut pathfinder_context = PathfinderContext::configure_and_proxy_check(
         network,
         config.data_directory,
         config.gateway_api_key,
+        ethereum.is_some(),
     )
     .await
     .context("Configuring pathfinder")?;
-
-    verify_networks(pathfinder_context.network, ethereum.chain)?;
+    pathfinder_context.gateway = pathfinder_context
+        .gateway
+        .with_feeder_gateway_fallbacks(config.feeder_gateway_fallbacks)
+        .with_hedge_delay(config.feeder_gateway_hedge_delay);
+
+    let settlement = match config.settlement {
+        _ if archive.is_some() => {
+            info!("Importing blocks from an archive, L1 sync is disabled");
+            None
+        }
+        config::Settlement::Ethereum(_) => {
+            let ethereum = ethereum.expect("Ethereum context is set up for Ethereum settlement");
+            verify_networks(
+                pathfinder_context.network,
+                ethereum.chain,
+                pathfinder_context.chain_spec.as_ref(),
+            )?;
+            Some(SettlementClient::Ethereum(ethereum.client))
+        }
+        config::Settlement::Starknet { url, core_contract } => {
+            info!(%url, core_contract=%core_contract.0, "Settling on Starknet");
+            let client = StarknetCoreContract::new(url, core_contract)
+                .context("Creating Starknet settlement client")?;
+            let chain = client
+                .get_chain()
+                .await
+                .context("Determining the settlement chain")?;
+            verify_networks(
+                pathfinder_context.network,
+                chain,
+                pathfinder_context.chain_spec.as_ref(),
+            )?;
+            Some(SettlementClient::Starknet(client))
+        }
+        config::Settlement::None => {
+            info!("No settlement layer configured, blocks will not be accepted on L1");
+            None
+        }
+    };
 
     // Setup and verify database
 
@@ -156,12 +222,30 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         )?;
 
     info!(location=?pathfinder_context.database, "Database migrated.");
-    verify_database(
-        &sync_storage,
-        pathfinder_context.network,
-        &pathfinder_context.gateway,
-    )
-    .await
+    let custom_genesis = pathfinder_context
+        .chain_spec
+        .as_ref()
+        .and_then(|spec| spec.genesis_hash);
+    match &archive {
+        Some(archive) => {
+            verify_database(
+                &sync_storage,
+                pathfinder_context.network,
+                custom_genesis,
+                archive,
+            )
+            .await
+        }
+        None => {
+            verify_database(
+                &sync_storage,
+                pathfinder_context.network,
+                custom_genesis,
+                &pathfinder_context.gateway,
+            )
+            .await
+        }
+    }
     .context("Verifying database")?;
 
     let sync_state = Arc::new(SyncState::default());
@@ -175,6 +259,27 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
             .get_events_max_uncached_bloom_filters_to_load,
     };
 
+    let sequencer_public_key = config.sync_sequencer_public_key.or(pathfinder_context
+        .chain_spec
+        .as_ref()
+        .and_then(|spec| spec.sequencer_public_key));
+
+    let feeder_gateway_handle = match config.feeder_gateway_server_address {
+        Some(address) => {
+            let (handle, local_addr) = pathfinder_lib::feeder_gateway::spawn_server(
+                address,
+                rpc_storage.clone(),
+                pathfinder_rpc::PendingWatcher::new(rx_pending.clone()),
+                pathfinder_context.l1_core_address,
+                sequencer_public_key.or(known_sequencer_public_key(pathfinder_context.network)),
+            )
+            .context("Starting the feeder gateway server")?;
+            info!("📡 Feeder gateway server started on: {}", local_addr);
+            handle
+        }
+        None => tokio::spawn(std::future::pending()),
+    };
+
     let context = pathfinder_rpc::context::RpcContext::new(
         rpc_storage,
         execution_storage,
@@ -183,7 +288,13 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         pathfinder_context.gateway.clone(),
         rx_pending,
         rpc_config,
-    );
+    )
+    .with_compiler(config.compiler);
+
+    let context = match &pathfinder_context.chain_spec {
+        Some(spec) => context.with_chain_config(spec.execution.clone()),
+        None => context,
+    };
 
     let context = if config.websocket.enabled {
         context.with_websockets(WebsocketContext::new(
@@ -194,6 +305,28 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         context
     };
 
+    let (p2p_handle, gossiper, p2p_client, p2p_admin) = start_p2p(
+        pathfinder_context.network_id,
+        p2p_storage,
+        config.p2p,
+        &pathfinder_context.database,
+        pathfinder_context.gateway.clone(),
+        config.poll_interval,
+    )
+    .await?;
+
+    let p2p_admin_handle = match p2p_admin {
+        #[cfg(feature = "p2p")]
+        Some((address, client)) => {
+            let (handle, local_addr) =
+                pathfinder_rpc::spawn_p2p_admin(address, context.clone().with_p2p(client))
+                    .context("Starting the p2p admin RPC server")?;
+            info!("📡 P2P admin RPC server started on: {}", local_addr);
+            handle
+        }
+        _ => tokio::spawn(std::future::pending::<anyhow::Result<()>>()),
+    };
+
     let default_version = match config.rpc_root_version {
         config::RpcVersion::V05 => pathfinder_rpc::DefaultVersion::V05,
         config::RpcVersion::V06 => pathfinder_rpc::DefaultVersion::V06,
@@ -206,14 +339,28 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         None => rpc_server,
     };
 
-    let (p2p_handle, gossiper) =
-        start_p2p(pathfinder_context.network_id, p2p_storage, config.p2p).await?;
+    let (l1_divergence_tx, l1_divergence_rx) = tokio::sync::watch::channel(None);
+
+    let signature_verification = signature_verification(
+        config.sync_verify_signatures,
+        sequencer_public_key,
+        pathfinder_context.network,
+        &pathfinder_context.gateway,
+    )
+    .await
+    .context("Configuring block signature verification")?;
+
+    let block_hash_meta = match pathfinder_context.chain_spec {
+        Some(spec) => spec.block_hash_meta,
+        None => state::block_hash::meta::for_chain(pathfinder_context.network).clone(),
+    };
 
     let sync_context = SyncContext {
         storage: sync_storage,
-        ethereum: ethereum.client,
+        ethereum: settlement,
         chain: pathfinder_context.network,
         chain_id: pathfinder_context.network_id,
+        block_hash_meta,
         core_address: pathfinder_context.l1_core_address,
         sequencer: pathfinder_context.gateway,
         state: sync_state.clone(),
@@ -223,15 +370,56 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         block_validation_mode: state::l2::BlockValidationMode::Strict,
         websocket_txs: rpc_server.get_topic_broadcasters().cloned(),
         block_cache_size: 1_000,
+        look_ahead: config.sync_look_ahead,
         restart_delay: config.debug.restart_delay,
         verify_tree_hashes: config.verify_tree_hashes,
         gossiper,
+        readiness: readiness.clone(),
+        l1_divergence: l1_divergence_tx,
+        block_batching: state::BlockBatching {
+            max_blocks: config.sync_batch_size,
+            max_duration: config.sync_batch_timeout,
+        },
+        target_block: config.sync_target_block,
+        compiler: config.compiler,
+        signature_verification,
     };
 
-    let sync_handle = if config.is_sync_enabled {
-        tokio::spawn(state::sync(sync_context, state::l1::sync, state::l2::sync))
-    } else {
-        tokio::spawn(std::future::pending())
+    // Kept to check for a divergence detected before we declare readiness.
+    let l1_diverged = l1_divergence_rx.clone();
+
+    let sync_handle = match (config.is_sync_enabled, archive, p2p_client) {
+        (false, ..) => tokio::spawn(std::future::pending()),
+        (true, Some(archive), _) => {
+            info!("Importing blocks from archive");
+
+            tokio::spawn(state::sync(
+                sync_context.with_sequencer(archive),
+                state::l1::sync,
+                state::l2::sync,
+            ))
+        }
+        #[cfg(feature = "p2p")]
+        (true, None, Some((p2p_client, heads, snapshot_sync))) => {
+            let settlement = sync_context
+                .ethereum
+                .clone()
+                .context("Syncing from the p2p network requires a settlement layer")?;
+            info!("Syncing from the p2p network");
+
+            let sync = pathfinder_lib::sync::p2p::Sync::new(
+                sync_context.storage.clone(),
+                p2p_client,
+                (settlement, sync_context.core_address),
+                sync_context.chain_id,
+                sync_context.target_block,
+                snapshot_sync,
+            );
+            tokio::spawn(sync_from_p2p(sync, heads, sync_context.head_poll_interval))
+        }
+        (true, None, _) => {
+            tokio::spawn(state::sync(sync_context, state::l1::sync, state::l2::sync))
+        }
     };
 
     let rpc_handle = if config.is_rpc_enabled {
@@ -240,15 +428,24 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
             .spawn()
             .context("Starting the RPC server")?;
         info!("📡 HTTP-RPC server started on: {}", local_addr);
-        rpc_handle
+        match config.l1_divergence_policy {
+            config::L1DivergencePolicy::Warn => rpc_handle,
+            config::L1DivergencePolicy::StopRpc => {
+                tokio::spawn(stop_rpc_on_l1_divergence(rpc_handle, l1_divergence_rx))
+            }
+        }
     } else {
         tokio::spawn(std::future::pending())
     };
 
-    tokio::spawn(update::poll_github_for_releases());
+    if config.sync_import_archive.is_none() {
+        tokio::spawn(update::poll_github_for_releases());
+    }
 
-    // We are now ready.
-    readiness.store(true, std::sync::atomic::Ordering::Relaxed);
+    // We are now ready, unless sync has already detected an L1 state divergence.
+    if l1_diverged.borrow().is_none() {
+        readiness.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
 
     // Monitor our critical spawned process tasks.
     tokio::select! {
@@ -270,6 +467,18 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
                 Err(err) => tracing::error!(error=%err, "P2P process ended unexpectedly"),
             }
         }
+        result = feeder_gateway_handle => {
+            match result {
+                Ok(_) => tracing::error!("Feeder gateway server process ended unexpectedly"),
+                Err(err) => tracing::error!(error=%err, "Feeder gateway server process ended unexpectedly"),
+            }
+        }
+        result = p2p_admin_handle => {
+            match result {
+                Ok(_) => tracing::error!("P2P admin RPC server process ended unexpectedly"),
+                Err(err) => tracing::error!(error=%err, "P2P admin RPC server process ended unexpectedly"),
+            }
+        }
     }
 
     anyhow::bail!("Unexpected shutdown");
@@ -330,14 +539,24 @@ fn permission_check(base: &std::path::Path) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Returns the p2p client if the node should sync from the p2p network instead of
+/// proxying the feeder gateway to it.
 #[cfg(feature = "p2p")]
 async fn start_p2p(
     chain_id: ChainId,
     storage: Storage,
     config: config::P2PConfig,
-) -> anyhow::Result<(tokio::task::JoinHandle<()>, state::Gossiper)> {
+    database: &std::path::Path,
+    gateway: starknet_gateway_client::Client,
+    poll_interval: std::time::Duration,
+) -> anyhow::Result<(
+    tokio::task::JoinHandle<()>,
+    state::Gossiper,
+    Option<(p2p::client::peer_agnostic::Client, p2p::HeadRx, bool)>,
+    Option<(std::net::SocketAddr, p2p::client::peer_aware::Client)>,
+)> {
     use p2p::libp2p::identity::Keypair;
-    use pathfinder_lib::p2p_network::P2PContext;
+    use pathfinder_lib::p2p_network::{P2PContext, SyncLimits};
     use serde::Deserialize;
     use std::{path::Path, time::Duration};
     use zeroize::Zeroizing;
@@ -371,6 +590,13 @@ async fn start_p2p(
         }
     };
 
+    let peers_file = database.with_extension("peers.json");
+
+    let per_second = |max| p2p::RateLimit {
+        max,
+        interval: Duration::from_secs(1),
+    };
+
     let context = P2PContext {
         cfg: p2p::Config {
             direct_connection_timeout: Duration::from_secs(30),
@@ -382,6 +608,10 @@ async fn start_p2p(
             ip_whitelist: config.ip_whitelist,
             bootstrap: Default::default(),
             eviction_timeout: Duration::from_secs(15 * 60),
+            peers_file: Some(peers_file),
+            max_concurrent_inbound_streams: config.max_concurrent_inbound_streams,
+            // Charges the sync limits below, see `p2p_network::start`.
+            response_meter: None,
             inbound_connections_rate_limit: p2p::RateLimit {
                 max: 10,
                 interval: Duration::from_secs(1),
@@ -389,17 +619,43 @@ async fn start_p2p(
         },
         chain_id,
         storage,
-        proxy: config.proxy,
         keypair,
         listen_on: config.listen_on,
         bootstrap_addresses: config.bootstrap_addresses,
         predefined_peers: config.predefined_peers,
+        sync_limits: SyncLimits {
+            max_blocks_per_request: config.max_blocks_per_request,
+            peer_requests: per_second(config.peer_requests_per_second),
+            total_requests: per_second(config.total_requests_per_second),
+            peer_bytes: per_second(config.peer_bytes_per_second),
+            total_bytes: per_second(config.total_bytes_per_second),
+        },
     };
 
-    let (p2p_client, _head_receiver, p2p_handle) =
-        pathfinder_lib::p2p_network::start(context).await?;
+    let (p2p_client, heads, p2p_handle) = pathfinder_lib::p2p_network::start(context).await?;
+
+    let sync_client = if config.proxy {
+        tokio::spawn(pathfinder_lib::p2p_network::cross_check_heads(
+            heads,
+            gateway,
+            p2p_client.clone(),
+            poll_interval,
+        ));
+        None
+    } else {
+        Some((p2p_client.clone(), heads, config.snapshot_sync))
+    };
 
-    Ok((p2p_handle, state::Gossiper::new(p2p_client)))
+    let admin = config
+        .admin_rpc_address
+        .map(|address| (address, p2p_client.peer_aware().clone()));
+
+    Ok((
+        p2p_handle,
+        state::Gossiper::new(p2p_client),
+        sync_client,
+        admin,
+    ))
 }
 
 #[cfg(not(feature = "p2p"))]
@@ -407,10 +663,101 @@ async fn start_p2p(
     _: ChainId,
     _: Storage,
     _: config::P2PConfig,
-) -> anyhow::Result<(tokio::task::JoinHandle<()>, state::Gossiper)> {
+    _: &std::path::Path,
+    _: starknet_gateway_client::Client,
+    _: std::time::Duration,
+) -> anyhow::Result<(
+    tokio::task::JoinHandle<()>,
+    state::Gossiper,
+    Option<std::convert::Infallible>,
+    Option<std::convert::Infallible>,
+)> {
     let join_handle = tokio::task::spawn(futures::future::pending());
 
-    Ok((join_handle, Default::default()))
+    Ok((join_handle, Default::default(), None, None))
+}
+
+/// Syncs from the p2p network up to the latest L1 checkpoint, polling for new checkpoints.
+/// In between, syncs up to the latest head announced by our peers as soon as it arrives.
+#[cfg(feature = "p2p")]
+async fn sync_from_p2p(
+    sync: pathfinder_lib::sync::p2p::Sync,
+    mut heads: p2p::HeadRx,
+    poll_interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    /// Syncing up to an announced head is abandoned after this long. Newer heads are picked up
+    /// once it is.
+    const HEAD_SYNC_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+    let sync = std::sync::Arc::new(sync);
+    let mut checkpoint_poll = tokio::time::interval(poll_interval);
+    checkpoint_poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // Syncs up to the latest announced head. Only one runs at a time, and heads announced in the
+    // meantime are picked up once it is done.
+    let mut head_sync: Option<tokio::task::JoinHandle<()>> = None;
+    let mut heads_open = true;
+
+    loop {
+        tokio::select! {
+            _ = checkpoint_poll.tick() => {
+                // Both write the same tables, so they must not run at the same time.
+                if let Some(head_sync) = head_sync.take() {
+                    let _ = head_sync.await;
+                }
+                if let Err(error) = sync.run().await {
+                    tracing::error!(reason=?error, "P2P sync failed");
+                }
+            }
+            changed = heads.changed(), if heads_open && head_sync.is_none() => {
+                if changed.is_err() {
+                    heads_open = false;
+                    continue;
+                }
+                let Some(head) = *heads.borrow_and_update() else {
+                    continue;
+                };
+
+                let sync = sync.clone();
+                head_sync = Some(tokio::spawn(async move {
+                    match tokio::time::timeout(HEAD_SYNC_TIMEOUT, sync.sync_head(head)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(error)) => {
+                            tracing::error!(reason=?error, "P2P sync of announced head failed")
+                        }
+                        Err(_) => {
+                            tracing::warn!(head=%head.number, "P2P sync of announced head timed out")
+                        }
+                    }
+                }));
+            }
+            _ = async { head_sync.as_mut().expect("Head sync is running").await }, if head_sync.is_some() => {
+                head_sync = None;
+            }
+        }
+    }
+}
+
+/// Stops the RPC server once sync reports that the L1 state root diverged from our
+/// local state commitment. The node itself keeps running so that it can be inspected.
+async fn stop_rpc_on_l1_divergence(
+    mut rpc_handle: tokio::task::JoinHandle<()>,
+    mut l1_divergence: tokio::sync::watch::Receiver<Option<state::StateDivergence>>,
+) {
+    tokio::select! {
+        _ = &mut rpc_handle => return,
+        result = l1_divergence.wait_for(Option::is_some) => {
+            if result.is_err() {
+                // Sync has shut down, so no divergence can be reported anymore.
+                _ = rpc_handle.await;
+                return;
+            }
+        }
+    }
+
+    tracing::error!("Stopping the RPC server due to an L1 state divergence");
+    rpc_handle.abort();
+
+    std::future::pending::<()>().await
 }
 
 /// Spawns the monitoring task at the given address.
@@ -480,12 +827,15 @@ struct PathfinderContext {
     gateway: starknet_gateway_client::Client,
     database: PathBuf,
     l1_core_address: H160,
+    /// The chain specification of a custom network, if one was given.
+    chain_spec: Option<chain_spec::ChainSpec>,
 }
 
 /// Used to hide private fn's for [PathfinderContext].
 mod pathfinder_context {
     use super::PathfinderContext;
-    use crate::config::NetworkConfig;
+    use crate::chain_spec::ChainSpec;
+    use crate::config::{CustomChain, NetworkConfig};
 
     use std::path::PathBuf;
 
@@ -501,6 +851,7 @@ mod pathfinder_context {
             cfg: NetworkConfig,
             data_directory: PathBuf,
             api_key: Option<String>,
+            settles_on_ethereum: bool,
         ) -> anyhow::Result<Self> {
             let context = match cfg {
                 NetworkConfig::Mainnet => Self {
@@ -509,6 +860,7 @@ mod pathfinder_context {
                     gateway: GatewayClient::mainnet().with_api_key(api_key),
                     database: data_directory.join("mainnet.sqlite"),
                     l1_core_address: H160::from(core_addr::MAINNET),
+                    chain_spec: None,
                 },
                 NetworkConfig::GoerliTestnet => Self {
                     network: Chain::GoerliTestnet,
@@ -516,6 +868,7 @@ mod pathfinder_context {
                     gateway: GatewayClient::goerli_testnet().with_api_key(api_key),
                     database: data_directory.join("goerli.sqlite"),
                     l1_core_address: H160::from(core_addr::GOERLI_TESTNET),
+                    chain_spec: None,
                 },
                 NetworkConfig::GoerliIntegration => Self {
                     network: Chain::GoerliIntegration,
@@ -523,6 +876,7 @@ mod pathfinder_context {
                     gateway: GatewayClient::goerli_integration().with_api_key(api_key),
                     database: data_directory.join("integration.sqlite"),
                     l1_core_address: H160::from(core_addr::GOERLI_INTEGRATION),
+                    chain_spec: None,
                 },
                 NetworkConfig::SepoliaTestnet => Self {
                     network: Chain::SepoliaTestnet,
@@ -530,6 +884,7 @@ mod pathfinder_context {
                     gateway: GatewayClient::sepolia_testnet().with_api_key(api_key),
                     database: data_directory.join("testnet-sepolia.sqlite"),
                     l1_core_address: H160::from(core_addr::SEPOLIA_TESTNET),
+                    chain_spec: None,
                 },
                 NetworkConfig::SepoliaIntegration => Self {
                     network: Chain::SepoliaIntegration,
@@ -537,17 +892,19 @@ mod pathfinder_context {
                     gateway: GatewayClient::sepolia_integration().with_api_key(api_key),
                     database: data_directory.join("integration-sepolia.sqlite"),
                     l1_core_address: H160::from(core_addr::SEPOLIA_INTEGRATION),
+                    chain_spec: None,
                 },
                 NetworkConfig::Custom {
                     gateway,
                     feeder_gateway,
-                    chain_id,
+                    chain,
                 } => Self::configure_custom(
                     gateway,
                     feeder_gateway,
-                    chain_id,
+                    chain,
                     data_directory,
                     api_key,
+                    settles_on_ethereum,
                 )
                 .await
                 .context("Configuring custom network")?,
@@ -562,9 +919,10 @@ mod pathfinder_context {
         async fn configure_custom(
             gateway: Url,
             feeder: Url,
-            chain_id: String,
+            chain: CustomChain,
             data_directory: PathBuf,
             api_key: Option<String>,
+            settles_on_ethereum: bool,
         ) -> anyhow::Result<Self> {
             use pathfinder_crypto::Felt;
             use starknet_gateway_client::GatewayApi;
@@ -573,15 +931,34 @@ mod pathfinder_context {
                 .context("Creating gateway client")?
                 .with_api_key(api_key);
 
-            let network_id =
-                ChainId(Felt::from_be_slice(chain_id.as_bytes()).context("Parsing chain ID")?);
+            let (network_id, chain_spec) = match chain {
+                CustomChain::Id(chain_id) => {
+                    let chain_id =
+                        Felt::from_be_slice(chain_id.as_bytes()).context("Parsing chain ID")?;
+                    (ChainId(chain_id), None)
+                }
+                CustomChain::Spec(path) => {
+                    let spec = ChainSpec::load(&path).with_context(|| {
+                        format!("Loading chain specification {}", path.display())
+                    })?;
+                    (spec.chain_id, Some(spec))
+                }
+            };
 
-            let l1_core_address = gateway
-                .eth_contract_addresses()
-                .await
-                .context("Downloading starknet L1 address from gateway for proxy check")?
-                .starknet
-                .0;
+            let spec_core_address = chain_spec.as_ref().and_then(|spec| spec.l1_core_address);
+            let l1_core_address = match spec_core_address {
+                Some(address) => address,
+                None if settles_on_ethereum => {
+                    gateway
+                        .eth_contract_addresses()
+                        .await
+                        .context("Downloading starknet L1 address from gateway for proxy check")?
+                        .starknet
+                        .0
+                }
+                // Gateways of chains without an Ethereum core contract need not serve one.
+                None => H160::zero(),
+            };
 
             // Check for proxies by comparing the core address against those of the known networks.
             let network = match l1_core_address.as_bytes() {
@@ -603,6 +980,7 @@ mod pathfinder_context {
                 gateway,
                 database: data_directory.join("custom.sqlite"),
                 l1_core_address,
+                chain_spec,
             };
 
             Ok(context)
@@ -610,26 +988,83 @@ mod pathfinder_context {
     }
 }
 
-/// Errors if there is a mismatch between the starknet and ethereum networks.
-fn verify_networks(starknet: Chain, ethereum: EthereumChain) -> anyhow::Result<()> {
-    if starknet != Chain::Custom {
-        let expected = match starknet {
-            Chain::Mainnet => EthereumChain::Mainnet,
-            Chain::GoerliTestnet | Chain::GoerliIntegration => EthereumChain::Goerli,
-            Chain::SepoliaTestnet | Chain::SepoliaIntegration => EthereumChain::Sepolia,
-            Chain::Custom => unreachable!("Already checked against"),
-        };
+/// Determines the sequencer public key to verify block signatures with. The key is known for
+/// mainnet, which includes proxies of mainnet, and can be configured for other networks.
+/// Otherwise it is fetched from the feeder gateway, which only guards against blocks being
+/// altered after the key was fetched, so configuring the key is preferable.
+async fn signature_verification(
+    mode: config::SignatureVerificationMode,
+    public_key: Option<PublicKey>,
+    network: Chain,
+    gateway: &impl GatewayApi,
+) -> anyhow::Result<state::l2::SignatureVerification> {
+    use config::SignatureVerificationMode;
+    use state::l2::SignatureVerification;
 
-        anyhow::ensure!(ethereum == expected, "Incorrect Ethereum network detected. Found {ethereum:?} but expected {expected:?} for {} Starknet", starknet);
+    if mode == SignatureVerificationMode::Disabled {
+        return Ok(SignatureVerification::Disabled);
     }
 
+    let public_key = match public_key.or(known_sequencer_public_key(network)) {
+        Some(public_key) => public_key,
+        None => {
+            let public_key = gateway.public_key().await.with_context(|| {
+                format!(
+                    "Fetching the sequencer public key of {network} from the feeder gateway, \
+                     set it with `sync.sequencer-public-key` instead"
+                )
+            })?;
+            tracing::warn!(public_key=%public_key.0, "Using the sequencer public key served by the feeder gateway, set `sync.sequencer-public-key` to pin it");
+            public_key
+        }
+    };
+    info!(public_key=%public_key.0, "Verifying block signatures");
+
+    Ok(match mode {
+        SignatureVerificationMode::Disabled => SignatureVerification::Disabled,
+        SignatureVerificationMode::Warn => SignatureVerification::Warn(public_key),
+        SignatureVerificationMode::Strict => SignatureVerification::Strict(public_key),
+    })
+}
+
+/// The key with which the sequencer of a well-known network signs blocks.
+fn known_sequencer_public_key(network: Chain) -> Option<PublicKey> {
+    match network {
+        Chain::Mainnet => Some(pathfinder_common::consts::MAINNET_SEQUENCER_PUBLIC_KEY),
+        _ => None,
+    }
+}
+
+/// Errors if there is a mismatch between the starknet and settlement networks. The settlement
+/// network of a custom network is only known if its chain specification declares it.
+fn verify_networks(
+    starknet: Chain,
+    ethereum: EthereumChain,
+    chain_spec: Option<&chain_spec::ChainSpec>,
+) -> anyhow::Result<()> {
+    let expected = match starknet {
+        Chain::Mainnet => EthereumChain::Mainnet,
+        Chain::GoerliTestnet | Chain::GoerliIntegration => EthereumChain::Goerli,
+        Chain::SepoliaTestnet | Chain::SepoliaIntegration => EthereumChain::Sepolia,
+        Chain::Custom => match chain_spec.and_then(|spec| spec.settlement_chain) {
+            Some(expected) => expected,
+            None => {
+                tracing::warn!(found=?ethereum, "Settlement network of the custom Starknet network is not verified, declare its `settlement_chain_id` in the chain specification");
+                return Ok(());
+            }
+        },
+    };
+
+    anyhow::ensure!(ethereum == expected, "Incorrect settlement network detected. Found {ethereum:?} but expected {expected:?} for {} Starknet", starknet);
+
     Ok(())
 }
 
 async fn verify_database(
     storage: &Storage,
     network: Chain,
-    gateway_client: &starknet_gateway_client::Client,
+    custom_genesis: Option<BlockHash>,
+    gateway_client: &impl GatewayApi,
 ) -> anyhow::Result<()> {
     let storage = storage.clone();
     let db_genesis = tokio::task::spawn_blocking(move || {
@@ -659,8 +1094,18 @@ async fn verify_database(
         };
 
         match (network, db_network) {
+            (Chain::Custom, _) if custom_genesis.is_some() => {
+                // Verify against the chain specification.
+                let spec_hash = custom_genesis.expect("Checked by the match guard");
+                anyhow::ensure!(
+                    database_genesis == spec_hash,
+                    "Database genesis block does not match the chain specification. {} != {}",
+                    database_genesis,
+                    spec_hash
+                );
+            }
             (Chain::Custom, _) => {
-                // Verify against gateway.
+                // Verify against the gateway, or the archive blocks are imported from.
                 let (_, gateway_hash) = gateway_client
                     .block_header(BlockNumber::GENESIS.into())
                     .await
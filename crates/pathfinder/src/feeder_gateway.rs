@@ -0,0 +1,523 @@
+//! Serves the subset of the Starknet feeder gateway REST API which pathfinder requires
+//! to sync. This lets other pathfinder nodes sync from this node using `--network custom`
+//! instead of the public feeder gateway.
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use pathfinder_common::{
+    BlockHash, BlockNumber, ClassHash, EventCommitment, PublicKey, TransactionCommitment,
+};
+use pathfinder_rpc::PendingWatcher;
+use pathfinder_storage::{BlockId, Storage, Transaction};
+use primitive_types::H160;
+use serde::{Deserialize, Serialize};
+use starknet_gateway_types::error::{KnownStarknetErrorCode, StarknetError, StarknetErrorCode};
+use starknet_gateway_types::reply::{self, Status};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Error code of a block whose signature is not stored, e.g. in databases predating signatures.
+const SIGNATURE_NOT_AVAILABLE: &str = "StarknetErrorCode.SIGNATURE_NOT_AVAILABLE";
+
+#[derive(Clone)]
+struct ServerContext {
+    storage: Storage,
+    pending: PendingWatcher,
+    /// The Starknet core contract address on Ethereum.
+    core_address: H160,
+    /// The key the stored block signatures were made with, if known.
+    public_key: Option<PublicKey>,
+}
+
+/// Spawns a server which hosts the feeder gateway endpoints under `/feeder_gateway`.
+pub fn spawn_server(
+    addr: SocketAddr,
+    storage: Storage,
+    pending: PendingWatcher,
+    core_address: H160,
+    public_key: Option<PublicKey>,
+) -> anyhow::Result<(tokio::task::JoinHandle<()>, SocketAddr)> {
+    let context = ServerContext {
+        storage,
+        pending,
+        core_address,
+        public_key,
+    };
+
+    let (addr, server) = warp::serve(routes(context))
+        .try_bind_ephemeral(addr)
+        .context("Binding feeder gateway server")?;
+
+    Ok((tokio::spawn(server), addr))
+}
+
+fn routes(
+    context: ServerContext,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("feeder_gateway" / String))
+        .and(warp::query::<Params>())
+        .and(warp::any().map(move || context.clone()))
+        .then(handle)
+        .with(warp::filters::trace::request())
+}
+
+/// Query parameters of all supported methods.
+#[derive(Debug, Deserialize)]
+struct Params {
+    #[serde(default, rename = "blockNumber")]
+    block_number: Option<String>,
+    #[serde(default, rename = "blockHash")]
+    block_hash: Option<BlockHash>,
+    #[serde(default, rename = "includeBlock")]
+    include_block: bool,
+    #[serde(default, rename = "headerOnly")]
+    header_only: bool,
+    #[serde(default, rename = "classHash")]
+    class_hash: Option<ClassHash>,
+}
+
+enum BlockParam {
+    Pending,
+    Stored(BlockId),
+}
+
+impl Params {
+    fn block(&self) -> Result<BlockParam, Error> {
+        match (self.block_number.as_deref(), self.block_hash) {
+            (Some("pending"), None) => Ok(BlockParam::Pending),
+            (Some("latest"), None) => Ok(BlockParam::Stored(BlockId::Latest)),
+            (Some(number), None) => number
+                .parse::<u64>()
+                .ok()
+                .and_then(BlockNumber::new)
+                .map(|number| BlockParam::Stored(number.into()))
+                .ok_or(Error::Starknet(KnownStarknetErrorCode::MalformedRequest)),
+            (None, Some(hash)) => Ok(BlockParam::Stored(hash.into())),
+            _ => Err(Error::Starknet(KnownStarknetErrorCode::MalformedRequest)),
+        }
+    }
+
+    fn class_hash(&self) -> Result<ClassHash, Error> {
+        self.class_hash
+            .ok_or(Error::Starknet(KnownStarknetErrorCode::MalformedRequest))
+    }
+}
+
+enum Error {
+    Starknet(KnownStarknetErrorCode),
+    /// The block exists, but its signature was not stored.
+    SignatureNotAvailable(BlockNumber),
+    UnknownMethod,
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+async fn handle(method: String, params: Params, context: ServerContext) -> warp::reply::Response {
+    let result = tokio::task::spawn_blocking(move || {
+        let mut db = context
+            .storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db.transaction().context("Creating database transaction")?;
+
+        match method.as_str() {
+            "get_block" => get_block(&tx, &context.pending, &params),
+            "get_state_update" => get_state_update(&tx, &context.pending, &params),
+            "get_signature" => get_signature(&tx, &params),
+            "get_class_by_hash" => get_class_by_hash(&tx, &params),
+            "get_compiled_class_by_class_hash" => get_compiled_class_by_class_hash(&tx, &params),
+            "get_contract_addresses" => json(&serde_json::json!({
+                "Starknet": context.core_address,
+            })),
+            // Without a known key, clients have to be configured with it instead.
+            "get_public_key" => match context.public_key {
+                Some(public_key) => json(&public_key),
+                None => Err(Error::UnknownMethod),
+            },
+            _ => Err(Error::UnknownMethod),
+        }
+    })
+    .await
+    .context("Joining blocking task")
+    .map_err(Error::from)
+    .and_then(|result| result);
+
+    match result {
+        Ok(body) => warp::http::Response::builder()
+            .header("content-type", "application/json")
+            .body(body.into())
+            .expect("Response is valid"),
+        Err(Error::Starknet(code)) => {
+            let status = match code {
+                KnownStarknetErrorCode::MalformedRequest => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            starknet_error(
+                status,
+                StarknetError {
+                    code: code.into(),
+                    message: String::new(),
+                },
+            )
+        }
+        Err(Error::SignatureNotAvailable(block_number)) => starknet_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StarknetError {
+                code: StarknetErrorCode::Unknown(SIGNATURE_NOT_AVAILABLE.to_owned()),
+                message: format!("Signature not available for block {block_number}"),
+            },
+        ),
+        Err(Error::UnknownMethod) => warp::http::Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Default::default())
+            .expect("Response is valid"),
+        Err(Error::Internal(error)) => {
+            tracing::error!(?error, "Feeder gateway request failed");
+            // Not a Starknet error, so that clients retry the request.
+            warp::http::Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Default::default())
+                .expect("Response is valid")
+        }
+    }
+}
+
+fn starknet_error(status: StatusCode, error: StarknetError) -> warp::reply::Response {
+    let body = serde_json::to_vec(&error).expect("Error serialization cannot fail");
+    warp::http::Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body.into())
+        .expect("Response is valid")
+}
+
+fn json(reply: &impl Serialize) -> Result<Vec<u8>, Error> {
+    Ok(serde_json::to_vec(reply).context("Serializing reply")?)
+}
+
+fn get_block(
+    tx: &Transaction<'_>,
+    pending: &PendingWatcher,
+    params: &Params,
+) -> Result<Vec<u8>, Error> {
+    let block_id = match params.block()? {
+        // A pending block has no hash, so there is no header to speak of.
+        BlockParam::Pending if params.header_only => {
+            return Err(Error::Starknet(KnownStarknetErrorCode::MalformedRequest))
+        }
+        BlockParam::Pending => return json(&pending.get(tx)?.block),
+        BlockParam::Stored(block_id) => block_id,
+    };
+
+    if params.header_only {
+        #[derive(Serialize)]
+        struct Header {
+            block_hash: BlockHash,
+            block_number: BlockNumber,
+        }
+
+        let (block_number, block_hash) = tx
+            .block_id(block_id)
+            .context("Querying block id")?
+            .ok_or(Error::Starknet(KnownStarknetErrorCode::BlockNotFound))?;
+
+        return json(&Header {
+            block_hash,
+            block_number,
+        });
+    }
+
+    json(&block(tx, block_id)?)
+}
+
+fn get_state_update(
+    tx: &Transaction<'_>,
+    pending: &PendingWatcher,
+    params: &Params,
+) -> Result<Vec<u8>, Error> {
+    #[derive(Serialize)]
+    struct WithBlock<B: Serialize> {
+        block: B,
+        state_update: reply::StateUpdate,
+    }
+
+    match params.block()? {
+        BlockParam::Pending => {
+            let pending = pending.get(tx)?;
+            let state_update = reply::StateUpdate::from((*pending.state_update).clone());
+
+            if params.include_block {
+                json(&WithBlock {
+                    block: &*pending.block,
+                    state_update,
+                })
+            } else {
+                json(&state_update)
+            }
+        }
+        BlockParam::Stored(block_id) => {
+            let state_update: reply::StateUpdate = tx
+                .state_update(block_id)
+                .context("Fetching state update")?
+                .ok_or(Error::Starknet(KnownStarknetErrorCode::BlockNotFound))?
+                .into();
+
+            if params.include_block {
+                json(&WithBlock {
+                    block: block(tx, block_id)?,
+                    state_update,
+                })
+            } else {
+                json(&state_update)
+            }
+        }
+    }
+}
+
+fn get_signature(tx: &Transaction<'_>, params: &Params) -> Result<Vec<u8>, Error> {
+    let BlockParam::Stored(block_id) = params.block()? else {
+        return Err(Error::Starknet(KnownStarknetErrorCode::BlockNotFound));
+    };
+
+    let (block_number, block_hash) = tx
+        .block_id(block_id)
+        .context("Querying block id")?
+        .ok_or(Error::Starknet(KnownStarknetErrorCode::BlockNotFound))?;
+
+    // Older databases may be missing signatures.
+    let signature = tx
+        .signature(block_number.into())
+        .context("Fetching signature")?
+        .ok_or(Error::SignatureNotAvailable(block_number))?;
+
+    let state_diff_commitment = tx
+        .state_update(block_number.into())
+        .context("Fetching state update")?
+        .context("State update missing")?
+        .compute_state_diff_commitment();
+
+    json(&reply::BlockSignature {
+        block_number,
+        signature: [signature.r, signature.s],
+        signature_input: reply::BlockSignatureInput {
+            block_hash,
+            state_diff_commitment,
+        },
+    })
+}
+
+fn get_class_by_hash(tx: &Transaction<'_>, params: &Params) -> Result<Vec<u8>, Error> {
+    tx.class_definition(params.class_hash()?)
+        .context("Fetching class definition")?
+        .ok_or(Error::Starknet(KnownStarknetErrorCode::UndeclaredClass))
+}
+
+fn get_compiled_class_by_class_hash(
+    tx: &Transaction<'_>,
+    params: &Params,
+) -> Result<Vec<u8>, Error> {
+    tx.casm_definition(params.class_hash()?)
+        .context("Fetching CASM definition")?
+        .ok_or(Error::Starknet(KnownStarknetErrorCode::UndeclaredClass))
+}
+
+fn block(tx: &Transaction<'_>, block_id: BlockId) -> Result<reply::Block, Error> {
+    let header = tx
+        .block_header(block_id)
+        .context("Fetching block header")?
+        .ok_or(Error::Starknet(KnownStarknetErrorCode::BlockNotFound))?;
+
+    let (transactions, transaction_receipts) = tx
+        .transaction_data_for_block(header.number.into())
+        .context("Fetching transaction data")?
+        .context("Transaction data missing")?
+        .into_iter()
+        .unzip();
+
+    let status = if tx
+        .block_is_l1_accepted(header.number.into())
+        .context("Querying block status")?
+    {
+        Status::AcceptedOnL1
+    } else {
+        Status::AcceptedOnL2
+    };
+
+    Ok(reply::Block {
+        block_hash: header.hash,
+        block_number: header.number,
+        eth_l1_gas_price_implementation_detail: None,
+        strk_l1_gas_price_implementation_detail: None,
+        l1_gas_price_implementation_detail: Some(reply::GasPrices {
+            price_in_wei: header.eth_l1_gas_price,
+            price_in_fri: header.strk_l1_gas_price,
+        }),
+        l1_data_gas_price: Some(reply::GasPrices {
+            price_in_wei: header.eth_l1_data_gas_price,
+            price_in_fri: header.strk_l1_data_gas_price,
+        }),
+        parent_block_hash: header.parent_hash,
+        sequencer_address: Some(header.sequencer_address),
+        state_commitment: header.state_commitment,
+        status,
+        timestamp: header.timestamp,
+        transaction_receipts,
+        transactions,
+        starknet_version: header.starknet_version,
+        l1_da_mode: Some(header.l1_da_mode.into()),
+        // Commitments were not stored for some older blocks, which is indistinguishable
+        // from the zero commitment of an empty block. Neither needs to be served.
+        transaction_commitment: Some(header.transaction_commitment)
+            .filter(|c| *c != TransactionCommitment::ZERO),
+        event_commitment: Some(header.event_commitment).filter(|c| *c != EventCommitment::ZERO),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::{BlockHeader, StateUpdate};
+
+    fn setup() -> (
+        impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone,
+        BlockHeader,
+    ) {
+        let storage = Storage::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+
+        let header = BlockHeader::builder()
+            .with_number(BlockNumber::GENESIS)
+            .finalize_with_hash(block_hash_bytes!(b"genesis"));
+        tx.insert_block_header(&header).unwrap();
+        tx.insert_transaction_data(header.hash, header.number, &[])
+            .unwrap();
+        tx.insert_state_update(
+            header.number,
+            &StateUpdate::default().with_block_hash(header.hash),
+        )
+        .unwrap();
+        tx.insert_cairo_class(class_hash_bytes!(b"class"), b"definition")
+            .unwrap();
+        tx.commit().unwrap();
+
+        let (_, pending) = tokio::sync::watch::channel(Default::default());
+        let context = ServerContext {
+            storage,
+            pending: PendingWatcher::new(pending),
+            core_address: H160::from_low_u64_be(1),
+            public_key: Some(public_key!("0x1234")),
+        };
+
+        (routes(context), header)
+    }
+
+    #[tokio::test]
+    async fn block_header() {
+        let (routes, header) = setup();
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_block?blockNumber=latest&headerOnly=true")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let reply: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply["block_hash"], serde_json::json!(header.hash));
+        assert_eq!(reply["block_number"], serde_json::json!(0));
+    }
+
+    #[tokio::test]
+    async fn state_update_with_block() {
+        let (routes, header) = setup();
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_state_update?blockNumber=0&includeBlock=true")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        #[derive(Deserialize)]
+        struct Dto {
+            block: reply::Block,
+            state_update: reply::StateUpdate,
+        }
+        let reply: Dto = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply.block.block_hash, header.hash);
+        assert_eq!(reply.state_update.block_hash, header.hash);
+    }
+
+    #[tokio::test]
+    async fn block_not_found() {
+        let (routes, _) = setup();
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_block?blockNumber=1")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let error: StarknetError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(error.code, KnownStarknetErrorCode::BlockNotFound.into());
+    }
+
+    #[tokio::test]
+    async fn class_by_hash() {
+        let (routes, _) = setup();
+
+        let response = warp::test::request()
+            .path(&format!(
+                "/feeder_gateway/get_class_by_hash?classHash={}&blockNumber=pending",
+                class_hash_bytes!(b"class")
+            ))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"definition");
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_class_by_hash?classHash=0x1234")
+            .reply(&routes)
+            .await;
+        let error: StarknetError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(error.code, KnownStarknetErrorCode::UndeclaredClass.into());
+    }
+
+    #[tokio::test]
+    async fn missing_signature() {
+        let (routes, _) = setup();
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_signature?blockNumber=0")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let error: StarknetError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            error.code,
+            StarknetErrorCode::Unknown(SIGNATURE_NOT_AVAILABLE.to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn public_key() {
+        let (routes, _) = setup();
+
+        let response = warp::test::request()
+            .path("/feeder_gateway/get_public_key")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let public_key: PublicKey = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(public_key, public_key!("0x1234"));
+    }
+}
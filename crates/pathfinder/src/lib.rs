@@ -1,8 +1,9 @@
 #![deny(rust_2018_idioms)]
 
+pub mod feeder_gateway;
 pub mod monitoring;
 pub mod state;
-mod sync;
+pub mod sync;
 
 #[cfg(feature = "p2p")]
 pub mod p2p_network;
@@ -1,19 +1,33 @@
+use std::time::Duration;
+
 use anyhow::Context;
-use p2p::client::peer_agnostic;
+use p2p::client::{peer_agnostic, peer_aware};
 use p2p::libp2p::{identity::Keypair, multiaddr::Multiaddr};
-use p2p::{HeadRx, HeadTx};
+use p2p::{Head, HeadRx, HeadTx, Misbehaviour};
 use p2p_proto::header::BlockHeadersResponse;
 use pathfinder_common::{BlockHash, BlockNumber, ChainId};
 use pathfinder_storage::Storage;
+use starknet_gateway_client::GatewayApi;
+use starknet_gateway_types::error::{KnownStarknetErrorCode, SequencerError};
 use tracing::Instrument;
 
+use crate::sync::p2p::verify_announcement;
+
 pub mod client;
+#[cfg(test)]
+pub(crate) mod simulation;
 mod sync_handlers;
 
+pub use sync_handlers::Limits as SyncLimits;
+
 use sync_handlers::{
-    get_classes, get_events, get_headers, get_receipts, get_state_diffs, get_transactions,
+    get_class_range, get_classes, get_classes_by_hash, get_contract_range, get_contract_storage,
+    get_events, get_headers, get_receipts, get_state_diffs, get_transactions, Budget,
 };
 
+/// Announcements waiting to be verified, further ones are dropped until there is room.
+const MAX_PENDING_ANNOUNCEMENTS: usize = 16;
+
 // Silence clippy
 pub type P2PNetworkHandle = (peer_agnostic::Client, HeadRx, tokio::task::JoinHandle<()>);
 
@@ -21,11 +35,12 @@ pub struct P2PContext {
     pub cfg: p2p::Config,
     pub chain_id: ChainId,
     pub storage: Storage,
-    pub proxy: bool,
     pub keypair: Keypair,
-    pub listen_on: Multiaddr,
+    pub listen_on: Vec<Multiaddr>,
     pub bootstrap_addresses: Vec<Multiaddr>,
     pub predefined_peers: Vec<Multiaddr>,
+    /// Limits on how much other peers can sync from us.
+    pub sync_limits: SyncLimits,
 }
 
 #[tracing::instrument(name = "p2p", skip_all)]
@@ -34,16 +49,22 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         cfg,
         chain_id,
         storage,
-        proxy,
         keypair,
         listen_on,
         bootstrap_addresses,
         predefined_peers,
+        sync_limits,
     } = context;
 
     let peer_id = keypair.public().to_peer_id();
     tracing::info!(%peer_id, "🖧 Starting P2P");
 
+    let budget = Budget::new(sync_limits);
+    let cfg = p2p::Config {
+        response_meter: Some(budget.meter()),
+        ..cfg
+    };
+
     let (p2p_client, mut p2p_events, p2p_main_loop) = p2p::new(keypair, cfg, chain_id);
 
     let mut main_loop_handle = {
@@ -51,10 +72,12 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         tokio::task::spawn(p2p_main_loop.run().instrument(span))
     };
 
-    p2p_client
-        .start_listening(listen_on)
-        .await
-        .context("Starting P2P listener")?;
+    for address in listen_on {
+        p2p_client
+            .start_listening(address)
+            .await
+            .context("Starting P2P listener")?;
+    }
 
     let ensure_peer_id_in_multiaddr = |addr: &Multiaddr, msg: &'static str| {
         addr.iter()
@@ -88,16 +111,20 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
 
     let block_propagation_topic = format!("blocks/{}", chain_id.to_hex_str());
 
-    if !proxy {
-        p2p_client.subscribe_topic(&block_propagation_topic).await?;
-        tracing::info!(topic=%block_propagation_topic, "Subscribed to");
-    }
+    // Proxies subscribe as well, so that they can check the announcements against the feeder
+    // gateway.
+    p2p_client.subscribe_topic(&block_propagation_topic).await?;
+    tracing::info!(topic=%block_propagation_topic, "Subscribed to");
 
-    for capability in p2p::PROTOCOLS {
+    for capability in p2p::PROTOCOLS.iter().copied().flatten() {
         p2p_client.provide_capability(capability).await?
     }
 
-    let (mut tx, rx) = tokio::sync::watch::channel(None);
+    let sync_client = peer_agnostic::Client::new(p2p_client.clone(), block_propagation_topic);
+
+    let (head_tx, rx) = tokio::sync::watch::channel(None);
+    let (announcements, announced) = tokio::sync::mpsc::channel(MAX_PENDING_ANNOUNCEMENTS);
+    tokio::spawn(verify_announcements(announced, sync_client.clone(), head_tx).in_current_span());
 
     let join_handle = {
         tokio::task::spawn(
@@ -109,7 +136,7 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
                             break;
                         }
                         Some(event) = p2p_events.recv() => {
-                            match handle_p2p_event(event, storage.clone(), &mut tx).await {
+                            match handle_p2p_event(event, storage.clone(), &p2p_client, &budget, &announcements).await {
                                 Ok(()) => {},
                                 Err(e) => { tracing::error!("Failed to handle P2P event: {}", e) },
                             }
@@ -121,48 +148,86 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         )
     };
 
-    Ok((
-        peer_agnostic::Client::new(p2p_client, block_propagation_topic),
-        rx,
-        join_handle,
-    ))
+    Ok((sync_client, rx, join_handle))
 }
 
 async fn handle_p2p_event(
     event: p2p::Event,
     storage: Storage,
-    tx: &mut HeadTx,
+    p2p_client: &peer_aware::Client,
+    budget: &Budget,
+    announcements: &tokio::sync::mpsc::Sender<Head>,
 ) -> anyhow::Result<()> {
     match event {
         p2p::Event::InboundHeadersSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
         } => {
-            get_headers(storage, request, channel).await?;
+            get_headers(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::InboundClassesSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
         } => {
-            get_classes(storage, request, channel).await?;
+            get_classes(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::InboundStateDiffsSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
         } => {
-            get_state_diffs(storage, request, channel).await?;
+            get_state_diffs(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::InboundTransactionsSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
         } => {
-            get_transactions(storage, request, channel).await?;
+            get_transactions(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::InboundReceiptsSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
         } => {
-            get_receipts(storage, request, channel).await?;
+            get_receipts(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::InboundEventsSyncRequest {
-            request, channel, ..
+            from,
+            request,
+            channel,
+        } => {
+            get_events(storage, budget.for_peer(from), request, channel).await?;
+        }
+        p2p::Event::InboundContractRangeSyncRequest {
+            from,
+            request,
+            channel,
+        } => {
+            get_contract_range(storage, budget.for_peer(from), request, channel).await?;
+        }
+        p2p::Event::InboundContractStorageSyncRequest {
+            from,
+            request,
+            channel,
+        } => {
+            get_contract_storage(storage, budget.for_peer(from), request, channel).await?;
+        }
+        p2p::Event::InboundClassRangeSyncRequest {
+            from,
+            request,
+            channel,
+        } => {
+            get_class_range(storage, budget.for_peer(from), request, channel).await?;
+        }
+        p2p::Event::InboundClassesByHashSyncRequest {
+            from,
+            request,
+            channel,
         } => {
-            get_events(storage, request, channel).await?;
+            get_classes_by_hash(storage, budget.for_peer(from), request, channel).await?;
         }
         p2p::Event::BlockPropagation { from, new_block } => {
             tracing::info!(%from, ?new_block, "Block Propagation");
@@ -173,24 +238,38 @@ async fn handle_p2p_event(
                 NewBlock::Header(BlockHeadersResponse::Header(hdr)) => {
                     BlockNumber::new(hdr.number).map(|n| (n, BlockHash(hdr.block_hash.0)))
                 }
-                NewBlock::Header(BlockHeadersResponse::Fin) => None,
+                NewBlock::Header(BlockHeadersResponse::Fin | BlockHeadersResponse::RateLimited) => {
+                    None
+                }
             };
 
-            match new_head {
-                Some((new_height, new_hash)) => {
-                    tx.send_if_modified(|head| -> bool {
-                        let current_height = head.unwrap_or_default().0;
+            let Some((number, hash)) = new_head else {
+                tracing::debug!(%from, "Received block propagation without a valid head");
+                p2p_client
+                    .penalize(from, Misbehaviour::MalformedMessage)
+                    .await;
+                return Ok(());
+            };
 
-                        if new_height > current_height {
-                            *head = Some((new_height, new_hash));
-                            true
-                        } else {
-                            false
-                        }
-                    });
+            match local_block_hash(storage, number).await? {
+                Some(local) if local == hash => {
+                    // We already have this block.
                 }
-                None => {
-                    tracing::warn!("Received block propagation without a valid head")
+                local => {
+                    // A block which conflicts with the local chain is not necessarily invalid,
+                    // as the local chain may have been reorganized since, so it is verified
+                    // like any other announcement.
+                    if let Some(local) = local {
+                        tracing::debug!(%from, %number, %hash, %local,
+                            "Announced block conflicts with the local chain"
+                        );
+                    }
+
+                    // Verifying the announcement takes a request to the peer, which must not
+                    // hold up the other events.
+                    if announcements.try_send(Head { from, number, hash }).is_err() {
+                        tracing::debug!(%from, %number, "Dropping announcement, too many are pending");
+                    }
                 }
             }
         }
@@ -199,3 +278,224 @@ async fn handle_p2p_event(
 
     Ok(())
 }
+
+/// Publishes the heads announced by peers once they are verified, see [verify_announcement].
+///
+/// Peers which announce an invalid head are penalized, and their announcement is dropped. As only
+/// verified heads are published, a peer cannot hold back sync by announcing a bogus block with a
+/// huge number.
+async fn verify_announcements(
+    mut announcements: tokio::sync::mpsc::Receiver<Head>,
+    p2p_client: peer_agnostic::Client,
+    tx: HeadTx,
+) {
+    while let Some(head) = announcements.recv().await {
+        if tx
+            .borrow()
+            .map_or(false, |current| current.number >= head.number)
+        {
+            continue;
+        }
+
+        match verify_announcement(&p2p_client, head).await {
+            Some(true) => {
+                tx.send_replace(Some(head));
+            }
+            Some(false) => {
+                p2p_client
+                    .penalize(head.from, Misbehaviour::InvalidAnnouncement)
+                    .await;
+            }
+            None => {}
+        }
+    }
+}
+
+async fn local_block_hash(
+    storage: Storage,
+    number: BlockNumber,
+) -> anyhow::Result<Option<BlockHash>> {
+    tokio::task::spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+        let block_id = db.block_id(number.into()).context("Querying block hash")?;
+        Ok(block_id.map(|(_, hash)| hash))
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Checks the heads announced by peers against the feeder gateway, which proxies follow.
+///
+/// Peers which announce a block with a different hash, or a block the feeder gateway still
+/// does not know about after `poll_interval`, are penalized.
+pub async fn cross_check_heads(
+    mut heads: HeadRx,
+    gateway: impl GatewayApi,
+    p2p_client: peer_agnostic::Client,
+    poll_interval: Duration,
+) {
+    // Heads the feeder gateway did not know about yet, in the order they are due to be checked
+    // again.
+    let mut pending = std::collections::VecDeque::<(Head, tokio::time::Instant)>::new();
+
+    loop {
+        let due = pending.front().map(|(_, due)| *due);
+        let recheck = async move {
+            match due {
+                Some(due) => tokio::time::sleep_until(due).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            changed = heads.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let Some(head) = *heads.borrow_and_update() else {
+                    continue;
+                };
+
+                match gateway_block_hash(&gateway, head.number).await {
+                    // The announcement may have simply beaten the feeder gateway to it.
+                    Ok(None) => {
+                        pending.push_back((head, tokio::time::Instant::now() + poll_interval))
+                    }
+                    result => cross_check_head(&p2p_client, head, result).await,
+                }
+            }
+            _ = recheck => {
+                let (head, _) = pending.pop_front().expect("A head is due");
+                let result = gateway_block_hash(&gateway, head.number).await;
+                cross_check_head(&p2p_client, head, result).await;
+            }
+        }
+    }
+}
+
+/// Penalizes the peer which announced `head` unless it matches the block hash of the feeder
+/// gateway.
+async fn cross_check_head(
+    p2p_client: &peer_agnostic::Client,
+    head: Head,
+    gateway_hash: anyhow::Result<Option<BlockHash>>,
+) {
+    match gateway_hash {
+        Ok(Some(hash)) if hash == head.hash => {}
+        Ok(gateway_hash) => {
+            tracing::debug!(from=%head.from, number=%head.number, hash=%head.hash, ?gateway_hash,
+                "Announced head does not match the feeder gateway"
+            );
+            p2p_client
+                .penalize(head.from, Misbehaviour::InvalidAnnouncement)
+                .await;
+        }
+        Err(error) => {
+            tracing::debug!(%error, "Fetching announced block from the feeder gateway");
+        }
+    }
+}
+
+async fn gateway_block_hash(
+    gateway: &impl GatewayApi,
+    number: BlockNumber,
+) -> anyhow::Result<Option<BlockHash>> {
+    match gateway.block_header(number.into()).await {
+        Ok((_, hash)) => Ok(Some(hash)),
+        Err(SequencerError::StarknetError(error))
+            if error.code == KnownStarknetErrorCode::BlockNotFound.into() =>
+        {
+            Ok(None)
+        }
+        Err(error) => Err(error).context("Fetching block header"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p2p::libp2p::PeerId;
+    use pathfinder_common::macro_prelude::*;
+    use starknet_gateway_client::MockGatewayApi;
+    use starknet_gateway_types::error::StarknetError;
+
+    use super::*;
+    use crate::p2p_network::simulation::Simulation;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    #[tokio::test]
+    async fn cross_check_heads_penalizes_mismatches() {
+        let mut sim = Simulation::new(1);
+        sim.start().await.unwrap();
+
+        let mut gateway = MockGatewayApi::new();
+        gateway
+            .expect_block_header()
+            .withf(|block| *block == BlockNumber::new_or_panic(1).into())
+            .returning(|_| Ok((BlockNumber::new_or_panic(1), block_hash!("0x1"))));
+        gateway
+            .expect_block_header()
+            .withf(|block| *block == BlockNumber::new_or_panic(2).into())
+            .returning(|_| Ok((BlockNumber::new_or_panic(2), block_hash!("0x2"))));
+        // The feeder gateway only learns about block 3 after it was announced.
+        let mut not_found = true;
+        gateway
+            .expect_block_header()
+            .withf(|block| *block == BlockNumber::new_or_panic(3).into())
+            .returning(move |_| {
+                if std::mem::take(&mut not_found) {
+                    Err(SequencerError::StarknetError(StarknetError {
+                        code: KnownStarknetErrorCode::BlockNotFound.into(),
+                        message: String::new(),
+                    }))
+                } else {
+                    Ok((BlockNumber::new_or_panic(3), block_hash!("0x3")))
+                }
+            });
+        // Block 4 never shows up.
+        gateway
+            .expect_block_header()
+            .withf(|block| *block == BlockNumber::new_or_panic(4).into())
+            .returning(|_| {
+                Err(SequencerError::StarknetError(StarknetError {
+                    code: KnownStarknetErrorCode::BlockNotFound.into(),
+                    message: String::new(),
+                }))
+            });
+
+        let (tx, rx) = tokio::sync::watch::channel(None);
+        let checker = tokio::spawn(cross_check_heads(rx, gateway, sim.client(0), POLL_INTERVAL));
+
+        let honest = PeerId::random();
+        let late = PeerId::random();
+        let wrong_hash = PeerId::random();
+        let unknown = PeerId::random();
+        let announcements = [
+            (honest, 1, block_hash!("0x1")),
+            (wrong_hash, 2, block_hash!("0x22")),
+            (late, 3, block_hash!("0x3")),
+            (unknown, 4, block_hash!("0x4")),
+        ];
+        for (from, number, hash) in announcements {
+            tx.send_replace(Some(Head {
+                from,
+                number: BlockNumber::new_or_panic(number),
+                hash,
+            }));
+            // Give the checker a chance to see every head.
+            tokio::time::sleep(POLL_INTERVAL / 10).await;
+        }
+        tokio::time::sleep(POLL_INTERVAL * 3).await;
+
+        let scores = sim.client(0).peer_aware().peer_scores().await;
+        assert!(!scores.contains_key(&honest));
+        assert!(!scores.contains_key(&late));
+        assert!(scores[&wrong_hash].score < 0);
+        assert!(scores[&unknown].score < 0);
+
+        checker.abort();
+    }
+}
@@ -0,0 +1,617 @@
+//! An in-process network of pathfinder p2p nodes, for testing p2p sync without a real network.
+//!
+//! Nodes are connected over the memory transport, see the `memory-transport` feature of [p2p].
+//! Each node serves sync requests from its own in-memory [Storage] just like a real node does,
+//! and is seeded with a [chain] which passes the same verification as a real one. Faults can be
+//! injected into a running simulation to script scenarios such as partitions, slow peers,
+//! malicious responders and churn.
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use fake::{Fake, Faker};
+use futures::channel::mpsc::Sender as ResponseSender;
+use futures::{SinkExt, StreamExt};
+use p2p::client::{peer_agnostic, peer_aware};
+use p2p::libp2p::identity::Keypair;
+use p2p::libp2p::multiaddr::{Multiaddr, Protocol};
+use p2p::libp2p::PeerId;
+use p2p::HeadRx;
+use p2p_proto::common::Hash;
+use p2p_proto::header::BlockHeadersResponse;
+use pathfinder_common::{
+    BlockHash, BlockHeader, BlockNumber, BlockTimestamp, ChainId, ClassCommitment, ContractAddress,
+    StateCommitment, StateUpdate, StorageAddress, StorageCommitment,
+};
+use pathfinder_crypto::Felt;
+use pathfinder_storage::Storage;
+use tokio::task::JoinHandle;
+
+use super::{handle_p2p_event, verify_announcements, Budget, SyncLimits};
+use crate::state::block_hash::{
+    calculate_event_commitment, calculate_transaction_commitment,
+    TransactionCommitmentFinalHashType,
+};
+
+pub(crate) const CHAIN_ID: ChainId = ChainId::GOERLI_TESTNET;
+
+/// How often [Simulation::converged] checks the nodes' storage.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A network of nodes, which are referred to by their index.
+pub(crate) struct Simulation {
+    nodes: Vec<Node>,
+}
+
+impl Simulation {
+    /// Creates `n` nodes with empty storage. None of them are running yet.
+    pub fn new(n: usize) -> Self {
+        Self {
+            nodes: (0..n).map(|_| Node::new()).collect(),
+        }
+    }
+
+    /// Inserts the blocks of a [chain] into the node's storage, along with their state.
+    pub fn seed(&self, node: usize, blocks: &[Block]) {
+        for (header, state_update) in blocks {
+            insert_block(&self.nodes[node].storage, header, state_update);
+        }
+    }
+
+    pub fn storage(&self, node: usize) -> Storage {
+        self.nodes[node].storage.clone()
+    }
+
+    pub fn peer_id(&self, node: usize) -> PeerId {
+        self.nodes[node].peer_id()
+    }
+
+    /// The client of a running node.
+    pub fn client(&self, node: usize) -> peer_agnostic::Client {
+        self.nodes[node].client().clone()
+    }
+
+    /// The verified heads announced to a running node.
+    pub fn heads(&self, node: usize) -> HeadRx {
+        self.nodes[node]
+            .running
+            .as_ref()
+            .expect("Node should be running")
+            .heads
+            .clone()
+    }
+
+    /// Starts all nodes and connects each of them to all of the others.
+    pub async fn start(&mut self) -> anyhow::Result<()> {
+        for node in &mut self.nodes {
+            node.start().await?;
+        }
+
+        for a in 0..self.nodes.len() {
+            for b in a + 1..self.nodes.len() {
+                self.connect(a, b).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connects two running nodes, unless they are connected already.
+    pub async fn connect(&self, a: usize, b: usize) -> anyhow::Result<()> {
+        if self.connected(a, b).await {
+            return Ok(());
+        }
+
+        let (a, b) = (&self.nodes[a], &self.nodes[b]);
+        a.client()
+            .peer_aware()
+            .dial(b.peer_id(), b.addr())
+            .await
+            .with_context(|| format!("Dialing {} from {}", b.peer_id(), a.peer_id()))
+    }
+
+    pub async fn connected(&self, a: usize, b: usize) -> bool {
+        let b = self.peer_id(b);
+        self.nodes[a]
+            .client()
+            .peer_aware()
+            .peers()
+            .await
+            .iter()
+            .any(|peer| peer.peer_id == b && peer.connected)
+    }
+
+    /// Stops a node, which closes all of its connections. Its storage is kept.
+    pub async fn stop(&mut self, node: usize) {
+        self.nodes[node].stop().await;
+    }
+
+    /// Restarts a stopped node and connects it to all other running nodes, apart from those on
+    /// the other side of a [partition](Self::partition).
+    pub async fn restart(&mut self, node: usize) -> anyhow::Result<()> {
+        self.nodes[node].start().await?;
+
+        let unreachable = self.nodes[node].faults().unreachable;
+        for other in 0..self.nodes.len() {
+            if other != node
+                && self.nodes[other].running.is_some()
+                && !unreachable.contains(&self.peer_id(other))
+            {
+                self.connect(node, other).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits the network in two: the given nodes and the rest. Nodes on different sides are
+    /// disconnected, and drop each other's sync requests until the partition is
+    /// [healed](Self::heal).
+    pub async fn partition(&self, side: &[usize]) -> anyhow::Result<()> {
+        let other_side = (0..self.nodes.len())
+            .filter(|node| !side.contains(node))
+            .collect::<Vec<_>>();
+
+        for &a in side {
+            for &b in &other_side {
+                let (peer_a, peer_b) = (self.peer_id(a), self.peer_id(b));
+                self.nodes[a].update_faults(|faults| {
+                    faults.unreachable.insert(peer_b);
+                });
+                self.nodes[b].update_faults(|faults| {
+                    faults.unreachable.insert(peer_a);
+                });
+
+                if self.nodes[a].running.is_some() && self.connected(a, b).await {
+                    self.nodes[a]
+                        .client()
+                        .peer_aware()
+                        .disconnect(peer_b)
+                        .await
+                        .with_context(|| format!("Disconnecting {peer_b} from {peer_a}"))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes all partitions and reconnects the running nodes to each other.
+    pub async fn heal(&self) -> anyhow::Result<()> {
+        for node in &self.nodes {
+            node.update_faults(|faults| faults.unreachable.clear());
+        }
+
+        let running = (0..self.nodes.len())
+            .filter(|&node| self.nodes[node].running.is_some())
+            .collect::<Vec<_>>();
+        for (i, &a) in running.iter().enumerate() {
+            for &b in &running[i + 1..] {
+                self.connect(a, b).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delays every sync request served by the node.
+    pub fn slow_down(&self, node: usize, delay: Duration) {
+        self.nodes[node].update_faults(|faults| faults.delay = delay);
+    }
+
+    /// Makes the node serve block headers with corrupted hashes.
+    pub fn corrupt_headers(&self, node: usize) {
+        self.nodes[node].update_faults(|faults| faults.malicious = true);
+    }
+
+    /// The latest block in the node's storage.
+    pub async fn head(&self, node: usize) -> anyhow::Result<Option<(BlockNumber, BlockHash)>> {
+        let storage = self.storage(node);
+        tokio::task::spawn_blocking(move || {
+            let mut db = storage
+                .connection()
+                .context("Creating database connection")?;
+            let db = db.transaction().context("Creating database transaction")?;
+            db.block_id(pathfinder_storage::BlockId::Latest)
+                .context("Querying latest block")
+        })
+        .await
+        .context("Joining blocking task")?
+    }
+
+    /// Waits until all of the given nodes have the same latest block, and returns it.
+    pub async fn converged(
+        &self,
+        nodes: &[usize],
+        timeout: Duration,
+    ) -> anyhow::Result<(BlockNumber, BlockHash)> {
+        let converge = async {
+            loop {
+                let mut heads = HashSet::new();
+                for &node in nodes {
+                    heads.insert(self.head(node).await?);
+                }
+
+                if let [Some(head)] = heads.into_iter().collect::<Vec<_>>().as_slice() {
+                    return anyhow::Ok(*head);
+                }
+
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+
+        tokio::time::timeout(timeout, converge)
+            .await
+            .with_context(|| format!("Nodes {nodes:?} did not converge within {timeout:?}"))?
+    }
+}
+
+/// Faults injected into the way a node serves sync requests.
+#[derive(Debug, Clone, Default)]
+struct Faults {
+    /// Delay before each request is served.
+    delay: Duration,
+    /// Serve block headers with corrupted hashes.
+    malicious: bool,
+    /// Peers whose requests are dropped without a response.
+    unreachable: HashSet<PeerId>,
+}
+
+impl Faults {
+    /// Applies the faults to an event. Returns `None` if the event should be dropped, otherwise
+    /// the event along with how long to wait before handling it.
+    fn apply(&self, event: p2p::Event) -> Option<(p2p::Event, Duration)> {
+        let Some(from) = requester(&event) else {
+            return Some((event, Duration::ZERO));
+        };
+
+        if self.unreachable.contains(&from) {
+            // Dropping the response channel closes the stream without a response.
+            return None;
+        }
+
+        let event = match event {
+            p2p::Event::InboundHeadersSyncRequest {
+                from,
+                request,
+                channel,
+            } if self.malicious => p2p::Event::InboundHeadersSyncRequest {
+                from,
+                request,
+                channel: corrupt_headers(channel),
+            },
+            event => event,
+        };
+
+        Some((event, self.delay))
+    }
+}
+
+struct Node {
+    keypair: Keypair,
+    port: u64,
+    storage: Storage,
+    /// Shared with the running node, and kept across restarts.
+    faults: Arc<Mutex<Faults>>,
+    running: Option<Running>,
+}
+
+struct Running {
+    client: peer_agnostic::Client,
+    heads: HeadRx,
+    main_loop: JoinHandle<()>,
+    events: JoinHandle<()>,
+    verifier: JoinHandle<()>,
+}
+
+impl Node {
+    fn new() -> Self {
+        // Memory transport ports are global to the process.
+        static NEXT_PORT: AtomicU64 = AtomicU64::new(1);
+
+        Self {
+            keypair: Keypair::generate_ed25519(),
+            port: NEXT_PORT.fetch_add(1, Ordering::Relaxed),
+            storage: Storage::in_memory().unwrap(),
+            faults: Default::default(),
+            running: None,
+        }
+    }
+
+    fn peer_id(&self) -> PeerId {
+        self.keypair.public().to_peer_id()
+    }
+
+    fn addr(&self) -> Multiaddr {
+        Multiaddr::empty()
+            .with(Protocol::Memory(self.port))
+            .with(Protocol::P2p(self.peer_id()))
+    }
+
+    fn client(&self) -> &peer_agnostic::Client {
+        &self
+            .running
+            .as_ref()
+            .expect("Node should be running")
+            .client
+    }
+
+    fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    fn update_faults(&self, f: impl FnOnce(&mut Faults)) {
+        f(&mut self.faults.lock().unwrap());
+    }
+
+    /// Starts the node the same way as [super::start], except that inbound events go through the
+    /// node's faults first.
+    async fn start(&mut self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.running.is_none(), "Node is already running");
+
+        let (client, events, main_loop) = p2p::new(self.keypair.clone(), config(), CHAIN_ID);
+        let main_loop = tokio::spawn(main_loop.run());
+
+        client
+            .start_listening(Multiaddr::empty().with(Protocol::Memory(self.port)))
+            .await
+            .context("Starting P2P listener")?;
+
+        let topic = format!("blocks/{}", CHAIN_ID.to_hex_str());
+        client.subscribe_topic(&topic).await?;
+
+        for capability in p2p::PROTOCOLS.iter().copied().flatten() {
+            client.provide_capability(capability).await?
+        }
+
+        let sync_client = peer_agnostic::Client::new(client.clone(), topic);
+
+        let (head_tx, heads) = tokio::sync::watch::channel(None);
+        let (announcements, announced) = tokio::sync::mpsc::channel(16);
+        let verifier = tokio::spawn(verify_announcements(
+            announced,
+            sync_client.clone(),
+            head_tx,
+        ));
+
+        let events = tokio::spawn(handle_events(
+            events,
+            self.storage.clone(),
+            client,
+            announcements,
+            self.faults.clone(),
+        ));
+
+        self.running = Some(Running {
+            client: sync_client,
+            heads,
+            main_loop,
+            events,
+            verifier,
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.events.abort();
+            running.verifier.abort();
+            running.main_loop.abort();
+            // Wait for the swarm to be dropped, so that the node's port is free again.
+            let _ = running.events.await;
+            let _ = running.main_loop.await;
+        }
+    }
+}
+
+async fn handle_events(
+    mut events: p2p::EventReceiver,
+    storage: Storage,
+    client: peer_aware::Client,
+    announcements: tokio::sync::mpsc::Sender<p2p::Head>,
+    faults: Arc<Mutex<Faults>>,
+) {
+    let budget = Budget::new(limits());
+
+    while let Some(event) = events.recv().await {
+        let faults = faults.lock().unwrap().clone();
+        let Some((event, delay)) = faults.apply(event) else {
+            continue;
+        };
+
+        if delay.is_zero() {
+            handle_event(event, storage.clone(), &client, &budget, &announcements).await;
+            continue;
+        }
+
+        // Delayed requests are served in the background, so that other events are not held up.
+        let (storage, client, budget, announcements) = (
+            storage.clone(),
+            client.clone(),
+            budget.clone(),
+            announcements.clone(),
+        );
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            handle_event(event, storage, &client, &budget, &announcements).await;
+        });
+    }
+}
+
+async fn handle_event(
+    event: p2p::Event,
+    storage: Storage,
+    client: &peer_aware::Client,
+    budget: &Budget,
+    announcements: &tokio::sync::mpsc::Sender<p2p::Head>,
+) {
+    if let Err(error) = handle_p2p_event(event, storage, client, budget, announcements).await {
+        tracing::debug!(%error, "Failed to handle P2P event");
+    }
+}
+
+/// The peer which sent the event, if it is a sync request.
+fn requester(event: &p2p::Event) -> Option<PeerId> {
+    match event {
+        p2p::Event::InboundHeadersSyncRequest { from, .. }
+        | p2p::Event::InboundClassesSyncRequest { from, .. }
+        | p2p::Event::InboundStateDiffsSyncRequest { from, .. }
+        | p2p::Event::InboundTransactionsSyncRequest { from, .. }
+        | p2p::Event::InboundReceiptsSyncRequest { from, .. }
+        | p2p::Event::InboundEventsSyncRequest { from, .. }
+        | p2p::Event::InboundContractRangeSyncRequest { from, .. }
+        | p2p::Event::InboundContractStorageSyncRequest { from, .. }
+        | p2p::Event::InboundClassRangeSyncRequest { from, .. }
+        | p2p::Event::InboundClassesByHashSyncRequest { from, .. } => Some(*from),
+        _ => None,
+    }
+}
+
+/// A block along with its state diff.
+pub(crate) type Block = (BlockHeader, StateUpdate);
+
+/// Creates a chain of `n` blocks which passes the same verification as a real chain when synced.
+///
+/// Blocks have no transactions. Each one deploys a contract and writes to the storage of the
+/// system contract, and its header commits to the resulting state.
+pub(crate) fn chain(n: usize) -> Vec<Block> {
+    // The state commitments are computed by applying each block's state diff, the same way
+    // sync does.
+    let storage = Storage::in_memory().unwrap();
+    let mut blocks: Vec<Block> = Vec::with_capacity(n);
+
+    for i in 0..n as u64 {
+        let parent_hash = blocks
+            .last()
+            .map_or(BlockHash::ZERO, |(header, _)| header.hash);
+        let header = BlockHeader::builder()
+            .with_number(BlockNumber::new_or_panic(i))
+            .with_parent_hash(parent_hash)
+            .with_timestamp(BlockTimestamp::new_or_panic(i))
+            .finalize_with_hash(BlockHash(Faker.fake()));
+        let final_hash_type =
+            TransactionCommitmentFinalHashType::for_version(&header.starknet_version).unwrap();
+
+        let mut header = BlockHeader {
+            transaction_commitment: calculate_transaction_commitment(&[], final_hash_type).unwrap(),
+            event_commitment: calculate_event_commitment(&[]).unwrap(),
+            ..header
+        };
+        let state_update = StateUpdate::default()
+            .with_deployed_contract(
+                ContractAddress::new_or_panic(Felt::from_u64(0x100 + i)),
+                Faker.fake(),
+            )
+            .with_system_storage_update(
+                ContractAddress::ONE,
+                StorageAddress::new_or_panic(Felt::from_u64(i)),
+                Faker.fake(),
+            );
+
+        let (storage_commitment, class_commitment) = insert_block(&storage, &header, &state_update);
+        header.storage_commitment = storage_commitment;
+        header.class_commitment = class_commitment;
+        header.state_commitment = StateCommitment::calculate(storage_commitment, class_commitment);
+
+        let state_update = state_update
+            .with_block_hash(header.hash)
+            .with_state_commitment(header.state_commitment);
+        blocks.push((header, state_update));
+    }
+
+    blocks
+}
+
+/// Inserts a block and applies its state diff, the same way sync does. Returns the resulting
+/// commitments.
+fn insert_block(
+    storage: &Storage,
+    header: &BlockHeader,
+    state_update: &StateUpdate,
+) -> (StorageCommitment, ClassCommitment) {
+    let mut db = storage.connection().unwrap();
+    let tx = db.transaction().unwrap();
+
+    tx.insert_block_header(header).unwrap();
+    tx.insert_signature(header.number, &Faker.fake()).unwrap();
+    tx.insert_transaction_data(header.hash, header.number, &[])
+        .unwrap();
+
+    let (storage_commitment, class_commitment) = crate::state::update_starknet_state(
+        &tx,
+        state_update,
+        false,
+        header.number,
+        storage.clone(),
+        &HashSet::new(),
+    )
+    .unwrap();
+    tx.insert_state_update(header.number, state_update).unwrap();
+    tx.update_block_header_commitments(header.number, storage_commitment, class_commitment)
+        .unwrap();
+
+    tx.commit().unwrap();
+    (storage_commitment, class_commitment)
+}
+
+/// Returns a response channel which replaces the hash of every header sent through it with a
+/// random one before passing it on to `channel`.
+fn corrupt_headers(
+    mut channel: ResponseSender<BlockHeadersResponse>,
+) -> ResponseSender<BlockHeadersResponse> {
+    let (tx, mut rx) = futures::channel::mpsc::channel(1);
+
+    tokio::spawn(async move {
+        while let Some(mut response) = rx.next().await {
+            if let BlockHeadersResponse::Header(header) = &mut response {
+                header.block_hash = Hash(Faker.fake());
+            }
+
+            if channel.send(response).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tx
+}
+
+fn config() -> p2p::Config {
+    p2p::Config {
+        direct_connection_timeout: Duration::ZERO,
+        relay_connection_timeout: Duration::ZERO,
+        max_inbound_direct_peers: 100,
+        max_inbound_relayed_peers: 0,
+        max_outbound_peers: 100,
+        // Connections are made by the simulation only.
+        low_watermark: 0,
+        ip_whitelist: vec!["127.0.0.1/32".parse().unwrap()],
+        bootstrap: Default::default(),
+        eviction_timeout: Duration::from_secs(15 * 60),
+        peers_file: None,
+        max_concurrent_inbound_streams: 100,
+        response_meter: None,
+        inbound_connections_rate_limit: p2p::RateLimit {
+            max: 1000,
+            interval: Duration::from_secs(1),
+        },
+    }
+}
+
+fn limits() -> SyncLimits {
+    let per_second = |max| p2p::RateLimit {
+        max,
+        interval: Duration::from_secs(1),
+    };
+
+    SyncLimits {
+        max_blocks_per_request: 100,
+        peer_requests: per_second(1000),
+        total_requests: per_second(1000),
+        peer_bytes: per_second(1 << 30),
+        total_bytes: per_second(1 << 30),
+    }
+}
@@ -8,77 +8,130 @@ use p2p_proto::common::{
 use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, SignedBlockHeader};
 use p2p_proto::receipt::{ReceiptsRequest, ReceiptsResponse};
+use p2p_proto::snapshot::{
+    ClassRange, ClassRangeRequest, ClassRangeResponse, ClassesByHashRequest, ClassesByHashResponse,
+    ContractRange, ContractRangeRequest, ContractRangeResponse, ContractState, ContractStorage,
+    ContractStorageRequest, ContractStorageResponse, PatriciaRangeProof,
+};
 use p2p_proto::state::{ContractDiff, ContractStoredValue, StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
-use pathfinder_common::{BlockHash, BlockNumber};
+use pathfinder_common::{
+    BlockHash, BlockNumber, ClassHash, ContractAddress, SierraHash, StorageAddress,
+};
 use pathfinder_crypto::Felt;
+use pathfinder_merkle_tree::tree::LeafRange;
+use pathfinder_merkle_tree::{ClassCommitmentTree, ContractsStorageTree, StorageCommitmentTree};
 use pathfinder_storage::Storage;
 use pathfinder_storage::Transaction;
 use starknet_gateway_types::class_definition;
 use tokio::sync::mpsc;
 
+mod budget;
 pub mod conv;
 #[cfg(test)]
 mod tests;
 
+pub use budget::{Budget, Limits, PeerBudget};
 use conv::ToDto;
 
 use self::conv::{cairo_def_into_dto, sierra_def_into_dto};
 
-#[cfg(not(test))]
-const MAX_BLOCKS_COUNT: u64 = 100;
-
 #[cfg(test)]
 const MAX_COUNT_IN_TESTS: u64 = 10;
-#[cfg(test)]
-const MAX_BLOCKS_COUNT: u64 = MAX_COUNT_IN_TESTS;
+
+/// Maximum number of contracts or storage slots in a single chunk of a snapshot range.
+const RANGE_CHUNK_LEN: usize = 1000;
+/// Classes are large enough that each chunk of a class range holds only one of them.
+const CLASS_RANGE_CHUNK_LEN: usize = 1;
 
 pub async fn get_headers(
     storage: Storage,
+    budget: PeerBudget,
     request: BlockHeadersRequest,
     tx: futures::channel::mpsc::Sender<BlockHeadersResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_headers, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_headers, tx).await
 }
 
 pub async fn get_classes(
     storage: Storage,
+    budget: PeerBudget,
     request: ClassesRequest,
     tx: futures::channel::mpsc::Sender<ClassesResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_classes, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_classes, tx).await
 }
 
 pub async fn get_state_diffs(
     storage: Storage,
+    budget: PeerBudget,
     request: StateDiffsRequest,
     tx: futures::channel::mpsc::Sender<StateDiffsResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_state_diffs, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_state_diffs, tx).await
 }
 
 pub async fn get_transactions(
     storage: Storage,
+    budget: PeerBudget,
     request: TransactionsRequest,
     tx: futures::channel::mpsc::Sender<TransactionsResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_transactions, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_transactions, tx).await
 }
 
 pub async fn get_receipts(
     storage: Storage,
+    budget: PeerBudget,
     request: ReceiptsRequest,
     tx: futures::channel::mpsc::Sender<ReceiptsResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_receipts, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_receipts, tx).await
 }
 
 pub async fn get_events(
     storage: Storage,
+    budget: PeerBudget,
     request: EventsRequest,
     tx: futures::channel::mpsc::Sender<EventsResponse>,
 ) -> anyhow::Result<()> {
-    spawn_blocking_get(request, storage, blocking::get_events, tx).await
+    spawn_blocking_get(request, storage, budget, blocking::get_events, tx).await
+}
+
+pub async fn get_contract_range(
+    storage: Storage,
+    budget: PeerBudget,
+    request: ContractRangeRequest,
+    tx: futures::channel::mpsc::Sender<ContractRangeResponse>,
+) -> anyhow::Result<()> {
+    spawn_blocking_get(request, storage, budget, blocking::get_contract_range, tx).await
+}
+
+pub async fn get_contract_storage(
+    storage: Storage,
+    budget: PeerBudget,
+    request: ContractStorageRequest,
+    tx: futures::channel::mpsc::Sender<ContractStorageResponse>,
+) -> anyhow::Result<()> {
+    spawn_blocking_get(request, storage, budget, blocking::get_contract_storage, tx).await
+}
+
+pub async fn get_class_range(
+    storage: Storage,
+    budget: PeerBudget,
+    request: ClassRangeRequest,
+    tx: futures::channel::mpsc::Sender<ClassRangeResponse>,
+) -> anyhow::Result<()> {
+    spawn_blocking_get(request, storage, budget, blocking::get_class_range, tx).await
+}
+
+pub async fn get_classes_by_hash(
+    storage: Storage,
+    budget: PeerBudget,
+    request: ClassesByHashRequest,
+    tx: futures::channel::mpsc::Sender<ClassesByHashResponse>,
+) -> anyhow::Result<()> {
+    spawn_blocking_get(request, storage, budget, blocking::get_classes_by_hash, tx).await
 }
 
 pub(crate) mod blocking {
@@ -87,49 +140,309 @@ pub(crate) mod blocking {
     pub(crate) fn get_headers(
         db_tx: Transaction<'_>,
         request: BlockHeadersRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<BlockHeadersResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_header, tx)
+        iterate(db_tx, request.iteration, max_blocks, get_header, tx)
     }
 
     pub(crate) fn get_classes(
         db_tx: Transaction<'_>,
         request: ClassesRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<ClassesResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_classes_for_block, tx)
+        iterate(
+            db_tx,
+            request.iteration,
+            max_blocks,
+            get_classes_for_block,
+            tx,
+        )
     }
 
     pub(crate) fn get_state_diffs(
         db_tx: Transaction<'_>,
         request: StateDiffsRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<StateDiffsResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_state_diff, tx)
+        iterate(db_tx, request.iteration, max_blocks, get_state_diff, tx)
     }
 
     pub(crate) fn get_transactions(
         db_tx: Transaction<'_>,
         request: TransactionsRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<TransactionsResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_transactions_for_block, tx)
+        iterate(
+            db_tx,
+            request.iteration,
+            max_blocks,
+            get_transactions_for_block,
+            tx,
+        )
     }
 
     pub(crate) fn get_receipts(
         db_tx: Transaction<'_>,
         request: ReceiptsRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<ReceiptsResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_receipts_for_block, tx)
+        iterate(
+            db_tx,
+            request.iteration,
+            max_blocks,
+            get_receipts_for_block,
+            tx,
+        )
     }
 
     pub(crate) fn get_events(
         db_tx: Transaction<'_>,
         request: EventsRequest,
+        max_blocks: u64,
         tx: mpsc::Sender<EventsResponse>,
     ) -> anyhow::Result<()> {
-        iterate(db_tx, request.iteration, get_events_for_block, tx)
+        iterate(
+            db_tx,
+            request.iteration,
+            max_blocks,
+            get_events_for_block,
+            tx,
+        )
+    }
+
+    /// For snapshot ranges `max_blocks` limits the number of chunks served per request.
+    pub(crate) fn get_contract_range(
+        db_tx: Transaction<'_>,
+        request: ContractRangeRequest,
+        max_blocks: u64,
+        tx: mpsc::Sender<ContractRangeResponse>,
+    ) -> anyhow::Result<()> {
+        let header = match state_block(&db_tx, request.block_number)? {
+            Some(block) => db_tx
+                .block_header(block.into())
+                .context("Querying block header")?,
+            None => None,
+        };
+        let Some(header) = header else {
+            return tx
+                .blocking_send(ContractRangeResponse::Fin)
+                .map_err(|_| anyhow::anyhow!("Sending Fin"));
+        };
+        let block = header.number;
+
+        iterate_range(
+            request.start.0,
+            request.end.0,
+            max_blocks,
+            |start, end| {
+                StorageCommitmentTree::get_range(
+                    &db_tx,
+                    block,
+                    ContractAddress(start),
+                    ContractAddress(end),
+                    RANGE_CHUNK_LEN,
+                )
+            },
+            |range| {
+                let state = range
+                    .leaves
+                    .iter()
+                    .map(|(key, _)| {
+                        let address = ContractAddress(
+                            Felt::from_bits(key).context("Mapping leaf path to address")?,
+                        );
+                        let class = db_tx
+                            .contract_class_hash(block.into(), address)
+                            .context("Querying class hash")?
+                            // System contracts do not have a class.
+                            .unwrap_or(ClassHash::ZERO);
+                        let storage = db_tx
+                            .contract_root(block, address)
+                            .context("Querying contract root")?
+                            .unwrap_or_default();
+                        let nonce = db_tx
+                            .contract_nonce(address, block.into())
+                            .context("Querying nonce")?
+                            .unwrap_or_default();
+
+                        Ok(ContractState {
+                            address: Address(address.0),
+                            class: Hash(class.0),
+                            storage: Hash(storage.0),
+                            nonce: nonce.0,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                Ok(ContractRangeResponse::Range(ContractRange {
+                    contracts_root: Hash(header.storage_commitment.0),
+                    classes_root: Hash(header.class_commitment.0),
+                    state,
+                    proof: proof_to_dto(range.proof),
+                }))
+            },
+            tx,
+        )
+    }
+
+    pub(crate) fn get_contract_storage(
+        db_tx: Transaction<'_>,
+        request: ContractStorageRequest,
+        max_blocks: u64,
+        tx: mpsc::Sender<ContractStorageResponse>,
+    ) -> anyhow::Result<()> {
+        let Some(block) = state_block(&db_tx, request.block_number)? else {
+            return tx
+                .blocking_send(ContractStorageResponse::Fin)
+                .map_err(|_| anyhow::anyhow!("Sending Fin"));
+        };
+        let contract = ContractAddress(request.address.0);
+
+        iterate_range(
+            request.start,
+            request.end,
+            max_blocks,
+            |start, end| {
+                ContractsStorageTree::get_range(
+                    &db_tx,
+                    contract,
+                    block,
+                    StorageAddress(start),
+                    StorageAddress(end),
+                    RANGE_CHUNK_LEN,
+                )
+            },
+            |range| {
+                let key_value = range
+                    .leaves
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok(ContractStoredValue {
+                            key: Felt::from_bits(key).context("Mapping leaf path to key")?,
+                            value: *value,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                Ok(ContractStorageResponse::Storage(ContractStorage {
+                    key_value,
+                    proof: proof_to_dto(range.proof),
+                }))
+            },
+            tx,
+        )
+    }
+
+    pub(crate) fn get_class_range(
+        db_tx: Transaction<'_>,
+        request: ClassRangeRequest,
+        max_blocks: u64,
+        tx: mpsc::Sender<ClassRangeResponse>,
+    ) -> anyhow::Result<()> {
+        let Some(block) = state_block(&db_tx, request.block_number)? else {
+            return tx
+                .blocking_send(ClassRangeResponse::Fin)
+                .map_err(|_| anyhow::anyhow!("Sending Fin"));
+        };
+
+        iterate_range(
+            request.start.0,
+            request.end.0,
+            max_blocks,
+            |start, end| {
+                ClassCommitmentTree::get_range(
+                    &db_tx,
+                    block,
+                    SierraHash(start),
+                    SierraHash(end),
+                    CLASS_RANGE_CHUNK_LEN,
+                )
+            },
+            |range| {
+                let mut classes = Vec::new();
+                let mut compiled_class_hashes = Vec::new();
+                for (key, _) in &range.leaves {
+                    let class_hash =
+                        ClassHash(Felt::from_bits(key).context("Mapping leaf path to class hash")?);
+                    let sierra = db_tx
+                        .class_definition_at(block.into(), class_hash)
+                        .context("Querying class definition")?
+                        .with_context(|| format!("Class definition {class_hash} not found"))?;
+                    let casm = db_tx
+                        .casm_definition(class_hash)
+                        .context("Querying compiled class definition")?
+                        .with_context(|| format!("Compiled class {class_hash} not found"))?;
+                    let casm_hash = db_tx
+                        .casm_hash_at(block.into(), class_hash)
+                        .context("Querying compiled class hash")?
+                        .with_context(|| format!("Compiled class hash {class_hash} not found"))?;
+
+                    let sierra = serde_json::from_slice::<class_definition::Sierra<'_>>(&sierra)?;
+                    classes.push(Class::Cairo1 {
+                        class: sierra_def_into_dto(sierra, casm),
+                        domain: 0, // TODO
+                        class_hash: Hash(class_hash.0),
+                    });
+                    compiled_class_hashes.push(Hash(casm_hash.0));
+                }
+
+                Ok(ClassRangeResponse::Range(ClassRange {
+                    classes,
+                    compiled_class_hashes,
+                    proof: proof_to_dto(range.proof),
+                }))
+            },
+            tx,
+        )
+    }
+
+    /// Only Cairo 0 classes are served, Sierra classes are part of the class ranges. Classes
+    /// which we don't have are skipped, and `max_blocks` limits the number of classes served
+    /// per request.
+    pub(crate) fn get_classes_by_hash(
+        db_tx: Transaction<'_>,
+        request: ClassesByHashRequest,
+        max_blocks: u64,
+        tx: mpsc::Sender<ClassesByHashResponse>,
+    ) -> anyhow::Result<()> {
+        let mut served = 0;
+        for class_hash in request.class_hashes {
+            if served >= max_blocks {
+                break;
+            }
+
+            let class_hash = ClassHash(class_hash.0);
+            let Some(definition) = db_tx
+                .class_definition(class_hash)
+                .context("Querying class definition")?
+            else {
+                continue;
+            };
+            let is_sierra = db_tx
+                .casm_definition(class_hash)
+                .context("Querying compiled class definition")?
+                .is_some();
+            if is_sierra {
+                continue;
+            }
+
+            let cairo_class = serde_json::from_slice::<class_definition::Cairo<'_>>(&definition)?;
+            tx.blocking_send(ClassesByHashResponse::Class(Class::Cairo0 {
+                class: cairo_def_into_dto(cairo_class),
+                domain: 0, // TODO
+                class_hash: Hash(class_hash.0),
+            }))
+            .map_err(|_| anyhow::anyhow!("Sending class"))?;
+            served += 1;
+        }
+
+        tx.blocking_send(ClassesByHashResponse::Fin)
+            .map_err(|_| anyhow::anyhow!("Sending Fin"))
     }
 }
 
@@ -371,6 +684,7 @@ fn get_events_for_block(
 fn iterate<T: Default + std::fmt::Debug>(
     db_tx: Transaction<'_>,
     iteration: Iteration,
+    max_blocks: u64,
     block_handler: impl Fn(&Transaction<'_>, BlockNumber, &mpsc::Sender<T>) -> anyhow::Result<bool>,
     tx: mpsc::Sender<T>,
 ) -> anyhow::Result<()> {
@@ -396,7 +710,7 @@ fn iterate<T: Default + std::fmt::Debug>(
         }
     };
 
-    let limit = limit.min(MAX_BLOCKS_COUNT);
+    let limit = limit.min(max_blocks);
 
     for i in 0..limit {
         if !block_handler(&db_tx, block_number, &tx)? {
@@ -421,6 +735,70 @@ fn iterate<T: Default + std::fmt::Debug>(
     Ok(())
 }
 
+/// Returns the block if we have its state, ie. its tries.
+fn state_block(db_tx: &Transaction<'_>, block: u64) -> anyhow::Result<Option<BlockNumber>> {
+    let Some(block) = BlockNumber::new(block) else {
+        return Ok(None);
+    };
+    let latest = db_tx
+        .latest_storage_root_block()
+        .context("Querying latest block with state")?;
+    if latest.map_or(true, |latest| block > latest) {
+        return Ok(None);
+    }
+    // Nodes which started from a snapshot don't have the tries of older blocks.
+    let root = db_tx
+        .storage_root_index(block)
+        .context("Querying storage root index")?;
+
+    Ok(root.map(|_| block))
+}
+
+/// Streams the leaves with keys in `start..=end` as chunks with their range proofs, followed by
+/// `Fin`.
+///
+/// Each chunk continues right after the last leaf of the previous one. An empty chunk completes
+/// the range, as does a chunk ending in `end`. Streaming stops early after `max_chunks` chunks,
+/// in which case the requester continues from the last leaf it received.
+fn iterate_range<T: Default>(
+    start: Felt,
+    end: Felt,
+    max_chunks: u64,
+    get_range: impl Fn(Felt, Felt) -> anyhow::Result<LeafRange>,
+    to_response: impl Fn(LeafRange) -> anyhow::Result<T>,
+    tx: mpsc::Sender<T>,
+) -> anyhow::Result<()> {
+    let mut start = start;
+    if start <= end {
+        for _ in 0..max_chunks {
+            let range = get_range(start, end)?;
+            let last = match range.leaves.last() {
+                Some((key, _)) => Some(Felt::from_bits(key).context("Mapping leaf path to felt")?),
+                None => None,
+            };
+
+            tx.blocking_send(to_response(range)?)
+                .map_err(|_| anyhow::anyhow!("Sending range"))?;
+
+            match last {
+                Some(last) if last < end => start = last + Felt::from_u64(1),
+                _ => break,
+            }
+        }
+    }
+
+    tx.blocking_send(T::default())
+        .map_err(|_| anyhow::anyhow!("Sending Fin"))?;
+
+    Ok(())
+}
+
+fn proof_to_dto(proof: Vec<pathfinder_common::trie::TrieNode>) -> PatriciaRangeProof {
+    PatriciaRangeProof {
+        nodes: proof.into_iter().map(ToDto::to_dto).collect(),
+    }
+}
+
 fn get_start_block_number(
     start: BlockNumberOrHash,
     tx: &Transaction<'_>,
@@ -435,20 +813,37 @@ fn get_start_block_number(
 /// Bails out early if the database operation fails or sending fails.
 /// The `getter` function is expected to send partial results through the tokio channel as soon as possible,
 /// ideally after each database read operation.
+///
+/// Requests which exceed the peer's [budget](PeerBudget) are answered with a lone `RateLimited`,
+/// and responses are cut short with a `Fin` once the peer runs out of bandwidth.
 async fn spawn_blocking_get<Request, Response, Getter>(
     request: Request,
     storage: Storage,
+    budget: PeerBudget,
     getter: Getter,
     mut tx: futures::channel::mpsc::Sender<Response>,
 ) -> anyhow::Result<()>
 where
     Request: Send + 'static,
-    Response: Send + 'static,
-    Getter: FnOnce(Transaction<'_>, Request, mpsc::Sender<Response>) -> anyhow::Result<()>
+    Response: RateLimited + Default + Send + 'static,
+    Getter: FnOnce(Transaction<'_>, Request, u64, mpsc::Sender<Response>) -> anyhow::Result<()>
         + Send
         + 'static,
 {
+    if let Err(rejection) = budget.admit() {
+        tracing::debug!(peer=%budget.peer(), reason=%rejection.as_str(), "Rejecting sync request");
+        metrics::increment_counter!(
+            "p2p_sync_requests_rejected_total",
+            "reason" => rejection.as_str()
+        );
+        tx.send(Response::rate_limited())
+            .await
+            .context("Sending RateLimited")?;
+        return Ok(());
+    }
+
     let span = tracing::Span::current();
+    let max_blocks = budget.max_blocks_per_request();
 
     let (sync_tx, mut rx) = mpsc::channel(1); // For backpressure
 
@@ -461,7 +856,7 @@ where
             let db_tx = connection
                 .transaction()
                 .context("Creating database transaction")?;
-            getter(db_tx, request, sync_tx)
+            getter(db_tx, request, max_blocks, sync_tx)
         })
         .await
         .context("Database read panic or shutting down")?
@@ -470,15 +865,59 @@ where
 
     let fwd_fut = async move {
         while let Some(x) = rx.recv().await {
+            // The bytes are charged once the response is encoded, see [Budget::meter].
+            if !budget.has_bandwidth() {
+                tracing::debug!(peer=%budget.peer(), "Truncating sync response, bandwidth exceeded");
+                metrics::increment_counter!("p2p_sync_responses_truncated_total");
+                // Dropping the receiver stops the database read.
+                drop(rx);
+                tx.send(Response::default()).await.context("Sending Fin")?;
+                return Ok(true);
+            }
             tx.send(x).await.context("Sending item")?;
         }
-        Ok::<_, anyhow::Error>(())
+        Ok::<_, anyhow::Error>(false)
     };
-    // Bail out early, either when db fails or sending fails
-    tokio::try_join!(db_fut, fwd_fut)?;
+
+    let (db_result, fwd_result) = tokio::join!(db_fut, fwd_fut);
+    let truncated = fwd_result?;
+    // The database read fails to send once the response is truncated.
+    if !truncated {
+        db_result?;
+    }
     Ok(())
 }
 
+/// Responses which tell the peer that its request was rejected.
+trait RateLimited {
+    fn rate_limited() -> Self;
+}
+
+macro_rules! impl_rate_limited {
+    ($($response:ty),* $(,)?) => {
+        $(
+            impl RateLimited for $response {
+                fn rate_limited() -> Self {
+                    Self::RateLimited
+                }
+            }
+        )*
+    };
+}
+
+impl_rate_limited!(
+    BlockHeadersResponse,
+    ClassesResponse,
+    StateDiffsResponse,
+    TransactionsResponse,
+    ReceiptsResponse,
+    EventsResponse,
+    ContractRangeResponse,
+    ContractStorageResponse,
+    ClassRangeResponse,
+    ClassesByHashResponse,
+);
+
 /// Returns next block number considering direction.
 ///
 /// None is returned if we're out-of-bounds.
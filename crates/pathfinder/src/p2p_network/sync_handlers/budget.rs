@@ -0,0 +1,313 @@
+//! Budgets which limit how much a single peer, and all peers combined, can request from us.
+//!
+//! Both the number of requests and the number of response bytes are limited using token buckets
+//! which are refilled continuously. A request is rejected up front if any of its buckets is empty.
+//! Response bytes are charged by the codec once they are sent, see [Budget::meter], so a single
+//! message is allowed to overdraw the byte budgets, and the response is cut short once they run
+//! out.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use p2p::libp2p::PeerId;
+use p2p::{RateLimit, ResponseMeter};
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Maximum number of blocks served in response to a single request.
+    pub max_blocks_per_request: u64,
+    /// Requests served to a single peer.
+    pub peer_requests: RateLimit,
+    /// Requests served to all peers combined.
+    pub total_requests: RateLimit,
+    /// Response bytes served to a single peer.
+    pub peer_bytes: RateLimit,
+    /// Response bytes served to all peers combined.
+    pub total_bytes: RateLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    PeerRequests,
+    TotalRequests,
+    PeerBytes,
+    TotalBytes,
+}
+
+impl Rejection {
+    /// Label used in metrics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rejection::PeerRequests => "peer_request_rate",
+            Rejection::TotalRequests => "total_request_rate",
+            Rejection::PeerBytes => "peer_bandwidth",
+            Rejection::TotalBytes => "total_bandwidth",
+        }
+    }
+}
+
+/// Shared between all inbound requests.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    state: Arc<Mutex<State>>,
+    max_blocks_per_request: u64,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            max_blocks_per_request: limits.max_blocks_per_request,
+            state: Arc::new(Mutex::new(State::new(limits, Instant::now()))),
+        }
+    }
+
+    /// Charges the bytes of the responses sent to each peer.
+    pub fn meter(&self) -> ResponseMeter {
+        let state = self.state.clone();
+        ResponseMeter::new(move |peer, bytes| {
+            state.lock().unwrap().charge(peer, bytes, Instant::now())
+        })
+    }
+
+    pub fn for_peer(&self, peer: PeerId) -> PeerBudget {
+        PeerBudget {
+            budget: self.clone(),
+            peer,
+        }
+    }
+}
+
+/// The budget for a single request from `peer`.
+#[derive(Debug, Clone)]
+pub struct PeerBudget {
+    budget: Budget,
+    peer: PeerId,
+}
+
+impl PeerBudget {
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    pub fn max_blocks_per_request(&self) -> u64 {
+        self.budget.max_blocks_per_request
+    }
+
+    /// Accounts for a new request, or rejects it if the peer or the node is over budget.
+    pub fn admit(&self) -> Result<(), Rejection> {
+        self.budget
+            .state
+            .lock()
+            .unwrap()
+            .admit(self.peer, Instant::now())
+    }
+
+    /// Returns `false` if the response should be cut short because the peer or the node ran out
+    /// of bandwidth.
+    pub fn has_bandwidth(&self) -> bool {
+        self.budget
+            .state
+            .lock()
+            .unwrap()
+            .has_bandwidth(self.peer, Instant::now())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    available: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            available: limit.max as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Refills the bucket in proportion to the time elapsed since the last refill.
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let capacity = limit.max as f64;
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.updated_at = now;
+
+        if limit.interval.is_zero() {
+            self.available = capacity;
+        } else {
+            let refill = capacity * elapsed.as_secs_f64() / limit.interval.as_secs_f64();
+            self.available = (self.available + refill).min(capacity);
+        }
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.available >= limit.max as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PeerBuckets {
+    requests: Bucket,
+    bytes: Bucket,
+}
+
+#[derive(Debug)]
+struct State {
+    limits: Limits,
+    total_requests: Bucket,
+    total_bytes: Bucket,
+    peers: HashMap<PeerId, PeerBuckets>,
+}
+
+impl State {
+    fn new(limits: Limits, now: Instant) -> Self {
+        Self {
+            total_requests: Bucket::full(&limits.total_requests, now),
+            total_bytes: Bucket::full(&limits.total_bytes, now),
+            peers: Default::default(),
+            limits,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let limits = &self.limits;
+        self.total_requests.refill(&limits.total_requests, now);
+        self.total_bytes.refill(&limits.total_bytes, now);
+        self.peers.values_mut().for_each(|buckets| {
+            buckets.requests.refill(&limits.peer_requests, now);
+            buckets.bytes.refill(&limits.peer_bytes, now);
+        });
+        // Peers with full buckets are no different from peers we have not seen yet.
+        self.peers.retain(|_, buckets| {
+            !buckets.requests.is_full(&limits.peer_requests)
+                || !buckets.bytes.is_full(&limits.peer_bytes)
+        });
+    }
+
+    fn peer(&mut self, peer: PeerId, now: Instant) -> &mut PeerBuckets {
+        let limits = &self.limits;
+        self.peers.entry(peer).or_insert_with(|| PeerBuckets {
+            requests: Bucket::full(&limits.peer_requests, now),
+            bytes: Bucket::full(&limits.peer_bytes, now),
+        })
+    }
+
+    fn admit(&mut self, peer: PeerId, now: Instant) -> Result<(), Rejection> {
+        self.refill(now);
+
+        let total_requests = self.total_requests.available;
+        let total_bytes = self.total_bytes.available;
+        let buckets = self.peer(peer, now);
+
+        if buckets.requests.available < 1.0 {
+            return Err(Rejection::PeerRequests);
+        }
+        if buckets.bytes.available <= 0.0 {
+            return Err(Rejection::PeerBytes);
+        }
+        if total_requests < 1.0 {
+            return Err(Rejection::TotalRequests);
+        }
+        if total_bytes <= 0.0 {
+            return Err(Rejection::TotalBytes);
+        }
+
+        buckets.requests.available -= 1.0;
+        self.total_requests.available -= 1.0;
+        Ok(())
+    }
+
+    fn has_bandwidth(&mut self, peer: PeerId, now: Instant) -> bool {
+        self.refill(now);
+
+        let total_bytes = self.total_bytes.available;
+        let buckets = self.peer(peer, now);
+
+        buckets.bytes.available > 0.0 && total_bytes > 0.0
+    }
+
+    fn charge(&mut self, peer: PeerId, bytes: usize, now: Instant) {
+        self.refill(now);
+
+        self.peer(peer, now).bytes.available -= bytes as f64;
+        self.total_bytes.available -= bytes as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn per_second(max: usize) -> RateLimit {
+        RateLimit {
+            max,
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_blocks_per_request: 10,
+            peer_requests: per_second(2),
+            total_requests: per_second(3),
+            peer_bytes: per_second(1000),
+            total_bytes: per_second(1500),
+        }
+    }
+
+    #[test]
+    fn request_rate() {
+        let now = Instant::now();
+        let mut state = State::new(limits(), now);
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+
+        assert_eq!(state.admit(alice, now), Ok(()));
+        assert_eq!(state.admit(alice, now), Ok(()));
+        assert_eq!(state.admit(alice, now), Err(Rejection::PeerRequests));
+        assert_eq!(state.admit(bob, now), Ok(()));
+        assert_eq!(state.admit(bob, now), Err(Rejection::TotalRequests));
+
+        // Half a second refills one request for each peer, and one and a half in total.
+        let now = now + Duration::from_millis(500);
+        assert_eq!(state.admit(alice, now), Ok(()));
+        assert_eq!(state.admit(alice, now), Err(Rejection::PeerRequests));
+        assert_eq!(state.admit(bob, now), Err(Rejection::TotalRequests));
+    }
+
+    #[test]
+    fn bytes_can_be_overdrawn_once() {
+        let now = Instant::now();
+        let mut state = State::new(limits(), now);
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+
+        state.charge(alice, 900, now);
+        assert!(state.has_bandwidth(alice, now));
+        state.charge(alice, 900, now);
+        assert!(!state.has_bandwidth(alice, now));
+        assert_eq!(state.admit(alice, now), Err(Rejection::PeerBytes));
+
+        assert_eq!(state.admit(bob, now), Err(Rejection::TotalBytes));
+        assert!(!state.has_bandwidth(bob, now));
+
+        // Alice is 800 bytes in debt, which takes 0.8s to pay off.
+        let now = now + Duration::from_millis(900);
+        assert_eq!(state.admit(alice, now), Ok(()));
+    }
+
+    #[test]
+    fn recovered_peers_are_forgotten() {
+        let now = Instant::now();
+        let mut state = State::new(limits(), now);
+        let peer = PeerId::random();
+
+        state.admit(peer, now).unwrap();
+        state.charge(peer, 100, now);
+        assert_eq!(state.peers.len(), 1);
+
+        state.refill(now + Duration::from_secs(1));
+        assert!(state.peers.is_empty());
+    }
+}
@@ -10,6 +10,7 @@ use p2p_proto::receipt::{
 use p2p_proto::transaction::{AccountSignature, ResourceBounds};
 use pathfinder_common::receipt::Receipt;
 use pathfinder_common::transaction::DataAvailabilityMode;
+use pathfinder_common::trie::TrieNode;
 use pathfinder_common::{event::Event, transaction::ResourceBound, transaction::Transaction};
 use pathfinder_common::{
     AccountDeploymentDataElem, L1DataAvailabilityMode, PaymasterDataElem, TransactionHash,
@@ -325,6 +326,21 @@ impl ToDto<p2p_proto::common::L1DataAvailabilityMode> for L1DataAvailabilityMode
     }
 }
 
+impl ToDto<p2p_proto::snapshot::PatriciaNode> for TrieNode {
+    fn to_dto(self) -> p2p_proto::snapshot::PatriciaNode {
+        use p2p_proto::snapshot::PatriciaNode;
+        match self {
+            TrieNode::Binary { left, right } => PatriciaNode::Binary { left, right },
+            TrieNode::Edge { child, path } => PatriciaNode::Edge {
+                length: path.len() as u32,
+                // Paths are at most 251 bits long.
+                path: Felt::from_bits(&path).expect("Edge path fits into a felt"),
+                value: child,
+            },
+        }
+    }
+}
+
 pub fn sierra_def_into_dto(sierra: Sierra<'_>, compiled: Vec<u8>) -> Cairo1Class {
     let into_dto = |x: SelectorAndFunctionIndex| SierraEntryPoint {
         selector: x.selector.0,
@@ -1,9 +1,29 @@
+use p2p::libp2p::PeerId;
+use p2p::RateLimit;
 use p2p_proto::common::{Direction, Step};
 use pathfinder_common::BlockNumber;
 use rstest::rstest;
 
+use super::{Budget, Limits, PeerBudget, MAX_COUNT_IN_TESTS};
+
 const I64_MAX: u64 = i64::MAX as u64;
 
+/// A budget which only limits the number of blocks per request.
+fn unlimited_budget() -> PeerBudget {
+    let unlimited = RateLimit {
+        max: usize::MAX,
+        interval: std::time::Duration::ZERO,
+    };
+    Budget::new(Limits {
+        max_blocks_per_request: MAX_COUNT_IN_TESTS,
+        peer_requests: unlimited.clone(),
+        total_requests: unlimited.clone(),
+        peer_bytes: unlimited.clone(),
+        total_bytes: unlimited,
+    })
+    .for_peer(PeerId::random())
+}
+
 #[rstest]
 #[case(0, 1, Direction::Forward, Some(1))]
 #[case(0, I64_MAX, Direction::Forward, Some(I64_MAX))]
@@ -30,8 +50,47 @@ fn get_next_block_number(
     );
 }
 
+#[tokio::test]
+async fn requests_over_budget_are_rate_limited() {
+    use futures::StreamExt;
+    use p2p_proto::common::{BlockNumberOrHash, Iteration};
+    use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse};
+    use pathfinder_storage::Storage;
+
+    let per_second = |max| RateLimit {
+        max,
+        interval: std::time::Duration::from_secs(1),
+    };
+    let budget = Budget::new(Limits {
+        max_blocks_per_request: MAX_COUNT_IN_TESTS,
+        peer_requests: per_second(0),
+        total_requests: per_second(10),
+        peer_bytes: per_second(1000),
+        total_bytes: per_second(1000),
+    })
+    .for_peer(PeerId::random());
+
+    let request = BlockHeadersRequest {
+        iteration: Iteration {
+            start: BlockNumberOrHash::Number(0),
+            direction: Direction::Forward,
+            limit: 1,
+            step: 1.into(),
+        },
+    };
+    let (tx, rx) = futures::channel::mpsc::channel(0);
+    let storage = Storage::in_memory().unwrap();
+    let (result, responses) = tokio::join!(
+        super::get_headers(storage, budget, request, tx),
+        rx.collect::<Vec<_>>()
+    );
+
+    result.unwrap();
+    assert_eq!(responses, vec![BlockHeadersResponse::RateLimited]);
+}
+
 mod boundary_conditions {
-    use super::I64_MAX;
+    use super::{unlimited_budget, I64_MAX};
     use crate::p2p_network::sync_handlers::{
         get_classes, get_events, get_headers, get_receipts, get_state_diffs, get_transactions,
     };
@@ -78,7 +137,12 @@ mod boundary_conditions {
                 async fn $name(#[case] iteration: Iteration) {
                     let storage = Storage::in_memory().unwrap();
                     let (tx, mut rx) = mpsc::channel(0);
-                    let _jh = tokio::spawn($uut_name(storage, $request { iteration }, tx));
+                    let _jh = tokio::spawn($uut_name(
+                        storage,
+                        unlimited_budget(),
+                        $request { iteration },
+                        tx,
+                    ));
                     assert_eq!(rx.next().await.unwrap(), Default::default());
                 }
             };
@@ -91,6 +155,42 @@ mod boundary_conditions {
         define_test!(receipts, get_receipts, ReceiptsRequest);
         define_test!(events, get_events, EventsRequest);
     }
+
+    mod missing_state_yields_fin {
+        use super::*;
+        use crate::p2p_network::sync_handlers::{
+            get_class_range, get_classes_by_hash, get_contract_range, get_contract_storage,
+        };
+        use p2p_proto::snapshot::{
+            ClassRangeRequest, ClassesByHashRequest, ContractRangeRequest, ContractStorageRequest,
+        };
+
+        macro_rules! define_test {
+            ($name:ident, $uut_name:ident, $request:ty) => {
+                #[tokio::test]
+                async fn $name() {
+                    let storage = Storage::in_memory().unwrap();
+                    let (tx, mut rx) = mpsc::channel(0);
+                    let _jh = tokio::spawn($uut_name(
+                        storage,
+                        unlimited_budget(),
+                        Faker.fake::<$request>(),
+                        tx,
+                    ));
+                    assert_eq!(rx.next().await.unwrap(), Default::default());
+                }
+            };
+        }
+
+        define_test!(contract_range, get_contract_range, ContractRangeRequest);
+        define_test!(
+            contract_storage,
+            get_contract_storage,
+            ContractStorageRequest
+        );
+        define_test!(class_range, get_class_range, ClassRangeRequest);
+        define_test!(classes_by_hash, get_classes_by_hash, ClassesByHashRequest);
+    }
 }
 
 /// Property tests, grouped to be immediately visible when executed
@@ -165,7 +265,7 @@ mod prop {
             let request = BlockHeadersRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_headers(storage, request, tx);
+                let getter_fut = sync_handlers::get_headers(storage, super::unlimited_budget(), request, tx);
                 // Waiting for both futures to run to completion is faster than spawning the getter
                 // and awaiting the receiver (almost 1s for 100 iterations on Ryzen 3700X).
                 // BTW, we cannot just await the getter and then the receiver
@@ -207,7 +307,7 @@ mod prop {
             let request = StateDiffsRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_state_diffs(storage, request, tx);
+                let getter_fut = sync_handlers::get_state_diffs(storage, super::unlimited_budget(), request, tx);
                 let (_, response) = tokio::join!(getter_fut, rx.collect::<Vec<_>>());
                 response
             });
@@ -283,7 +383,7 @@ mod prop {
             let request = ClassesRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_classes(storage, request, tx);
+                let getter_fut = sync_handlers::get_classes(storage, super::unlimited_budget(), request, tx);
                 let (_, response) = tokio::join!(getter_fut, rx.collect::<Vec<_>>());
                 response
             });
@@ -371,7 +471,7 @@ mod prop {
             let request = TransactionsRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_transactions(storage, request, tx);
+                let getter_fut = sync_handlers::get_transactions(storage, super::unlimited_budget(), request, tx);
                 let (_, responses) = tokio::join!(getter_fut, rx.collect::<Vec<_>>());
                 responses
             });
@@ -413,7 +513,7 @@ mod prop {
             let request = ReceiptsRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_receipts(storage, request, tx);
+                let getter_fut = sync_handlers::get_receipts(storage, super::unlimited_budget(), request, tx);
                 let (_, responses) = tokio::join!(getter_fut, rx.collect::<Vec<_>>());
                 responses
             });
@@ -456,7 +556,7 @@ mod prop {
             let request = EventsRequest { iteration: Iteration { start: BlockNumberOrHash::Number(start_block), limit, step, direction, } };
             let mut responses = Runtime::new().unwrap().block_on(async {
                 let (tx, rx) = mpsc::channel(0);
-                let getter_fut = sync_handlers::get_events(storage, request, tx);
+                let getter_fut = sync_handlers::get_events(storage, super::unlimited_budget(), request, tx);
                 let (_, response) = tokio::join!(getter_fut, rx.collect::<Vec<_>>());
                 response
             });
@@ -1,4 +1,6 @@
 pub mod block_hash;
 mod sync;
 
-pub use sync::{l1, l2, sync, Gossiper, SyncContext};
+#[cfg(feature = "p2p")]
+pub(crate) use sync::update_starknet_state;
+pub use sync::{l1, l2, sync, BlockBatching, Gossiper, StateDivergence, SyncContext};
@@ -3,7 +3,7 @@ use pathfinder_common::event::Event;
 use pathfinder_common::receipt::Receipt;
 use pathfinder_common::transaction::{Transaction, TransactionVariant};
 use pathfinder_common::{
-    BlockHash, BlockNumber, BlockTimestamp, Chain, ChainId, EventCommitment, SequencerAddress,
+    BlockHash, BlockNumber, BlockTimestamp, ChainId, EventCommitment, SequencerAddress,
     StarknetVersion, StateCommitment, TransactionCommitment, TransactionSignatureElem,
 };
 use pathfinder_crypto::{
@@ -17,7 +17,9 @@ use starknet_gateway_types::reply::Block;
 pub enum VerifyResult {
     Match((TransactionCommitment, EventCommitment)),
     Mismatch,
-    NotVerifiable,
+    /// The block hash cannot be recomputed, but the block's transactions and commitments
+    /// have been verified.
+    NotVerifiable((TransactionCommitment, EventCommitment)),
 }
 
 /// Verify the block hash value.
@@ -32,15 +34,24 @@ pub enum VerifyResult {
 ///
 /// See the `compute_block_hash.py` helper script that uses the cairo-lang
 /// Python implementation to compute the block hash for details.
+///
+/// Independently of the block hash, the hashes of all transactions are verified,
+/// and the transaction and event commitments are compared to the ones included in
+/// the block. An error names the offending transaction or commitment.
 pub fn verify_block_hash(
     block: &Block,
-    chain: Chain,
+    meta_info: &BlockHashMetaInfo,
     chain_id: ChainId,
     expected_block_hash: BlockHash,
 ) -> Result<VerifyResult> {
-    let meta_info = meta::for_chain(chain);
+    verify_transactions(block, chain_id)?;
+    let (transaction_commitment, event_commitment) = verify_commitments(block)?;
+
     if !meta_info.can_verify(block.block_number) {
-        return Ok(VerifyResult::NotVerifiable);
+        return Ok(VerifyResult::NotVerifiable((
+            transaction_commitment,
+            event_commitment,
+        )));
     }
 
     let num_transactions: u64 = block
@@ -49,18 +60,7 @@ pub fn verify_block_hash(
         .try_into()
         .expect("too many transactions in block");
 
-    let transaction_final_hash_type =
-        TransactionCommitmentFinalHashType::for_version(&block.starknet_version)?;
-    let transaction_commitment =
-        calculate_transaction_commitment(&block.transactions, transaction_final_hash_type)?;
-    let event_commitment = calculate_event_commitment(&block.transaction_receipts)?;
-
     let verified = if meta_info.uses_pre_0_7_hash_algorithm(block.block_number) {
-        anyhow::ensure!(
-            chain != Chain::Custom,
-            "Chain::Custom should not have any pre 0.7 block hashes"
-        );
-
         let block_hash = compute_final_hash_pre_0_7(
             block.block_number,
             block.state_commitment,
@@ -102,7 +102,89 @@ pub fn verify_block_hash(
     })
 }
 
-mod meta {
+/// Verifies the hash of each transaction, and that each receipt belongs to the
+/// transaction at the same index.
+pub fn verify_transactions(block: &Block, chain_id: ChainId) -> Result<()> {
+    verify_transaction_hashes(
+        block.block_number,
+        &block.transactions,
+        &block.transaction_receipts,
+        chain_id,
+    )
+}
+
+/// Like [verify_transactions], for transactions and receipts which are not part of a
+/// feeder gateway block.
+pub fn verify_transaction_hashes(
+    block_number: BlockNumber,
+    transactions: &[Transaction],
+    receipts: &[Receipt],
+    chain_id: ChainId,
+) -> Result<()> {
+    use rayon::prelude::*;
+
+    anyhow::ensure!(
+        transactions.len() == receipts.len(),
+        "Block {block_number} has {} transactions but {} receipts",
+        transactions.len(),
+        receipts.len()
+    );
+
+    transactions
+        .par_iter()
+        .zip(receipts.par_iter())
+        .enumerate()
+        .try_for_each(|(i, (transaction, receipt))| {
+            anyhow::ensure!(
+                transaction.verify_hash(chain_id),
+                "Transaction hash mismatch for transaction {} at index {i} of block {block_number}",
+                transaction.hash
+            );
+            anyhow::ensure!(
+                receipt.transaction_hash == transaction.hash,
+                "Receipt at index {i} of block {block_number} belongs to transaction {} instead of {}",
+                receipt.transaction_hash,
+                transaction.hash
+            );
+            Ok(())
+        })
+}
+
+/// Computes the transaction and event commitments of a block, and compares them to
+/// the commitments included in the block if there are any.
+pub fn verify_commitments(block: &Block) -> Result<(TransactionCommitment, EventCommitment)> {
+    let transaction_final_hash_type =
+        TransactionCommitmentFinalHashType::for_version(&block.starknet_version)?;
+    let transaction_commitment =
+        calculate_transaction_commitment(&block.transactions, transaction_final_hash_type)?;
+    let event_commitment = calculate_event_commitment(&block.transaction_receipts)?;
+
+    if let Some(expected) = block.transaction_commitment {
+        anyhow::ensure!(
+            expected == transaction_commitment,
+            "Transaction commitment mismatch in block {}, computed {} instead of {}",
+            block.block_number,
+            transaction_commitment,
+            expected
+        );
+    }
+
+    if let Some(expected) = block.event_commitment {
+        anyhow::ensure!(
+            expected == event_commitment,
+            "Event commitment mismatch in block {}, computed {} instead of {}",
+            block.block_number,
+            event_commitment,
+            expected
+        );
+    }
+
+    Ok((transaction_commitment, event_commitment))
+}
+
+pub use meta::BlockHashMetaInfo;
+
+pub mod meta {
     use pathfinder_common::{sequencer_address, BlockNumber, Chain, SequencerAddress};
     use std::ops::Range;
 
@@ -126,11 +208,14 @@ mod meta {
     ///   value is irrecoverable.
     /// * After Starknet 0.8.2 all blocks include the correct sequencer address
     ///   value.
+    ///
+    /// None of this affects the transaction hashes and the transaction and event
+    /// commitments, which are verified for every block.
     #[derive(Clone)]
     pub struct BlockHashMetaInfo {
         /// The number of the first block that was hashed with the Starknet 0.7 hash algorithm.
         pub first_0_7_block: BlockNumber,
-        /// The range of block numbers that can't be verified because of an unknown sequencer address.
+        /// The range of block numbers whose block hash can't be verified because of an unknown sequencer address.
         pub not_verifiable_range: Option<Range<BlockNumber>>,
         /// Fallback sequencer address to use for blocks that don't include the address.
         pub fallback_sequencer_address: Option<SequencerAddress>,
@@ -499,6 +584,7 @@ mod tests {
     use pathfinder_common::felt;
     use pathfinder_common::macro_prelude::*;
     use pathfinder_common::transaction::{EntryPointType, InvokeTransactionV0};
+    use pathfinder_common::Chain;
 
     #[test]
     fn test_event_hash() {
@@ -571,7 +657,7 @@ mod tests {
         assert_matches!(
             verify_block_hash(
                 &block,
-                Chain::GoerliTestnet,
+                meta::for_chain(Chain::GoerliTestnet),
                 ChainId::GOERLI_TESTNET,
                 block.block_hash
             )
@@ -590,7 +676,7 @@ mod tests {
         assert_matches!(
             verify_block_hash(
                 &block,
-                Chain::GoerliTestnet,
+                meta::for_chain(Chain::GoerliTestnet),
                 ChainId::GOERLI_TESTNET,
                 block.block_hash
             )
@@ -610,7 +696,7 @@ mod tests {
         assert_matches!(
             verify_block_hash(
                 &block,
-                Chain::GoerliTestnet,
+                meta::for_chain(Chain::GoerliTestnet),
                 ChainId::GOERLI_TESTNET,
                 block.block_hash,
             )
@@ -627,7 +713,7 @@ mod tests {
         assert_matches!(
             verify_block_hash(
                 &block,
-                Chain::GoerliIntegration,
+                meta::for_chain(Chain::GoerliIntegration),
                 ChainId::GOERLI_INTEGRATION,
                 block.block_hash,
             )
@@ -646,7 +732,7 @@ mod tests {
         assert_matches!(
             verify_block_hash(
                 &block,
-                Chain::GoerliTestnet,
+                meta::for_chain(Chain::GoerliTestnet),
                 ChainId::GOERLI_TESTNET,
                 block.block_hash
             )
@@ -654,4 +740,84 @@ mod tests {
             VerifyResult::Match(_)
         );
     }
+
+    #[test]
+    fn test_block_hash_custom_meta_info() {
+        let json = starknet_gateway_test_fixtures::v0_9_0::block::NUMBER_90000;
+        let block: Block = serde_json::from_str(json).unwrap();
+
+        let meta_info = BlockHashMetaInfo {
+            not_verifiable_range: Some(block.block_number..block.block_number + 1),
+            ..meta::for_chain(Chain::GoerliTestnet).clone()
+        };
+
+        assert_matches!(
+            verify_block_hash(
+                &block,
+                &meta_info,
+                ChainId::GOERLI_TESTNET,
+                block.block_hash
+            )
+            .unwrap(),
+            VerifyResult::NotVerifiable(_)
+        );
+    }
+
+    mod contents {
+        use super::*;
+
+        fn block() -> Block {
+            let json = starknet_gateway_test_fixtures::integration::block::NUMBER_285915;
+            serde_json::from_str(json).unwrap()
+        }
+
+        fn verify(block: &Block) -> anyhow::Result<VerifyResult> {
+            verify_block_hash(
+                block,
+                meta::for_chain(Chain::GoerliIntegration),
+                ChainId::GOERLI_INTEGRATION,
+                block.block_hash,
+            )
+        }
+
+        #[test]
+        fn transaction_hash_mismatch() {
+            let mut block = block();
+            let index = block.transactions.len() - 1;
+            block.transactions[index].hash = transaction_hash!("0x123");
+            block.transaction_receipts[index].transaction_hash = transaction_hash!("0x123");
+
+            let error = verify(&block).unwrap_err().to_string();
+            assert!(error.contains("Transaction hash mismatch"), "{error}");
+            assert!(error.contains("123 at index 1 of block 285915"), "{error}");
+        }
+
+        #[test]
+        fn receipt_of_other_transaction() {
+            let mut block = block();
+            block.transaction_receipts.swap(0, 1);
+
+            let error = verify(&block).unwrap_err().to_string();
+            assert!(error.contains("Receipt at index"), "{error}");
+        }
+
+        #[test]
+        fn commitment_mismatch() {
+            let mut block = block();
+            let (transaction_commitment, event_commitment) =
+                assert_matches!(verify(&block).unwrap(), VerifyResult::Match(c) => c);
+
+            block.transaction_commitment = Some(transaction_commitment);
+            block.event_commitment = Some(event_commitment);
+            assert_matches!(verify(&block).unwrap(), VerifyResult::Match(_));
+
+            block.event_commitment = Some(event_commitment_bytes!(b"wrong"));
+            let error = verify(&block).unwrap_err().to_string();
+            assert!(error.contains("Event commitment mismatch"), "{error}");
+
+            block.transaction_commitment = Some(transaction_commitment_bytes!(b"wrong"));
+            let error = verify(&block).unwrap_err().to_string();
+            assert!(error.contains("Transaction commitment mismatch"), "{error}");
+        }
+    }
 }
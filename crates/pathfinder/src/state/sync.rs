@@ -22,12 +22,16 @@ use starknet_gateway_client::GatewayApi;
 use starknet_gateway_types::reply::Block;
 use starknet_gateway_types::reply::PendingBlock;
 
+use std::collections::HashSet;
 use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 use tokio::sync::mpsc::{self, Receiver};
 
+use crate::state::block_hash::BlockHashMetaInfo;
 use crate::state::l1::L1SyncContext;
 use crate::state::l2::{BlockChain, L2SyncContext};
 
@@ -65,9 +69,11 @@ pub enum SyncEvent {
 
 pub struct SyncContext<G, E> {
     pub storage: Storage,
-    pub ethereum: E,
+    /// The settlement layer, or [None] if L1 sync is disabled.
+    pub ethereum: Option<E>,
     pub chain: Chain,
     pub chain_id: ChainId,
+    pub block_hash_meta: BlockHashMetaInfo,
     pub core_address: H160,
     pub sequencer: G,
     pub state: Arc<SyncState>,
@@ -76,9 +82,78 @@ pub struct SyncContext<G, E> {
     pub block_validation_mode: l2::BlockValidationMode,
     pub websocket_txs: Option<TopicBroadcasters>,
     pub block_cache_size: usize,
+    /// The number of L2 blocks to download ahead of the current head.
+    pub look_ahead: usize,
     pub restart_delay: Duration,
     pub verify_tree_hashes: bool,
     pub gossiper: Gossiper,
+    /// Cleared if the L1 state root diverges from our local state commitment.
+    pub readiness: Arc<AtomicBool>,
+    /// Published once the L1 state root diverges from our local state commitment.
+    pub l1_divergence: WatchSender<Option<StateDivergence>>,
+    pub block_batching: BlockBatching,
+    /// Sync stops at this block, leaving the node's state frozen.
+    pub target_block: Option<BlockNumber>,
+    pub compiler: pathfinder_compiler::Compiler,
+    pub signature_verification: l2::SignatureVerification,
+}
+
+/// Limits for grouping consecutive blocks into a single database transaction while
+/// sync is far behind the head of the chain.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockBatching {
+    /// The maximum number of blocks per database transaction. One disables batching.
+    pub max_blocks: NonZeroUsize,
+    /// The maximum time to wait for further blocks before committing a batch.
+    pub max_duration: Duration,
+}
+
+impl BlockBatching {
+    /// Commits every block in its own database transaction.
+    pub const DISABLED: Self = Self {
+        max_blocks: NonZeroUsize::MIN,
+        max_duration: Duration::ZERO,
+    };
+}
+
+/// The state root committed on L1 for a block differs from the state commitment
+/// we computed and stored locally for the same block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateDivergence {
+    pub block_number: BlockNumber,
+    pub l1_state_root: StateCommitment,
+    pub local_state_commitment: StateCommitment,
+}
+
+impl<G, E> SyncContext<G, E> {
+    /// Replaces the source of L2 blocks, e.g. with a local archive.
+    pub fn with_sequencer<S>(self, sequencer: S) -> SyncContext<S, E> {
+        SyncContext {
+            storage: self.storage,
+            ethereum: self.ethereum,
+            chain: self.chain,
+            chain_id: self.chain_id,
+            block_hash_meta: self.block_hash_meta,
+            core_address: self.core_address,
+            sequencer,
+            state: self.state,
+            head_poll_interval: self.head_poll_interval,
+            pending_data: self.pending_data,
+            block_validation_mode: self.block_validation_mode,
+            websocket_txs: self.websocket_txs,
+            block_cache_size: self.block_cache_size,
+            look_ahead: self.look_ahead,
+            restart_delay: self.restart_delay,
+            verify_tree_hashes: self.verify_tree_hashes,
+            gossiper: self.gossiper,
+            readiness: self.readiness,
+            l1_divergence: self.l1_divergence,
+            block_batching: self.block_batching,
+            target_block: self.target_block,
+            compiler: self.compiler,
+            signature_verification: self.signature_verification,
+        }
+    }
 }
 
 impl<G, E> From<&SyncContext<G, E>> for L1SyncContext<E>
@@ -91,6 +166,7 @@ where
             chain: value.chain,
             core_address: value.core_address,
             poll_interval: value.head_poll_interval,
+            target_block: value.target_block,
         }
     }
 }
@@ -102,10 +178,14 @@ where
     fn from(value: &SyncContext<G, E>) -> Self {
         Self {
             sequencer: value.sequencer.clone(),
-            chain: value.chain,
+            block_hash_meta: value.block_hash_meta.clone(),
             chain_id: value.chain_id,
             block_validation_mode: value.block_validation_mode,
             storage: value.storage.clone(),
+            look_ahead: value.look_ahead,
+            target_block: value.target_block,
+            compiler: value.compiler,
+            signature_verification: value.signature_verification,
         }
     }
 }
@@ -170,6 +250,7 @@ where
         ethereum: _,
         chain: _,
         chain_id: _,
+        block_hash_meta: _,
         core_address: _,
         sequencer,
         state,
@@ -178,9 +259,16 @@ where
         block_validation_mode: _,
         websocket_txs,
         block_cache_size,
+        look_ahead: _,
         restart_delay,
         verify_tree_hashes: _,
         gossiper,
+        readiness,
+        l1_divergence,
+        block_batching,
+        target_block,
+        compiler: _,
+        signature_verification: _,
     } = context;
 
     let mut db_conn = storage
@@ -214,6 +302,7 @@ where
         starting_block_num,
         head_poll_interval,
         gossiper,
+        target_block,
     ));
 
     // Start L1 producer task. Clone the event sender so that the channel remains open
@@ -240,6 +329,9 @@ where
         pending_data,
         verify_tree_hashes: context.verify_tree_hashes,
         websocket_txs,
+        readiness,
+        l1_divergence,
+        block_batching,
     };
     let mut consumer_handle = tokio::spawn(consumer(event_receiver, consumer_context));
 
@@ -359,6 +451,9 @@ struct ConsumerContext {
     pub pending_data: WatchSender<PendingData>,
     pub verify_tree_hashes: bool,
     pub websocket_txs: Option<TopicBroadcasters>,
+    pub readiness: Arc<AtomicBool>,
+    pub l1_divergence: WatchSender<Option<StateDivergence>>,
+    pub block_batching: BlockBatching,
 }
 
 async fn consumer(mut events: Receiver<SyncEvent>, context: ConsumerContext) -> anyhow::Result<()> {
@@ -368,6 +463,9 @@ async fn consumer(mut events: Receiver<SyncEvent>, context: ConsumerContext) ->
         pending_data,
         verify_tree_hashes,
         mut websocket_txs,
+        readiness,
+        l1_divergence,
+        block_batching,
     } = context;
 
     let mut last_block_start = std::time::Instant::now();
@@ -392,102 +490,158 @@ async fn consumer(mut events: Receiver<SyncEvent>, context: ConsumerContext) ->
     })
     .context("Fetching latest block time")?;
 
-    while let Some(event) = events.recv().await {
+    // An event which ended a block batch, and still needs to be handled.
+    let mut deferred = None;
+
+    loop {
+        let event = match deferred.take() {
+            Some(event) => event,
+            None => match events.recv().await {
+                Some(event) => event,
+                None => break,
+            },
+        };
+
         use SyncEvent::*;
         match event {
             L1Update(update) => {
-                l1_update(&mut db_conn, &update).await?;
+                let divergence = l1_update(&mut db_conn, &update).await?;
                 tracing::info!("L1 sync updated to block {}", update.block_number);
+
+                if let Some(divergence) = divergence {
+                    report_state_divergence(divergence, &readiness, &l1_divergence);
+                }
             }
-            Block((block, (tx_comm, ev_comm)), state_update, signature, timings) => {
-                if block.block_number < next_number {
-                    tracing::debug!("Ignoring duplicate block {}", block.block_number);
-                    continue;
+            block @ Block(..) => {
+                let mut batch = vec![block];
+
+                // Group the blocks that follow into the same database transaction while
+                // we are far behind the head of the chain.
+                let far_behind = match &*state.status.read().await {
+                    Syncing::False(_) => false,
+                    Syncing::Status(status) => {
+                        status.highest.number.get()
+                            > next_number.get() + block_batching.max_blocks.get() as u64
+                    }
+                };
+                if far_behind && block_batching.max_blocks.get() > 1 {
+                    let deadline = tokio::time::Instant::now() + block_batching.max_duration;
+                    let mut batch_blocks = 1;
+
+                    while batch_blocks < block_batching.max_blocks.get() {
+                        match tokio::time::timeout_at(deadline, events.recv()).await {
+                            Ok(Some(event @ Block(..))) => {
+                                batch.push(event);
+                                batch_blocks += 1;
+                            }
+                            Ok(Some(event @ (CairoClass { .. } | SierraClass { .. }))) => {
+                                batch.push(event)
+                            }
+                            Ok(Some(event)) => {
+                                deferred = Some(event);
+                                break;
+                            }
+                            // Either the channel closed or we ran out of time.
+                            Ok(None) | Err(_) => break,
+                        }
+                    }
                 }
 
-                let block_number = block.block_number;
-                let block_hash = block.block_hash;
-                let block_timestamp = block.timestamp;
-                let storage_updates: usize = state_update
-                    .contract_updates
-                    .iter()
-                    .map(|x| x.1.storage.len())
-                    .sum();
-                let update_t = std::time::Instant::now();
-                l2_update(
+                let updated = l2_update(
                     &mut db_conn,
-                    *block,
-                    tx_comm,
-                    ev_comm,
-                    *state_update,
-                    *signature,
+                    batch,
+                    next_number,
                     verify_tree_hashes,
                     storage.clone(),
-                    &mut websocket_txs,
                 )
-                .await
-                .with_context(|| format!("Update L2 state to {block_number}"))?;
-                let block_time = last_block_start.elapsed();
-                let update_t = update_t.elapsed();
-                last_block_start = std::time::Instant::now();
+                .await?;
+
+                for UpdatedBlock {
+                    header,
+                    divergence,
+                    timings,
+                    storage_updates,
+                    update_t,
+                } in updated
+                {
+                    let block_number = header.number;
+                    let block_hash = header.hash;
+                    let block_timestamp = header.timestamp;
+
+                    if let Some(divergence) = divergence {
+                        report_state_divergence(divergence, &readiness, &l1_divergence);
+                    }
 
-                block_time_avg = block_time_avg.mul_f32(1.0 - BLOCK_TIME_WEIGHT)
-                    + block_time.mul_f32(BLOCK_TIME_WEIGHT);
+                    if let Some(sender) = &websocket_txs {
+                        if let Err(e) = sender.new_head.send_if_receiving(header.into()) {
+                            tracing::error!(error=?e, "Failed to send header over websocket broadcaster.");
+                            // Disable websocket entirely so that the closed channel doesn't spam this error. It
+                            // is unlikely that any error here wouldn't simply repeat indefinitely.
+                            websocket_txs = None;
+                        }
+                    }
 
-                // Update sync status
-                match &mut *state.status.write().await {
-                    Syncing::False(_) => {}
-                    Syncing::Status(status) => {
-                        status.current = NumberedBlock::from((block_hash, block_number));
+                    let block_time = last_block_start.elapsed();
+                    last_block_start = std::time::Instant::now();
+
+                    block_time_avg = block_time_avg.mul_f32(1.0 - BLOCK_TIME_WEIGHT)
+                        + block_time.mul_f32(BLOCK_TIME_WEIGHT);
 
-                        metrics::gauge!("current_block", block_number.get() as f64);
+                    // Update sync status
+                    match &mut *state.status.write().await {
+                        Syncing::False(_) => {}
+                        Syncing::Status(status) => {
+                            status.current = NumberedBlock::from((block_hash, block_number));
 
-                        if status.highest.number <= block_number {
-                            status.highest = status.current;
-                            metrics::gauge!("highest_block", block_number.get() as f64);
+                            metrics::gauge!("current_block", block_number.get() as f64);
+
+                            if status.highest.number <= block_number {
+                                status.highest = status.current;
+                                metrics::gauge!("highest_block", block_number.get() as f64);
+                            }
                         }
                     }
-                }
 
-                let now_timestamp = time::OffsetDateTime::now_utc().unix_timestamp() as u64;
-                let latency = now_timestamp.saturating_sub(block_timestamp.get());
-
-                let download_time = (timings.block_download
-                    + timings.class_declaration
-                    + timings.signature_download)
-                    .as_secs_f64();
-
-                metrics::gauge!("block_download", download_time);
-                metrics::gauge!("block_processing", update_t.as_secs_f64());
-                metrics::gauge!("block_latency", latency as f64);
-                metrics::gauge!(
-                    "block_time",
-                    (block_timestamp.get() - latest_timestamp.get()) as f64
-                );
-                latest_timestamp = block_timestamp;
-                next_number += 1;
-
-                // Give a simple log under INFO level, and a more verbose log
-                // with timing information under DEBUG+ level.
-                //
-                // This should be removed if we have a configurable log level.
-                // See the docs for LevelFilter for more information.
-                match tracing::level_filters::LevelFilter::current().into_level() {
-                    None => {}
-                    Some(level) if level <= tracing::Level::INFO => {
-                        tracing::info!("Updated Starknet state with block {}", block_number)
-                    }
-                    Some(_) => {
-                        tracing::debug!("Updated Starknet state with block {} after {:2}s ({:2}s avg). contracts ({:2}s), {} storage updates ({:2}s). Block downloaded in {:2}s, signature in {:2}s",
-                                    block_number,
-                                    block_time.as_secs_f32(),
-                                    block_time_avg.as_secs_f32(),
-                                    timings.class_declaration.as_secs_f32(),
-                                    storage_updates,
-                                    update_t.as_secs_f32(),
-                                    timings.block_download.as_secs_f32(),
-                                    timings.signature_download.as_secs_f32(),
-                                );
+                    let now_timestamp = time::OffsetDateTime::now_utc().unix_timestamp() as u64;
+                    let latency = now_timestamp.saturating_sub(block_timestamp.get());
+
+                    let download_time = (timings.block_download
+                        + timings.class_declaration
+                        + timings.signature_download)
+                        .as_secs_f64();
+
+                    metrics::gauge!("block_download", download_time);
+                    metrics::gauge!("block_processing", update_t.as_secs_f64());
+                    metrics::gauge!("block_latency", latency as f64);
+                    metrics::gauge!(
+                        "block_time",
+                        (block_timestamp.get() - latest_timestamp.get()) as f64
+                    );
+                    latest_timestamp = block_timestamp;
+                    next_number = block_number + 1;
+
+                    // Give a simple log under INFO level, and a more verbose log
+                    // with timing information under DEBUG+ level.
+                    //
+                    // This should be removed if we have a configurable log level.
+                    // See the docs for LevelFilter for more information.
+                    match tracing::level_filters::LevelFilter::current().into_level() {
+                        None => {}
+                        Some(level) if level <= tracing::Level::INFO => {
+                            tracing::info!("Updated Starknet state with block {}", block_number)
+                        }
+                        Some(_) => {
+                            tracing::debug!("Updated Starknet state with block {} after {:2}s ({:2}s avg). contracts ({:2}s), {} storage updates ({:2}s). Block downloaded in {:2}s, signature in {:2}s",
+                                        block_number,
+                                        block_time.as_secs_f32(),
+                                        block_time_avg.as_secs_f32(),
+                                        timings.class_declaration.as_secs_f32(),
+                                        storage_updates,
+                                        update_t.as_secs_f32(),
+                                        timings.block_download.as_secs_f32(),
+                                        timings.signature_download.as_secs_f32(),
+                                    );
+                        }
                     }
                 }
             }
@@ -516,6 +670,7 @@ async fn consumer(mut events: Receiver<SyncEvent>, context: ConsumerContext) ->
                         .context("Creating database transaction")?;
                     tx.insert_cairo_class(hash, &definition)
                         .context("Inserting new cairo class")?;
+                    tx.dequeue_class(hash).context("Dequeuing cairo class")?;
                     tx.commit().context("Committing database transaction")
                 })
                 .with_context(|| format!("Insert Cairo contract definition with hash: {hash}"))?;
@@ -539,6 +694,8 @@ async fn consumer(mut events: Receiver<SyncEvent>, context: ConsumerContext) ->
                         &casm_definition,
                     )
                     .context("Inserting sierra class")?;
+                    tx.dequeue_class(ClassHash(sierra_hash.0))
+                        .context("Dequeuing sierra class")?;
                     tx.commit().context("Committing database transaction")
                 })
                 .with_context(|| {
@@ -611,7 +768,8 @@ async fn latest_n_blocks(
     })
 }
 
-/// Periodically updates sync state with the latest block height.
+/// Periodically updates sync state with the latest block height, or with the target
+/// block once the chain has moved past it.
 ///
 /// If feature `p2p` is enabled and node type is `proxy`
 /// propagates latest head after every change or otherwise every 2 minutes.
@@ -622,12 +780,27 @@ async fn update_sync_status_latest(
     starting_block_num: BlockNumber,
     poll_interval: Duration,
     gossiper: Gossiper,
+    target_block: Option<BlockNumber>,
 ) -> anyhow::Result<()> {
     let starting = NumberedBlock::from((starting_block_hash, starting_block_num));
     let mut last_propagated = Instant::now();
+    let mut target = None;
 
     loop {
-        match sequencer.head().await {
+        let head = match (target, target_block) {
+            (Some(target), _) => Ok(target),
+            (None, Some(target_block)) => match sequencer.head().await {
+                Ok((block_number, _)) if block_number >= target_block => {
+                    let head = sequencer.block_header(target_block.into()).await;
+                    target = head.as_ref().ok().copied();
+                    head
+                }
+                other => other,
+            },
+            (None, None) => sequencer.head().await,
+        };
+
+        match head {
             Ok((block_number, block_hash)) => {
                 let latest = NumberedBlock::from((block_hash, block_number));
 
@@ -684,10 +857,14 @@ async fn propagate_head(gossiper: &Gossiper, last_propagated: &mut Instant, head
     *last_propagated = Instant::now();
 }
 
+/// Stores the L1 state update and advances the L1-L2 pointer if the block hashes match.
+///
+/// Returns a [StateDivergence] if the L1 state root differs from the state commitment
+/// we have stored for the same block.
 async fn l1_update(
     connection: &mut Connection,
     update: &EthereumStateUpdate,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<StateDivergence>> {
     tokio::task::block_in_place(move || {
         let transaction = connection
             .transaction_with_behavior(TransactionBehavior::Immediate)
@@ -697,163 +874,200 @@ async fn l1_update(
             .upsert_l1_state(update)
             .context("Insert update")?;
 
-        let l2_hash = transaction
-            .block_hash(update.block_number.into())
-            .context("Fetching block hash")?;
+        let l2_header = transaction
+            .block_header(update.block_number.into())
+            .context("Fetching block header")?;
 
-        if let Some(l2_hash) = l2_hash {
-            if l2_hash == update.block_hash {
+        let mut divergence = None;
+
+        if let Some(l2_header) = l2_header {
+            if l2_header.hash == update.block_hash {
                 transaction
                     .update_l1_l2_pointer(Some(update.block_number))
                     .context("Updating L1-L2 pointer")?;
                 tracing::info!(block=?update.block_number, "Updated L1/L2 match");
             } else {
-                tracing::warn!(block_number=?update.block_number, L1=?update.block_hash, L2=?l2_hash, "L1/L2 block hash mismatch");
+                tracing::warn!(block_number=?update.block_number, L1=?update.block_hash, L2=?l2_header.hash, "L1/L2 block hash mismatch");
                 if let Some(matching_block_number) = transaction.l1_l2_pointer()? {
                     tracing::warn!(block_number=?matching_block_number, "Most recent L1/L2 block hash match")
                 }
             }
+
+            divergence = state_divergence(update, &l2_header);
         }
 
-        transaction.commit().context("Commit database transaction")
+        transaction
+            .commit()
+            .context("Commit database transaction")?;
+
+        Ok(divergence)
     })
 }
 
-/// Returns the new [StateCommitment] after the update.
-#[allow(clippy::too_many_arguments)]
+/// Returns a [StateDivergence] if the L1 state root in `update` does not match the
+/// state commitment of our local `header` for the same block.
+///
+/// A local block with a different hash is on a fork which is about to be reorged away, so it
+/// is left to the reorg logic instead.
+fn state_divergence(update: &EthereumStateUpdate, header: &BlockHeader) -> Option<StateDivergence> {
+    if update.block_number != header.number
+        || update.block_hash != header.hash
+        || update.state_root == header.state_commitment
+    {
+        return None;
+    }
+
+    Some(StateDivergence {
+        block_number: header.number,
+        l1_state_root: update.state_root,
+        local_state_commitment: header.state_commitment,
+    })
+}
+
+/// Flags the node as unhealthy and notifies any listeners of the L1/L2 state divergence.
+fn report_state_divergence(
+    divergence: StateDivergence,
+    readiness: &AtomicBool,
+    l1_divergence: &WatchSender<Option<StateDivergence>>,
+) {
+    tracing::error!(
+        block_number=%divergence.block_number,
+        L1=%divergence.l1_state_root,
+        local=%divergence.local_state_commitment,
+        "L1 state root does not match local state commitment"
+    );
+    metrics::increment_counter!("l1_state_root_mismatch_total");
+    metrics::gauge!(
+        "l1_state_root_mismatch_block",
+        divergence.block_number.get() as f64
+    );
+
+    readiness.store(false, std::sync::atomic::Ordering::Relaxed);
+    l1_divergence.send_if_modified(|current| {
+        // Only the first divergence is of interest, later ones are a consequence of it.
+        if current.is_none() {
+            *current = Some(divergence);
+            true
+        } else {
+            false
+        }
+    });
+}
+
+/// A block which has been persisted by [l2_update].
+struct UpdatedBlock {
+    header: BlockHeader,
+    /// Set if L1 has already committed to a different state root for this block.
+    divergence: Option<StateDivergence>,
+    timings: l2::Timings,
+    storage_updates: usize,
+    update_t: Duration,
+}
+
+/// Persists a batch of consecutive blocks, along with the classes declared for them, in a
+/// single database transaction.
+///
+/// Blocks older than `next_number` have already been stored and are skipped.
 async fn l2_update(
     connection: &mut Connection,
-    block: Block,
-    transaction_commitment: TransactionCommitment,
-    event_commitment: EventCommitment,
-    state_update: StateUpdate,
-    signature: BlockCommitmentSignature,
+    batch: Vec<SyncEvent>,
+    mut next_number: BlockNumber,
     verify_tree_hashes: bool,
     // we need this so that we can create extra read-only transactions for
     // parallel contract state updates
     storage: Storage,
-    websocket_txs: &mut Option<TopicBroadcasters>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<UpdatedBlock>> {
     tokio::task::block_in_place(move || {
         let transaction = connection
             .transaction_with_behavior(TransactionBehavior::Immediate)
             .context("Create database transaction")?;
-        let (storage_commitment, class_commitment) = update_starknet_state(
-            &transaction,
-            &state_update,
-            verify_tree_hashes,
-            block.block_number,
-            storage,
-        )
-        .context("Updating Starknet state")?;
-        let state_commitment = StateCommitment::calculate(storage_commitment, class_commitment);
-
-        // Ensure that roots match.. what should we do if it doesn't? For now the whole sync process ends..
-        #[cfg(not(feature = "p2p"))]
-        anyhow::ensure!(
-            state_commitment == block.state_commitment,
-            "State root mismatch"
-        );
-
-        // In p2p the state commitment can be missing, which is marked as 0.
-        // Once signature support is added this way of verifying state commitment will be deprecated.
-        #[cfg(feature = "p2p")]
-        anyhow::ensure!(
-            block.state_commitment == StateCommitment::ZERO
-                || state_commitment == block.state_commitment,
-            "State root mismatch"
-        );
 
-        let transaction_count = block.transactions.len();
-        let event_count = block
-            .transaction_receipts
-            .iter()
-            .map(|r| r.events.len())
-            .sum();
-
-        // Update L2 database. These types shouldn't be options at this level,
-        // but for now the unwraps are "safe" in that these should only ever be
-        // None for pending queries to the sequencer, but we aren't using those here.
-        let header = BlockHeader {
-            hash: block.block_hash,
-            parent_hash: block.parent_block_hash,
-            number: block.block_number,
-            timestamp: block.timestamp,
-            // Default value for cairo <0.8.2 is 0
-            eth_l1_gas_price: block.eth_l1_gas_price().unwrap_or(GasPrice::ZERO),
-            // Default value for Starknet <0.13.0 is zero
-            strk_l1_gas_price: block.strk_l1_gas_price().unwrap_or(GasPrice::ZERO),
-            // Default value for Starknet <0.13.1 is zero
-            eth_l1_data_gas_price: block
-                .l1_data_gas_price
-                .map(|x| x.price_in_wei)
-                .unwrap_or(GasPrice::ZERO),
-            // Default value for Starknet <0.13.1 is zero
-            strk_l1_data_gas_price: block
-                .l1_data_gas_price
-                .map(|x| x.price_in_fri)
-                .unwrap_or(GasPrice::ZERO),
-            sequencer_address: block
-                .sequencer_address
-                .unwrap_or(SequencerAddress(Felt::ZERO)),
-            starknet_version: block.starknet_version,
-            class_commitment,
-            event_commitment,
-            state_commitment,
-            storage_commitment,
-            transaction_commitment,
-            transaction_count,
-            event_count,
-            l1_da_mode: block.l1_da_mode.map(Into::into).unwrap_or_default(),
-        };
-
-        transaction
-            .insert_block_header(&header)
-            .context("Inserting block header into database")?;
-
-        // Insert the transactions.
-        anyhow::ensure!(
-            block.transactions.len() == block.transaction_receipts.len(),
-            "Transactions and receipts mismatch. There were {} transactions and {} receipts.",
-            block.transactions.len(),
-            block.transaction_receipts.len()
-        );
-        let transaction_data = block
-            .transactions
-            .into_iter()
-            .zip(block.transaction_receipts.into_iter())
-            .collect::<Vec<_>>();
-
-        transaction
-            .insert_transaction_data(header.hash, header.number, &transaction_data)
-            .context("Insert transaction data into database")?;
-
-        // Insert state updates
-        transaction
-            .insert_state_update(block.block_number, &state_update)
-            .context("Insert state update into database")?;
+        // Contracts updated by earlier blocks in this batch. Their changes are not yet
+        // visible to the read-only connections used for parallel contract state updates.
+        let mut uncommitted = HashSet::new();
+        let mut updated = Vec::new();
+
+        for event in batch {
+            match event {
+                SyncEvent::Block((block, (tx_comm, ev_comm)), state_update, signature, timings) => {
+                    let block_number = block.block_number;
+                    if block_number < next_number {
+                        tracing::debug!("Ignoring duplicate block {}", block_number);
+                        continue;
+                    }
 
-        // Insert signature
-        transaction
-            .insert_signature(block.block_number, &signature)
-            .context("Insert signature into database")?;
+                    let storage_updates: usize = state_update
+                        .contract_updates
+                        .iter()
+                        .map(|x| x.1.storage.len())
+                        .sum();
+                    let update_t = std::time::Instant::now();
+
+                    let (header, divergence) = insert_block(
+                        &transaction,
+                        *block,
+                        tx_comm,
+                        ev_comm,
+                        &state_update,
+                        *signature,
+                        verify_tree_hashes,
+                        storage.clone(),
+                        &uncommitted,
+                    )
+                    .with_context(|| format!("Update L2 state to {block_number}"))?;
+
+                    uncommitted.extend(
+                        state_update
+                            .contract_updates
+                            .keys()
+                            .chain(state_update.system_contract_updates.keys())
+                            .copied(),
+                    );
+                    next_number = block_number + 1;
+
+                    updated.push(UpdatedBlock {
+                        header,
+                        divergence,
+                        timings,
+                        storage_updates,
+                        update_t: update_t.elapsed(),
+                    });
+                }
+                SyncEvent::CairoClass { definition, hash } => {
+                    transaction
+                        .insert_cairo_class(hash, &definition)
+                        .with_context(|| {
+                            format!("Insert Cairo contract definition with hash: {hash}")
+                        })?;
+                    transaction
+                        .dequeue_class(hash)
+                        .context("Dequeuing cairo class")?;
 
-        // Track combined L1 and L2 state.
-        let l1_l2_head = transaction.l1_l2_pointer().context("Query L1-L2 head")?;
-        let expected_next = l1_l2_head
-            .map(|head| head + 1)
-            .unwrap_or(BlockNumber::GENESIS);
-
-        if expected_next == header.number {
-            if let Some(l1_state) = transaction
-                .l1_state_at_number(header.number)
-                .context("Query L1 state")?
-            {
-                if l1_state.block_hash == header.hash {
+                    tracing::debug!(%hash, "Inserted new Cairo class");
+                }
+                SyncEvent::SierraClass {
+                    sierra_definition,
+                    sierra_hash,
+                    casm_definition,
+                    casm_hash,
+                } => {
+                    transaction
+                        .insert_sierra_class(
+                            &sierra_hash,
+                            &sierra_definition,
+                            &casm_hash,
+                            &casm_definition,
+                        )
+                        .with_context(|| {
+                            format!("Insert Sierra contract definition with hash: {sierra_hash}")
+                        })?;
                     transaction
-                        .update_l1_l2_pointer(Some(header.number))
-                        .context("Update L1-L2 head")?;
+                        .dequeue_class(ClassHash(sierra_hash.0))
+                        .context("Dequeuing sierra class")?;
+
+                    tracing::debug!(sierra=%sierra_hash, casm=%casm_hash, "Inserted new Sierra class");
                 }
+                other => anyhow::bail!("Unexpected event in block batch: {other:?}"),
             }
         }
 
@@ -861,19 +1075,149 @@ async fn l2_update(
             .commit()
             .context("Commit database transaction")?;
 
-        if let Some(sender) = websocket_txs {
-            if let Err(e) = sender.new_head.send_if_receiving(header.into()) {
-                tracing::error!(error=?e, "Failed to send header over websocket broadcaster.");
-                // Disable websocket entirely so that the closed channel doesn't spam this error. It
-                // is unlikely that any error here wouldn't simply repeat indefinitely.
-                *websocket_txs = None;
+        Ok(updated)
+    })
+}
+
+/// Stores the block and its state update as part of `transaction`.
+///
+/// Also returns a [StateDivergence] if L1 has already committed to a state root for this
+/// block which differs from the state commitment we computed.
+#[allow(clippy::too_many_arguments)]
+fn insert_block(
+    transaction: &Transaction<'_>,
+    block: Block,
+    transaction_commitment: TransactionCommitment,
+    event_commitment: EventCommitment,
+    state_update: &StateUpdate,
+    signature: BlockCommitmentSignature,
+    verify_tree_hashes: bool,
+    storage: Storage,
+    uncommitted: &HashSet<ContractAddress>,
+) -> anyhow::Result<(BlockHeader, Option<StateDivergence>)> {
+    let (storage_commitment, class_commitment) = update_starknet_state(
+        transaction,
+        state_update,
+        verify_tree_hashes,
+        block.block_number,
+        storage,
+        uncommitted,
+    )
+    .context("Updating Starknet state")?;
+    let state_commitment = StateCommitment::calculate(storage_commitment, class_commitment);
+
+    // Ensure that roots match.. what should we do if it doesn't? For now the whole sync process ends..
+    #[cfg(not(feature = "p2p"))]
+    anyhow::ensure!(
+        state_commitment == block.state_commitment,
+        "State root mismatch"
+    );
+
+    // In p2p the state commitment can be missing, which is marked as 0.
+    // Once signature support is added this way of verifying state commitment will be deprecated.
+    #[cfg(feature = "p2p")]
+    anyhow::ensure!(
+        block.state_commitment == StateCommitment::ZERO
+            || state_commitment == block.state_commitment,
+        "State root mismatch"
+    );
+
+    let transaction_count = block.transactions.len();
+    let event_count = block
+        .transaction_receipts
+        .iter()
+        .map(|r| r.events.len())
+        .sum();
+
+    // Update L2 database. These types shouldn't be options at this level,
+    // but for now the unwraps are "safe" in that these should only ever be
+    // None for pending queries to the sequencer, but we aren't using those here.
+    let header = BlockHeader {
+        hash: block.block_hash,
+        parent_hash: block.parent_block_hash,
+        number: block.block_number,
+        timestamp: block.timestamp,
+        // Default value for cairo <0.8.2 is 0
+        eth_l1_gas_price: block.eth_l1_gas_price().unwrap_or(GasPrice::ZERO),
+        // Default value for Starknet <0.13.0 is zero
+        strk_l1_gas_price: block.strk_l1_gas_price().unwrap_or(GasPrice::ZERO),
+        // Default value for Starknet <0.13.1 is zero
+        eth_l1_data_gas_price: block
+            .l1_data_gas_price
+            .map(|x| x.price_in_wei)
+            .unwrap_or(GasPrice::ZERO),
+        // Default value for Starknet <0.13.1 is zero
+        strk_l1_data_gas_price: block
+            .l1_data_gas_price
+            .map(|x| x.price_in_fri)
+            .unwrap_or(GasPrice::ZERO),
+        sequencer_address: block
+            .sequencer_address
+            .unwrap_or(SequencerAddress(Felt::ZERO)),
+        starknet_version: block.starknet_version,
+        class_commitment,
+        event_commitment,
+        state_commitment,
+        storage_commitment,
+        transaction_commitment,
+        transaction_count,
+        event_count,
+        l1_da_mode: block.l1_da_mode.map(Into::into).unwrap_or_default(),
+    };
+
+    transaction
+        .insert_block_header(&header)
+        .context("Inserting block header into database")?;
+
+    // Insert the transactions.
+    anyhow::ensure!(
+        block.transactions.len() == block.transaction_receipts.len(),
+        "Transactions and receipts mismatch. There were {} transactions and {} receipts.",
+        block.transactions.len(),
+        block.transaction_receipts.len()
+    );
+    let transaction_data = block
+        .transactions
+        .into_iter()
+        .zip(block.transaction_receipts.into_iter())
+        .collect::<Vec<_>>();
+
+    transaction
+        .insert_transaction_data(header.hash, header.number, &transaction_data)
+        .context("Insert transaction data into database")?;
+
+    // Insert state updates
+    transaction
+        .insert_state_update(block.block_number, state_update)
+        .context("Insert state update into database")?;
+
+    // Insert signature
+    transaction
+        .insert_signature(block.block_number, &signature)
+        .context("Insert signature into database")?;
+
+    // Track combined L1 and L2 state.
+    let l1_state = transaction
+        .l1_state_at_number(header.number)
+        .context("Query L1 state")?;
+    let l1_l2_head = transaction.l1_l2_pointer().context("Query L1-L2 head")?;
+    let expected_next = l1_l2_head
+        .map(|head| head + 1)
+        .unwrap_or(BlockNumber::GENESIS);
+
+    if expected_next == header.number {
+        if let Some(l1_state) = &l1_state {
+            if l1_state.block_hash == header.hash {
+                transaction
+                    .update_l1_l2_pointer(Some(header.number))
+                    .context("Update L1-L2 head")?;
             }
         }
+    }
 
-        Ok(())
-    })?;
+    let divergence = l1_state.and_then(|l1_state| state_divergence(&l1_state, &header));
 
-    Ok(())
+    Ok((header, divergence))
 }
 
 async fn l2_reorg(connection: &mut Connection, reorg_tail: BlockNumber) -> anyhow::Result<()> {
@@ -933,7 +1277,7 @@ async fn l2_reorg(connection: &mut Connection, reorg_tail: BlockNumber) -> anyho
     })
 }
 
-fn update_starknet_state(
+pub(crate) fn update_starknet_state(
     transaction: &Transaction<'_>,
     state_update: &StateUpdate,
     verify_hashes: bool,
@@ -941,6 +1285,8 @@ fn update_starknet_state(
     // we need this so that we can create extra read-only transactions for
     // parallel contract state updates
     storage: Storage,
+    // contracts with changes in `transaction` that the read-only transactions can't see
+    uncommitted: &HashSet<ContractAddress>,
 ) -> anyhow::Result<(StorageCommitment, ClassCommitment)> {
     use rayon::prelude::*;
 
@@ -951,16 +1297,38 @@ fn update_starknet_state(
     }
     .with_verify_hashes(verify_hashes);
 
+    // System contracts are only distinguished by not having a nonce or class, so their
+    // tries can be updated alongside all other contracts.
+    let contract_updates =
+        state_update
+            .contract_updates
+            .par_iter()
+            .map(|(contract_address, update)| {
+                (
+                    *contract_address,
+                    &update.storage,
+                    update.nonce,
+                    update.class.as_ref().map(|x| x.class_hash()),
+                )
+            });
+    let system_contract_updates = state_update
+        .system_contract_updates
+        .par_iter()
+        .map(|(contract_address, update)| (*contract_address, &update.storage, None, None));
+
+    let (uncommitted_updates, committed_updates): (Vec<_>, Vec<_>) = contract_updates
+        .chain(system_contract_updates)
+        .partition(|(contract_address, ..)| uncommitted.contains(contract_address));
+
     let (send, recv) = std::sync::mpsc::channel();
 
     rayon::scope(|s| {
         s.spawn(|_| {
-            let result: Result<Vec<_>, _> = state_update
-                .contract_updates
-                .par_iter()
+            let result: Result<Vec<_>, _> = committed_updates
+                .into_par_iter()
                 .map_init(
                     || storage.clone().connection(),
-                    |connection, (contract_address, update)| {
+                    |connection, (contract_address, storage_updates, nonce, class_hash)| {
                         let connection = match connection {
                             Ok(connection) => connection,
                             Err(e) => anyhow::bail!(
@@ -970,14 +1338,15 @@ fn update_starknet_state(
                         };
                         let transaction = connection.transaction()?;
                         update_contract_state(
-                            *contract_address,
-                            &update.storage,
-                            update.nonce,
-                            update.class.as_ref().map(|x| x.class_hash()),
+                            contract_address,
+                            storage_updates,
+                            nonce,
+                            class_hash,
                             &transaction,
                             verify_hashes,
                             block,
                         )
+                        .with_context(|| format!("Updating state of contract {contract_address}"))
                     },
                 )
                 .collect();
@@ -985,8 +1354,25 @@ fn update_starknet_state(
         })
     });
 
-    let contract_update_results = recv.recv().context("Panic on rayon thread")??;
+    let mut contract_update_results = recv.recv().context("Panic on rayon thread")??;
+
+    // These depend on earlier, uncommitted blocks so they have to be computed using `transaction`.
+    for (contract_address, storage_updates, nonce, class_hash) in uncommitted_updates {
+        let contract_update_result = update_contract_state(
+            contract_address,
+            storage_updates,
+            nonce,
+            class_hash,
+            transaction,
+            verify_hashes,
+            block,
+        )
+        .with_context(|| format!("Updating state of contract {contract_address}"))?;
+
+        contract_update_results.push(contract_update_result);
+    }
 
+    // Merge the per-contract results into the write transaction.
     for contract_update_result in contract_update_results.into_iter() {
         storage_commitment_tree
             .set(
@@ -999,27 +1385,6 @@ fn update_starknet_state(
             .context("Inserting contract update result")?;
     }
 
-    for (contract, update) in &state_update.system_contract_updates {
-        let update_result = update_contract_state(
-            *contract,
-            &update.storage,
-            None,
-            None,
-            transaction,
-            verify_hashes,
-            block,
-        )
-        .context("Update system contract state")?;
-
-        storage_commitment_tree
-            .set(*contract, update_result.state_hash)
-            .context("Updating system contract storage commitment tree")?;
-
-        update_result
-            .insert(block, transaction)
-            .context("Persisting system contract trie updates")?;
-    }
-
     // Apply storage commitment tree changes.
     let (storage_commitment, nodes) = storage_commitment_tree
         .commit()
@@ -1084,17 +1449,21 @@ fn update_starknet_state(
 #[cfg(test)]
 mod tests {
     use super::l2;
-    use crate::state::sync::{consumer, ConsumerContext, SyncEvent};
+    use crate::state::sync::{
+        consumer, BlockBatching, ConsumerContext, StateDivergence, SyncEvent,
+    };
     use pathfinder_common::{
         felt_bytes, BlockHash, BlockHeader, BlockNumber, ClassHash, EventCommitment, SierraHash,
         StateCommitment, StateUpdate, TransactionCommitment,
     };
     use pathfinder_common::{macro_prelude::*, BlockCommitmentSignature};
     use pathfinder_crypto::Felt;
+    use pathfinder_ethereum::EthereumStateUpdate;
     use pathfinder_rpc::SyncState;
     use pathfinder_storage::Storage;
     use starknet_gateway_types::reply::Block;
     use starknet_gateway_types::reply::{self, GasPrices};
+    use std::sync::atomic::AtomicBool;
     use std::sync::Arc;
 
     /// Generate some arbitrary block chain data from genesis onwards.
@@ -1200,6 +1569,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1220,6 +1592,162 @@ mod tests {
         assert!(!should_not_exist);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batched_block_updates() {
+        use pathfinder_rpc::v02::types::syncing::{NumberedBlock, Status, Syncing};
+
+        let storage = Storage::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(100);
+
+        let block_data = generate_block_data();
+        let num_blocks = block_data.len();
+        let last = block_data.last().unwrap().0 .0.clone();
+
+        for (a, b, c, d) in block_data {
+            event_tx.send(SyncEvent::Block(a, b, c, d)).await.unwrap();
+        }
+        // A reorg ends the batch and must only be applied after it.
+        event_tx
+            .send(SyncEvent::Reorg(last.block_number))
+            .await
+            .unwrap();
+        drop(event_tx);
+
+        // Pretend that we are far behind the head so that batching kicks in.
+        let far_ahead = NumberedBlock::from((BlockHash::ZERO, BlockNumber::new_or_panic(1000)));
+        let state = Arc::new(SyncState::default());
+        *state.status.write().await = Syncing::Status(Status {
+            starting: NumberedBlock::from((BlockHash::ZERO, BlockNumber::GENESIS)),
+            current: NumberedBlock::from((BlockHash::ZERO, BlockNumber::GENESIS)),
+            highest: far_ahead,
+        });
+
+        let (tx, _rx) = tokio::sync::watch::channel(Default::default());
+        let context = ConsumerContext {
+            storage,
+            state: state.clone(),
+            pending_data: tx,
+            verify_tree_hashes: false,
+            websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching {
+                max_blocks: std::num::NonZeroUsize::new(num_blocks).unwrap(),
+                max_duration: std::time::Duration::from_secs(10),
+            },
+        };
+
+        consumer(event_rx, context).await.unwrap();
+
+        match &*state.status.read().await {
+            Syncing::Status(status) => assert_eq!(status.current.number, last.block_number),
+            Syncing::False(_) => panic!("Sync status should be set"),
+        }
+
+        let tx = connection.transaction().unwrap();
+        for i in 0..num_blocks - 1 {
+            let should_exist = tx
+                .block_exists(BlockNumber::new_or_panic(i as u64).into())
+                .unwrap();
+            assert!(should_exist, "Block {i} should exist");
+        }
+        let reorged = tx.block_exists(last.block_number.into()).unwrap();
+        assert!(!reorged);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn l1_state_root_divergence() {
+        let storage = Storage::in_memory().unwrap();
+
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(100);
+
+        let block_data = generate_block_data();
+        let block1 = block_data[1].0 .0.clone();
+        for (a, b, c, d) in block_data {
+            event_tx.send(SyncEvent::Block(a, b, c, d)).await.unwrap();
+        }
+        let l1_update = EthereumStateUpdate {
+            state_root: state_commitment_bytes!(b"diverging state root"),
+            block_number: block1.block_number,
+            block_hash: block1.block_hash,
+        };
+        event_tx
+            .send(SyncEvent::L1Update(l1_update.clone()))
+            .await
+            .unwrap();
+        // Close the event channel which allows the consumer task to exit.
+        drop(event_tx);
+
+        let readiness = Arc::new(AtomicBool::new(true));
+        let (divergence_tx, divergence_rx) = tokio::sync::watch::channel(None);
+        let (tx, _rx) = tokio::sync::watch::channel(Default::default());
+        let context = ConsumerContext {
+            storage,
+            state: Arc::new(SyncState::default()),
+            pending_data: tx,
+            verify_tree_hashes: false,
+            websocket_txs: None,
+            readiness: readiness.clone(),
+            l1_divergence: divergence_tx,
+            block_batching: BlockBatching::DISABLED,
+        };
+
+        consumer(event_rx, context).await.unwrap();
+
+        assert!(!readiness.load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(
+            *divergence_rx.borrow(),
+            Some(StateDivergence {
+                block_number: block1.block_number,
+                l1_state_root: l1_update.state_root,
+                local_state_commitment: block1.state_commitment,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn l1_state_root_of_a_forked_block_is_not_a_divergence() {
+        let storage = Storage::in_memory().unwrap();
+
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(100);
+
+        let block_data = generate_block_data();
+        let block1 = block_data[1].0 .0.clone();
+        for (a, b, c, d) in block_data {
+            event_tx.send(SyncEvent::Block(a, b, c, d)).await.unwrap();
+        }
+        // L1 committed to a different block at the same height, which the reorg logic handles.
+        let l1_update = EthereumStateUpdate {
+            state_root: state_commitment_bytes!(b"diverging state root"),
+            block_number: block1.block_number,
+            block_hash: block_hash_bytes!(b"forked block hash"),
+        };
+        event_tx.send(SyncEvent::L1Update(l1_update)).await.unwrap();
+        // Close the event channel which allows the consumer task to exit.
+        drop(event_tx);
+
+        let readiness = Arc::new(AtomicBool::new(true));
+        let (divergence_tx, divergence_rx) = tokio::sync::watch::channel(None);
+        let (tx, _rx) = tokio::sync::watch::channel(Default::default());
+        let context = ConsumerContext {
+            storage,
+            state: Arc::new(SyncState::default()),
+            pending_data: tx,
+            verify_tree_hashes: false,
+            websocket_txs: None,
+            readiness: readiness.clone(),
+            l1_divergence: divergence_tx,
+            block_batching: BlockBatching::DISABLED,
+        };
+
+        consumer(event_rx, context).await.unwrap();
+
+        assert!(readiness.load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(*divergence_rx.borrow(), None);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn reorg() {
         let storage = Storage::in_memory().unwrap();
@@ -1245,6 +1773,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1302,6 +1833,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1346,6 +1880,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1382,6 +1919,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1421,6 +1961,9 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
@@ -1457,8 +2000,133 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
+            readiness: Arc::new(AtomicBool::new(true)),
+            l1_divergence: tokio::sync::watch::channel(None).0,
+            block_batching: BlockBatching::DISABLED,
         };
 
         consumer(event_rx, context).await.unwrap();
     }
+
+    mod update_starknet_state {
+        use super::super::update_starknet_state;
+        use pathfinder_common::macro_prelude::*;
+        use pathfinder_common::{BlockNumber, ContractAddress, StateUpdate, StorageCommitment};
+        use pathfinder_merkle_tree::contract_state::update_contract_state;
+        use pathfinder_merkle_tree::StorageCommitmentTree;
+        use pathfinder_storage::{Storage, Transaction};
+        use std::collections::HashSet;
+
+        /// Updates the contract tries one by one in `transaction`, the way system contracts used
+        /// to be handled, as a reference for the parallel pass.
+        fn sequential(
+            transaction: &Transaction<'_>,
+            state_update: &StateUpdate,
+            block: BlockNumber,
+        ) -> StorageCommitment {
+            let mut tree = match block.parent() {
+                Some(parent) => StorageCommitmentTree::load(transaction, parent).unwrap(),
+                None => StorageCommitmentTree::empty(transaction),
+            };
+
+            let contract_updates = state_update
+                .contract_updates
+                .iter()
+                .map(|(address, update)| {
+                    (
+                        *address,
+                        &update.storage,
+                        update.nonce,
+                        update.class.as_ref().map(|x| x.class_hash()),
+                    )
+                });
+            let system_contract_updates = state_update
+                .system_contract_updates
+                .iter()
+                .map(|(address, update)| (*address, &update.storage, None, None));
+
+            for (address, storage, nonce, class_hash) in
+                contract_updates.chain(system_contract_updates)
+            {
+                let result = update_contract_state(
+                    address,
+                    storage,
+                    nonce,
+                    class_hash,
+                    transaction,
+                    true,
+                    block,
+                )
+                .unwrap();
+                tree.set(address, result.state_hash).unwrap();
+                result.insert(block, transaction).unwrap();
+            }
+
+            let (commitment, nodes) = tree.commit().unwrap();
+            let root_idx = transaction.insert_storage_trie(commitment, &nodes).unwrap();
+            transaction
+                .insert_storage_root(block, Some(root_idx))
+                .unwrap();
+            commitment
+        }
+
+        #[test]
+        fn system_contracts_match_sequential_update() {
+            let blocks = [
+                StateUpdate::default()
+                    .with_deployed_contract(contract_address!("0x100"), class_hash!("0x10"))
+                    .with_storage_update(
+                        contract_address!("0x100"),
+                        storage_address!("0x1"),
+                        storage_value!("0x2"),
+                    )
+                    .with_system_storage_update(
+                        ContractAddress::ONE,
+                        storage_address!("0x0"),
+                        storage_value!("0x3"),
+                    ),
+                // Both kinds of contracts now have tries to load from the parent block.
+                StateUpdate::default()
+                    .with_contract_nonce(contract_address!("0x100"), contract_nonce!("0x1"))
+                    .with_storage_update(
+                        contract_address!("0x100"),
+                        storage_address!("0x1"),
+                        storage_value!("0x4"),
+                    )
+                    .with_system_storage_update(
+                        ContractAddress::ONE,
+                        storage_address!("0x1"),
+                        storage_value!("0x5"),
+                    ),
+            ];
+
+            let parallel = Storage::in_memory().unwrap();
+            let reference = Storage::in_memory().unwrap();
+
+            for (number, state_update) in blocks.iter().enumerate() {
+                let block = BlockNumber::new_or_panic(number as u64);
+
+                let mut connection = parallel.connection().unwrap();
+                let transaction = connection.transaction().unwrap();
+                let (actual, _) = update_starknet_state(
+                    &transaction,
+                    state_update,
+                    true,
+                    block,
+                    parallel.clone(),
+                    &HashSet::new(),
+                )
+                .unwrap();
+                transaction.commit().unwrap();
+
+                let mut connection = reference.connection().unwrap();
+                let transaction = connection.transaction().unwrap();
+                let expected = sequential(&transaction, state_update, block);
+                transaction.commit().unwrap();
+
+                assert_eq!(actual, expected, "block {number}");
+                assert_ne!(actual, StorageCommitment::ZERO);
+            }
+        }
+    }
 }
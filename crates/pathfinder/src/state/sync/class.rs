@@ -1,6 +1,23 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
 use anyhow::Context;
-use pathfinder_common::{ClassHash, SierraHash, StarknetVersion};
+use futures::stream::{FuturesUnordered, StreamExt};
+use pathfinder_common::{CasmHash, ClassHash, SierraHash, StarknetVersion};
+use pathfinder_compiler::Compiler;
+use pathfinder_storage::{QueuedClass, Storage};
 use starknet_gateway_client::GatewayApi;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::state::sync::l2::BlockValidationMode;
+use crate::state::sync::SyncEvent;
+
+/// The number of classes which are downloaded and compiled concurrently.
+const WORKERS: usize = 8;
+/// The longest delay before retrying a class which keeps failing.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// The number of failed downloads after which the queue gives up on a class, and fails.
+const MAX_ATTEMPTS: u32 = 10;
 
 pub enum DownloadedClass {
     Cairo {
@@ -14,13 +31,25 @@ pub enum DownloadedClass {
     },
 }
 
+/// Downloads a class and compiles it if it is a Sierra class.
+///
+/// The class hash is recomputed from the downloaded definition, and for Sierra classes the
+/// hash of the CASM is compared to the compiled class hash declared for it. The CASM is
+/// fetched from the gateway instead if our compiler fails or produces a different CASM.
+///
+/// The class is always returned under the queued hash, since that is the hash the state
+/// refers to, even if a mismatch is tolerated by `mode`.
 pub async fn download_class<SequencerClient: GatewayApi>(
     sequencer: &SequencerClient,
-    class_hash: ClassHash,
-    version: StarknetVersion,
+    class: &QueuedClass,
+    compiler: Compiler,
+    mode: BlockValidationMode,
 ) -> Result<DownloadedClass, anyhow::Error> {
     use starknet_gateway_types::class_hash::compute_class_hash;
 
+    let class_hash = class.hash;
+    let version = class.starknet_version.clone();
+
     let definition = sequencer
         .pending_class_by_hash(class_hash)
         .await
@@ -39,30 +68,36 @@ pub async fn download_class<SequencerClient: GatewayApi>(
     use starknet_gateway_types::class_hash::ComputedClassHash;
     match hash {
         ComputedClassHash::Cairo(hash) => {
+            // Our Cairo 0 class hash computation is known to disagree for some historical
+            // classes, so a mismatch is never fatal.
             if class_hash != hash {
                 tracing::warn!(expected=%class_hash, computed=%hash, "Cairo 0 class hash mismatch");
+                metrics::increment_counter!("class_hash_mismatch_total", "kind" => "cairo");
             }
 
-            Ok(DownloadedClass::Cairo { definition, hash })
+            Ok(DownloadedClass::Cairo {
+                definition,
+                hash: class_hash,
+            })
         }
         starknet_gateway_types::class_hash::ComputedClassHash::Sierra(hash) => {
-            anyhow::ensure!(
-                class_hash == hash,
-                "Class hash mismatch, {} instead of {}",
-                hash,
-                class_hash.0
-            );
+            if class_hash != hash {
+                match mode {
+                    BlockValidationMode::Strict => {
+                        anyhow::bail!("Class hash mismatch, {} instead of {}", hash, class_hash.0)
+                    }
+                    BlockValidationMode::AllowMismatch => {
+                        tracing::warn!(expected=%class_hash, computed=%hash, "Sierra class hash mismatch");
+                        metrics::increment_counter!("class_hash_mismatch_total", "kind" => "sierra");
+                    }
+                }
+            }
 
-            // FIXME(integration reset): work-around for integration containing Sierra classes
-            // that are incompatible with production compiler. This will get "fixed" in the future
-            // by resetting integration to remove these classes at which point we can revert this.
-            //
-            // The work-around ignores compilation errors on integration, and instead replaces the
-            // casm definition with empty bytes.
             let (casm_definition, sierra_definition) =
                 tokio::task::spawn_blocking(move || -> (anyhow::Result<_>, _) {
                     (
-                        pathfinder_compiler::compile_to_casm(&definition, &version)
+                        compiler
+                            .compile_to_casm(&definition, &version)
                             .context("Compiling Sierra class"),
                         definition,
                     )
@@ -70,22 +105,656 @@ pub async fn download_class<SequencerClient: GatewayApi>(
                 .await?;
 
             let casm_definition = match casm_definition {
-                Ok(casm_definition) => casm_definition,
+                Ok(casm_definition) => Some(casm_definition),
                 Err(error) => {
                     tracing::info!(class_hash=%hash, ?error, "CASM compilation failed, falling back to fetching from gateway");
-                    sequencer
+                    None
+                }
+            };
+
+            let casm_definition = match (casm_definition, class.casm_hash) {
+                (Some(casm_definition), Some(declared)) => {
+                    let (computed, casm_definition) = compute_casm_hash(casm_definition).await?;
+                    match computed {
+                        Ok(computed) if computed == declared => Some(casm_definition),
+                        Ok(computed) => {
+                            tracing::warn!(class_hash=%hash, %declared, %computed, "CASM hash mismatch, falling back to fetching from gateway");
+                            None
+                        }
+                        Err(error) => {
+                            tracing::warn!(class_hash=%hash, ?error, "Computing CASM hash failed, falling back to fetching from gateway");
+                            None
+                        }
+                    }
+                }
+                (casm_definition, _) => casm_definition,
+            };
+
+            let casm_definition = match casm_definition {
+                Some(casm_definition) => casm_definition,
+                None => {
+                    let casm_definition = sequencer
                         .pending_casm_by_hash(class_hash)
                         .await
                         .with_context(|| format!("Downloading CASM {}", class_hash.0))?
-                        .to_vec()
+                        .to_vec();
+
+                    match class.casm_hash {
+                        Some(declared) => {
+                            let (computed, casm_definition) =
+                                compute_casm_hash(casm_definition).await?;
+                            let computed = computed.context("Computing CASM hash")?;
+                            if computed != declared {
+                                match mode {
+                                    BlockValidationMode::Strict => anyhow::bail!(
+                                        "CASM hash mismatch for class {}, {} instead of {}",
+                                        class_hash.0,
+                                        computed,
+                                        declared
+                                    ),
+                                    BlockValidationMode::AllowMismatch => {
+                                        tracing::warn!(class_hash=%hash, %declared, %computed, "CASM hash mismatch");
+                                        metrics::increment_counter!("class_hash_mismatch_total", "kind" => "casm");
+                                    }
+                                }
+                            }
+                            casm_definition
+                        }
+                        None => casm_definition,
+                    }
                 }
             };
 
             Ok(DownloadedClass::Sierra {
                 sierra_definition,
-                sierra_hash: SierraHash(hash.0),
+                sierra_hash: SierraHash(class_hash.0),
                 casm_definition,
             })
         }
     }
 }
+
+async fn compute_casm_hash(
+    casm_definition: Vec<u8>,
+) -> anyhow::Result<(anyhow::Result<CasmHash>, Vec<u8>)> {
+    tokio::task::spawn_blocking(move || {
+        (
+            pathfinder_compiler::casm_class_hash(&casm_definition),
+            casm_definition,
+        )
+    })
+    .await
+    .context("Joining CASM hash task")
+}
+
+/// Emits a downloaded class as a [SyncEvent].
+///
+/// A Sierra class is only emitted along with the compiled class hash declared for it,
+/// since that is what has been added to the class commitment tree. Returns `false` if
+/// the class was not emitted because of that.
+pub async fn emit_class(
+    class: DownloadedClass,
+    casm_hash: Option<CasmHash>,
+    tx_event: &mpsc::Sender<SyncEvent>,
+) -> anyhow::Result<bool> {
+    match class {
+        DownloadedClass::Cairo { definition, hash } => tx_event
+            .send(SyncEvent::CairoClass { definition, hash })
+            .await
+            .with_context(|| {
+                format!(
+                    "Sending Event::NewCairoContract for declared class {}",
+                    hash.0
+                )
+            })?,
+        DownloadedClass::Sierra {
+            sierra_definition,
+            sierra_hash,
+            casm_definition,
+        } => {
+            // This can occur if the Sierra class was only deployed, and was declared in a
+            // previous block which has not been persisted by the database yet.
+            let Some(casm_hash) = casm_hash else {
+                return Ok(false);
+            };
+
+            tx_event
+                .send(SyncEvent::SierraClass {
+                    sierra_definition,
+                    sierra_hash,
+                    casm_definition,
+                    casm_hash,
+                })
+                .await
+                .with_context(|| {
+                    format!(
+                        "Sending Event::NewSierraContract for declared class {}",
+                        sierra_hash.0
+                    )
+                })?
+        }
+    }
+
+    Ok(true)
+}
+
+/// Handle to the workers which download and compile the classes queued in the database.
+///
+/// Downloaded classes are emitted as [SyncEvent]s and only removed from the database
+/// queue once the consumer has stored them, so that outstanding work is resumed after
+/// a restart. Failed downloads are retried with an increasing delay, up to
+/// [MAX_ATTEMPTS] times.
+#[derive(Clone)]
+pub struct ClassQueue {
+    requests: mpsc::UnboundedSender<Request>,
+    storage: Storage,
+}
+
+/// Owns the workers of a [ClassQueue], which are aborted when this is dropped.
+///
+/// Handles to the queue may outlive the sync which spawned it, so this ensures that a
+/// restarted sync never runs alongside the workers of its predecessor.
+pub struct ClassQueueWorkers(tokio::task::JoinHandle<()>);
+
+impl Drop for ClassQueueWorkers {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+enum Request {
+    Enqueue(Vec<QueuedClass>),
+    Wait(Vec<ClassHash>, oneshot::Sender<anyhow::Result<()>>),
+}
+
+impl ClassQueue {
+    /// Spawns the workers, which first resume the classes already queued in the database.
+    ///
+    /// The workers stop once all handles have been dropped, or the returned
+    /// [ClassQueueWorkers] is dropped.
+    pub fn spawn<SequencerClient>(
+        sequencer: SequencerClient,
+        tx_event: mpsc::Sender<SyncEvent>,
+        storage: Storage,
+        compiler: Compiler,
+        mode: BlockValidationMode,
+    ) -> (Self, ClassQueueWorkers)
+    where
+        SequencerClient: GatewayApi + Clone + Send + 'static,
+    {
+        let (requests, rx) = mpsc::unbounded_channel();
+
+        let handle = tokio::spawn({
+            let storage = storage.clone();
+            async move {
+                if let Err(error) = work(sequencer, tx_event, storage, compiler, mode, rx).await {
+                    tracing::error!(?error, "Class download queue failed");
+                }
+            }
+        });
+
+        (Self { requests, storage }, ClassQueueWorkers(handle))
+    }
+
+    /// Persists the classes in the database queue and schedules their download.
+    pub async fn enqueue(&self, classes: Vec<QueuedClass>) -> anyhow::Result<()> {
+        if classes.is_empty() {
+            return Ok(());
+        }
+
+        let classes = database(&self.storage, move |tx| {
+            for class in &classes {
+                tx.enqueue_class(class.hash, class.casm_hash, &class.starknet_version)
+                    .context("Enqueuing class")?;
+            }
+            Ok(classes)
+        })
+        .await?;
+
+        self.requests
+            .send(Request::Enqueue(classes))
+            .map_err(|_| anyhow::anyhow!("Class download queue closed"))
+    }
+
+    /// Resolves once all of the given classes have been emitted. Classes which are not
+    /// queued are considered emitted.
+    ///
+    /// Fails if one of the classes could not be downloaded within [MAX_ATTEMPTS].
+    pub async fn wait(&self, classes: Vec<ClassHash>) -> anyhow::Result<()> {
+        if classes.is_empty() {
+            return Ok(());
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.requests
+            .send(Request::Wait(classes, tx))
+            .map_err(|_| anyhow::anyhow!("Class download queue closed"))?;
+
+        rx.await.context("Class download queue closed")?
+    }
+}
+
+async fn work<SequencerClient>(
+    sequencer: SequencerClient,
+    tx_event: mpsc::Sender<SyncEvent>,
+    storage: Storage,
+    compiler: Compiler,
+    mode: BlockValidationMode,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+) -> anyhow::Result<()>
+where
+    SequencerClient: GatewayApi + Clone + Send + 'static,
+{
+    let outstanding = database(&storage, |tx| tx.queued_classes())
+        .await
+        .context("Loading queued classes")?;
+    if !outstanding.is_empty() {
+        tracing::info!(count=%outstanding.len(), "Resuming queued class downloads");
+    }
+
+    let mut queued = outstanding
+        .iter()
+        .map(|class| class.hash)
+        .collect::<HashSet<_>>();
+    let mut ready = VecDeque::from(outstanding);
+    let mut in_flight = FuturesUnordered::new();
+    let mut retries = FuturesUnordered::new();
+    let mut waiters: Vec<(HashSet<ClassHash>, oneshot::Sender<anyhow::Result<()>>)> = Vec::new();
+
+    loop {
+        while in_flight.len() < WORKERS {
+            let Some(class) = ready.pop_front() else {
+                break;
+            };
+            in_flight.push(download(sequencer.clone(), class, compiler, mode));
+        }
+
+        tokio::select! {
+            request = requests.recv() => match request {
+                None => return Ok(()),
+                Some(Request::Enqueue(classes)) => {
+                    for class in classes {
+                        if queued.insert(class.hash) {
+                            ready.push_back(class);
+                        }
+                    }
+                }
+                Some(Request::Wait(classes, tx)) => {
+                    let pending = classes
+                        .into_iter()
+                        .filter(|hash| queued.contains(hash))
+                        .collect::<HashSet<_>>();
+
+                    if pending.is_empty() {
+                        let _ = tx.send(Ok(()));
+                    } else {
+                        waiters.push((pending, tx));
+                    }
+                }
+            },
+            Some((class, result)) = in_flight.next() => match result {
+                Ok(downloaded) => {
+                    let emitted = emit_class(downloaded, class.casm_hash, &tx_event).await?;
+                    if !emitted {
+                        // Nothing will be stored, so nothing else would remove it.
+                        database(&storage, move |tx| tx.dequeue_class(class.hash))
+                            .await
+                            .context("Dequeuing class")?;
+                    }
+
+                    queued.remove(&class.hash);
+
+                    for (pending, _) in &mut waiters {
+                        pending.remove(&class.hash);
+                    }
+                    let (done, waiting) = std::mem::take(&mut waiters)
+                        .into_iter()
+                        .partition::<Vec<_>, _>(|(pending, _)| pending.is_empty());
+                    waiters = waiting;
+                    for (_, tx) in done {
+                        let _ = tx.send(Ok(()));
+                    }
+                }
+                Err(error) => {
+                    let hash = class.hash;
+                    database(&storage, move |tx| tx.record_class_download_failure(hash))
+                        .await
+                        .context("Recording class download failure")?;
+
+                    let mut class = class;
+                    class.attempts += 1;
+
+                    if class.attempts >= MAX_ATTEMPTS {
+                        let error = error.context(format!(
+                            "Downloading class {} failed {} times",
+                            class.hash, class.attempts
+                        ));
+                        for (pending, tx) in waiters {
+                            if pending.contains(&class.hash) {
+                                let _ = tx.send(Err(anyhow::anyhow!("{error:#}")));
+                            }
+                        }
+                        return Err(error);
+                    }
+
+                    let delay = Duration::from_secs(1 << class.attempts.min(6)).min(MAX_RETRY_DELAY);
+                    tracing::warn!(class_hash=%class.hash, attempts=%class.attempts, ?delay, ?error, "Class download failed, retrying");
+
+                    retries.push(async move {
+                        tokio::time::sleep(delay).await;
+                        class
+                    });
+                }
+            },
+            Some(class) = retries.next() => ready.push_back(class),
+        }
+    }
+}
+
+async fn download<SequencerClient: GatewayApi>(
+    sequencer: SequencerClient,
+    class: QueuedClass,
+    compiler: Compiler,
+    mode: BlockValidationMode,
+) -> (QueuedClass, anyhow::Result<DownloadedClass>) {
+    let result = download_class(&sequencer, &class, compiler, mode).await;
+    (class, result)
+}
+
+/// Runs a single database transaction on a blocking thread.
+async fn database<T: Send + 'static>(
+    storage: &Storage,
+    f: impl FnOnce(&pathfinder_storage::Transaction<'_>) -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    let storage = storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db.transaction().context("Creating database transaction")?;
+        let result = f(&tx)?;
+        tx.commit().context("Committing database transaction")?;
+        Ok(result)
+    })
+    .await
+    .context("Joining database task")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_common::macro_prelude::*;
+    use starknet_gateway_client::MockGatewayApi;
+    use starknet_gateway_test_fixtures::class_definitions::{
+        CAIRO_1_1_0_RC0_SIERRA,
+        CAIRO_TESTNET_0331118F4E4EB8A8DDB0F4493E09612E380EF527991C49A15C42574AB48DD747 as CAIRO,
+        CAIRO_TESTNET_0331118F4E4EB8A8DDB0F4493E09612E380EF527991C49A15C42574AB48DD747_CLASS_HASH as CAIRO_HASH,
+    };
+    use starknet_gateway_types::error::SequencerError;
+
+    fn queued(hash: ClassHash) -> QueuedClass {
+        QueuedClass {
+            hash,
+            casm_hash: None,
+            starknet_version: StarknetVersion::default(),
+            attempts: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_queued_classes() {
+        let storage = Storage::in_memory().unwrap();
+        {
+            let mut db = storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+            tx.enqueue_class(CAIRO_HASH, None, &StarknetVersion::default())
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let mut mock = MockGatewayApi::new();
+        mock.expect_pending_class_by_hash()
+            .withf(move |x| x == &CAIRO_HASH)
+            .times(1)
+            .return_once(|_| Ok(bytes::Bytes::from_static(CAIRO)));
+
+        let (tx_event, mut rx_event) = mpsc::channel(1);
+        let (queue, _workers) = ClassQueue::spawn(
+            std::sync::Arc::new(mock),
+            tx_event,
+            storage,
+            Compiler::InProcess,
+            BlockValidationMode::Strict,
+        );
+
+        let event = rx_event.recv().await.unwrap();
+        assert!(matches!(event, SyncEvent::CairoClass { hash, .. } if hash == CAIRO_HASH));
+
+        queue.wait(vec![CAIRO_HASH]).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_failed_downloads() {
+        let storage = Storage::in_memory().unwrap();
+
+        let mut mock = MockGatewayApi::new();
+        let mut seq = mockall::Sequence::new();
+        mock.expect_pending_class_by_hash()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Err(SequencerError::InvalidStarknetErrorVariant));
+        mock.expect_pending_class_by_hash()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Ok(bytes::Bytes::from_static(CAIRO)));
+
+        let (tx_event, mut rx_event) = mpsc::channel(1);
+        let (queue, _workers) = ClassQueue::spawn(
+            std::sync::Arc::new(mock),
+            tx_event,
+            storage.clone(),
+            Compiler::InProcess,
+            BlockValidationMode::Strict,
+        );
+        queue.enqueue(vec![queued(CAIRO_HASH)]).await.unwrap();
+
+        let wait = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.wait(vec![CAIRO_HASH]).await }
+        });
+
+        let event = rx_event.recv().await.unwrap();
+        assert!(matches!(event, SyncEvent::CairoClass { hash, .. } if hash == CAIRO_HASH));
+        wait.await.unwrap().unwrap();
+
+        // The failure is persisted, and the class stays queued until it has been stored.
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        let queued = tx.queued_classes().unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].attempts, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let storage = Storage::in_memory().unwrap();
+
+        let mut mock = MockGatewayApi::new();
+        mock.expect_pending_class_by_hash()
+            .times(MAX_ATTEMPTS as usize)
+            .returning(|_| Err(SequencerError::InvalidStarknetErrorVariant));
+
+        let (tx_event, _rx_event) = mpsc::channel(1);
+        let (queue, _workers) = ClassQueue::spawn(
+            std::sync::Arc::new(mock),
+            tx_event,
+            storage,
+            Compiler::InProcess,
+            BlockValidationMode::Strict,
+        );
+        queue.enqueue(vec![queued(CAIRO_HASH)]).await.unwrap();
+
+        let error = queue.wait(vec![CAIRO_HASH]).await.unwrap_err();
+        assert!(error.to_string().contains("failed 10 times"), "{error:#}");
+
+        // The queue stops, so that sync fails instead of waiting forever.
+        let error = queue.enqueue(vec![queued(CAIRO_HASH)]).await;
+        assert!(error.is_err());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_workers_stops_the_queue() {
+        let storage = Storage::in_memory().unwrap();
+
+        let (tx_event, _rx_event) = mpsc::channel(1);
+        let (queue, workers) = ClassQueue::spawn(
+            std::sync::Arc::new(MockGatewayApi::new()),
+            tx_event,
+            storage,
+            Compiler::InProcess,
+            BlockValidationMode::Strict,
+        );
+        drop(workers);
+        tokio::task::yield_now().await;
+
+        // Handles which outlive the workers, e.g. of a restarted sync, can no longer
+        // schedule work.
+        assert!(queue.wait(vec![CAIRO_HASH]).await.is_err());
+    }
+
+    mod verification {
+        use super::*;
+
+        #[tokio::test]
+        async fn cairo_class_hash_mismatch() {
+            let class = queued(class_hash_bytes!(b"not the hash"));
+
+            let mut mock = MockGatewayApi::new();
+            mock.expect_pending_class_by_hash()
+                .returning(|_| Ok(bytes::Bytes::from_static(CAIRO)));
+
+            for mode in [
+                BlockValidationMode::Strict,
+                BlockValidationMode::AllowMismatch,
+            ] {
+                let downloaded = download_class(&mock, &class, Compiler::InProcess, mode)
+                    .await
+                    .unwrap();
+                // Emitted under the queued hash, which is what the state refers to.
+                assert!(
+                    matches!(downloaded, DownloadedClass::Cairo { hash, .. } if hash == class.hash)
+                );
+            }
+        }
+
+        /// Returns the Sierra fixture's class hash and the hash of our compilation of it.
+        fn sierra_hashes() -> (ClassHash, CasmHash) {
+            use starknet_gateway_types::class_hash::{compute_class_hash, ComputedClassHash};
+
+            let ComputedClassHash::Sierra(class_hash) =
+                compute_class_hash(CAIRO_1_1_0_RC0_SIERRA).unwrap()
+            else {
+                panic!("Fixture is a Sierra class");
+            };
+            let casm =
+                pathfinder_compiler::compile_to_casm(CAIRO_1_1_0_RC0_SIERRA, &version()).unwrap();
+            let casm_hash = pathfinder_compiler::casm_class_hash(&casm).unwrap();
+
+            (class_hash, casm_hash)
+        }
+
+        fn version() -> StarknetVersion {
+            StarknetVersion::new(0, 11, 2)
+        }
+
+        #[tokio::test]
+        async fn matching_casm_hash() {
+            let (class_hash, casm_hash) = sierra_hashes();
+            let class = QueuedClass {
+                casm_hash: Some(casm_hash),
+                starknet_version: version(),
+                ..queued(class_hash)
+            };
+
+            // The CASM is not fetched from the gateway.
+            let mut mock = MockGatewayApi::new();
+            mock.expect_pending_class_by_hash()
+                .returning(|_| Ok(bytes::Bytes::from_static(CAIRO_1_1_0_RC0_SIERRA)));
+
+            let downloaded = download_class(
+                &mock,
+                &class,
+                Compiler::InProcess,
+                BlockValidationMode::Strict,
+            )
+            .await
+            .unwrap();
+            assert!(matches!(downloaded, DownloadedClass::Sierra { .. }));
+        }
+
+        #[tokio::test]
+        async fn sierra_class_hash_mismatch() {
+            let (class_hash, _) = sierra_hashes();
+            let class = QueuedClass {
+                starknet_version: version(),
+                ..queued(class_hash_bytes!(b"not the hash"))
+            };
+
+            let mut mock = MockGatewayApi::new();
+            mock.expect_pending_class_by_hash()
+                .returning(|_| Ok(bytes::Bytes::from_static(CAIRO_1_1_0_RC0_SIERRA)));
+
+            download_class(
+                &mock,
+                &class,
+                Compiler::InProcess,
+                BlockValidationMode::Strict,
+            )
+            .await
+            .unwrap_err();
+
+            let downloaded = download_class(
+                &mock,
+                &class,
+                Compiler::InProcess,
+                BlockValidationMode::AllowMismatch,
+            )
+            .await
+            .unwrap();
+            assert!(
+                matches!(downloaded, DownloadedClass::Sierra { sierra_hash, .. } if sierra_hash.0 == class.hash.0)
+            );
+        }
+
+        #[tokio::test]
+        async fn casm_hash_mismatch() {
+            let (class_hash, _) = sierra_hashes();
+            let class = QueuedClass {
+                casm_hash: Some(casm_hash!("0x123")),
+                starknet_version: version(),
+                ..queued(class_hash)
+            };
+
+            // Falls back to the gateway's CASM, which is rejected as well if it does
+            // not match the declared hash.
+            let casm =
+                pathfinder_compiler::compile_to_casm(CAIRO_1_1_0_RC0_SIERRA, &version()).unwrap();
+            let mut mock = MockGatewayApi::new();
+            mock.expect_pending_class_by_hash()
+                .returning(|_| Ok(bytes::Bytes::from_static(CAIRO_1_1_0_RC0_SIERRA)));
+            mock.expect_pending_casm_by_hash()
+                .times(1)
+                .return_once(move |_| Ok(bytes::Bytes::from(casm)));
+
+            let error = download_class(
+                &mock,
+                &class,
+                Compiler::InProcess,
+                BlockValidationMode::Strict,
+            )
+            .await
+            .unwrap_err();
+            assert!(error.to_string().contains("CASM hash mismatch"));
+        }
+    }
+}
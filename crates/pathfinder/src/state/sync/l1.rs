@@ -1,6 +1,6 @@
 use std::{num::NonZeroU64, time::Duration};
 
-use pathfinder_common::Chain;
+use pathfinder_common::{BlockNumber, Chain};
 use pathfinder_ethereum::{EthereumApi, EthereumStateUpdate};
 use pathfinder_retry::Retry;
 use primitive_types::H160;
@@ -10,11 +10,14 @@ use crate::state::sync::SyncEvent;
 
 #[derive(Clone)]
 pub struct L1SyncContext<EthereumClient> {
-    pub ethereum: EthereumClient,
+    /// The settlement layer, or [None] if L1 sync is disabled.
+    pub ethereum: Option<EthereumClient>,
     pub chain: Chain,
     /// The Starknet core contract address on Ethereum
     pub core_address: H160,
     pub poll_interval: Duration,
+    /// L1 state updates past this block are ignored.
+    pub target_block: Option<BlockNumber>,
 }
 
 /// Syncs L1 state update logs. Emits [Ethereum state update](EthereumStateUpdate)
@@ -31,8 +34,14 @@ where
         chain: _,
         core_address,
         poll_interval,
+        target_block,
     } = context;
 
+    let Some(ethereum) = ethereum else {
+        tracing::info!("No settlement layer configured, L1 sync is disabled");
+        return std::future::pending().await;
+    };
+
     let mut previous = EthereumStateUpdate::default();
 
     loop {
@@ -45,7 +54,9 @@ where
         .when(|_| true)
         .await?;
 
-        if previous != state_update {
+        let past_target = target_block.is_some_and(|target| state_update.block_number > target);
+
+        if previous != state_update && !past_target {
             previous = state_update.clone();
             tx_event.send(SyncEvent::L1Update(state_update)).await?;
         }
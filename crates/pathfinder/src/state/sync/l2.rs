@@ -1,20 +1,22 @@
-use crate::state::block_hash::{verify_block_hash, VerifyResult};
-use crate::state::sync::class::{download_class, DownloadedClass};
+use crate::state::block_hash::{verify_block_hash, BlockHashMetaInfo, VerifyResult};
+use crate::state::sync::class::{download_class, emit_class, ClassQueue};
 use crate::state::sync::{pending, SyncEvent};
 use anyhow::{anyhow, Context};
 use pathfinder_common::state_update::ContractClassUpdate;
 use pathfinder_common::{
-    BlockHash, BlockNumber, Chain, ChainId, ClassHash, EventCommitment, StarknetVersion,
-    StateCommitment, StateUpdate, TransactionCommitment,
+    BlockCommitmentSignature, BlockHash, BlockNumber, ChainId, ClassHash, EventCommitment,
+    PublicKey, StarknetVersion, StateCommitment, StateUpdate, TransactionCommitment,
 };
-use pathfinder_storage::Storage;
+use pathfinder_compiler::Compiler;
+use pathfinder_storage::{QueuedClass, Storage};
 use starknet_gateway_client::GatewayApi;
 use starknet_gateway_types::{
     error::SequencerError,
-    reply::{Block, Status},
+    reply::{self, Block, Status},
 };
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
@@ -81,10 +83,16 @@ impl BlockChain {
 #[derive(Clone)]
 pub struct L2SyncContext<GatewayClient> {
     pub sequencer: GatewayClient,
-    pub chain: Chain,
+    pub block_hash_meta: BlockHashMetaInfo,
     pub chain_id: ChainId,
     pub block_validation_mode: BlockValidationMode,
     pub storage: Storage,
+    /// The number of blocks to download ahead of the current head.
+    pub look_ahead: usize,
+    /// Sync stops once this block has been downloaded.
+    pub target_block: Option<BlockNumber>,
+    pub compiler: Compiler,
+    pub signature_verification: SignatureVerification,
 }
 
 pub async fn sync<GatewayClient>(
@@ -98,13 +106,39 @@ where
 {
     let L2SyncContext {
         sequencer,
-        chain,
+        block_hash_meta,
         chain_id,
         block_validation_mode,
         storage,
+        look_ahead,
+        target_block,
+        compiler,
+        signature_verification,
     } = context;
 
+    // The workers are aborted once this sync exits, even if the look-ahead tasks still
+    // hold on to the queue.
+    let (class_queue, _class_queue_workers) = ClassQueue::spawn(
+        sequencer.clone(),
+        tx_event.clone(),
+        storage.clone(),
+        compiler,
+        block_validation_mode,
+    );
+
     let mut pending_handle = None;
+    let mut look_ahead = LookAhead {
+        sequencer: sequencer.clone(),
+        class_queue: class_queue.clone(),
+        block_hash_meta: block_hash_meta.clone(),
+        chain_id,
+        mode: block_validation_mode,
+        storage: storage.clone(),
+        depth: look_ahead,
+        target: target_block,
+        known_head: None,
+        queue: VecDeque::new(),
+    };
 
     'outer: loop {
         // Get the next block from L2.
@@ -113,98 +147,146 @@ where
             None => (BlockNumber::GENESIS, None),
         };
 
-        // We start downloading the signature for the block
-        let signature_handle = tokio::spawn({
-            let sequencer = sequencer.clone();
-            async move {
-                let t_signature = std::time::Instant::now();
-                let result = sequencer.signature(next.into()).await;
-                let t_signature = t_signature.elapsed();
-
-                (result, t_signature)
-            }
-        });
+        if let Some(target) = target_block.filter(|target| next > *target) {
+            tracing::info!(%target, "Reached target block, stopping L2 sync");
+            look_ahead.clear();
+            // Returning would cause the sync process to be restarted.
+            return std::future::pending().await;
+        }
 
-        let t_block = std::time::Instant::now();
+        let (block, commitments, state_update, remainder) = match look_ahead.take(next).await {
+            Some(prefetched) => {
+                let Prefetched {
+                    block,
+                    commitments,
+                    state_update,
+                    signature,
+                    classes,
+                    timings,
+                } = prefetched;
+
+                let remainder = BlockRemainder::Prefetched {
+                    signature,
+                    classes,
+                    timings,
+                };
 
-        let (block, commitments, state_update) = loop {
-            match download_block(
-                next,
-                chain,
-                chain_id,
-                head_meta.map(|h| h.1),
-                &sequencer,
-                block_validation_mode,
-            )
-            .await?
-            {
-                DownloadBlock::Block(block, commitments, state_update) => {
-                    break (block, commitments, state_update)
-                }
-                DownloadBlock::AtHead => {
-                    const PENDING_POLL_INTERVAL: std::time::Duration =
-                        std::time::Duration::from_secs(2);
-
-                    if cfg!(feature = "p2p") {
-                        // Not implemented yet for P2P
-                        tracing::info!("Skipping the pending blocks polling");
-                        tokio::time::sleep(PENDING_POLL_INTERVAL).await;
-                    } else if pending_handle.is_none() {
-                        tracing::info!("At head of chain, enabling polling of pending data");
-                        pending_handle = Some(tokio::spawn(pending::poll_pending(
-                            tx_event.clone(),
-                            sequencer.clone(),
-                            PENDING_POLL_INTERVAL,
-                            storage.clone(),
-                        )));
+                (block, commitments, state_update, remainder)
+            }
+            None => {
+                // We start downloading the signature for the block
+                let signature_handle = tokio::spawn({
+                    let sequencer = sequencer.clone();
+                    async move {
+                        let t_signature = std::time::Instant::now();
+                        let result = sequencer.signature(next.into()).await;
+                        let t_signature = t_signature.elapsed();
+
+                        (result, t_signature)
                     }
+                });
 
-                    // Poll the head until it changes. This query is very quick and cheap to perform.
-                    // Once its changed we exit the loop to try download the next block.
-                    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
-                    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
-                    loop {
-                        let (_, hash) = sequencer.head().await.context("Polling head of chain")?;
-                        if hash != head.unwrap_or_default().1 {
-                            break;
-                        }
-                        interval.tick().await;
-                    }
-                }
-                DownloadBlock::Reorg => {
-                    head = match head {
-                        Some(some_head) => reorg(
-                            &some_head,
-                            chain,
-                            chain_id,
-                            &tx_event,
-                            &sequencer,
-                            block_validation_mode,
-                            &blocks,
-                        )
-                        .await
-                        .context("L2 reorg")?,
-                        None => None,
-                    };
+                let t_block = std::time::Instant::now();
 
-                    match &head {
-                        Some((number, hash, commitment)) => {
-                            blocks.push(*number, *hash, *commitment)
+                let (block, commitments, state_update) = loop {
+                    match download_block(
+                        next,
+                        &block_hash_meta,
+                        chain_id,
+                        head_meta.map(|h| h.1),
+                        &sequencer,
+                        block_validation_mode,
+                    )
+                    .await?
+                    {
+                        DownloadBlock::Block(block, commitments, state_update) => {
+                            break (block, commitments, state_update)
+                        }
+                        DownloadBlock::AtHead => {
+                            const PENDING_POLL_INTERVAL: std::time::Duration =
+                                std::time::Duration::from_secs(2);
+
+                            if cfg!(feature = "p2p") {
+                                // Not implemented yet for P2P
+                                tracing::info!("Skipping the pending blocks polling");
+                                tokio::time::sleep(PENDING_POLL_INTERVAL).await;
+                            } else if pending_handle.is_none() && target_block.is_none() {
+                                // Pending data is not polled with a target block since it
+                                // would build on top of it.
+                                tracing::info!(
+                                    "At head of chain, enabling polling of pending data"
+                                );
+                                pending_handle = Some(tokio::spawn(pending::poll_pending(
+                                    tx_event.clone(),
+                                    sequencer.clone(),
+                                    PENDING_POLL_INTERVAL,
+                                    storage.clone(),
+                                    compiler,
+                                    block_validation_mode,
+                                )));
+                            }
+
+                            // Poll the head until it changes. This query is very quick and cheap to perform.
+                            // Once its changed we exit the loop to try download the next block.
+                            let mut interval =
+                                tokio::time::interval(tokio::time::Duration::from_secs(1));
+                            interval
+                                .set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                            loop {
+                                let (_, hash) =
+                                    sequencer.head().await.context("Polling head of chain")?;
+                                if hash != head.unwrap_or_default().1 {
+                                    break;
+                                }
+                                interval.tick().await;
+                            }
+                        }
+                        DownloadBlock::Reorg => {
+                            head = match head {
+                                Some(some_head) => reorg(
+                                    &some_head,
+                                    &block_hash_meta,
+                                    chain_id,
+                                    &tx_event,
+                                    &sequencer,
+                                    block_validation_mode,
+                                    &blocks,
+                                )
+                                .await
+                                .context("L2 reorg")?,
+                                None => None,
+                            };
+
+                            match &head {
+                                Some((number, hash, commitment)) => {
+                                    blocks.push(*number, *hash, *commitment)
+                                }
+                                None => blocks.reset_to_genesis(),
+                            }
+
+                            continue 'outer;
                         }
-                        None => blocks.reset_to_genesis(),
                     }
+                };
+                let t_block = t_block.elapsed();
 
-                    continue 'outer;
-                }
+                let remainder = BlockRemainder::Downloading {
+                    signature_handle,
+                    t_block,
+                };
+
+                (block, commitments, state_update, remainder)
             }
         };
-        let t_block = t_block.elapsed();
 
         if let Some(some_head) = &head {
             if some_head.1 != block.parent_block_hash {
+                // Anything downloaded ahead builds on the now invalid head.
+                look_ahead.clear();
+
                 head = reorg(
                     some_head,
-                    chain,
+                    &block_hash_meta,
                     chain_id,
                     &tx_event,
                     &sequencer,
@@ -223,41 +305,52 @@ where
             }
         }
 
-        // Download and emit newly declared classes.
-        let t_declare = std::time::Instant::now();
-        download_new_classes(
-            &state_update,
-            &sequencer,
-            &tx_event,
-            &block.starknet_version,
-            storage.clone(),
-        )
-        .await
-        .with_context(|| format!("Handling newly declared classes for block {next:?}"))?;
-        let t_declare = t_declare.elapsed();
-
-        // Download signature
-        let (signature_result, t_signature) =
-            signature_handle.await.context("Joining signature task")?;
-        let (signature, t_signature) = match signature_result {
-            Ok(signature) => (signature, t_signature),
-            Err(SequencerError::StarknetError(err))
-                if err.code
-                    == starknet_gateway_types::error::KnownStarknetErrorCode::BlockNotFound
-                        .into() =>
-            {
-                // There is a race condition here: if the query for the signature was made _before_ the block was
-                // published -- but by the time we actually queried for the block it was there. In this case
-                // we just retry the signature download.
-                let t_signature = std::time::Instant::now();
-                let signature = sequencer.signature(next.into()).await.with_context(|| {
-                    format!("Fetch signature for block {next:?} from sequencer")
+        let (signature, timings) = match remainder {
+            BlockRemainder::Prefetched {
+                signature,
+                classes,
+                timings,
+            } => {
+                // Usually already emitted while downloading ahead.
+                class_queue.wait(classes).await.with_context(|| {
+                    format!("Handling newly declared classes for block {next:?}")
                 })?;
-                (signature, t_signature.elapsed())
+
+                (signature, timings)
             }
-            Err(err) => {
-                return Err(err)
-                    .context(format!("Fetch signature for block {next:?} from sequencer"))
+            BlockRemainder::Downloading {
+                signature_handle,
+                t_block,
+            } => {
+                // Queue newly declared classes, and wait for them to be emitted.
+                let t_declare = std::time::Instant::now();
+                let classes =
+                    missing_classes(&state_update, &block.starknet_version, storage.clone())
+                        .await
+                        .with_context(|| {
+                            format!("Handling newly declared classes for block {next:?}")
+                        })?;
+                let hashes = classes.iter().map(|class| class.hash).collect();
+                class_queue.enqueue(classes).await?;
+                class_queue.wait(hashes).await.with_context(|| {
+                    format!("Handling newly declared classes for block {next:?}")
+                })?;
+                let t_declare = t_declare.elapsed();
+
+                // Download signature
+                let (signature_result, t_signature) =
+                    signature_handle.await.context("Joining signature task")?;
+                let (signature, t_signature) =
+                    retry_signature_if_not_found(signature_result, t_signature, next, &sequencer)
+                        .await?;
+
+                let timings = Timings {
+                    block_download: t_block,
+                    class_declaration: t_declare,
+                    signature_download: t_signature,
+                };
+
+                (signature, timings)
             }
         };
 
@@ -268,17 +361,14 @@ where
             signature.signature_input.block_hash.0,
             block.block_hash.0,
         );
+        let state_update = check_signature(signature_verification, &signature, state_update)
+            .await
+            .with_context(|| format!("Verifying signature of block {next}"))?;
         let signature = signature.into();
 
         head = Some((next, block.block_hash, state_update.state_commitment));
         blocks.push(next, block.block_hash, state_update.state_commitment);
 
-        let timings = Timings {
-            block_download: t_block,
-            class_declaration: t_declare,
-            signature_download: t_signature,
-        };
-
         tx_event
             .send(SyncEvent::Block(
                 (block, commitments),
@@ -288,11 +378,274 @@ where
             ))
             .await
             .context("Event channel closed")?;
+
+        look_ahead.fill(next + 1).await;
+    }
+}
+
+/// The parts of a block which still need handling once its parent hash has been checked.
+enum BlockRemainder {
+    /// Everything was already downloaded by the [LookAhead].
+    Prefetched {
+        signature: reply::BlockSignature,
+        /// Queued for download while prefetching.
+        classes: Vec<ClassHash>,
+        timings: Timings,
+    },
+    /// The signature is still being downloaded and the classes have not been queued yet.
+    Downloading {
+        signature_handle:
+            tokio::task::JoinHandle<(Result<reply::BlockSignature, SequencerError>, Duration)>,
+        t_block: Duration,
+    },
+}
+
+/// A block and everything required to emit it, downloaded ahead of time.
+struct Prefetched {
+    block: Box<Block>,
+    commitments: (TransactionCommitment, EventCommitment),
+    state_update: Box<StateUpdate>,
+    signature: reply::BlockSignature,
+    /// New classes of the block, queued for download.
+    classes: Vec<ClassHash>,
+    timings: Timings,
+}
+
+/// Downloads the blocks following the current head concurrently, so that catching up is
+/// not bound by the latency of the feeder gateway.
+///
+/// Blocks are handed out strictly in order and are subject to the same parent hash checks
+/// as any other block. Any gap, failure or reorg simply discards the queue and sync falls
+/// back to downloading the next block itself.
+struct LookAhead<GatewayClient> {
+    sequencer: GatewayClient,
+    class_queue: ClassQueue,
+    block_hash_meta: BlockHashMetaInfo,
+    chain_id: ChainId,
+    mode: BlockValidationMode,
+    storage: Storage,
+    /// The maximum number of blocks to download ahead.
+    depth: usize,
+    /// No blocks past this one are downloaded.
+    target: Option<BlockNumber>,
+    /// The latest block known to exist on the feeder gateway.
+    known_head: Option<BlockNumber>,
+    queue: VecDeque<(
+        BlockNumber,
+        tokio::task::JoinHandle<anyhow::Result<Option<Prefetched>>>,
+    )>,
+}
+
+impl<GatewayClient> LookAhead<GatewayClient>
+where
+    GatewayClient: GatewayApi + Clone + Send + 'static,
+{
+    /// Returns the downloaded data for `block` if it is next in the queue.
+    async fn take(&mut self, block: BlockNumber) -> Option<Prefetched> {
+        match self.queue.front() {
+            Some((number, _)) if *number == block => {}
+            _ => {
+                self.clear();
+                return None;
+            }
+        }
+
+        let (_, handle) = self.queue.pop_front()?;
+        match handle.await {
+            Ok(Ok(Some(prefetched))) => return Some(prefetched),
+            Ok(Ok(None)) => {}
+            Ok(Err(error)) => {
+                tracing::debug!(%block, ?error, "Downloading block ahead failed");
+            }
+            Err(error) => {
+                tracing::debug!(%block, %error, "Joining look-ahead task failed");
+            }
+        }
+
+        self.clear();
+        None
+    }
+
+    fn clear(&mut self) {
+        for (_, handle) in self.queue.drain(..) {
+            handle.abort();
+        }
+    }
+
+    /// Starts downloading blocks from `next` onwards, up to the look-ahead depth and
+    /// never past the latest block known to exist on the feeder gateway.
+    async fn fill(&mut self, next: BlockNumber) {
+        if self.depth == 0 {
+            return;
+        }
+
+        let end = next + self.depth as u64;
+        let mut number = self
+            .queue
+            .back()
+            .map(|(number, _)| *number + 1)
+            .unwrap_or(next);
+
+        if number >= end {
+            return;
+        }
+
+        if self.known_head.map_or(true, |head| head < number) {
+            match self.sequencer.head().await {
+                Ok((head, _)) => self.known_head = Some(head),
+                Err(error) => {
+                    tracing::debug!(%error, "Polling head for look-ahead failed");
+                    return;
+                }
+            }
+        }
+
+        let Some(mut known_head) = self.known_head else {
+            return;
+        };
+        if let Some(target) = self.target {
+            known_head = known_head.min(target);
+        }
+
+        while number < end && number <= known_head {
+            let handle = tokio::spawn(prefetch_block(
+                number,
+                self.block_hash_meta.clone(),
+                self.chain_id,
+                self.sequencer.clone(),
+                self.class_queue.clone(),
+                self.mode,
+                self.storage.clone(),
+            ));
+            self.queue.push_back((number, handle));
+
+            number += 1;
+        }
+    }
+}
+
+/// Downloads the block and its signature, and queues its new classes. Returns `None` if
+/// the block is not available as a regular block, in which case sync handles it itself.
+async fn prefetch_block<GatewayClient: GatewayApi>(
+    block_number: BlockNumber,
+    block_hash_meta: BlockHashMetaInfo,
+    chain_id: ChainId,
+    sequencer: GatewayClient,
+    class_queue: ClassQueue,
+    mode: BlockValidationMode,
+    storage: Storage,
+) -> anyhow::Result<Option<Prefetched>> {
+    let signature = async {
+        let t_signature = std::time::Instant::now();
+        let result = sequencer.signature(block_number.into()).await;
+        (result, t_signature.elapsed())
+    };
+    let block = async {
+        let t_block = std::time::Instant::now();
+        let result = download_block(
+            block_number,
+            &block_hash_meta,
+            chain_id,
+            None,
+            &sequencer,
+            mode,
+        )
+        .await;
+        (result, t_block.elapsed())
+    };
+
+    let ((signature, t_signature), (block, t_block)) = tokio::join!(signature, block);
+
+    let DownloadBlock::Block(block, commitments, state_update) = block? else {
+        return Ok(None);
+    };
+    let (signature, t_signature) =
+        retry_signature_if_not_found(signature, t_signature, block_number, &sequencer).await?;
+
+    let t_declare = std::time::Instant::now();
+    let classes = missing_classes(&state_update, &block.starknet_version, storage)
+        .await
+        .with_context(|| format!("Querying new classes for block {block_number:?}"))?;
+    let hashes = classes.iter().map(|class| class.hash).collect();
+    class_queue
+        .enqueue(classes)
+        .await
+        .with_context(|| format!("Queuing new classes for block {block_number:?}"))?;
+
+    Ok(Some(Prefetched {
+        block,
+        commitments,
+        state_update,
+        signature,
+        classes: hashes,
+        timings: Timings {
+            block_download: t_block,
+            class_declaration: t_declare.elapsed(),
+            signature_download: t_signature,
+        },
+    }))
+}
+
+/// Retries the signature download if the signature was not found.
+///
+/// There is a race condition here: the signature may have been queried _before_ the block was
+/// published, while the block was queried after it. In this case we just retry the signature
+/// download.
+async fn retry_signature_if_not_found<GatewayClient: GatewayApi>(
+    result: Result<reply::BlockSignature, SequencerError>,
+    t_signature: Duration,
+    block_number: BlockNumber,
+    sequencer: &GatewayClient,
+) -> anyhow::Result<(reply::BlockSignature, Duration)> {
+    match result {
+        Ok(signature) => Ok((signature, t_signature)),
+        Err(SequencerError::StarknetError(err))
+            if err.code
+                == starknet_gateway_types::error::KnownStarknetErrorCode::BlockNotFound.into() =>
+        {
+            let t_signature = std::time::Instant::now();
+            let signature = sequencer
+                .signature(block_number.into())
+                .await
+                .with_context(|| {
+                    format!("Fetch signature for block {block_number:?} from sequencer")
+                })?;
+            Ok((signature, t_signature.elapsed()))
+        }
+        Err(err) => Err(err).context(format!(
+            "Fetch signature for block {block_number:?} from sequencer"
+        )),
     }
 }
 
 /// Download and emit new contract classes.
 ///
+/// Used for pending data, which does not go through the [ClassQueue] since it is not
+/// persisted. See [missing_classes] for where new classes can come from.
+pub async fn download_new_classes(
+    state_update: &StateUpdate,
+    sequencer: &impl GatewayApi,
+    tx_event: &mpsc::Sender<SyncEvent>,
+    version: &StarknetVersion,
+    storage: Storage,
+    compiler: Compiler,
+    mode: BlockValidationMode,
+) -> Result<(), anyhow::Error> {
+    let classes = missing_classes(state_update, version, storage).await?;
+
+    for class in classes {
+        let downloaded = download_class(sequencer, &class, compiler, mode)
+            .await
+            .with_context(|| format!("Downloading class {}", class.hash.0))?;
+
+        emit_class(downloaded, class.casm_hash, tx_event).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the classes introduced by `state_update` which are not yet in the database.
+///
 /// New classes can come from:
 /// - DECLARE transactions
 /// - `old_declared_contracts` from the state diff (Cairo 0.x classes)
@@ -304,13 +657,11 @@ where
 /// can show up in `replaced_classes`. This is caused by DECLARE v0 transactions
 /// that were _failing_ but the sequencer has still added the class to its list of
 /// known classes...
-pub async fn download_new_classes(
+async fn missing_classes(
     state_update: &StateUpdate,
-    sequencer: &impl GatewayApi,
-    tx_event: &mpsc::Sender<SyncEvent>,
     version: &StarknetVersion,
     storage: Storage,
-) -> Result<(), anyhow::Error> {
+) -> Result<Vec<QueuedClass>, anyhow::Error> {
     let deployed_classes = state_update
         .contract_updates
         .iter()
@@ -333,10 +684,10 @@ pub async fn download_new_classes(
         .collect::<Vec<_>>();
 
     if new_classes.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    let require_downloading = tokio::task::spawn_blocking(move || {
+    let missing = tokio::task::spawn_blocking(move || {
         let mut db_conn = storage
             .connection()
             .context("Creating database connection")?;
@@ -352,7 +703,7 @@ pub async fn download_new_classes(
             .into_iter()
             .zip(exists.into_iter())
             .filter_map(|(class, exist)| (!exist).then_some(class))
-            .collect::<HashSet<_>>();
+            .collect::<Vec<_>>();
 
         anyhow::Ok(missing)
     })
@@ -360,56 +711,22 @@ pub async fn download_new_classes(
     .context("Joining database task")?
     .context("Querying database for missing classes")?;
 
-    for class_hash in require_downloading {
-        let class = download_class(sequencer, class_hash, version.clone())
-            .await
-            .with_context(|| format!("Downloading class {}", class_hash.0))?;
-
-        match class {
-            DownloadedClass::Cairo { definition, hash } => tx_event
-                .send(SyncEvent::CairoClass { definition, hash })
-                .await
-                .with_context(|| {
-                    format!(
-                        "Sending Event::NewCairoContract for declared class {}",
-                        class_hash.0
-                    )
-                })?,
-            DownloadedClass::Sierra {
-                sierra_definition,
-                sierra_hash,
-                casm_definition,
-            } => {
-                // NOTE: we _have_ to use the same compiled_class_class hash as returned by the feeder gateway,
-                // since that's what has been added to the class commitment tree.
-                let Some(casm_hash) = state_update
-                    .declared_sierra_classes
-                    .iter()
-                    .find_map(|(sierra, casm)| (sierra.0 == class_hash.0).then_some(*casm))
-                else {
-                    // This can occur if the sierra was in here as a deploy contract, if the class was
-                    // declared in a previous block but not yet persisted by the database.
-                    continue;
-                };
-                tx_event
-                    .send(SyncEvent::SierraClass {
-                        sierra_definition,
-                        sierra_hash,
-                        casm_definition,
-                        casm_hash,
-                    })
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Sending Event::NewSierraContract for declared class {}",
-                            class_hash.0
-                        )
-                    })?
-            }
-        }
-    }
-
-    Ok(())
+    let classes = missing
+        .into_iter()
+        .map(|hash| QueuedClass {
+            hash,
+            // NOTE: we _have_ to use the same compiled class hash as returned by the feeder gateway,
+            // since that's what has been added to the class commitment tree.
+            casm_hash: state_update
+                .declared_sierra_classes
+                .iter()
+                .find_map(|(sierra, casm)| (sierra.0 == hash.0).then_some(*casm)),
+            starknet_version: version.clone(),
+            attempts: 0,
+        })
+        .collect();
+
+    Ok(classes)
 }
 
 enum DownloadBlock {
@@ -422,6 +739,77 @@ enum DownloadBlock {
     Reorg,
 }
 
+/// How sync checks the sequencer's signature of each block.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SignatureVerification {
+    #[default]
+    Disabled,
+    /// Logs a warning and emits a metric for blocks not signed by the sequencer.
+    Warn(PublicKey),
+    /// Fails sync on blocks not signed by the sequencer.
+    Strict(PublicKey),
+}
+
+/// Verifies that the sequencer with `public_key` signed the block and its state update.
+fn verify_signature(
+    public_key: PublicKey,
+    signature: &reply::BlockSignature,
+    state_update: &StateUpdate,
+) -> anyhow::Result<()> {
+    let state_diff_commitment = state_update.compute_state_diff_commitment();
+    anyhow::ensure!(
+        state_diff_commitment == signature.signature_input.state_diff_commitment,
+        "State diff commitment mismatch, signed {:x}, computed {:x}",
+        signature.signature_input.state_diff_commitment.0,
+        state_diff_commitment.0,
+    );
+
+    BlockCommitmentSignature::from(signature.clone())
+        .verify(
+            public_key,
+            signature.signature_input.block_hash,
+            state_diff_commitment,
+        )
+        .map_err(|e| anyhow!("Invalid block signature: {e}"))
+}
+
+/// Checks the block's signature according to `verification`. Returns the state update so
+/// that it need not be cloned for the blocking task.
+async fn check_signature(
+    verification: SignatureVerification,
+    signature: &reply::BlockSignature,
+    state_update: Box<StateUpdate>,
+) -> anyhow::Result<Box<StateUpdate>> {
+    let public_key = match verification {
+        SignatureVerification::Disabled => return Ok(state_update),
+        SignatureVerification::Warn(key) | SignatureVerification::Strict(key) => key,
+    };
+
+    let block_number = signature.block_number;
+    let signature = signature.clone();
+    let (state_update, result) = tokio::task::spawn_blocking(move || {
+        let result = verify_signature(public_key, &signature, &state_update);
+        (state_update, result)
+    })
+    .await
+    .context("Joining signature verification task")?;
+
+    match (result, verification) {
+        (Ok(()), _) => {}
+        (Err(error), SignatureVerification::Warn(_)) => {
+            tracing::warn!(
+                block=%block_number,
+                error=%format!("{error:#}"),
+                "Block signature verification failed"
+            );
+            metrics::increment_counter!("block_signature_invalid_total");
+        }
+        (Err(error), _) => return Err(error),
+    }
+
+    Ok(state_update)
+}
+
 #[derive(Copy, Clone, Default)]
 pub enum BlockValidationMode {
     #[default]
@@ -433,7 +821,7 @@ pub enum BlockValidationMode {
 
 async fn download_block(
     block_number: BlockNumber,
-    chain: Chain,
+    block_hash_meta: &BlockHashMetaInfo,
     chain_id: ChainId,
     prev_block_hash: Option<BlockHash>,
     sequencer: &impl GatewayApi,
@@ -443,22 +831,29 @@ async fn download_block(
 
     let result = sequencer.state_update_with_block(block_number).await;
 
-    let result = match result {
+    match result {
         Ok((block, state_update)) => {
             let block = Box::new(block);
             let state_update = Box::new(state_update);
 
             // Check if block hash is correct.
+            let block_hash_meta = block_hash_meta.clone();
             let verify_hash = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
                 let block_number = block.block_number;
                 // In p2p the state commitment which is required to calculate the block hash can be missing, and in such case it is marked as 0s.
+                // Only the block hash is unverifiable then, the commitments are still computed.
                 #[cfg(feature = "p2p")]
                 if block.state_commitment == StateCommitment::ZERO {
-                    return Ok((block, VerifyResult::NotVerifiable));
+                    crate::state::block_hash::verify_transactions(&block, chain_id)
+                        .with_context(move || format!("Verify block {block_number}"))?;
+                    let commitments = crate::state::block_hash::verify_commitments(&block)
+                        .with_context(move || format!("Verify block {block_number}"))?;
+                    return Ok((block, VerifyResult::NotVerifiable(commitments)));
                 }
 
-                let verify_result = verify_block_hash(&block, chain, chain_id, block.block_hash)
-                    .with_context(move || format!("Verify block {block_number}"))?;
+                let verify_result =
+                    verify_block_hash(&block, &block_hash_meta, chain_id, block.block_hash)
+                        .with_context(move || format!("Verify block {block_number}"))?;
                 Ok((block, verify_result))
             });
             let (block, verify_result) = verify_hash.await.context("Verify block hash")??;
@@ -468,13 +863,11 @@ async fn download_block(
                     VerifyResult::Match(commitments),
                     _,
                 ) => Ok(DownloadBlock::Block(block, commitments, state_update)),
-                (Status::AcceptedOnL1 | Status::AcceptedOnL2, VerifyResult::NotVerifiable, _) => {
-                    Ok(DownloadBlock::Block(
-                        block,
-                        Default::default(),
-                        state_update,
-                    ))
-                }
+                (
+                    Status::AcceptedOnL1 | Status::AcceptedOnL2,
+                    VerifyResult::NotVerifiable(commitments),
+                    _,
+                ) => Ok(DownloadBlock::Block(block, commitments, state_update)),
                 (
                     Status::AcceptedOnL1 | Status::AcceptedOnL2,
                     VerifyResult::Mismatch,
@@ -521,41 +914,12 @@ async fn download_block(
             }
         }
         Err(other) => Err(other).context("Download block from sequencer"),
-    };
-
-    match result {
-        Ok(DownloadBlock::Block(block, commitments, state_update)) => {
-            use rayon::prelude::*;
-
-            let (send, recv) = tokio::sync::oneshot::channel();
-
-            rayon::spawn(move || {
-                let result = block
-                    .transactions
-                    .par_iter()
-                    .enumerate()
-                    .try_for_each(|(i, txn)| {
-                        if !txn.verify_hash(chain_id) {
-                            anyhow::bail!("Transaction hash mismatch: block {block_number} idx {i}")
-                        };
-                        Ok(())
-                    })
-                    .map(|_| block);
-
-                let _ = send.send(result);
-            });
-
-            let block = recv.await.expect("Panic on rayon thread")?;
-
-            Ok(DownloadBlock::Block(block, commitments, state_update))
-        }
-        Ok(DownloadBlock::AtHead | DownloadBlock::Reorg) | Err(_) => result,
     }
 }
 
 async fn reorg(
     head: &(BlockNumber, BlockHash, StateCommitment),
-    chain: Chain,
+    block_hash_meta: &BlockHashMetaInfo,
     chain_id: ChainId,
     tx_event: &mpsc::Sender<SyncEvent>,
     sequencer: &impl GatewayApi,
@@ -578,7 +942,7 @@ async fn reorg(
 
         match download_block(
             previous_block_number,
-            chain,
+            block_hash_meta,
             chain_id,
             Some(previous.0),
             sequencer,
@@ -613,13 +977,14 @@ async fn reorg(
 mod tests {
 
     mod sync {
+        use crate::state::block_hash::meta;
         use crate::state::l2::{BlockChain, L2SyncContext};
         use pathfinder_common::macro_prelude::*;
         use pathfinder_common::BlockCommitmentSignature;
         use pathfinder_common::StateUpdate;
         use starknet_gateway_types::reply::GasPrices;
 
-        use super::super::{sync, BlockValidationMode, SyncEvent};
+        use super::super::{sync, BlockValidationMode, SignatureVerification, SyncEvent};
         use assert_matches::assert_matches;
         use pathfinder_common::{
             BlockHash, BlockId, BlockNumber, BlockTimestamp, Chain, ChainId, ClassHash,
@@ -802,10 +1167,14 @@ mod tests {
             let sequencer = std::sync::Arc::new(sequencer);
             let context = L2SyncContext {
                 sequencer,
-                chain: Chain::GoerliTestnet,
+                block_hash_meta: meta::for_chain(Chain::GoerliTestnet).clone(),
                 chain_id: ChainId::GOERLI_TESTNET,
                 block_validation_mode: MODE,
                 storage,
+                look_ahead: 0,
+                target_block: None,
+                compiler: pathfinder_compiler::Compiler::InProcess,
+                signature_verification: SignatureVerification::Disabled,
             };
 
             tokio::spawn(sync(
@@ -1122,6 +1491,226 @@ mod tests {
                 });
             }
 
+            #[tokio::test]
+            async fn from_genesis_with_look_ahead() {
+                use mockall::predicate::eq;
+
+                let (tx_event, mut rx_event) = tokio::sync::mpsc::channel(1);
+                let mut mock = MockGatewayApi::new();
+
+                // Downloads ahead of the head happen concurrently, so no ordering is enforced.
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK0_NUMBER))
+                    .times(1)
+                    .return_once(|_| Ok((BLOCK0.clone(), STATE_UPDATE0.clone())));
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK1_NUMBER))
+                    .times(1)
+                    .return_once(|_| Ok((BLOCK1.clone(), STATE_UPDATE1.clone())));
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK2_NUMBER))
+                    .returning(|_| Err(block_not_found()));
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK0_NUMBER)))
+                    .times(1)
+                    .return_once(|_| Ok(BLOCK0_SIGNATURE.clone()));
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK1_NUMBER)))
+                    .times(1)
+                    .return_once(|_| Ok(BLOCK1_SIGNATURE.clone()));
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK2_NUMBER)))
+                    .returning(|_| Err(block_not_found()));
+                mock.expect_pending_class_by_hash()
+                    .withf(|x| x == &CONTRACT0_HASH)
+                    .times(1)
+                    .return_once(|_| Ok(CONTRACT0_DEF.clone()));
+                mock.expect_pending_class_by_hash()
+                    .withf(|x| x == &CONTRACT1_HASH)
+                    .times(1)
+                    .return_once(|_| Ok(CONTRACT1_DEF.clone()));
+                mock.expect_block_header()
+                    .with(eq(BlockId::Latest))
+                    .returning(|_| Ok((BLOCK1.block_number, BLOCK1.block_hash)));
+
+                let context = L2SyncContext {
+                    sequencer: std::sync::Arc::new(mock),
+                    block_hash_meta: meta::for_chain(Chain::GoerliTestnet).clone(),
+                    chain_id: ChainId::GOERLI_TESTNET,
+                    block_validation_mode: MODE,
+                    storage: Storage::in_memory().unwrap(),
+                    look_ahead: 3,
+                    target_block: None,
+                    compiler: pathfinder_compiler::Compiler::InProcess,
+                    signature_verification: SignatureVerification::Disabled,
+                };
+
+                let _jh = tokio::spawn(sync(
+                    tx_event,
+                    context,
+                    None,
+                    BlockChain::with_capacity(100, vec![]),
+                ));
+
+                assert_matches!(rx_event.recv().await.unwrap(),
+                    SyncEvent::CairoClass { hash, .. } => {
+                        assert_eq!(hash, CONTRACT0_HASH);
+                });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::Block((block, _), state_update, signature, _) => {
+                    assert_eq!(*block, *BLOCK0);
+                    assert_eq_sorted!(*state_update, *STATE_UPDATE0);
+                    assert_eq!(*signature, BLOCK0_COMMITMENT_SIGNATURE);
+                });
+                assert_matches!(rx_event.recv().await.unwrap(),
+                    SyncEvent::CairoClass { hash, .. } => {
+                    assert_eq!(hash, CONTRACT1_HASH);
+                });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::Block((block, _), state_update, signature, _) => {
+                    assert_eq!(*block, *BLOCK1);
+                    assert_eq_sorted!(*state_update, *STATE_UPDATE1);
+                    assert_eq!(*signature, BLOCK1_COMMITMENT_SIGNATURE);
+                });
+            }
+
+            #[tokio::test]
+            async fn look_ahead_retries_missing_signature() {
+                use mockall::predicate::eq;
+
+                let (tx_event, mut rx_event) = tokio::sync::mpsc::channel(1);
+                let mut mock = MockGatewayApi::new();
+                let mut signature_seq = mockall::Sequence::new();
+
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK0_NUMBER))
+                    .times(1)
+                    .return_once(|_| Ok((BLOCK0.clone(), STATE_UPDATE0.clone())));
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK1_NUMBER))
+                    .times(1)
+                    .return_once(|_| Ok((BLOCK1.clone(), STATE_UPDATE1.clone())));
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK2_NUMBER))
+                    .returning(|_| Err(block_not_found()));
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK0_NUMBER)))
+                    .times(1)
+                    .return_once(|_| Ok(BLOCK0_SIGNATURE.clone()));
+                // The signature of block 1 is not published yet when it is first queried.
+                expect_signature(
+                    &mut mock,
+                    &mut signature_seq,
+                    BLOCK1_NUMBER.into(),
+                    Err(block_not_found()),
+                );
+                expect_signature(
+                    &mut mock,
+                    &mut signature_seq,
+                    BLOCK1_NUMBER.into(),
+                    Ok(BLOCK1_SIGNATURE.clone()),
+                );
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK2_NUMBER)))
+                    .returning(|_| Err(block_not_found()));
+                mock.expect_pending_class_by_hash()
+                    .withf(|x| x == &CONTRACT0_HASH)
+                    .times(1)
+                    .return_once(|_| Ok(CONTRACT0_DEF.clone()));
+                mock.expect_pending_class_by_hash()
+                    .withf(|x| x == &CONTRACT1_HASH)
+                    .times(1)
+                    .return_once(|_| Ok(CONTRACT1_DEF.clone()));
+                mock.expect_block_header()
+                    .with(eq(BlockId::Latest))
+                    .returning(|_| Ok((BLOCK1.block_number, BLOCK1.block_hash)));
+
+                let context = L2SyncContext {
+                    sequencer: std::sync::Arc::new(mock),
+                    block_hash_meta: meta::for_chain(Chain::GoerliTestnet).clone(),
+                    chain_id: ChainId::GOERLI_TESTNET,
+                    block_validation_mode: MODE,
+                    storage: Storage::in_memory().unwrap(),
+                    look_ahead: 3,
+                    target_block: None,
+                    compiler: pathfinder_compiler::Compiler::InProcess,
+                    signature_verification: SignatureVerification::Disabled,
+                };
+
+                let _jh = tokio::spawn(sync(
+                    tx_event,
+                    context,
+                    None,
+                    BlockChain::with_capacity(100, vec![]),
+                ));
+
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::CairoClass { .. });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::Block((block, _), _, _, _) => {
+                    assert_eq!(*block, *BLOCK0);
+                });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::CairoClass { .. });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::Block((block, _), _, signature, _) => {
+                    assert_eq!(*block, *BLOCK1);
+                    assert_eq!(*signature, BLOCK1_COMMITMENT_SIGNATURE);
+                });
+            }
+
+            #[tokio::test]
+            async fn stops_at_target_block() {
+                use mockall::predicate::eq;
+
+                let (tx_event, mut rx_event) = tokio::sync::mpsc::channel(1);
+                let mut mock = MockGatewayApi::new();
+
+                // Nothing past the target block may be requested.
+                mock.expect_state_update_with_block()
+                    .with(eq(BLOCK0_NUMBER))
+                    .times(1)
+                    .return_once(|_| Ok((BLOCK0.clone(), STATE_UPDATE0.clone())));
+                mock.expect_signature()
+                    .with(eq(BlockId::from(BLOCK0_NUMBER)))
+                    .times(1)
+                    .return_once(|_| Ok(BLOCK0_SIGNATURE.clone()));
+                mock.expect_pending_class_by_hash()
+                    .withf(|x| x == &CONTRACT0_HASH)
+                    .times(1)
+                    .return_once(|_| Ok(CONTRACT0_DEF.clone()));
+                mock.expect_block_header()
+                    .with(eq(BlockId::Latest))
+                    .returning(|_| Ok((BLOCK1.block_number, BLOCK1.block_hash)));
+
+                let context = L2SyncContext {
+                    sequencer: std::sync::Arc::new(mock),
+                    block_hash_meta: meta::for_chain(Chain::GoerliTestnet).clone(),
+                    chain_id: ChainId::GOERLI_TESTNET,
+                    block_validation_mode: MODE,
+                    storage: Storage::in_memory().unwrap(),
+                    look_ahead: 3,
+                    target_block: Some(BLOCK0_NUMBER),
+                    compiler: pathfinder_compiler::Compiler::InProcess,
+                    signature_verification: SignatureVerification::Disabled,
+                };
+
+                let _jh = tokio::spawn(sync(
+                    tx_event,
+                    context,
+                    None,
+                    BlockChain::with_capacity(100, vec![]),
+                ));
+
+                assert_matches!(rx_event.recv().await.unwrap(),
+                    SyncEvent::CairoClass { hash, .. } => {
+                        assert_eq!(hash, CONTRACT0_HASH);
+                });
+                assert_matches!(rx_event.recv().await.unwrap(), SyncEvent::Block((block, _), _, _, _) => {
+                    assert_eq!(*block, *BLOCK0);
+                });
+
+                // Sync remains alive but idle, a closed channel would indicate a failure.
+                let result =
+                    tokio::time::timeout(std::time::Duration::from_millis(200), rx_event.recv())
+                        .await;
+                assert!(result.is_err());
+            }
+
             #[tokio::test]
             async fn resumed_after_genesis() {
                 let (tx_event, mut rx_event) = tokio::sync::mpsc::channel(1);
@@ -1173,10 +1762,14 @@ mod tests {
                 let mock = std::sync::Arc::new(mock);
                 let context = L2SyncContext {
                     sequencer: mock,
-                    chain: Chain::GoerliTestnet,
+                    block_hash_meta: meta::for_chain(Chain::GoerliTestnet).clone(),
                     chain_id: ChainId::GOERLI_TESTNET,
                     block_validation_mode: MODE,
                     storage: Storage::in_memory().unwrap(),
+                    look_ahead: 0,
+                    target_block: None,
+                    compiler: pathfinder_compiler::Compiler::InProcess,
+                    signature_verification: SignatureVerification::Disabled,
                 };
 
                 let _jh = tokio::spawn(sync(
@@ -2417,4 +3010,77 @@ mod tests {
             assert!(uut.get(&BlockNumber::new_or_panic(3)).is_none());
         }
     }
+
+    mod signature {
+        use pathfinder_common::consts::MAINNET_SEQUENCER_PUBLIC_KEY;
+        use pathfinder_common::macro_prelude::*;
+        use pathfinder_common::StateUpdate;
+        use starknet_gateway_test_fixtures::v0_12_2;
+        use starknet_gateway_types::reply;
+
+        use super::super::{check_signature, verify_signature, SignatureVerification};
+
+        fn block_350000() -> (reply::BlockSignature, Box<StateUpdate>) {
+            let signature = serde_json::from_str(v0_12_2::signature::BLOCK_350000).unwrap();
+            let state_update: reply::StateUpdate =
+                serde_json::from_str(v0_12_2::state_update::BLOCK_350000).unwrap();
+
+            (signature, Box::new(state_update.into()))
+        }
+
+        #[test]
+        fn valid() {
+            let (signature, state_update) = block_350000();
+            verify_signature(MAINNET_SEQUENCER_PUBLIC_KEY, &signature, &state_update).unwrap();
+        }
+
+        #[test]
+        fn wrong_key() {
+            let (signature, state_update) = block_350000();
+            let error =
+                verify_signature(public_key!("0x1234"), &signature, &state_update).unwrap_err();
+            assert!(error.to_string().contains("Invalid block signature"));
+        }
+
+        #[test]
+        fn tampered_state_update() {
+            let (signature, state_update) = block_350000();
+            let state_update = (*state_update).with_storage_update(
+                contract_address!("0x1"),
+                storage_address!("0x2"),
+                storage_value!("0x3"),
+            );
+            let error = verify_signature(MAINNET_SEQUENCER_PUBLIC_KEY, &signature, &state_update)
+                .unwrap_err();
+            assert!(error.to_string().contains("State diff commitment mismatch"));
+        }
+
+        #[tokio::test]
+        async fn modes() {
+            let (signature, state_update) = block_350000();
+            let wrong_key = public_key!("0x1234");
+
+            check_signature(
+                SignatureVerification::Strict(MAINNET_SEQUENCER_PUBLIC_KEY),
+                &signature,
+                state_update.clone(),
+            )
+            .await
+            .unwrap();
+            check_signature(
+                SignatureVerification::Warn(wrong_key),
+                &signature,
+                state_update.clone(),
+            )
+            .await
+            .unwrap();
+            check_signature(
+                SignatureVerification::Strict(wrong_key),
+                &signature,
+                state_update,
+            )
+            .await
+            .unwrap_err();
+        }
+    }
 }
@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
 use pathfinder_common::BlockHash;
+use pathfinder_compiler::Compiler;
 use pathfinder_storage::Storage;
 use starknet_gateway_client::GatewayApi;
 use tokio::time::Instant;
 
+use crate::state::sync::l2::BlockValidationMode;
 use crate::state::sync::SyncEvent;
 
 /// Poll's the Sequencer's pending block and emits [pending events](SyncEvent::Pending)
@@ -21,6 +23,8 @@ pub async fn poll_pending<S: GatewayApi + Clone + Send + 'static>(
     sequencer: S,
     poll_interval: std::time::Duration,
     storage: Storage,
+    compiler: Compiler,
+    mode: BlockValidationMode,
 ) {
     let mut prev_tx_count = 0;
     let mut prev_hash = BlockHash::default();
@@ -57,6 +61,8 @@ pub async fn poll_pending<S: GatewayApi + Clone + Send + 'static>(
             &tx_event,
             &block.starknet_version,
             storage.clone(),
+            compiler,
+            mode,
         )
         .await
         {
@@ -173,6 +179,8 @@ mod tests {
                 sequencer,
                 std::time::Duration::ZERO,
                 Storage::in_memory().unwrap(),
+                pathfinder_compiler::Compiler::InProcess,
+                crate::state::l2::BlockValidationMode::Strict,
             )
             .await
         });
@@ -243,6 +251,8 @@ mod tests {
                 sequencer,
                 std::time::Duration::ZERO,
                 Storage::in_memory().unwrap(),
+                pathfinder_compiler::Compiler::InProcess,
+                crate::state::l2::BlockValidationMode::Strict,
             )
             .await
         });
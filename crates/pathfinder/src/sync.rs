@@ -1,3 +1,3 @@
 #[cfg(feature = "p2p")]
 #[allow(dead_code)]
-mod p2p;
+pub mod p2p;
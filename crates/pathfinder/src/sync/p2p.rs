@@ -1,41 +1,75 @@
 #![allow(dead_code, unused_variables)]
+mod blocks;
 mod headers;
+mod snapshot;
+#[cfg(test)]
+mod tests;
 
 use anyhow::Context;
-use pathfinder_common::{BlockHash, BlockNumber};
+use pathfinder_common::{BlockHash, BlockNumber, ChainId};
 use pathfinder_ethereum::EthereumStateUpdate;
 use pathfinder_storage::Storage;
 use primitive_types::H160;
 use tokio::task::spawn_blocking;
 
 use p2p::client::peer_agnostic::Client as P2PClient;
+use p2p::{Head, Misbehaviour, PeerData};
+
+/// How many times in a row syncing up to an announced head tries to fill the same gap in the
+/// header chain before giving up.
+const MAX_GAP_ATTEMPTS: usize = 3;
 
 /// Provides P2P sync capability for blocks secured by L1.
 pub struct Sync {
     storage: Storage,
     p2p: P2PClient,
     // TODO: merge these two inside the client.
-    eth_client: pathfinder_ethereum::EthereumClient,
+    eth_client: pathfinder_ethereum::SettlementClient,
     eth_address: H160,
+    chain_id: ChainId,
+    /// Sync stops at this block, even if the L1 checkpoint is newer.
+    target_block: Option<BlockNumber>,
+    /// Sync the state of the newest block as a snapshot when starting from scratch, instead of
+    /// applying every block's state diff since genesis.
+    snapshot_sync: bool,
 }
 
 impl Sync {
     pub fn new(
         storage: Storage,
         p2p: P2PClient,
-        ethereum: (pathfinder_ethereum::EthereumClient, H160),
+        ethereum: (pathfinder_ethereum::SettlementClient, H160),
+        chain_id: ChainId,
+        target_block: Option<BlockNumber>,
+        snapshot_sync: bool,
     ) -> Self {
         Self {
             storage,
             p2p,
             eth_client: ethereum.0,
             eth_address: ethereum.1,
+            chain_id,
+            target_block,
+            snapshot_sync,
         }
     }
 
-    /// Syncs using p2p until the latest Ethereum checkpoint.
+    /// Syncs using p2p until the latest Ethereum checkpoint and then on to the newest block
+    /// peers have, or until the target block if one is set.
     pub async fn run(&self) -> anyhow::Result<()> {
         use pathfinder_ethereum::EthereumApi;
+
+        if let Some(target) = self.target_block {
+            if target_header(self.storage.clone(), target)
+                .await
+                .context("Querying target header")?
+                .is_some()
+            {
+                tracing::info!(%target, "Target block reached, skipping sync");
+                return Ok(());
+            }
+        }
+
         let checkpoint = self
             .eth_client
             .get_starknet_state(&self.eth_address)
@@ -57,18 +91,163 @@ impl Sync {
         // necessary being rolled back (potentially all data if the header sync process is frequently interrupted), so this
         // ensures sync will progress even under bad conditions.
         let anchor = checkpoint;
-        persist_anchor(self.storage.clone(), anchor.clone())
+        persist_anchor(self.storage.clone(), anchor.clone(), self.target_block)
             .await
             .context("Persisting new Ethereum anchor")?;
 
+        let stop = match self.target_block {
+            Some(target) => anchor.block_number.min(target),
+            None => anchor.block_number,
+        };
+
         // Sync missing headers in reverse chronological order, from the new anchor to genesis.
         self.sync_headers(anchor).await.context("Syncing headers")?;
 
+        if self.snapshot_sync {
+            self.sync_snapshot(stop)
+                .await
+                .context("Syncing state snapshot")?;
+        }
+
         // Sync the rest of the data in chronological order.
+        self.sync_blocks(stop).await.context("Syncing block data")?;
+
+        if self.target_block.is_none() {
+            self.sync_past_anchor()
+                .await
+                .context("Syncing blocks newer than the L1 anchor")?;
+        }
+
+        self.backfill_bodies()
+            .await
+            .context("Syncing blocks skipped by the snapshot")?;
 
         Ok(())
     }
 
+    /// Syncs the blocks newer than the local chain which peers have, until no peer has a newer
+    /// block. Unlike the blocks up to the L1 anchor, these are only verified to extend the local
+    /// chain.
+    async fn sync_past_anchor(&self) -> anyhow::Result<()> {
+        /// Headers are requested and persisted in chunks of this size.
+        const CHUNK: u64 = 1024;
+        /// Peers which served invalid headers are penalized, so retrying gives other peers a
+        /// chance to provide valid headers.
+        const MAX_ATTEMPTS: usize = 5;
+
+        let mut attempt = 1;
+        loop {
+            let Some((tip, tip_hash)) = latest_header(self.storage.clone())
+                .await
+                .context("Querying latest header")?
+            else {
+                // Headers are synced from the anchor first, so there is always a tip.
+                return Ok(());
+            };
+
+            let Some(PeerData {
+                peer,
+                data: headers,
+            }) = self
+                .p2p
+                .block_headers_from_any_peer(tip + 1, CHUNK)
+                .await
+                .context("Fetching headers")?
+            else {
+                tracing::debug!(%tip, "No peer has a newer block");
+                return Ok(());
+            };
+            let complete = headers.len() as u64 == CHUNK;
+
+            match self.verify_extension(tip, tip_hash, peer, headers).await {
+                Ok(headers) => {
+                    let head = headers::persist(headers, self.storage.clone())
+                        .await
+                        .context("Persisting headers")?
+                        .data
+                        .header
+                        .number;
+                    tracing::info!(%head, "Headers past the L1 anchor synced");
+                    attempt = 1;
+
+                    self.sync_blocks(head).await.context("Syncing block data")?;
+                }
+                Err(misbehaviour) => {
+                    self.p2p.penalize(peer, misbehaviour).await;
+                    anyhow::ensure!(
+                        attempt < MAX_ATTEMPTS,
+                        "No peer served valid headers after block {tip}"
+                    );
+                    attempt += 1;
+                    continue;
+                }
+            }
+
+            if !complete {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks that the headers served by `peer` are valid and extend the chain ending in `tip`.
+    async fn verify_extension(
+        &self,
+        tip: BlockNumber,
+        tip_hash: BlockHash,
+        peer: p2p::libp2p::PeerId,
+        headers: Vec<p2p::client::types::SignedBlockHeader>,
+    ) -> Result<Vec<PeerData<p2p::client::types::SignedBlockHeader>>, Misbehaviour> {
+        let mut parent = (tip, tip_hash);
+        let mut verified = Vec::with_capacity(headers.len());
+
+        for header in headers {
+            if header.header.number != parent.0 + 1 || header.header.parent_hash != parent.1 {
+                tracing::debug!(%peer, block=%header.header.number, "Headers do not extend the local chain");
+                return Err(Misbehaviour::CommitmentMismatch);
+            }
+            parent = (header.header.number, header.header.hash);
+
+            let header = headers::verify(PeerData::new(peer, header))
+                .await
+                .map_err(|error| {
+                    tracing::debug!(%peer, %error, "Invalid header");
+                    error
+                        .misbehaviour()
+                        .unwrap_or(Misbehaviour::CommitmentMismatch)
+                })?;
+            verified.push(header);
+        }
+
+        Ok(verified)
+    }
+
+    /// Syncs up to a head announced by a peer. Unlike [Self::run], the synced blocks are not
+    /// secured by L1.
+    ///
+    /// The head is expected to have passed [verify_announcement] already.
+    pub async fn sync_head(&self, head: Head) -> anyhow::Result<()> {
+        if self.target_block.is_some() {
+            // Sync never goes past the target block, which is secured by L1.
+            return Ok(());
+        }
+
+        let known = target_header(self.storage.clone(), head.number)
+            .await
+            .context("Querying announced header")?
+            == Some((head.number, head.hash));
+        if known {
+            return Ok(());
+        }
+
+        self.sync_headers_from(head.number, head.hash)
+            .await
+            .context("Syncing headers")?;
+
+        self.sync_blocks(head.number)
+            .await
+            .context("Syncing block data")
+    }
+
     /// Syncs all headers in reverse chronological order, from the anchor point
     /// back to genesis. Fills in any gaps left by previous header syncs.
     ///
@@ -76,58 +255,286 @@ impl Sync {
     /// guarantee that all sync'd headers are secured by L1.
     ///
     /// No guarantees are made about any headers newer than the anchor.
+    ///
+    /// Headers past the target block are only used to verify the chain's continuity
+    /// from the anchor and are not persisted.
     async fn sync_headers(&self, anchor: EthereumStateUpdate) -> anyhow::Result<()> {
-        while let Some(gap) =
-            headers::next_gap(self.storage.clone(), anchor.block_number, anchor.block_hash)
+        let target = self
+            .target_block
+            .filter(|target| *target < anchor.block_number);
+
+        loop {
+            // Once the target header is stored, it replaces the anchor as the point to search from.
+            let (head, head_hash) = match target {
+                Some(target) => target_header(self.storage.clone(), target)
+                    .await
+                    .context("Querying target header")?
+                    .unwrap_or((anchor.block_number, anchor.block_hash)),
+                None => (anchor.block_number, anchor.block_hash),
+            };
+
+            let Some(gap) = headers::next_gap(self.storage.clone(), head, head_hash)
                 .await
                 .context("Finding next gap in header chain")?
+            else {
+                break;
+            };
+
+            self.sync_gap(gap, target).await;
+        }
+
+        Ok(())
+    }
+
+    /// Syncs all headers in reverse chronological order, from a head announced by a peer back
+    /// to the local chain.
+    ///
+    /// Gives up once the same gap has been attempted [MAX_GAP_ATTEMPTS] times without any
+    /// progress.
+    async fn sync_headers_from(
+        &self,
+        head: BlockNumber,
+        head_hash: BlockHash,
+    ) -> anyhow::Result<()> {
+        let mut last_gap = None;
+        let mut attempts = 0;
+        while let Some(gap) = headers::next_gap(self.storage.clone(), head, head_hash)
+            .await
+            .context("Finding next gap in header chain")?
         {
-            use futures::StreamExt;
-            use futures::TryStreamExt;
+            let range = (gap.tail, gap.head);
+            attempts = if last_gap == Some(range) {
+                attempts + 1
+            } else {
+                1
+            };
+            anyhow::ensure!(
+                attempts <= MAX_GAP_ATTEMPTS,
+                "No peer served the headers of blocks {}..={}",
+                gap.tail,
+                gap.head
+            );
+            last_gap = Some(range);
+
+            self.sync_gap(gap, None).await;
+        }
+
+        Ok(())
+    }
 
-            // TODO: create a tracing scope for this gap start, stop.
+    /// Fills a single gap in the header chain. Errors caused by peers are logged and the
+    /// offending peer is penalized, so that the caller can simply look for the next gap.
+    async fn sync_gap(&self, gap: headers::HeaderGap, target: Option<BlockNumber>) {
+        use futures::StreamExt;
+        use futures::TryStreamExt;
 
-            tracing::info!("Syncing headers");
+        // TODO: create a tracing scope for this gap start, stop.
 
-            // TODO: consider .inspect_ok(tracing::trace!) for each stage.
-            let result = self
-                .p2p
-                .clone()
-                // TODO: consider buffering in the client to reduce request latency.
-                .header_stream(gap.head, gap.tail, true)
-                .scan((gap.head, gap.head_hash, false), headers::check_continuity)
-                // TODO: rayon scope this.
-                .and_then(headers::verify)
-                // chunk so that persisting to storage can be batched.
-                .try_chunks(1024)
-                // TODO: Pull out remaining data from try_chunks error.
-                //       try_chunks::Error is a tuple of Err(data, error) so we
-                //       should re-stream that as Ok(data), Err(error). Right now
-                //       we just map to Err(error).
-                .map_err(|e| e.1)
-                .and_then(|x| headers::persist(x, self.storage.clone()))
-                .inspect_ok(|x| tracing::info!(tail=%x.data.header.number, "Header chunk synced"))
-                // Drive stream to completion.
-                .try_fold((), |_state, _x| std::future::ready(Ok(())))
-                .await;
-
-            match result {
+        tracing::info!("Syncing headers");
+
+        // TODO: consider .inspect_ok(tracing::trace!) for each stage.
+        let result = self
+            .p2p
+            .clone()
+            // TODO: consider buffering in the client to reduce request latency.
+            .header_stream(gap.head, gap.tail, true)
+            .scan((gap.head, gap.head_hash, false), headers::check_continuity)
+            // TODO: rayon scope this.
+            .and_then(headers::verify)
+            .try_filter(|x| {
+                std::future::ready(target.map_or(true, |target| x.data.header.number <= target))
+            })
+            // chunk so that persisting to storage can be batched.
+            .try_chunks(1024)
+            // TODO: Pull out remaining data from try_chunks error.
+            //       try_chunks::Error is a tuple of Err(data, error) so we
+            //       should re-stream that as Ok(data), Err(error). Right now
+            //       we just map to Err(error).
+            .map_err(|e| e.1)
+            .and_then(|x| headers::persist(x, self.storage.clone()))
+            .inspect_ok(|x| tracing::info!(tail=%x.data.header.number, "Header chunk synced"))
+            // Drive stream to completion.
+            .try_fold((), |_state, _x| std::future::ready(Ok(())))
+            .await;
+
+        match result {
+            Ok(()) => {
+                tracing::info!("Syncing headers complete");
+            }
+            Err(error) => {
+                if let Some(peer_data) = error.peer_id_and_data() {
+                    if let Some(misbehaviour) = error.misbehaviour() {
+                        self.p2p.penalize(peer_data.peer, misbehaviour).await;
+                    }
+                    tracing::debug!(
+                        peer=%peer_data.peer, block=%peer_data.data.header.number, %error,
+                        "Error while streaming headers"
+                    );
+                } else {
+                    tracing::debug!(%error, "Error while streaming headers");
+                }
+            }
+        }
+    }
+
+    /// Syncs the state of the `block` as a snapshot, unless some block already has state.
+    /// An interrupted snapshot is resumed instead, even if it is of an older block.
+    ///
+    /// Blocks older than the snapshot are left without state diffs, so block sync continues
+    /// after the snapshot. Their transactions, receipts and events are synced afterwards, see
+    /// [Self::backfill_bodies].
+    async fn sync_snapshot(&self, block: BlockNumber) -> anyhow::Result<()> {
+        /// Peers which served invalid data are penalized, so retrying gives other peers a
+        /// chance to provide valid data.
+        const MAX_ATTEMPTS: usize = 5;
+
+        let mut attempt = 1;
+        loop {
+            // The progress is reloaded on each attempt, as failed attempts keep the chunks
+            // stored until then.
+            let Some((header, progress)) = snapshot::pending(self.storage.clone(), block)
+                .await
+                .context("Querying snapshot progress")?
+            else {
+                return Ok(());
+            };
+            let block = header.number;
+
+            if attempt == 1 {
+                tracing::info!(%block, "Syncing state snapshot");
+            }
+
+            match snapshot::sync(&self.p2p, self.storage.clone(), &header, progress).await {
                 Ok(()) => {
-                    tracing::info!("Syncing headers complete");
+                    tracing::info!(%block, "State snapshot synced");
+                    return Ok(());
                 }
                 Err(error) => {
-                    if let Some(peer_data) = error.peer_id_and_data() {
-                        // TODO: punish peer.
-                        tracing::debug!(
-                            peer=%peer_data.peer, block=%peer_data.data.header.number, %error,
-                            "Error while streaming headers"
-                        );
-                    } else {
-                        tracing::debug!(%error, "Error while streaming headers");
+                    if let Some(peer) = error.peer() {
+                        self.p2p
+                            .penalize(peer, Misbehaviour::CommitmentMismatch)
+                            .await;
+                    }
+
+                    if attempt == MAX_ATTEMPTS {
+                        return Err(error.into());
                     }
+                    tracing::debug!(%block, %attempt, %error, "Syncing state snapshot failed, retrying");
+                    attempt += 1;
                 }
             }
         }
+    }
+
+    /// Syncs the transactions, receipts and events of the blocks up to the state snapshot, which
+    /// were skipped by [Self::sync_snapshot].
+    async fn backfill_bodies(&self) -> anyhow::Result<()> {
+        use futures::StreamExt;
+        use futures::TryStreamExt;
+
+        /// Blocks are fetched concurrently and persisted in batches of this size.
+        const BATCH: u64 = 64;
+        /// Upper bound on the number of blocks fetched at the same time.
+        const MAX_CONCURRENT_BLOCKS: usize = 8;
+        /// Peers which served invalid data are penalized, so retrying gives other peers a
+        /// chance to provide valid data.
+        const MAX_ATTEMPTS: usize = 5;
+
+        loop {
+            let headers = snapshot::missing_bodies(self.storage.clone(), BATCH)
+                .await
+                .context("Querying blocks skipped by the snapshot")?;
+            let (Some(first), Some(last)) = (headers.first(), headers.last()) else {
+                return Ok(());
+            };
+            let (first, last) = (first.number, last.number);
+
+            let mut attempt = 1;
+            let bodies = loop {
+                let result = futures::stream::iter(&headers)
+                    .map(|header| async move {
+                        blocks::fetch_bodies(&self.p2p, header, self.chain_id)
+                            .await
+                            .map(|bodies| (header.clone(), bodies))
+                    })
+                    .buffered(MAX_CONCURRENT_BLOCKS)
+                    .try_collect::<Vec<_>>()
+                    .await;
+
+                match result {
+                    Ok(bodies) => break bodies,
+                    Err(error) => {
+                        if let Some(peer) = error.peer() {
+                            self.p2p
+                                .penalize(peer, Misbehaviour::CommitmentMismatch)
+                                .await;
+                        }
+
+                        if attempt == MAX_ATTEMPTS {
+                            return Err(error)
+                                .with_context(|| format!("Syncing blocks {first} to {last}"));
+                        }
+                        tracing::debug!(%first, %last, %attempt, %error, "Syncing blocks failed, retrying");
+                        attempt += 1;
+                    }
+                }
+            };
+
+            snapshot::persist_bodies(self.storage.clone(), bodies)
+                .await
+                .context("Persisting transaction data")?;
+
+            tracing::debug!(%first, %last, "Blocks skipped by the snapshot synced");
+        }
+    }
+
+    /// Syncs the transactions, receipts, events, state diff and classes of each block in
+    /// chronological order, from the oldest block without state up to `stop`.
+    ///
+    /// The state of each block is verified against its header's state commitment, so
+    /// headers must have been synced first.
+    async fn sync_blocks(&self, stop: BlockNumber) -> anyhow::Result<()> {
+        /// Peers which served invalid data are penalized, so retrying gives other peers a
+        /// chance to provide valid data.
+        const MAX_ATTEMPTS: usize = 5;
+
+        while let Some((header, parent_state_commitment)) =
+            blocks::next_missing(self.storage.clone(), stop)
+                .await
+                .context("Querying next block without state")?
+        {
+            let mut attempt = 1;
+            loop {
+                let fetched =
+                    blocks::fetch(&self.p2p, &header, parent_state_commitment, self.chain_id).await;
+                let result = match fetched {
+                    Ok(data) => blocks::persist(self.storage.clone(), header.clone(), data).await,
+                    Err(error) => Err(error),
+                };
+
+                if let Err(error) = &result {
+                    if let Some(peer) = error.peer() {
+                        self.p2p
+                            .penalize(peer, Misbehaviour::CommitmentMismatch)
+                            .await;
+                    }
+                }
+
+                match result {
+                    Ok(()) => break,
+                    Err(error) if attempt < MAX_ATTEMPTS => {
+                        tracing::debug!(block=%header.number, %attempt, %error, "Syncing block failed, retrying");
+                        attempt += 1;
+                    }
+                    Err(error) => {
+                        return Err(error)
+                            .with_context(|| format!("Syncing block {}", header.number));
+                    }
+                }
+            }
+
+            tracing::debug!(block=%header.number, "Block synced");
+        }
 
         Ok(())
     }
@@ -319,17 +726,114 @@ impl LocalState {
     }
 }
 
+/// Checks that the peer which announced `head` can serve a valid header for it. Returns `None` if
+/// the header could not be fetched, for example because the peer rate limited us, in which case
+/// the announcement cannot be judged.
+pub(crate) async fn verify_announcement(p2p: &P2PClient, head: Head) -> Option<bool> {
+    let header = match p2p.block_header_from(head.from, head.number).await {
+        Ok(Some(header)) => header,
+        Ok(None) => {
+            tracing::debug!(peer=%head.from, block=%head.number, "Announcing peer does not have the block");
+            return Some(false);
+        }
+        Err(error) => {
+            tracing::debug!(peer=%head.from, block=%head.number, %error, "Fetching announced header failed");
+            return None;
+        }
+    };
+
+    if header.header.hash != head.hash {
+        tracing::debug!(peer=%head.from, block=%head.number, "Announced hash does not match the header");
+        return Some(false);
+    }
+
+    match headers::verify(PeerData::new(head.from, header)).await {
+        Ok(_) => Some(true),
+        Err(error) => {
+            tracing::debug!(peer=%head.from, block=%head.number, %error, "Announced header is invalid");
+            Some(false)
+        }
+    }
+}
+
 /// Rolls back local chain-state until the given anchor point, making it the tip of the local chain. If this is ['None']
 /// then all data will be rolled back.
 async fn rollback_to_anchor(storage: Storage, anchor: Option<BlockNumber>) -> anyhow::Result<()> {
     spawn_blocking(move || {
-        todo!("Rollback storage to anchor point");
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db
+            .transaction_with_behavior(pathfinder_storage::TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        let Some((mut head, _)) = db
+            .block_id(pathfinder_storage::BlockId::Latest)
+            .context("Querying latest header")?
+        else {
+            return Ok(());
+        };
+        let tail = anchor.map_or(BlockNumber::GENESIS, |anchor| anchor + 1);
+
+        while head >= tail {
+            db.purge_block(head)
+                .with_context(|| format!("Purging block {head}"))?;
+
+            if head == BlockNumber::GENESIS {
+                break;
+            }
+            head -= 1;
+        }
+
+        // The L1-L2 pointer can't point beyond the anchor, since the anchor is the latest block
+        // verified by L1.
+        let l1_l2_head = db.l1_l2_pointer().context("Querying L1-L2 head")?;
+        if l1_l2_head.is_some() && l1_l2_head > anchor {
+            db.update_l1_l2_pointer(anchor)
+                .context("Updating L1-L2 head")?;
+        }
+
+        db.commit().context("Committing database transaction")
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+async fn target_header(
+    storage: Storage,
+    target: BlockNumber,
+) -> anyhow::Result<Option<(BlockNumber, BlockHash)>> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+        db.block_id(target.into()).context("Querying target header")
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+async fn latest_header(storage: Storage) -> anyhow::Result<Option<(BlockNumber, BlockHash)>> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+        db.block_id(pathfinder_storage::BlockId::Latest)
+            .context("Querying latest header")
     })
     .await
     .context("Joining blocking task")?
 }
 
-async fn persist_anchor(storage: Storage, anchor: EthereumStateUpdate) -> anyhow::Result<()> {
+/// Persists the anchor and points the L1-L2 pointer at it, or at the target block if the
+/// anchor lies beyond it.
+async fn persist_anchor(
+    storage: Storage,
+    anchor: EthereumStateUpdate,
+    target: Option<BlockNumber>,
+) -> anyhow::Result<()> {
     spawn_blocking(move || {
         let mut db = storage
             .connection()
@@ -338,7 +842,11 @@ async fn persist_anchor(storage: Storage, anchor: EthereumStateUpdate) -> anyhow
         db.upsert_l1_state(&anchor).context("Inserting anchor")?;
         // TODO: this is a bit dodgy, but is used by the sync process. However it destroys
         //       some RPC assumptions which we should be aware of.
-        db.update_l1_l2_pointer(Some(anchor.block_number))
+        let pointer = match target {
+            Some(target) => anchor.block_number.min(target),
+            None => anchor.block_number,
+        };
+        db.update_l1_l2_pointer(Some(pointer))
             .context("Updating L1-L2 pointer")?;
         db.commit().context("Committing database transaction")?;
         Ok(())
@@ -0,0 +1,594 @@
+//! Syncs the block data which is not part of the header i.e. transactions, receipts, events,
+//! state diffs and declared classes.
+//!
+//! Blocks are synced one at a time in chronological order, as the state of each block is
+//! applied on top of its parent's state. Blocks older than a state snapshot have no state
+//! diffs, so only their transactions, receipts and events are synced, see [fetch_bodies].
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use p2p::client::peer_agnostic::Client as P2PClient;
+use p2p::client::types::Receipt as P2PReceipt;
+use p2p::libp2p::PeerId;
+use p2p_proto::class::Class;
+use pathfinder_common::event::Event;
+use pathfinder_common::receipt::Receipt;
+use pathfinder_common::transaction::{Transaction, TransactionVariant};
+use pathfinder_common::{
+    BlockHeader, BlockNumber, CasmHash, ChainId, ClassHash, SierraHash, StateCommitment,
+    StateUpdate, TransactionHash, TransactionIndex,
+};
+use pathfinder_storage::Storage;
+use tokio::task::spawn_blocking;
+
+use crate::p2p_network::client::conv::{
+    cairo_hash_and_def_from_dto, sierra_defs_and_hashes_from_dto,
+};
+use crate::state::block_hash::{
+    calculate_event_commitment, calculate_transaction_commitment, verify_transaction_hashes,
+    TransactionCommitmentFinalHashType,
+};
+
+/// The data of a single block, verified against its header.
+///
+/// The state diff can only be verified once it has been applied, see [persist].
+pub(super) struct BlockData {
+    transactions: Vec<(Transaction, Receipt)>,
+    state_update: StateUpdate,
+    cairo_definitions: Vec<(ClassHash, Vec<u8>)>,
+    sierra_definitions: Vec<(SierraHash, Vec<u8>, CasmHash, Vec<u8>)>,
+    /// The peers which served the state diff and the classes, which are blamed if the
+    /// state commitment does not match. Peers which served nothing at all are not blamed,
+    /// as they might simply not have the block.
+    state_diff_peer: Option<PeerId>,
+    classes_peer: Option<PeerId>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum BlockSyncError {
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+    /// The data served by the peer failed verification.
+    #[error("Invalid data from peer {peer}: {error:#}")]
+    BadData { peer: PeerId, error: anyhow::Error },
+}
+
+impl BlockSyncError {
+    /// The peer which served invalid data, if this error is its fault.
+    pub fn peer(&self) -> Option<PeerId> {
+        match self {
+            BlockSyncError::Other(_) => None,
+            BlockSyncError::BadData { peer, .. } => Some(*peer),
+        }
+    }
+
+    /// Blames the peer, if any, for the error.
+    fn blame(peer: Option<PeerId>) -> impl FnOnce(anyhow::Error) -> Self {
+        move |error| match peer {
+            Some(peer) => Self::BadData { peer, error },
+            None => Self::Other(error),
+        }
+    }
+}
+
+/// Returns the header of the oldest block whose state has not been synced yet along with the
+/// state commitment of its parent, unless that block is newer than `stop` or its header is
+/// missing.
+pub(super) async fn next_missing(
+    storage: Storage,
+    stop: BlockNumber,
+) -> anyhow::Result<Option<(BlockHeader, StateCommitment)>> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+
+        let latest = db
+            .latest_storage_root_block()
+            .context("Querying latest block with state")?;
+        let next = latest.map(|latest| latest + 1).unwrap_or_default();
+
+        if next > stop {
+            return Ok(None);
+        }
+
+        let parent_state_commitment = match latest {
+            Some(latest) => {
+                db.block_header(latest.into())
+                    .context("Querying parent block header")?
+                    .context("Parent block header missing")?
+                    .state_commitment
+            }
+            None => StateCommitment::ZERO,
+        };
+
+        Ok(db
+            .block_header(next.into())
+            .context("Querying block header")?
+            .map(|header| (header, parent_state_commitment)))
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Fetches the data of a block from peers and verifies it against the block's header.
+///
+/// Peers which respond without a state diff or classes although the block has them are
+/// skipped, which is the case if the state commitment differs from the parent's, or the block
+/// declares classes.
+pub(super) async fn fetch(
+    p2p: &P2PClient,
+    header: &BlockHeader,
+    parent_state_commitment: StateCommitment,
+    chain_id: ChainId,
+) -> Result<BlockData, BlockSyncError> {
+    let block = header.number;
+
+    let transactions = fetch_bodies(p2p, header, chain_id).await?;
+    let state_update = p2p
+        .block_state_diff(block, header.state_commitment == parent_state_commitment)
+        .await
+        .context("Fetching state diff")?;
+    let declares = transactions.iter().any(|(transaction, _)| {
+        matches!(
+            transaction.variant,
+            TransactionVariant::DeclareV0(_)
+                | TransactionVariant::DeclareV1(_)
+                | TransactionVariant::DeclareV2(_)
+                | TransactionVariant::DeclareV3(_)
+        )
+    });
+    let classes = p2p
+        .block_classes(block, !declares)
+        .await
+        .context("Fetching classes")?;
+
+    let header = header.clone();
+    spawn_blocking(move || {
+        let state_diff_peer = (state_update.data.change_count() > 0).then_some(state_update.peer);
+        let classes_peer = (!classes.data.is_empty()).then_some(classes.peer);
+        let state_update = state_update
+            .data
+            .with_block_hash(header.hash)
+            .with_state_commitment(header.state_commitment);
+        let (state_update, cairo_definitions, sierra_definitions) =
+            declare_classes(classes.data, &transactions, state_update)
+                .context("Verifying classes")
+                .map_err(BlockSyncError::blame(classes_peer))?;
+
+        Ok(BlockData {
+            transactions,
+            state_update,
+            cairo_definitions,
+            sierra_definitions,
+            state_diff_peer,
+            classes_peer,
+        })
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Fetches the transactions of a block along with their receipts and events from peers, and
+/// verifies them against the block's header.
+pub(super) async fn fetch_bodies(
+    p2p: &P2PClient,
+    header: &BlockHeader,
+    chain_id: ChainId,
+) -> Result<Vec<(Transaction, Receipt)>, BlockSyncError> {
+    let block = header.number;
+
+    // Peers which don't have the block respond with no data at all, so there is no point
+    // in asking for data the header says does not exist.
+    let (transactions, receipts) = match header.transaction_count {
+        0 => (None, None),
+        count => {
+            let transactions = p2p
+                .block_transactions(block, count)
+                .await
+                .context("Fetching transactions")?;
+            let receipts = p2p
+                .block_receipts(block, count)
+                .await
+                .context("Fetching receipts")?;
+            (Some(transactions), Some(receipts))
+        }
+    };
+    let events = match header.event_count {
+        0 => None,
+        count => Some(
+            p2p.block_events(block, count)
+                .await
+                .context("Fetching events")?,
+        ),
+    };
+
+    let header = header.clone();
+    spawn_blocking(move || {
+        let transactions_peer = transactions.as_ref().map(|x| x.peer);
+        let receipts_peer = receipts.as_ref().map(|x| x.peer);
+        let events_peer = events.as_ref().map(|x| x.peer);
+        let transactions = transactions.map(|x| x.data).unwrap_or_default();
+        let receipts = receipts.map(|x| x.data).unwrap_or_default();
+        let events = events.map(|x| x.data).unwrap_or_default();
+
+        let receipts = assemble_receipts(&transactions, receipts, events)
+            .context("Verifying receipts")
+            .map_err(BlockSyncError::blame(receipts_peer))?;
+
+        verify_transactions(&transactions, &receipts, &header, chain_id)
+            .context("Verifying transactions")
+            .map_err(BlockSyncError::blame(transactions_peer))?;
+
+        let event_commitment =
+            calculate_event_commitment(&receipts).context("Calculating event commitment")?;
+        if event_commitment != header.event_commitment {
+            return Err(BlockSyncError::blame(events_peer)(anyhow::anyhow!(
+                "Event commitment mismatch, computed {} instead of {}",
+                event_commitment,
+                header.event_commitment
+            )));
+        }
+
+        Ok(transactions.into_iter().zip(receipts).collect())
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Applies the block's state diff, verifies the resulting state commitment against the
+/// header and persists the block's data.
+pub(super) async fn persist(
+    storage: Storage,
+    header: BlockHeader,
+    data: BlockData,
+) -> Result<(), BlockSyncError> {
+    spawn_blocking(move || -> Result<(), BlockSyncError> {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db.transaction().context("Creating database transaction")?;
+
+        let BlockData {
+            transactions,
+            state_update,
+            cairo_definitions,
+            sierra_definitions,
+            state_diff_peer,
+            classes_peer,
+        } = data;
+
+        for (class_hash, definition) in &cairo_definitions {
+            tx.insert_cairo_class(*class_hash, definition)
+                .context("Inserting cairo class")?;
+        }
+        for (sierra_hash, sierra_definition, casm_hash, casm_definition) in &sierra_definitions {
+            tx.insert_sierra_class(sierra_hash, sierra_definition, casm_hash, casm_definition)
+                .context("Inserting sierra class")?;
+        }
+
+        // Peers only serve a cairo class for the block which declared it first, so classes
+        // declared again must already be stored.
+        let redeclared = state_update
+            .declared_cairo_classes
+            .iter()
+            .filter(|class_hash| !cairo_definitions.iter().any(|(x, _)| x == *class_hash))
+            .copied()
+            .collect::<Vec<_>>();
+        let exist = tx
+            .class_definitions_exist(&redeclared)
+            .context("Querying redeclared classes")?;
+        if !exist.into_iter().all(|x| x) {
+            return Err(BlockSyncError::blame(classes_peer)(anyhow::anyhow!(
+                "Definitions of declared classes are missing"
+            )));
+        }
+
+        let (storage_commitment, class_commitment) = crate::state::update_starknet_state(
+            &tx,
+            &state_update,
+            false,
+            header.number,
+            storage.clone(),
+            &HashSet::new(),
+        )
+        .context("Updating Starknet state")?;
+        let state_commitment = StateCommitment::calculate(storage_commitment, class_commitment);
+        if state_commitment != header.state_commitment {
+            return Err(BlockSyncError::blame(state_diff_peer)(anyhow::anyhow!(
+                "State commitment mismatch, computed {} instead of {}",
+                state_commitment,
+                header.state_commitment
+            )));
+        }
+
+        tx.insert_transaction_data(header.hash, header.number, &transactions)
+            .context("Inserting transaction data")?;
+        tx.insert_state_update(header.number, &state_update)
+            .context("Inserting state update")?;
+        tx.update_block_header_commitments(header.number, storage_commitment, class_commitment)
+            .context("Updating block header commitments")?;
+
+        tx.commit().context("Committing database transaction")?;
+        Ok(())
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Verifies the transaction hashes and the transaction commitment.
+fn verify_transactions(
+    transactions: &[Transaction],
+    receipts: &[Receipt],
+    header: &BlockHeader,
+    chain_id: ChainId,
+) -> anyhow::Result<()> {
+    verify_transaction_hashes(header.number, transactions, receipts, chain_id)?;
+
+    let final_hash_type =
+        TransactionCommitmentFinalHashType::for_version(&header.starknet_version)?;
+    let transaction_commitment = calculate_transaction_commitment(transactions, final_hash_type)
+        .context("Calculating transaction commitment")?;
+    anyhow::ensure!(
+        transaction_commitment == header.transaction_commitment,
+        "Transaction commitment mismatch, computed {} instead of {}",
+        transaction_commitment,
+        header.transaction_commitment
+    );
+
+    Ok(())
+}
+
+/// Combines the receipts and events, which peers serve separately, into the receipts of
+/// the block's transactions.
+fn assemble_receipts(
+    transactions: &[Transaction],
+    receipts: Vec<P2PReceipt>,
+    events: Vec<(TransactionHash, Event)>,
+) -> anyhow::Result<Vec<Receipt>> {
+    anyhow::ensure!(
+        transactions.len() == receipts.len(),
+        "Expected {} receipts but got {}",
+        transactions.len(),
+        receipts.len()
+    );
+
+    let mut events_by_transaction = HashMap::<_, Vec<_>>::new();
+    for (transaction_hash, event) in events {
+        events_by_transaction
+            .entry(transaction_hash)
+            .or_default()
+            .push(event);
+    }
+
+    let receipts = transactions
+        .iter()
+        .zip(receipts)
+        .enumerate()
+        .map(|(i, (transaction, receipt))| {
+            anyhow::ensure!(
+                receipt.transaction_hash == transaction.hash,
+                "Receipt at index {i} belongs to transaction {} instead of {}",
+                receipt.transaction_hash,
+                transaction.hash
+            );
+
+            Ok(Receipt {
+                actual_fee: receipt.actual_fee,
+                events: events_by_transaction
+                    .remove(&transaction.hash)
+                    .unwrap_or_default(),
+                execution_resources: receipt.execution_resources,
+                l2_to_l1_messages: receipt.l2_to_l1_messages,
+                execution_status: receipt.execution_status,
+                transaction_hash: receipt.transaction_hash,
+                transaction_index: TransactionIndex::new_or_panic(i as u64),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if let Some(transaction_hash) = events_by_transaction.keys().next() {
+        anyhow::bail!("Received events of unknown transaction {transaction_hash}");
+    }
+
+    Ok(receipts)
+}
+
+/// Verifies the class hashes and checks that the classes match the block's declare
+/// transactions. The declarations are added to the state update.
+///
+/// Cairo classes are also accepted if the state diff deploys them or replaces a contract's
+/// class with them, but classes the block does not refer to at all are rejected.
+///
+/// Returns the state update along with the cairo and sierra class definitions.
+#[allow(clippy::type_complexity)]
+fn declare_classes(
+    classes: Vec<Class>,
+    transactions: &[(Transaction, Receipt)],
+    mut state_update: StateUpdate,
+) -> anyhow::Result<(
+    StateUpdate,
+    Vec<(ClassHash, Vec<u8>)>,
+    Vec<(SierraHash, Vec<u8>, CasmHash, Vec<u8>)>,
+)> {
+    // The compiled class hash is taken from the transaction, as the casm served by a peer
+    // may have been compiled locally.
+    let mut declared_sierra = HashMap::new();
+    // Before Starknet 0.9 classes were declared implicitly by deploying them, so a cairo
+    // class does not need a matching declare transaction.
+    let mut referenced_cairo = state_update
+        .contract_updates
+        .values()
+        .filter_map(|update| update.class.as_ref().map(|class| class.class_hash()))
+        .collect::<HashSet<_>>();
+    for (transaction, _) in transactions {
+        match &transaction.variant {
+            TransactionVariant::DeclareV0(x) | TransactionVariant::DeclareV1(x) => {
+                state_update = state_update.with_declared_cairo_class(x.class_hash);
+                referenced_cairo.insert(x.class_hash);
+            }
+            TransactionVariant::DeclareV2(x) => {
+                declared_sierra.insert(SierraHash(x.class_hash.0), x.compiled_class_hash);
+            }
+            TransactionVariant::DeclareV3(x) => {
+                declared_sierra.insert(SierraHash(x.class_hash.0), x.compiled_class_hash);
+            }
+            _ => {}
+        }
+    }
+
+    let mut cairo_definitions = Vec::new();
+    let mut sierra_definitions = Vec::new();
+
+    for class in classes {
+        match class {
+            Class::Cairo0 {
+                class, class_hash, ..
+            } => {
+                anyhow::ensure!(
+                    referenced_cairo.contains(&ClassHash(class_hash.0)),
+                    "Cairo class {} is neither declared nor deployed in this block",
+                    class_hash.0
+                );
+
+                let (computed, definition) = cairo_hash_and_def_from_dto(class)?;
+                anyhow::ensure!(
+                    computed.0 == class_hash.0,
+                    "Cairo class hash mismatch, computed {} instead of {}",
+                    computed,
+                    class_hash.0
+                );
+
+                state_update = state_update.with_declared_cairo_class(computed);
+                cairo_definitions.push((computed, definition));
+            }
+            Class::Cairo1 {
+                class, class_hash, ..
+            } => {
+                let (computed, definition, _, casm_definition) =
+                    sierra_defs_and_hashes_from_dto(class)?;
+                anyhow::ensure!(
+                    computed.0 == class_hash.0,
+                    "Sierra class hash mismatch, computed {} instead of {}",
+                    computed,
+                    class_hash.0
+                );
+
+                let casm_hash = declared_sierra
+                    .remove(&computed)
+                    .with_context(|| format!("Sierra class {computed} was not declared"))?;
+
+                state_update = state_update.with_declared_sierra_class(computed, casm_hash);
+                sierra_definitions.push((computed, definition, casm_hash, casm_definition));
+            }
+        }
+    }
+
+    if let Some(sierra_hash) = declared_sierra.keys().next() {
+        anyhow::bail!("Missing definition of sierra class {sierra_hash}");
+    }
+
+    Ok((state_update, cairo_definitions, sierra_definitions))
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::receipt::ExecutionStatus;
+    use pathfinder_common::EventKey;
+
+    use super::*;
+
+    fn transaction(hash: TransactionHash) -> Transaction {
+        Transaction {
+            hash,
+            variant: TransactionVariant::InvokeV0(Default::default()),
+        }
+    }
+
+    fn receipt(transaction_hash: TransactionHash) -> P2PReceipt {
+        P2PReceipt {
+            transaction_hash,
+            ..Default::default()
+        }
+    }
+
+    fn event(key: EventKey) -> Event {
+        Event {
+            data: vec![],
+            from_address: contract_address!("0x1234"),
+            keys: vec![key],
+        }
+    }
+
+    #[test]
+    fn events_are_grouped_by_transaction() {
+        let transactions = vec![
+            transaction(transaction_hash!("0x1")),
+            transaction(transaction_hash!("0x2")),
+            transaction(transaction_hash!("0x3")),
+        ];
+        let receipts = transactions.iter().map(|x| receipt(x.hash)).collect();
+        let events = vec![
+            (transaction_hash!("0x1"), event(event_key!("0xa"))),
+            (transaction_hash!("0x1"), event(event_key!("0xb"))),
+            (transaction_hash!("0x3"), event(event_key!("0xc"))),
+        ];
+
+        let receipts = assemble_receipts(&transactions, receipts, events).unwrap();
+
+        let events = receipts
+            .iter()
+            .map(|x| x.events.iter().map(|e| e.keys[0]).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                vec![event_key!("0xa"), event_key!("0xb")],
+                vec![],
+                vec![event_key!("0xc")],
+            ]
+        );
+        let indices = receipts
+            .iter()
+            .map(|x| x.transaction_index.get())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert!(receipts
+            .iter()
+            .all(|x| x.execution_status == ExecutionStatus::Succeeded));
+    }
+
+    #[test]
+    fn receipt_of_other_transaction_is_rejected() {
+        let transactions = vec![transaction(transaction_hash!("0x1"))];
+        let receipts = vec![receipt(transaction_hash!("0x2"))];
+
+        assemble_receipts(&transactions, receipts, vec![]).unwrap_err();
+    }
+
+    #[test]
+    fn unreferenced_cairo_class_is_rejected() {
+        use fake::{Fake, Faker};
+        use p2p_proto::common::Hash;
+
+        let class = Class::Cairo0 {
+            class: Faker.fake(),
+            domain: 0,
+            class_hash: Hash(class_hash!("0x1234").0),
+        };
+
+        let error = declare_classes(vec![class], &[], StateUpdate::default()).unwrap_err();
+        assert!(error.to_string().contains("neither declared nor deployed"));
+    }
+
+    #[test]
+    fn events_of_unknown_transaction_are_rejected() {
+        let transactions = vec![transaction(transaction_hash!("0x1"))];
+        let receipts = vec![receipt(transaction_hash!("0x1"))];
+        let events = vec![(transaction_hash!("0x2"), event(event_key!("0xa")))];
+
+        assemble_receipts(&transactions, receipts, events).unwrap_err();
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code, unused_variables)]
 use anyhow::Context;
-use p2p::{client::types::SignedBlockHeader, PeerData};
+use p2p::{client::types::SignedBlockHeader, Misbehaviour, PeerData};
 use pathfinder_common::{BlockHash, BlockNumber, ClassCommitment, StorageCommitment};
 use pathfinder_storage::Storage;
 use tokio::task::spawn_blocking;
@@ -94,6 +94,17 @@ impl HeaderSyncError {
             HeaderSyncError::Discontinuity(x) => Some(x),
         }
     }
+
+    /// How the peer which served the header misbehaved, if this error is its fault.
+    pub fn misbehaviour(&self) -> Option<Misbehaviour> {
+        match self {
+            HeaderSyncError::DatabaseError(_) => None,
+            HeaderSyncError::BadSignature(_) => Some(Misbehaviour::InvalidSignature),
+            HeaderSyncError::BadBlockHash(_) | HeaderSyncError::Discontinuity(_) => {
+                Some(Misbehaviour::CommitmentMismatch)
+            }
+        }
+    }
 }
 
 /// Ensures the header block ID matches expectations.
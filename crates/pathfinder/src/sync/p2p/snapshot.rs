@@ -0,0 +1,799 @@
+//! Syncs the state of a single block from peers as a snapshot of its tries, instead of
+//! applying every state diff since genesis.
+//!
+//! The contracts, storage and classes tries are fetched in chunks of consecutive leaves. Each
+//! chunk is verified against its trie's root using the chunk's range proof, and the roots are
+//! verified against the block's state commitment. The snapshot is therefore as trustworthy as
+//! the block's header.
+//!
+//! Each verified chunk is stored right away, along with the progress made, so that an
+//! interrupted snapshot sync resumes where it left off. The tries are built up chunk by chunk,
+//! and only become the state of the block once all of them are complete.
+//!
+//! Cairo 0 classes are not part of the classes trie. Their definitions are fetched by the class
+//! hashes of the contracts once the tries are complete, and are verified by hashing them.
+//!
+//! Blocks older than the snapshot have no state diffs, so these are not synced. The transactions,
+//! receipts and events of these blocks are synced once the snapshot is complete, see
+//! [missing_bodies].
+use anyhow::Context;
+use bitvec::prelude::{BitVec, Msb0};
+use futures::{StreamExt, TryStreamExt};
+use p2p::client::peer_agnostic::Client as P2PClient;
+use p2p::client::types::TryFromDto;
+use p2p::libp2p::PeerId;
+use p2p_proto::class::Class;
+use p2p_proto::snapshot::{ClassRange, ContractRange, ContractStorage, PatriciaRangeProof};
+use pathfinder_common::hash::{FeltHash, PedersenHash, PoseidonHash};
+use pathfinder_common::receipt::Receipt;
+use pathfinder_common::transaction::Transaction;
+use pathfinder_common::trie::TrieNode;
+use pathfinder_common::{
+    felt, BlockHeader, BlockNumber, CasmHash, ClassCommitment, ClassHash, ContractAddress,
+    ContractNonce, ContractRoot, ContractStateHash, SierraHash, StateCommitment, StorageAddress,
+    StorageCommitment, StorageValue,
+};
+use pathfinder_crypto::Felt;
+use pathfinder_merkle_tree::contract_state::calculate_contract_state_hash;
+use pathfinder_merkle_tree::range::verify_range;
+use pathfinder_merkle_tree::{ClassCommitmentTree, ContractsStorageTree, StorageCommitmentTree};
+use pathfinder_storage::{SnapshotProgress, SnapshotStorage, Storage, TransactionBehavior};
+use tokio::task::spawn_blocking;
+
+use super::blocks::BlockSyncError;
+use crate::p2p_network::client::conv::{
+    cairo_hash_and_def_from_dto, sierra_defs_and_hashes_from_dto,
+};
+
+/// The largest key of a trie, ranges are always requested up to it.
+const MAX_KEY: Felt = felt!("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+/// Upper bound on the number of contracts whose storage is fetched at the same time.
+const MAX_CONCURRENT_CONTRACTS: usize = 16;
+/// Upper bound on the number of Cairo 0 classes requested at once.
+const MAX_CAIRO_CLASSES_PER_REQUEST: usize = 10;
+
+struct ContractLeaf {
+    address: ContractAddress,
+    class: ClassHash,
+    nonce: ContractNonce,
+    root: ContractRoot,
+    state_hash: ContractStateHash,
+}
+
+struct SierraClass {
+    hash: SierraHash,
+    definition: Vec<u8>,
+    casm_hash: CasmHash,
+    casm_definition: Vec<u8>,
+}
+
+/// Returns the header of the block whose state is being synced as a snapshot, along with the
+/// progress made so far. A snapshot of `block` is started if no block has any state yet.
+pub(super) async fn pending(
+    storage: Storage,
+    block: BlockNumber,
+) -> anyhow::Result<Option<(BlockHeader, SnapshotProgress)>> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        let has_state = db
+            .latest_storage_root_block()
+            .context("Querying latest block with state")?
+            .is_some();
+        if has_state {
+            return Ok(None);
+        }
+
+        // An interrupted snapshot is resumed, even if a newer block could be synced instead.
+        let progress = db
+            .snapshot_progress()
+            .context("Querying snapshot progress")?
+            .unwrap_or_else(|| SnapshotProgress::new(block));
+        let Some(header) = db
+            .block_header(progress.block.into())
+            .context("Querying block header")?
+        else {
+            return Ok(None);
+        };
+
+        db.upsert_snapshot_progress(&progress)
+            .context("Persisting snapshot progress")?;
+        db.commit().context("Committing database transaction")?;
+
+        Ok(Some((header, progress)))
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Fetches the state of the block from peers, continuing from the progress made so far, and
+/// makes it the state of the block once it is complete.
+pub(super) async fn sync(
+    p2p: &P2PClient,
+    storage: Storage,
+    header: &BlockHeader,
+    mut progress: SnapshotProgress,
+) -> Result<(), BlockSyncError> {
+    let block = header.number;
+
+    while let Some(start) = progress.next_contract {
+        let chunks = p2p
+            .contract_range(block, start, ContractAddress(MAX_KEY))
+            .await
+            .context("Fetching contract range")?;
+        let peer = chunks.peer;
+
+        for chunk in chunks.data {
+            progress =
+                persist_contracts(storage.clone(), header.clone(), progress, chunk, peer).await?;
+            if progress.next_contract.is_none() {
+                break;
+            }
+        }
+        // The peer stopped before completing the range, continue with another request.
+    }
+
+    // Contracts with storage are queued as the contracts are stored.
+    let contracts = spawn_blocking({
+        let storage = storage.clone();
+        move || {
+            let mut db = storage
+                .connection()
+                .context("Creating database connection")?;
+            let db = db.transaction().context("Creating database transaction")?;
+            db.snapshot_storage()
+                .context("Querying contracts with missing storage")
+        }
+    })
+    .await
+    .context("Joining blocking task")??;
+    futures::stream::iter(contracts)
+        .map(|contract| sync_storage(p2p, storage.clone(), block, contract))
+        .buffer_unordered(MAX_CONCURRENT_CONTRACTS)
+        .try_collect::<()>()
+        .await?;
+
+    if let Some((_, ClassCommitment::ZERO)) = progress.commitments {
+        progress.next_class = None;
+    }
+    while let Some(start) = progress.next_class {
+        let chunks = p2p
+            .class_range(block, start, ClassHash(MAX_KEY))
+            .await
+            .context("Fetching class range")?;
+        let peer = chunks.peer;
+
+        for chunk in chunks.data {
+            progress = persist_classes(storage.clone(), block, progress, chunk, peer).await?;
+            if progress.next_class.is_none() {
+                break;
+            }
+        }
+    }
+
+    sync_cairo_classes(p2p, storage.clone(), block).await?;
+
+    finish(storage, block, progress).await?;
+
+    Ok(())
+}
+
+/// Verifies a chunk of the contracts trie and stores it, along with the progress made. Contracts
+/// with storage are queued for [sync_storage].
+async fn persist_contracts(
+    storage: Storage,
+    header: BlockHeader,
+    mut progress: SnapshotProgress,
+    chunk: ContractRange,
+    peer: PeerId,
+) -> Result<SnapshotProgress, BlockSyncError> {
+    spawn_blocking(move || {
+        let storage_commitment = StorageCommitment(chunk.contracts_root.0);
+        let class_commitment = ClassCommitment(chunk.classes_root.0);
+        let state_commitment = StateCommitment::calculate(storage_commitment, class_commitment);
+        if state_commitment != header.state_commitment {
+            return Err(BlockSyncError::BadData {
+                peer,
+                error: anyhow::anyhow!(
+                    "State commitment mismatch, computed {} instead of {}",
+                    state_commitment,
+                    header.state_commitment
+                ),
+            });
+        }
+
+        let start = progress.next_contract.unwrap_or_default();
+        let (leaves, next) = verify_contracts(storage_commitment, start, chunk)
+            .context("Verifying contract range")
+            .map_err(|error| BlockSyncError::BadData { peer, error })?;
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        let contracts = leaves
+            .iter()
+            .map(|x| (x.address, x.class, x.nonce, x.state_hash))
+            .collect::<Vec<_>>();
+        tx.insert_snapshot_contracts(header.number, &contracts)
+            .context("Inserting contracts")?;
+
+        let mut trie =
+            StorageCommitmentTree::load_root(&tx, header.number, progress.storage_root_index);
+        for leaf in &leaves {
+            trie.set(leaf.address, leaf.state_hash)
+                .context("Updating contracts trie")?;
+
+            if leaf.root != ContractRoot::ZERO {
+                tx.upsert_snapshot_storage(&SnapshotStorage {
+                    contract: leaf.address,
+                    root: leaf.root,
+                    next_key: StorageAddress::ZERO,
+                    root_index: None,
+                })
+                .context("Queuing contract storage")?;
+            }
+        }
+        let (root, nodes) = trie.commit().context("Committing contracts trie")?;
+        if !nodes.is_empty() {
+            let index = tx
+                .insert_storage_trie(root, &nodes)
+                .context("Persisting contracts trie")?;
+            progress.storage_root_index = Some(index);
+        }
+        if next.is_none() && root != storage_commitment {
+            return Err(anyhow::anyhow!(
+                "Contracts trie mismatch, computed {} instead of {}",
+                root,
+                storage_commitment
+            )
+            .into());
+        }
+
+        progress.commitments = Some((storage_commitment, class_commitment));
+        progress.next_contract = next.map(ContractAddress);
+        tx.upsert_snapshot_progress(&progress)
+            .context("Persisting snapshot progress")?;
+        tx.commit().context("Committing database transaction")?;
+
+        Ok(progress)
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+fn verify_contracts(
+    root: StorageCommitment,
+    start: ContractAddress,
+    chunk: ContractRange,
+) -> anyhow::Result<(Vec<ContractLeaf>, Option<Felt>)> {
+    let leaves = chunk
+        .state
+        .into_iter()
+        .map(|x| {
+            let class = ClassHash(x.class.0);
+            let nonce = ContractNonce(x.nonce);
+            let root = ContractRoot(x.storage.0);
+            ContractLeaf {
+                address: ContractAddress(x.address.0),
+                class,
+                nonce,
+                root,
+                state_hash: calculate_contract_state_hash(class, root, nonce),
+            }
+        })
+        .collect::<Vec<_>>();
+    let hashes = leaves
+        .iter()
+        .map(|x| Ok((key(x.address.0)?, x.state_hash.0)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let next = verify_chunk::<PedersenHash>(root.0, start.0, &hashes, chunk.proof)?;
+    Ok((leaves, next))
+}
+
+/// Fetches the storage of a contract, storing each verified chunk along with the progress made.
+async fn sync_storage(
+    p2p: &P2PClient,
+    storage: Storage,
+    block: BlockNumber,
+    mut contract: SnapshotStorage,
+) -> Result<(), BlockSyncError> {
+    loop {
+        let chunks = p2p
+            .contract_storage(
+                block,
+                contract.contract,
+                contract.next_key,
+                StorageAddress(MAX_KEY),
+            )
+            .await
+            .with_context(|| format!("Fetching storage of contract {}", contract.contract))?;
+        let peer = chunks.peer;
+
+        for chunk in chunks.data {
+            match persist_storage(storage.clone(), block, contract, chunk, peer).await? {
+                Some(remaining) => contract = remaining,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Verifies a chunk of a contract's storage trie and stores it, along with the progress made.
+/// Returns the contract's remaining storage, unless it is complete.
+async fn persist_storage(
+    storage: Storage,
+    block: BlockNumber,
+    mut contract: SnapshotStorage,
+    chunk: ContractStorage,
+    peer: PeerId,
+) -> Result<Option<SnapshotStorage>, BlockSyncError> {
+    spawn_blocking(move || {
+        let address = contract.contract;
+        let next = chunk
+            .key_value
+            .iter()
+            .map(|x| Ok((key(x.key)?, x.value)))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .and_then(|leaves| {
+                verify_chunk::<PedersenHash>(
+                    contract.root.0,
+                    contract.next_key.0,
+                    &leaves,
+                    chunk.proof,
+                )
+            })
+            .with_context(|| format!("Verifying storage of contract {address}"))
+            .map_err(|error| BlockSyncError::BadData { peer, error })?;
+        let values = chunk
+            .key_value
+            .into_iter()
+            .map(|x| (StorageAddress(x.key), StorageValue(x.value)))
+            .collect::<Vec<_>>();
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        tx.insert_snapshot_storage(block, address, &values)
+            .context("Inserting storage")?;
+
+        let mut trie = ContractsStorageTree::load_root(&tx, address, block, contract.root_index);
+        for (key, value) in &values {
+            trie.set(*key, *value).context("Updating storage trie")?;
+        }
+        let (root, nodes) = trie.commit().context("Committing storage trie")?;
+        if !nodes.is_empty() {
+            let index = tx
+                .insert_contract_trie(root, &nodes)
+                .context("Persisting storage trie")?;
+            contract.root_index = Some(index);
+        }
+
+        let remaining = match next {
+            Some(next) => {
+                contract.next_key = StorageAddress(next);
+                tx.upsert_snapshot_storage(&contract)
+                    .context("Persisting storage progress")?;
+                Some(contract)
+            }
+            None if root != contract.root => {
+                return Err(anyhow::anyhow!(
+                    "Storage trie mismatch of contract {}, computed {} instead of {}",
+                    address,
+                    root,
+                    contract.root
+                )
+                .into());
+            }
+            None => {
+                tx.insert_contract_root(block, address, contract.root_index)
+                    .context("Inserting storage root")?;
+                tx.delete_snapshot_storage(address)
+                    .context("Deleting storage progress")?;
+                None
+            }
+        };
+        tx.commit().context("Committing database transaction")?;
+
+        Ok(remaining)
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Verifies a chunk of the classes trie and stores its Sierra classes, along with their
+/// compiled classes and the progress made.
+async fn persist_classes(
+    storage: Storage,
+    block: BlockNumber,
+    mut progress: SnapshotProgress,
+    chunk: ClassRange,
+    peer: PeerId,
+) -> Result<SnapshotProgress, BlockSyncError> {
+    spawn_blocking(move || {
+        let (_, class_commitment) = progress
+            .commitments
+            .context("Classes trie root is unknown")?;
+        let start = progress.next_class.unwrap_or_default();
+        let (classes, next) = verify_classes(class_commitment, start, chunk)
+            .context("Verifying class range")
+            .map_err(|error| BlockSyncError::BadData { peer, error })?;
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        let mut leaves = Vec::new();
+        for class in &classes {
+            tx.insert_sierra_class(
+                &class.hash,
+                &class.definition,
+                &class.casm_hash,
+                &class.casm_definition,
+            )
+            .context("Inserting sierra class")?;
+            let leaf = pathfinder_common::calculate_class_commitment_leaf_hash(class.casm_hash);
+            leaves.push((class.hash, class.casm_hash, leaf));
+        }
+        tx.insert_snapshot_classes(block, &leaves)
+            .context("Inserting classes")?;
+
+        let mut trie = ClassCommitmentTree::load_root(&tx, block, progress.class_root_index);
+        for (sierra_hash, _, leaf) in &leaves {
+            trie.set(*sierra_hash, *leaf)
+                .context("Updating classes trie")?;
+        }
+        let (root, nodes) = trie.commit().context("Committing classes trie")?;
+        if !nodes.is_empty() {
+            let index = tx
+                .insert_class_trie(root, &nodes)
+                .context("Persisting classes trie")?;
+            progress.class_root_index = Some(index);
+        }
+        if next.is_none() && root != class_commitment {
+            return Err(anyhow::anyhow!(
+                "Classes trie mismatch, computed {} instead of {}",
+                root,
+                class_commitment
+            )
+            .into());
+        }
+
+        progress.next_class = next.map(ClassHash);
+        tx.upsert_snapshot_progress(&progress)
+            .context("Persisting snapshot progress")?;
+        tx.commit().context("Committing database transaction")?;
+
+        Ok(progress)
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+fn verify_classes(
+    root: ClassCommitment,
+    start: ClassHash,
+    chunk: ClassRange,
+) -> anyhow::Result<(Vec<SierraClass>, Option<Felt>)> {
+    anyhow::ensure!(
+        chunk.classes.len() == chunk.compiled_class_hashes.len(),
+        "Expected {} compiled class hashes but got {}",
+        chunk.classes.len(),
+        chunk.compiled_class_hashes.len()
+    );
+
+    // The compiled class hash is taken from the peer as the casm it serves may have been
+    // compiled locally. It is verified as part of the leaf.
+    let mut classes = Vec::new();
+    let mut leaves = Vec::new();
+    for (class, casm_hash) in chunk.classes.into_iter().zip(chunk.compiled_class_hashes) {
+        let Class::Cairo1 { class, .. } = class else {
+            anyhow::bail!("Cairo 0 classes are not part of the classes trie");
+        };
+        let (hash, definition, _, casm_definition) = sierra_defs_and_hashes_from_dto(class)?;
+        let casm_hash = CasmHash(casm_hash.0);
+
+        let leaf = pathfinder_common::calculate_class_commitment_leaf_hash(casm_hash);
+        leaves.push((key(hash.0)?, leaf.0));
+        classes.push(SierraClass {
+            hash,
+            definition,
+            casm_hash,
+            casm_definition,
+        });
+    }
+
+    let next = verify_chunk::<PoseidonHash>(root.0, start.0, &leaves, chunk.proof)?;
+    Ok((classes, next))
+}
+
+/// Fetches the definitions of the Cairo 0 classes of the stored contracts. Each stored batch
+/// leaves fewer classes missing, so this resumes where it left off.
+async fn sync_cairo_classes(
+    p2p: &P2PClient,
+    storage: Storage,
+    block: BlockNumber,
+) -> Result<(), BlockSyncError> {
+    loop {
+        let missing = spawn_blocking({
+            let storage = storage.clone();
+            move || {
+                let mut db = storage
+                    .connection()
+                    .context("Creating database connection")?;
+                let db = db.transaction().context("Creating database transaction")?;
+                db.snapshot_missing_classes(block)
+                    .context("Querying missing classes")
+            }
+        })
+        .await
+        .context("Joining blocking task")??;
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        // Peers may serve only some of the classes, those left out are requested again.
+        for hashes in missing.chunks(MAX_CAIRO_CLASSES_PER_REQUEST) {
+            let classes = p2p
+                .classes_by_hash(block, hashes)
+                .await
+                .context("Fetching cairo classes")?;
+            persist_cairo_classes(
+                storage.clone(),
+                block,
+                hashes.to_vec(),
+                classes.data,
+                classes.peer,
+            )
+            .await?;
+        }
+    }
+}
+
+/// Verifies that the classes are Cairo 0 classes with the `requested` hashes and stores them.
+async fn persist_cairo_classes(
+    storage: Storage,
+    block: BlockNumber,
+    requested: Vec<ClassHash>,
+    classes: Vec<Class>,
+    peer: PeerId,
+) -> Result<(), BlockSyncError> {
+    spawn_blocking(move || {
+        let classes = verify_cairo_classes(&requested, classes)
+            .context("Verifying cairo classes")
+            .map_err(|error| BlockSyncError::BadData { peer, error })?;
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        tx.insert_snapshot_cairo_classes(block, &classes)
+            .context("Inserting cairo classes")?;
+        tx.commit().context("Committing database transaction")?;
+
+        Ok(())
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+fn verify_cairo_classes(
+    requested: &[ClassHash],
+    classes: Vec<Class>,
+) -> anyhow::Result<Vec<(ClassHash, Vec<u8>)>> {
+    classes
+        .into_iter()
+        .map(|class| {
+            let Class::Cairo0 { class, .. } = class else {
+                anyhow::bail!("Sierra classes are part of the classes trie");
+            };
+            // The hash is computed from the definition, so the class is the one requested.
+            let (hash, definition) = cairo_hash_and_def_from_dto(class)?;
+            anyhow::ensure!(
+                requested.contains(&hash),
+                "Cairo class {hash} was not requested"
+            );
+            Ok((hash, definition))
+        })
+        .collect()
+}
+
+/// Makes the snapshot the state of its block, once all of its tries are complete.
+async fn finish(
+    storage: Storage,
+    block: BlockNumber,
+    progress: SnapshotProgress,
+) -> anyhow::Result<()> {
+    spawn_blocking(move || {
+        let (storage_commitment, class_commitment) = progress
+            .commitments
+            .context("State commitments are unknown")?;
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        tx.insert_storage_root(block, progress.storage_root_index)
+            .context("Inserting contracts trie root")?;
+        tx.insert_class_root(block, progress.class_root_index)
+            .context("Inserting classes trie root")?;
+        tx.update_block_header_commitments(block, storage_commitment, class_commitment)
+            .context("Updating block header commitments")?;
+
+        tx.commit().context("Committing database transaction")
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Returns the headers of up to `count` consecutive blocks, oldest first, whose transactions,
+/// receipts and events were skipped by the snapshot. These are the blocks up to and including
+/// the snapshot, once the snapshot is complete.
+pub(super) async fn missing_bodies(
+    storage: Storage,
+    count: u64,
+) -> anyhow::Result<Vec<BlockHeader>> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+
+        let Some(progress) = db
+            .snapshot_progress()
+            .context("Querying snapshot progress")?
+        else {
+            return Ok(Vec::new());
+        };
+        let complete = db
+            .latest_storage_root_block()
+            .context("Querying latest block with state")?
+            .is_some();
+        if !complete {
+            return Ok(Vec::new());
+        }
+
+        let last = progress.block.min(progress.next_body_block + (count - 1));
+        (progress.next_body_block.get()..=last.get())
+            .map(|number| {
+                db.block_header(BlockNumber::new_or_panic(number).into())
+                    .context("Querying block header")?
+                    .with_context(|| format!("Header of block {number} is missing"))
+            })
+            .collect()
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Stores the transactions, receipts and events of consecutive blocks returned by
+/// [missing_bodies], along with the progress made.
+pub(super) async fn persist_bodies(
+    storage: Storage,
+    bodies: Vec<(BlockHeader, Vec<(Transaction, Receipt)>)>,
+) -> anyhow::Result<()> {
+    spawn_blocking(move || {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context("Creating database transaction")?;
+
+        let mut progress = tx
+            .snapshot_progress()
+            .context("Querying snapshot progress")?
+            .context("Snapshot progress is missing")?;
+        for (header, transactions) in &bodies {
+            tx.insert_transaction_data(header.hash, header.number, transactions)
+                .context("Inserting transaction data")?;
+            progress.next_body_block = header.number + 1;
+        }
+
+        if progress.next_body_block > progress.block {
+            tx.delete_snapshot_progress()
+                .context("Deleting snapshot progress")?;
+        } else {
+            tx.upsert_snapshot_progress(&progress)
+                .context("Persisting snapshot progress")?;
+        }
+
+        tx.commit().context("Committing database transaction")
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// The path of a leaf in a trie.
+fn key(felt: Felt) -> anyhow::Result<BitVec<u8, Msb0>> {
+    anyhow::ensure!(!felt.has_more_than_251_bits(), "Key {felt} is out of range");
+    Ok(felt.view_bits().to_bitvec())
+}
+
+/// Verifies that `leaves` are a chunk of the trie with the given `root`, starting at `start`.
+///
+/// A non-empty chunk covers the keys up to its last leaf, while an empty chunk covers all of the
+/// remaining keys. Returns the start of the next chunk, unless the range is complete.
+fn verify_chunk<H: FeltHash>(
+    root: Felt,
+    start: Felt,
+    leaves: &[(BitVec<u8, Msb0>, Felt)],
+    proof: PatriciaRangeProof,
+) -> anyhow::Result<Option<Felt>> {
+    let proof = proof
+        .nodes
+        .into_iter()
+        .map(TrieNode::try_from_dto)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("Parsing range proof")?;
+
+    let last = match leaves.last() {
+        Some((key, _)) => Some(Felt::from_bits(key).context("Mapping leaf path to felt")?),
+        None => None,
+    };
+    let end = last.unwrap_or(MAX_KEY);
+
+    verify_range::<H, 251>(root, start.view_bits(), end.view_bits(), leaves, &proof)?;
+
+    Ok(last
+        .filter(|last| *last < MAX_KEY)
+        .map(|last| last + Felt::from_u64(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use p2p_proto::snapshot::PatriciaNode;
+
+    use super::*;
+
+    #[test]
+    fn chunk_continues_after_its_last_leaf() {
+        let key = felt!("0x5");
+        let value = felt!("0x42");
+        let root = TrieNode::Edge {
+            child: value,
+            path: key.view_bits().to_bitvec(),
+        }
+        .hash::<PedersenHash>();
+        let proof = PatriciaRangeProof {
+            nodes: vec![PatriciaNode::Edge {
+                length: 251,
+                path: key,
+                value,
+            }],
+        };
+
+        let leaves = [(key.view_bits().to_bitvec(), value)];
+        let next = verify_chunk::<PedersenHash>(root, Felt::ZERO, &leaves, proof).unwrap();
+        assert_eq!(next, Some(felt!("0x6")));
+    }
+
+    #[test]
+    fn empty_chunk_completes_range() {
+        let proof = PatriciaRangeProof { nodes: vec![] };
+        let next = verify_chunk::<PedersenHash>(Felt::ZERO, felt!("0x5"), &[], proof).unwrap();
+        assert_eq!(next, None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Sync scenarios on a [simulated network](crate::p2p_network::simulation).
+use std::time::Duration;
+
+use fake::{Fake, Faker};
+use p2p::Head;
+use p2p_proto::common::{BlockId, Hash};
+use pathfinder_common::{BlockHash, BlockNumber};
+use pathfinder_ethereum::{EthereumClient, SettlementClient};
+use primitive_types::H160;
+
+use super::Sync;
+use crate::p2p_network::simulation::{chain, Block, Simulation, CHAIN_ID};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Syncs the node up to the head of `chain` with [Sync], the same way it syncs up to a head
+/// announced by the peer `from`.
+async fn sync(sim: &Simulation, node: usize, from: usize, chain: &[Block]) -> anyhow::Result<()> {
+    // Syncing up to an announced head does not involve the settlement layer.
+    let settlement =
+        SettlementClient::Ethereum(EthereumClient::new("http://localhost".parse().unwrap())?);
+    let sync = Sync::new(
+        sim.storage(node),
+        sim.client(node),
+        (settlement, H160::zero()),
+        CHAIN_ID,
+        None,
+        false,
+    );
+
+    let (number, hash) = head_of(chain);
+    sync.sync_head(Head {
+        from: sim.peer_id(from),
+        number,
+        hash,
+    })
+    .await
+}
+
+/// The head of the chain.
+fn head_of(chain: &[Block]) -> (BlockNumber, BlockHash) {
+    let header = &chain.last().unwrap().0;
+    (header.number, header.hash)
+}
+
+#[tokio::test]
+async fn converges() {
+    let chain = chain(30);
+    let mut sim = Simulation::new(4);
+    for node in 0..3 {
+        sim.seed(node, &chain);
+    }
+    sim.seed(3, &chain[..10]);
+    sim.start().await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, sync(&sim, 3, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let head = sim.converged(&[0, 1, 2, 3], TIMEOUT).await.unwrap();
+    assert_eq!(head, head_of(&chain));
+}
+
+#[tokio::test]
+async fn converges_once_partition_heals() {
+    let chain = chain(10);
+    let mut sim = Simulation::new(3);
+    sim.seed(0, &chain);
+    sim.seed(1, &chain);
+    sim.start().await.unwrap();
+
+    sim.partition(&[2]).await.unwrap();
+
+    // Nothing can be synced from across the partition.
+    let error = tokio::time::timeout(TIMEOUT, sync(&sim, 2, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("No peer served the headers of blocks 0..=9"));
+    assert_eq!(sim.head(2).await.unwrap(), None);
+
+    sim.heal().await.unwrap();
+    tokio::time::timeout(TIMEOUT, sync(&sim, 2, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+
+    sim.converged(&[0, 1, 2], TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn converges_with_slow_peers() {
+    let chain = chain(10);
+    let mut sim = Simulation::new(3);
+    sim.seed(0, &chain);
+    sim.seed(1, &chain);
+    sim.slow_down(0, Duration::from_millis(200));
+    sim.slow_down(1, Duration::from_millis(200));
+    sim.start().await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, sync(&sim, 2, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+
+    sim.converged(&[0, 1, 2], TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn malicious_responder_is_penalized() {
+    let chain = chain(10);
+    let mut sim = Simulation::new(3);
+    sim.seed(0, &chain);
+    sim.seed(1, &chain);
+    sim.corrupt_headers(0);
+    sim.start().await.unwrap();
+
+    // Leave the malicious node as the only one to sync from. Its corrupted headers get it
+    // banned, after which there is no one left to sync from.
+    sim.partition(&[1]).await.unwrap();
+    let error = tokio::time::timeout(TIMEOUT, sync(&sim, 2, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("No peer served the headers of blocks 0..=9"));
+    assert_eq!(sim.head(2).await.unwrap(), None);
+
+    let scores = sim.client(2).peer_aware().peer_scores().await;
+    assert!(scores[&sim.peer_id(0)].banned);
+
+    sim.heal().await.unwrap();
+    tokio::time::timeout(TIMEOUT, sync(&sim, 2, 1, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+
+    sim.converged(&[1, 2], TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn converges_despite_churn() {
+    let chain = chain(20);
+    let mut sim = Simulation::new(4);
+    sim.seed(0, &chain);
+    sim.seed(1, &chain);
+    sim.seed(2, &chain[..10]);
+    sim.start().await.unwrap();
+    // Peers which don't have a block serve an empty state diff for it, which is not held against
+    // them, so the node without any blocks only joins once the others have synced.
+    sim.stop(3).await;
+
+    sim.stop(0).await;
+    tokio::time::timeout(TIMEOUT, sync(&sim, 2, 1, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+    sim.converged(&[1, 2], TIMEOUT).await.unwrap();
+
+    sim.restart(0).await.unwrap();
+    sim.restart(3).await.unwrap();
+    sim.stop(1).await;
+    tokio::time::timeout(TIMEOUT, sync(&sim, 3, 0, &chain))
+        .await
+        .unwrap()
+        .unwrap();
+    sim.converged(&[0, 2, 3], TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_verified_announcements_are_published() {
+    let chain = chain(10);
+    let mut sim = Simulation::new(3);
+    sim.seed(0, &chain);
+    sim.seed(1, &chain);
+    sim.seed(2, &chain[..5]);
+    sim.start().await.unwrap();
+
+    let (number, hash) = head_of(&chain);
+    let valid = BlockId {
+        number: number.get(),
+        hash: Hash(hash.0),
+    };
+    // No one can serve this block, so it must not be published even though it is newer.
+    let bogus = BlockId {
+        number: 1_000_000,
+        hash: Faker.fake(),
+    };
+
+    let mut heads = sim.heads(2);
+    let published = async {
+        loop {
+            // Gossip only reaches the other nodes once they know about each other's
+            // subscriptions, so keep announcing until it does.
+            let _ = sim.client(1).propagate_new_head(bogus).await;
+            let _ = sim.client(0).propagate_new_head(valid).await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            let scores = sim.client(2).peer_aware().peer_scores().await;
+            let penalized = scores
+                .get(&sim.peer_id(1))
+                .map_or(false, |score| score.score < 0);
+            if let (Some(head), true) = (*heads.borrow_and_update(), penalized) {
+                return head;
+            }
+        }
+    };
+    let head = tokio::time::timeout(TIMEOUT, published).await.unwrap();
+
+    assert_eq!(
+        head,
+        Head {
+            from: sim.peer_id(0),
+            number,
+            hash,
+        }
+    );
+}
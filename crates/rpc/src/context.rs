@@ -29,6 +29,11 @@ pub struct RpcContext {
     pub sequencer: SequencerClient,
     pub websocket: Option<WebsocketContext>,
     pub config: RpcConfig,
+    pub compiler: pathfinder_compiler::Compiler,
+    pub chain_config: pathfinder_executor::ChainConfig,
+    /// Used by the p2p admin methods, which are disabled if this is not set.
+    #[cfg(feature = "p2p")]
+    pub p2p: Option<p2p::client::peer_aware::Client>,
 }
 
 impl RpcContext {
@@ -52,6 +57,10 @@ impl RpcContext {
             sequencer,
             websocket: None,
             config,
+            compiler: Default::default(),
+            chain_config: Default::default(),
+            #[cfg(feature = "p2p")]
+            p2p: None,
         }
     }
 
@@ -125,10 +134,29 @@ impl RpcContext {
         context.with_pending_data(rx)
     }
 
+    pub fn with_compiler(self, compiler: pathfinder_compiler::Compiler) -> Self {
+        Self { compiler, ..self }
+    }
+
+    pub fn with_chain_config(self, chain_config: pathfinder_executor::ChainConfig) -> Self {
+        Self {
+            chain_config,
+            ..self
+        }
+    }
+
     pub fn with_websockets(self, websockets: WebsocketContext) -> Self {
         Self {
             websocket: Some(websockets),
             ..self
         }
     }
+
+    #[cfg(feature = "p2p")]
+    pub fn with_p2p(self, p2p: p2p::client::peer_aware::Client) -> Self {
+        Self {
+            p2p: Some(p2p),
+            ..self
+        }
+    }
 }
@@ -76,6 +76,8 @@ pub enum ApplicationError {
     UnexpectedError { data: String },
     #[error("Too many storage keys requested")]
     ProofLimitExceeded { limit: u32, requested: u32 },
+    #[error("P2P admin methods are disabled")]
+    P2PAdminDisabled,
     #[error("Internal error")]
     GatewayError(starknet_gateway_types::error::StarknetError),
     #[error("Transaction execution error")]
@@ -131,6 +133,7 @@ impl ApplicationError {
             ApplicationError::UnexpectedError { .. } => 63,
             // doc/rpc/pathfinder_rpc_api.json
             ApplicationError::ProofLimitExceeded { .. } => 10000,
+            ApplicationError::P2PAdminDisabled => 10001,
             // https://www.jsonrpc.org/specification#error_object
             ApplicationError::GatewayError(_)
             | ApplicationError::Internal(_)
@@ -205,10 +208,39 @@ impl ApplicationError {
                 "requested": requested,
             })),
             ApplicationError::ValidationFailureV06(error) => Some(json!(error)),
+            ApplicationError::P2PAdminDisabled => None,
         }
     }
 }
 
+/// Returns the error a state query at `block` fails with if the block's state is not stored, which
+/// is the case for blocks before the state snapshot a node synced. The error is meant for the
+/// [Custom](ApplicationError::Custom) variant, while blocks which don't exist are left to the
+/// caller.
+pub(crate) fn state_not_available(
+    tx: &pathfinder_storage::Transaction<'_>,
+    block: pathfinder_storage::BlockId,
+) -> anyhow::Result<Option<anyhow::Error>> {
+    use anyhow::Context;
+
+    let Some(oldest) = tx
+        .oldest_storage_root_block()
+        .context("Querying oldest block with state")?
+    else {
+        return Ok(None);
+    };
+    let Some((number, _)) = tx.block_id(block).context("Querying block number")? else {
+        return Ok(None);
+    };
+
+    Ok((number < oldest).then(|| {
+        anyhow::anyhow!(
+            "State of block {number} is not available, the node only has the state of block \
+             {oldest} onwards"
+        )
+    }))
+}
+
 /// Generates an enum subset of [ApplicationError] along with boilerplate for mapping the variants back to [ApplicationError].
 ///
 /// This is useful for RPC methods which only emit a few of the [ApplicationError] variants as this macro can be
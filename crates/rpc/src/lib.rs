@@ -17,7 +17,7 @@ pub mod v06;
 pub mod v07;
 
 pub use executor::compose_executor_transaction;
-pub use pending::PendingData;
+pub use pending::{PendingData, PendingWatcher};
 
 use crate::jsonrpc::rpc_handler;
 use crate::jsonrpc::websocket::websocket_handler;
@@ -196,6 +196,45 @@ impl RpcServer {
     }
 }
 
+/// Starts an HTTP-RPC server which only serves the p2p admin methods, with `context` carrying
+/// the p2p client, see [RpcContext::with_p2p].
+///
+/// These methods allow anyone who can reach the server to manage the node's peers, so the
+/// server only listens on loopback addresses.
+#[cfg(feature = "p2p")]
+pub fn spawn_p2p_admin(
+    addr: SocketAddr,
+    context: RpcContext,
+) -> anyhow::Result<(JoinHandle<anyhow::Result<()>>, SocketAddr)> {
+    use axum::routing::post;
+
+    anyhow::ensure!(
+        addr.ip().is_loopback(),
+        "P2P admin RPC address {addr} is not a loopback address"
+    );
+
+    let listener = std::net::TcpListener::bind(addr)
+        .with_context(|| format!("Binding p2p admin RPC address {addr}"))?;
+    let addr = listener
+        .local_addr()
+        .context("Getting local address from listener")?;
+    let server = axum::Server::from_tcp(listener).context("Binding server to tcp listener")?;
+
+    let routes = pathfinder::register_p2p_admin_routes().build(context);
+    let router = axum::Router::new()
+        .route("/", post(rpc_handler))
+        .with_state(routes);
+
+    let server_handle = tokio::spawn(async move {
+        server
+            .serve(router.into_make_service())
+            .await
+            .map_err(Into::into)
+    });
+
+    Ok((server_handle, addr))
+}
+
 pub struct SyncState {
     pub status: RwLock<Syncing>,
 }
@@ -804,6 +843,62 @@ mod tests {
         assert!(!status.is_success());
     }
 
+    #[cfg(feature = "p2p")]
+    #[tokio::test]
+    async fn p2p_admin_is_only_served_locally() {
+        let specification = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("doc")
+            .join("rpc")
+            .join("pathfinder_p2p_admin_api.json");
+        let specification = std::fs::File::open(specification).unwrap();
+        let specification = serde_json::from_reader::<_, serde_json::Value>(specification).unwrap();
+        let methods = specification["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+
+        let addr: SocketAddr = "0.0.0.0:0".parse().unwrap();
+        spawn_p2p_admin(addr, RpcContext::for_tests()).unwrap_err();
+
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (_admin_jh, admin_addr) = spawn_p2p_admin(addr, RpcContext::for_tests()).unwrap();
+        let (_jh, addr) = RpcServer::new(addr, RpcContext::for_tests(), DefaultVersion::V05)
+            .spawn()
+            .unwrap();
+
+        let client = reqwest::Client::new();
+        let error_code = |url: String, method: &str| {
+            let request = client.post(url).json(&json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "id": 0,
+            }));
+            async move {
+                let res: serde_json::Value = request.send().await.unwrap().json().await.unwrap();
+                res["error"]["code"].clone()
+            }
+        };
+
+        let method_not_found = json!(-32601);
+        for method in methods {
+            let code = error_code(format!("http://{admin_addr}/"), method).await;
+            assert_ne!(code, method_not_found, "{method} on the admin server");
+
+            for route in ["/", "/rpc/pathfinder/v0.1"] {
+                let code = error_code(format!("http://{addr}{route}"), method).await;
+                assert_eq!(code, method_not_found, "{method} on {route}");
+            }
+        }
+
+        // The test context has no p2p client.
+        let code = error_code(format!("http://{admin_addr}/"), "pathfinder_p2p_peers").await;
+        assert_eq!(code, json!(10001));
+    }
+
     #[rustfmt::skip]
     #[rstest::rstest]
     #[case::root_api  ("/", "v05/starknet_api_openrpc.json",       &[])]
@@ -9,3 +9,13 @@ pub fn register_routes() -> RpcRouterBuilder {
         .register("pathfinder_getProof",             methods::get_proof)
         .register("pathfinder_getTransactionStatus", methods::get_transaction_status)
 }
+
+/// The p2p admin methods, which are only served on their own local-only listener.
+#[cfg(feature = "p2p")]
+#[rustfmt::skip]
+pub fn register_p2p_admin_routes() -> RpcRouterBuilder {
+    RpcRouter::builder("v0.1")
+        .register("pathfinder_p2p_peers",      methods::p2p_peers)
+        .register("pathfinder_p2p_dial",       methods::p2p_dial)
+        .register("pathfinder_p2p_disconnect", methods::p2p_disconnect)
+}
@@ -1,5 +1,9 @@
 mod get_proof;
 mod get_transaction_status;
+#[cfg(feature = "p2p")]
+mod p2p_admin;
 
 pub(crate) use get_proof::get_proof;
 pub(crate) use get_transaction_status::get_transaction_status;
+#[cfg(feature = "p2p")]
+pub(crate) use p2p_admin::{p2p_dial, p2p_disconnect, p2p_peers};
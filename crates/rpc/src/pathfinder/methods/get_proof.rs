@@ -19,6 +19,7 @@ pub struct GetProofInput {
 #[derive(Debug)]
 pub enum GetProofError {
     Internal(anyhow::Error),
+    Custom(anyhow::Error),
     BlockNotFound,
     ProofLimitExceeded { limit: u32, requested: u32 },
 }
@@ -37,6 +38,7 @@ impl From<GetProofError> for crate::error::ApplicationError {
             }
             GetProofError::BlockNotFound => Self::BlockNotFound,
             GetProofError::Internal(internal) => Self::Internal(internal),
+            GetProofError::Custom(error) => Self::Custom(error),
         }
     }
 }
@@ -179,6 +181,9 @@ pub async fn get_proof(
             .block_header(block_id)
             .context("Fetching block header")?
             .ok_or(GetProofError::BlockNotFound)?;
+        if let Some(error) = crate::error::state_not_available(&tx, header.number.into())? {
+            return Err(GetProofError::Custom(error));
+        }
 
         let state_commitment = match header.state_commitment {
             StateCommitment::ZERO => None,
@@ -0,0 +1,138 @@
+//! Admin methods for inspecting and managing the node's p2p peers. They are only served on the
+//! local-only admin listener, see [crate::spawn_p2p_admin].
+use anyhow::Context;
+use p2p::client::peer_aware::Client;
+use p2p::libp2p::multiaddr::Protocol;
+use p2p::libp2p::{Multiaddr, PeerId};
+
+use crate::context::RpcContext;
+
+crate::error::generate_rpc_error_subset!(P2PError: P2PAdminDisabled);
+
+fn client(context: &RpcContext) -> Result<&Client, P2PError> {
+    context.p2p.as_ref().ok_or(P2PError::P2PAdminDisabled)
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+pub struct Peer {
+    peer_id: String,
+    address: Option<String>,
+    connected: bool,
+    direction: Direction,
+    relayed: bool,
+    min_ping_ms: Option<u128>,
+    score: i32,
+    banned: bool,
+}
+
+#[derive(Copy, Clone, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl From<p2p::PeerInfo> for Peer {
+    fn from(peer: p2p::PeerInfo) -> Self {
+        Self {
+            peer_id: peer.peer_id.to_string(),
+            address: peer.addr.map(|addr| addr.to_string()),
+            connected: peer.connected,
+            direction: if peer.outbound {
+                Direction::Outbound
+            } else {
+                Direction::Inbound
+            },
+            relayed: peer.relayed,
+            min_ping_ms: peer.min_ping.map(|ping| ping.as_millis()),
+            score: peer.score.score,
+            banned: peer.score.banned,
+        }
+    }
+}
+
+/// Lists the known peers, including recently disconnected ones.
+pub async fn p2p_peers(context: RpcContext) -> Result<Vec<Peer>, P2PError> {
+    let mut peers = client(&context)?
+        .peers()
+        .await
+        .into_iter()
+        .map(Peer::from)
+        .collect::<Vec<_>>();
+    peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+    Ok(peers)
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct DialInput {
+    /// Must include the peer ID, e.g. `/ip4/127.0.0.1/tcp/20002/p2p/<peer ID>`.
+    address: String,
+}
+
+/// Connects to a peer, completing once the connection is established.
+pub async fn p2p_dial(context: RpcContext, input: DialInput) -> Result<(), P2PError> {
+    let client = client(&context)?;
+
+    let address = input
+        .address
+        .parse::<Multiaddr>()
+        .context("Parsing address")
+        .map_err(P2PError::Custom)?;
+    let peer_id = address
+        .iter()
+        .find_map(|protocol| match protocol {
+            Protocol::P2p(peer_id) => Some(peer_id),
+            _ => None,
+        })
+        .context("Address must include the peer ID")
+        .map_err(P2PError::Custom)?;
+
+    client
+        .dial(peer_id, address)
+        .await
+        .context("Dialing peer")
+        .map_err(P2PError::Custom)
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct DisconnectInput {
+    peer_id: String,
+}
+
+/// Closes all connections to a peer. The peer is free to reconnect.
+pub async fn p2p_disconnect(context: RpcContext, input: DisconnectInput) -> Result<(), P2PError> {
+    let client = client(&context)?;
+
+    let peer_id = input
+        .peer_id
+        .parse::<PeerId>()
+        .context("Parsing peer ID")
+        .map_err(P2PError::Custom)?;
+
+    client
+        .disconnect(peer_id)
+        .await
+        .context("Disconnecting peer")
+        .map_err(P2PError::Custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn disabled_by_default() {
+        let context = RpcContext::for_tests();
+
+        let error = p2p_peers(context.clone()).await.unwrap_err();
+        assert_matches::assert_matches!(error, P2PError::P2PAdminDisabled);
+
+        let input = DisconnectInput {
+            peer_id: PeerId::random().to_string(),
+        };
+        let error = p2p_disconnect(context, input).await.unwrap_err();
+        assert_matches::assert_matches!(error, P2PError::P2PAdminDisabled);
+    }
+}
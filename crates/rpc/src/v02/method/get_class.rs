@@ -47,6 +47,9 @@ pub async fn get_class(
         if !block_exists {
             return Err(GetClassError::BlockNotFound);
         }
+        if let Some(error) = crate::error::state_not_available(&tx, block_id)? {
+            return Err(GetClassError::Custom(error));
+        }
 
         // If the class is declared in the pending block, then we shouldn't check the class's
         // declaration point.
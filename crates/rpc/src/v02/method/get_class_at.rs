@@ -47,6 +47,9 @@ pub async fn get_class_at(
         if !tx.block_exists(block_id)? {
             return Err(GetClassAtError::BlockNotFound);
         }
+        if let Some(error) = crate::error::state_not_available(&tx, block_id)? {
+            return Err(GetClassAtError::Custom(error));
+        }
 
         let class_hash = match pending_class_hash {
             Some(class_hash) => class_hash,
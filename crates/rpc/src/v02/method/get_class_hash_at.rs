@@ -53,6 +53,9 @@ pub async fn get_class_hash_at(
         if !tx.block_exists(block_id)? {
             return Err(GetClassHashAtError::BlockNotFound);
         }
+        if let Some(error) = crate::error::state_not_available(&tx, block_id)? {
+            return Err(GetClassHashAtError::Custom(error));
+        }
 
         tx.contract_class_hash(block_id, input.contract_address)
             .context("Fetching class hash from database")?
@@ -54,6 +54,9 @@ pub async fn get_nonce(
         if !block_exists {
             return Err(GetNonceError::BlockNotFound);
         }
+        if let Some(error) = crate::error::state_not_available(&tx, block_id)? {
+            return Err(GetNonceError::Custom(error));
+        }
 
         let nonce = tx
             .contract_nonce(contract_address, block_id)
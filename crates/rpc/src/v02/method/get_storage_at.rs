@@ -55,6 +55,9 @@ pub async fn get_storage_at(
         if !tx.block_exists(block_id)? {
             return Err(GetStorageAtError::BlockNotFound);
         }
+        if let Some(error) = crate::error::state_not_available(&tx, block_id)? {
+            return Err(GetStorageAtError::Custom(error));
+        }
 
         let value = tx
             .storage_value(block_id, input.contract_address, input.key)
@@ -81,7 +84,7 @@ mod tests {
     use assert_matches::assert_matches;
     use serde_json::json;
 
-    use pathfinder_common::{macro_prelude::*, BlockNumber};
+    use pathfinder_common::{macro_prelude::*, BlockHeader, BlockNumber};
 
     /// # Important
     ///
@@ -290,6 +293,35 @@ mod tests {
         assert_matches!(result, Err(GetStorageAtError::ContractNotFound));
     }
 
+    #[tokio::test]
+    async fn state_before_snapshot_is_not_available() {
+        let storage = pathfinder_storage::Storage::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        let genesis = BlockHeader::builder().finalize_with_hash(block_hash!("0x1"));
+        let snapshot = genesis
+            .child_builder()
+            .finalize_with_hash(block_hash!("0x2"));
+        tx.insert_block_header(&genesis).unwrap();
+        tx.insert_block_header(&snapshot).unwrap();
+        // A node which synced a state snapshot has no state before the snapshot's block.
+        tx.insert_storage_root(snapshot.number, None).unwrap();
+        tx.commit().unwrap();
+        let ctx = RpcContext::for_tests().with_storage(storage);
+
+        let result = get_storage_at(
+            ctx,
+            GetStorageAtInput {
+                contract_address: contract_address!("0x1"),
+                key: storage_address!("0x2"),
+                block_id: BlockId::Number(BlockNumber::GENESIS),
+            },
+        )
+        .await;
+
+        assert_matches!(result, Err(GetStorageAtError::Custom(_)));
+    }
+
     #[tokio::test]
     async fn block_not_found_by_number() {
         let ctx = RpcContext::for_tests_with_pending().await;
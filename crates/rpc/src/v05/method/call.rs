@@ -104,12 +104,21 @@ pub async fn call(context: RpcContext, input: CallInput) -> Result<CallOutput, C
                     .block_header(block_id)
                     .context("Querying block header")?
                     .ok_or(CallError::BlockNotFound)?;
+                if let Some(error) = crate::error::state_not_available(&db, block_id)? {
+                    return Err(CallError::Custom(error));
+                }
 
                 (header, None)
             }
         };
 
-        let state = ExecutionState::simulation(&db, context.chain_id, header, pending);
+        let state = ExecutionState::simulation(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            pending,
+        );
 
         let result = pathfinder_executor::call(
             state,
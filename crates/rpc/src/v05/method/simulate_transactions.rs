@@ -118,13 +118,21 @@ pub async fn simulate_transactions(
                     .block_header(block_id)
                     .context("Fetching block header")?
                     .ok_or(SimulateTransactionError::BlockNotFound)?;
+                if let Some(error) = crate::error::state_not_available(&db, block_id)? {
+                    return Err(SimulateTransactionError::Custom(error));
+                }
 
                 (header, None)
             }
         };
 
-        let state =
-            pathfinder_executor::ExecutionState::simulation(&db, context.chain_id, header, pending);
+        let state = pathfinder_executor::ExecutionState::simulation(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            pending,
+        );
 
         let transactions = input
             .transactions
@@ -183,8 +183,21 @@ pub async fn trace_transaction(
             (header, transactions.clone(), context.cache.clone())
         };
 
+        // Transactions are executed on top of the state of the parent block.
+        if let Some(parent) = header.number.parent() {
+            if let Some(error) = crate::error::state_not_available(&db, parent.into())? {
+                return Err(TraceTransactionError::Custom(error));
+            }
+        }
+
         let hash = header.hash;
-        let state = ExecutionState::trace(&db, context.chain_id, header, None);
+        let state = ExecutionState::trace(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            None,
+        );
 
         let transactions = transactions
             .iter()
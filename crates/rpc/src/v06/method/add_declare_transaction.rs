@@ -1,6 +1,8 @@
 use crate::context::RpcContext;
 use crate::felt::RpcFelt;
 use crate::v02::types::request::BroadcastedDeclareTransaction;
+use crate::v02::types::SierraContractClass;
+use anyhow::Context;
 use pathfinder_common::{ClassHash, TransactionHash};
 use starknet_gateway_client::GatewayApi;
 use starknet_gateway_types::error::SequencerError;
@@ -179,6 +181,8 @@ pub async fn add_declare_transaction(
             })
         }
         Transaction::Declare(BroadcastedDeclareTransaction::V2(tx)) => {
+            compile(&context, &tx.contract_class).await?;
+
             let contract_definition: SierraContractDefinition = tx
                 .contract_class
                 .try_into()
@@ -206,6 +210,8 @@ pub async fn add_declare_transaction(
             })
         }
         Transaction::Declare(BroadcastedDeclareTransaction::V3(tx)) => {
+            compile(&context, &tx.contract_class).await?;
+
             let contract_definition: SierraContractDefinition = tx
                 .contract_class
                 .try_into()
@@ -250,6 +256,34 @@ pub async fn add_declare_transaction(
     }
 }
 
+/// Rejects Sierra classes which we cannot compile before forwarding them to the gateway.
+///
+/// Only done if the compiler is isolated, as anyone can submit a class and compiling it in
+/// process is unbounded in time and memory. Otherwise the gateway remains the only check.
+async fn compile(
+    context: &RpcContext,
+    contract_class: &SierraContractClass,
+) -> Result<(), AddDeclareTransactionError> {
+    let compiler = context.compiler;
+    if !matches!(compiler, pathfinder_compiler::Compiler::Isolated(_)) {
+        return Ok(());
+    }
+
+    let definition = contract_class
+        .serialize_to_json()
+        .context("Serializing Sierra class definition")?;
+
+    tokio::task::spawn_blocking(move || compiler.compile_to_casm_with_latest_compiler(&definition))
+        .await
+        .context("Joining compiler task")?
+        .map_err(|error| {
+            tracing::debug!(?error, "Declared Sierra class failed to compile");
+            AddDeclareTransactionError::CompilationFailed
+        })?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,12 +138,21 @@ pub async fn estimate_fee(
                     .block_header(block_id)
                     .context("Querying block header")?
                     .ok_or(EstimateFeeError::BlockNotFound)?;
+                if let Some(error) = crate::error::state_not_available(&db, block_id)? {
+                    return Err(EstimateFeeError::Custom(error));
+                }
 
                 (header, None)
             }
         };
 
-        let state = ExecutionState::simulation(&db, context.chain_id, header, pending);
+        let state = ExecutionState::simulation(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            pending,
+        );
 
         let skip_validate = input
             .simulation_flags
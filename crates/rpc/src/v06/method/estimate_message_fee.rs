@@ -120,6 +120,9 @@ pub(crate) async fn estimate_message_fee_impl(
                     .block_header(block_id)
                     .context("Querying block header")?
                     .ok_or(EstimateMessageFeeError::BlockNotFound)?;
+                if let Some(error) = crate::error::state_not_available(&db, block_id)? {
+                    return Err(EstimateMessageFeeError::Custom(error));
+                }
 
                 (header, None)
             }
@@ -129,7 +132,13 @@ pub(crate) async fn estimate_message_fee_impl(
             return Err(EstimateMessageFeeError::ContractNotFound);
         }
 
-        let state = ExecutionState::simulation(&db, context.chain_id, header, pending);
+        let state = ExecutionState::simulation(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            pending,
+        );
 
         let transaction = create_executor_transaction(input, context.chain_id)?;
 
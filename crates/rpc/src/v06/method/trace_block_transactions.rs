@@ -220,8 +220,21 @@ pub async fn trace_block_transactions(
             .map(|transaction| compose_executor_transaction(transaction, &db))
             .collect::<Result<Vec<_>, _>>()?;
 
+        // Transactions are executed on top of the state of the parent block.
+        if let Some(parent) = header.number.parent() {
+            if let Some(error) = crate::error::state_not_available(&db, parent.into())? {
+                return Err(TraceBlockTransactionsError::Custom(error));
+            }
+        }
+
         let hash = header.hash;
-        let state = ExecutionState::trace(&db, context.chain_id, header, None);
+        let state = ExecutionState::trace(
+            &db,
+            context.chain_id,
+            context.chain_config.clone(),
+            header,
+            None,
+        );
         let traces = pathfinder_executor::trace(state, cache, hash, transactions, true, true)?;
 
         let result = traces
@@ -4,11 +4,13 @@ use std::sync::Arc;
 
 mod block;
 mod class;
+mod class_queue;
 mod ethereum;
 mod event;
 mod reference;
 mod reorg_counter;
 mod signature;
+mod snapshot;
 mod state_update;
 pub(crate) mod transaction;
 mod trie;
@@ -23,6 +25,9 @@ pub use event::{EmittedEvent, EventFilter, EventFilterError, PageOfEvents};
 
 pub(crate) use reorg_counter::ReorgCounter;
 
+pub use class_queue::QueuedClass;
+pub use snapshot::{SnapshotProgress, SnapshotStorage};
+
 pub use transaction::TransactionStatus;
 
 pub use trie::{Child, Node, StoredNode};
@@ -115,6 +120,16 @@ impl<'inner> Transaction<'inner> {
         block::block_header(self, block)
     }
 
+    /// Sets the storage and class commitments of an existing header.
+    pub fn update_block_header_commitments(
+        &self,
+        block: BlockNumber,
+        storage_commitment: StorageCommitment,
+        class_commitment: ClassCommitment,
+    ) -> anyhow::Result<()> {
+        block::update_block_header_commitments(self, block, storage_commitment, class_commitment)
+    }
+
     /// Returns the closest ancestor header that is in storage.
     ///
     /// i.e. returns the latest header with number < target.
@@ -288,6 +303,95 @@ impl<'inner> Transaction<'inner> {
         class::insert_cairo_class(self, cairo_hash, definition)
     }
 
+    /// Adds a class to the queue of classes which sync still has to download.
+    pub fn enqueue_class(
+        &self,
+        hash: ClassHash,
+        casm_hash: Option<CasmHash>,
+        starknet_version: &StarknetVersion,
+    ) -> anyhow::Result<()> {
+        class_queue::enqueue_class(self, hash, casm_hash, starknet_version)
+    }
+
+    pub fn queued_classes(&self) -> anyhow::Result<Vec<QueuedClass>> {
+        class_queue::queued_classes(self)
+    }
+
+    pub fn record_class_download_failure(&self, hash: ClassHash) -> anyhow::Result<()> {
+        class_queue::record_class_download_failure(self, hash)
+    }
+
+    /// Removes a class from the download queue, once it has been stored.
+    pub fn dequeue_class(&self, hash: ClassHash) -> anyhow::Result<()> {
+        class_queue::dequeue_class(self, hash)
+    }
+
+    /// The progress of the state snapshot sync, if one is underway.
+    pub fn snapshot_progress(&self) -> anyhow::Result<Option<SnapshotProgress>> {
+        snapshot::snapshot_progress(self)
+    }
+
+    pub fn upsert_snapshot_progress(&self, progress: &SnapshotProgress) -> anyhow::Result<()> {
+        snapshot::upsert_snapshot_progress(self, progress)
+    }
+
+    /// Removes the progress of the state snapshot sync, once it is complete.
+    pub fn delete_snapshot_progress(&self) -> anyhow::Result<()> {
+        snapshot::delete_snapshot_progress(self)
+    }
+
+    /// The contracts whose storage is still being synced as part of the state snapshot.
+    pub fn snapshot_storage(&self) -> anyhow::Result<Vec<SnapshotStorage>> {
+        snapshot::snapshot_storage(self)
+    }
+
+    pub fn upsert_snapshot_storage(&self, storage: &SnapshotStorage) -> anyhow::Result<()> {
+        snapshot::upsert_snapshot_storage(self, storage)
+    }
+
+    pub fn delete_snapshot_storage(&self, contract: ContractAddress) -> anyhow::Result<()> {
+        snapshot::delete_snapshot_storage(self, contract)
+    }
+
+    pub fn insert_snapshot_contracts(
+        &self,
+        block: BlockNumber,
+        contracts: &[(ContractAddress, ClassHash, ContractNonce, ContractStateHash)],
+    ) -> anyhow::Result<()> {
+        snapshot::insert_snapshot_contracts(self, block, contracts)
+    }
+
+    pub fn insert_snapshot_storage(
+        &self,
+        block: BlockNumber,
+        contract: ContractAddress,
+        values: &[(StorageAddress, StorageValue)],
+    ) -> anyhow::Result<()> {
+        snapshot::insert_snapshot_storage(self, block, contract, values)
+    }
+
+    pub fn insert_snapshot_classes(
+        &self,
+        block: BlockNumber,
+        classes: &[(SierraHash, CasmHash, ClassCommitmentLeafHash)],
+    ) -> anyhow::Result<()> {
+        snapshot::insert_snapshot_classes(self, block, classes)
+    }
+
+    /// The classes of the contracts in the state snapshot of `block` whose definitions are
+    /// missing.
+    pub fn snapshot_missing_classes(&self, block: BlockNumber) -> anyhow::Result<Vec<ClassHash>> {
+        snapshot::snapshot_missing_classes(self, block)
+    }
+
+    pub fn insert_snapshot_cairo_classes(
+        &self,
+        block: BlockNumber,
+        classes: &[(ClassHash, Vec<u8>)],
+    ) -> anyhow::Result<()> {
+        snapshot::insert_snapshot_cairo_classes(self, block, classes)
+    }
+
     pub fn insert_class_commitment_leaf(
         &self,
         block: BlockNumber,
@@ -472,6 +576,17 @@ impl<'inner> Transaction<'inner> {
         trie::storage_root_index(self, block)
     }
 
+    /// Returns the newest block whose state has been applied to the storage trie.
+    pub fn latest_storage_root_block(&self) -> anyhow::Result<Option<BlockNumber>> {
+        trie::latest_storage_root_block(self)
+    }
+
+    /// Returns the oldest block whose state is available. Nodes which synced a state snapshot
+    /// only have the state of the snapshot's block onwards.
+    pub fn oldest_storage_root_block(&self) -> anyhow::Result<Option<BlockNumber>> {
+        trie::oldest_storage_root_block(self)
+    }
+
     pub fn contract_root_index(
         &self,
         block: BlockNumber,
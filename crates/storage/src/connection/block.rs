@@ -1,5 +1,8 @@
 use anyhow::Context;
-use pathfinder_common::{BlockHash, BlockHeader, BlockNumber, GasPrice, StarknetVersion};
+use pathfinder_common::{
+    BlockHash, BlockHeader, BlockNumber, ClassCommitment, GasPrice, StarknetVersion,
+    StorageCommitment,
+};
 
 use crate::{prelude::*, BlockId};
 
@@ -48,6 +51,26 @@ pub(super) fn insert_block_header(
     Ok(())
 }
 
+/// Sets the storage and class commitments of a header which was stored before its state
+/// was known, as happens during p2p sync.
+pub(super) fn update_block_header_commitments(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    storage_commitment: StorageCommitment,
+    class_commitment: ClassCommitment,
+) -> anyhow::Result<()> {
+    let updated = tx
+        .inner()
+        .execute(
+            "UPDATE block_headers SET storage_commitment = ?, class_commitment = ? WHERE number = ?",
+            params![&storage_commitment, &class_commitment, &block],
+        )
+        .context("Updating block header commitments")?;
+    anyhow::ensure!(updated == 1, "Block header {block} not found");
+
+    Ok(())
+}
+
 pub(super) fn next_ancestor(
     tx: &Transaction<'_>,
     target: BlockNumber,
@@ -535,6 +558,30 @@ mod tests {
         assert_eq!(class_exists, None);
     }
 
+    #[test]
+    fn update_commitments() {
+        let (mut connection, headers) = setup();
+        let tx = connection.transaction().unwrap();
+        let target = headers.last().unwrap();
+
+        let storage_commitment = storage_commitment_bytes!(b"updated storage commitment");
+        let class_commitment = class_commitment_bytes!(b"updated class commitment");
+        tx.update_block_header_commitments(target.number, storage_commitment, class_commitment)
+            .unwrap();
+
+        let result = tx.block_header(target.number.into()).unwrap().unwrap();
+        let expected = BlockHeader {
+            storage_commitment,
+            class_commitment,
+            ..target.clone()
+        };
+        assert_eq!(result, expected);
+
+        let past_head = target.number + 1;
+        tx.update_block_header_commitments(past_head, storage_commitment, class_commitment)
+            .unwrap_err();
+    }
+
     #[test]
     fn block_id() {
         let (mut connection, headers) = setup();
@@ -0,0 +1,142 @@
+use anyhow::Context;
+use pathfinder_common::{CasmHash, ClassHash, StarknetVersion};
+
+use crate::prelude::*;
+
+/// A class which sync still has to download, and compile if it is a Sierra class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedClass {
+    pub hash: ClassHash,
+    /// The compiled class hash declared for a Sierra class.
+    pub casm_hash: Option<CasmHash>,
+    /// The version of the block which introduced the class, used to pick the compiler.
+    pub starknet_version: StarknetVersion,
+    /// The number of failed attempts so far.
+    pub attempts: u32,
+}
+
+pub(super) fn enqueue_class(
+    tx: &Transaction<'_>,
+    hash: ClassHash,
+    casm_hash: Option<CasmHash>,
+    starknet_version: &StarknetVersion,
+) -> anyhow::Result<()> {
+    tx.inner()
+        .execute(
+            r"INSERT OR IGNORE INTO class_download_queue
+               ( class_hash,  casm_hash,  starknet_version)
+        VALUES (:class_hash, :casm_hash, :starknet_version)",
+            named_params! {
+                ":class_hash": &hash,
+                ":casm_hash": &casm_hash,
+                ":starknet_version": &starknet_version.as_str(),
+            },
+        )
+        .context("Inserting queued class")?;
+
+    Ok(())
+}
+
+pub(super) fn queued_classes(tx: &Transaction<'_>) -> anyhow::Result<Vec<QueuedClass>> {
+    let mut stmt = tx
+        .inner()
+        .prepare_cached(
+            "SELECT class_hash, casm_hash, starknet_version, attempts FROM class_download_queue",
+        )
+        .context("Preparing statement")?;
+
+    let classes = stmt
+        .query_map([], |row| {
+            let hash = row.get_class_hash(0)?;
+            let casm_hash = row.get_optional_casm_hash(1)?;
+            let starknet_version = StarknetVersion::from(row.get::<_, String>(2)?);
+            let attempts = row.get(3)?;
+
+            Ok(QueuedClass {
+                hash,
+                casm_hash,
+                starknet_version,
+                attempts,
+            })
+        })
+        .context("Querying queued classes")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Iterating over queued classes")?;
+
+    Ok(classes)
+}
+
+pub(super) fn record_class_download_failure(
+    tx: &Transaction<'_>,
+    hash: ClassHash,
+) -> anyhow::Result<()> {
+    tx.inner()
+        .execute(
+            "UPDATE class_download_queue SET attempts = attempts + 1 WHERE class_hash = ?",
+            params![&hash],
+        )
+        .context("Updating queued class")?;
+
+    Ok(())
+}
+
+pub(super) fn dequeue_class(tx: &Transaction<'_>, hash: ClassHash) -> anyhow::Result<()> {
+    tx.inner()
+        .execute(
+            "DELETE FROM class_download_queue WHERE class_hash = ?",
+            params![&hash],
+        )
+        .context("Deleting queued class")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn queue_roundtrip() {
+        let storage = crate::Storage::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let cairo = class_hash!("0x1");
+        let sierra = class_hash!("0x2");
+        let version = StarknetVersion::new(0, 13, 1);
+
+        tx.enqueue_class(cairo, None, &version).unwrap();
+        tx.enqueue_class(sierra, Some(casm_hash!("0x22")), &version)
+            .unwrap();
+        // Enqueuing again is a no-op and keeps the recorded failures.
+        tx.record_class_download_failure(cairo).unwrap();
+        tx.enqueue_class(cairo, None, &version).unwrap();
+
+        let mut queued = tx.queued_classes().unwrap();
+        queued.sort_by_key(|class| class.hash);
+        assert_eq!(
+            queued,
+            vec![
+                QueuedClass {
+                    hash: cairo,
+                    casm_hash: None,
+                    starknet_version: version.clone(),
+                    attempts: 1,
+                },
+                QueuedClass {
+                    hash: sierra,
+                    casm_hash: Some(casm_hash!("0x22")),
+                    starknet_version: version.clone(),
+                    attempts: 0,
+                },
+            ]
+        );
+
+        tx.dequeue_class(cairo).unwrap();
+        let queued = tx.queued_classes().unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].hash, sierra);
+    }
+}
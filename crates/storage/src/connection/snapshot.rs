@@ -0,0 +1,428 @@
+use anyhow::Context;
+use pathfinder_common::{
+    BlockNumber, CasmHash, ClassCommitment, ClassCommitmentLeafHash, ClassHash, ContractAddress,
+    ContractNonce, ContractRoot, ContractStateHash, SierraHash, StorageAddress, StorageCommitment,
+    StorageValue,
+};
+
+use crate::prelude::*;
+
+/// The progress of syncing the state of a block as a snapshot, followed by the transactions,
+/// receipts and events of the blocks up to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotProgress {
+    pub block: BlockNumber,
+    /// The roots of the block's contracts and classes tries, once they have been verified
+    /// against the block's state commitment.
+    pub commitments: Option<(StorageCommitment, ClassCommitment)>,
+    /// The start of the remaining range of contracts, `None` once all contracts are stored.
+    pub next_contract: Option<ContractAddress>,
+    /// The root of the contracts trie built so far.
+    pub storage_root_index: Option<u64>,
+    /// The start of the remaining range of classes, `None` once all classes are stored.
+    pub next_class: Option<ClassHash>,
+    /// The root of the classes trie built so far.
+    pub class_root_index: Option<u64>,
+    /// The oldest block whose transactions, receipts and events are still missing.
+    pub next_body_block: BlockNumber,
+}
+
+impl SnapshotProgress {
+    pub fn new(block: BlockNumber) -> Self {
+        Self {
+            block,
+            commitments: None,
+            next_contract: Some(ContractAddress::ZERO),
+            storage_root_index: None,
+            next_class: Some(ClassHash::ZERO),
+            class_root_index: None,
+            next_body_block: BlockNumber::GENESIS,
+        }
+    }
+}
+
+/// A contract whose storage is still being synced as part of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotStorage {
+    pub contract: ContractAddress,
+    /// The root of the contract's storage trie, as committed to by the contracts trie.
+    pub root: ContractRoot,
+    /// The start of the remaining range of storage slots.
+    pub next_key: StorageAddress,
+    /// The root of the contract's storage trie built so far.
+    pub root_index: Option<u64>,
+}
+
+pub(super) fn snapshot_progress(tx: &Transaction<'_>) -> anyhow::Result<Option<SnapshotProgress>> {
+    tx.inner()
+        .query_row(
+            r"SELECT block_number, storage_commitment, class_commitment, next_contract,
+                storage_root_index, next_class, class_root_index, next_body_block
+            FROM snapshot_sync",
+            [],
+            |row| {
+                let block = row.get_block_number(0)?;
+                let storage_commitment = row.get_optional_storage_commitment(1)?;
+                let class_commitment = row.get_optional_class_commitment(2)?;
+                let next_contract = row.get_optional_felt(3)?.map(ContractAddress);
+                let storage_root_index = row.get::<_, Option<u64>>(4)?;
+                let next_class = row.get_optional_felt(5)?.map(ClassHash);
+                let class_root_index = row.get::<_, Option<u64>>(6)?;
+                let next_body_block = row.get_block_number(7)?;
+
+                Ok(SnapshotProgress {
+                    block,
+                    commitments: storage_commitment.zip(class_commitment),
+                    next_contract,
+                    storage_root_index,
+                    next_class,
+                    class_root_index,
+                    next_body_block,
+                })
+            },
+        )
+        .optional()
+        .context("Querying snapshot progress")
+}
+
+pub(super) fn upsert_snapshot_progress(
+    tx: &Transaction<'_>,
+    progress: &SnapshotProgress,
+) -> anyhow::Result<()> {
+    let (storage_commitment, class_commitment) = progress.commitments.unzip();
+
+    // There is only ever a single snapshot.
+    tx.inner()
+        .execute(
+            "DELETE FROM snapshot_sync WHERE block_number != ?",
+            params![&progress.block],
+        )
+        .context("Deleting other snapshot progress")?;
+    tx.inner()
+        .execute(
+            r"INSERT OR REPLACE INTO snapshot_sync
+               ( block_number,  storage_commitment,  class_commitment,  next_contract,
+                 storage_root_index,  next_class,  class_root_index,  next_body_block)
+        VALUES (:block_number, :storage_commitment, :class_commitment, :next_contract,
+                :storage_root_index, :next_class, :class_root_index, :next_body_block)",
+            named_params! {
+                ":block_number": &progress.block,
+                ":storage_commitment": &storage_commitment,
+                ":class_commitment": &class_commitment,
+                ":next_contract": &progress.next_contract,
+                ":storage_root_index": &progress.storage_root_index,
+                ":next_class": &progress.next_class,
+                ":class_root_index": &progress.class_root_index,
+                ":next_body_block": &progress.next_body_block,
+            },
+        )
+        .context("Upserting snapshot progress")?;
+
+    Ok(())
+}
+
+pub(super) fn delete_snapshot_progress(tx: &Transaction<'_>) -> anyhow::Result<()> {
+    tx.inner()
+        .execute("DELETE FROM snapshot_sync", [])
+        .context("Deleting snapshot progress")?;
+    tx.inner()
+        .execute("DELETE FROM snapshot_sync_storage", [])
+        .context("Deleting snapshot storage progress")?;
+
+    Ok(())
+}
+
+pub(super) fn snapshot_storage(tx: &Transaction<'_>) -> anyhow::Result<Vec<SnapshotStorage>> {
+    let mut stmt = tx
+        .inner()
+        .prepare_cached(
+            "SELECT contract_address, contract_root, next_key, root_index FROM snapshot_sync_storage",
+        )
+        .context("Preparing statement")?;
+
+    let contracts = stmt
+        .query_map([], |row| {
+            let contract = row.get_contract_address(0)?;
+            let root = row.get_contract_root(1)?;
+            let next_key = row.get_storage_address(2)?;
+            let root_index = row.get::<_, Option<u64>>(3)?;
+
+            Ok(SnapshotStorage {
+                contract,
+                root,
+                next_key,
+                root_index,
+            })
+        })
+        .context("Querying snapshot storage progress")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Iterating over snapshot storage progress")?;
+
+    Ok(contracts)
+}
+
+pub(super) fn upsert_snapshot_storage(
+    tx: &Transaction<'_>,
+    storage: &SnapshotStorage,
+) -> anyhow::Result<()> {
+    tx.inner()
+        .execute(
+            r"INSERT OR REPLACE INTO snapshot_sync_storage
+               ( contract_address,  contract_root,  next_key,  root_index)
+        VALUES (:contract_address, :contract_root, :next_key, :root_index)",
+            named_params! {
+                ":contract_address": &storage.contract,
+                ":contract_root": &storage.root,
+                ":next_key": &storage.next_key,
+                ":root_index": &storage.root_index,
+            },
+        )
+        .context("Upserting snapshot storage progress")?;
+
+    Ok(())
+}
+
+pub(super) fn delete_snapshot_storage(
+    tx: &Transaction<'_>,
+    contract: ContractAddress,
+) -> anyhow::Result<()> {
+    tx.inner()
+        .execute(
+            "DELETE FROM snapshot_sync_storage WHERE contract_address = ?",
+            params![&contract],
+        )
+        .context("Deleting snapshot storage progress")?;
+
+    Ok(())
+}
+
+/// Inserts the class, nonce and state hash of each contract as its state at `block`.
+pub(super) fn insert_snapshot_contracts(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    contracts: &[(ContractAddress, ClassHash, ContractNonce, ContractStateHash)],
+) -> anyhow::Result<()> {
+    let mut insert_contract = tx
+        .inner()
+        .prepare_cached(
+            "INSERT INTO contract_updates (block_number, contract_address, class_hash) VALUES (?, ?, ?)",
+        )
+        .context("Preparing contract insert statement")?;
+    let mut insert_nonce = tx
+        .inner()
+        .prepare_cached(
+            "INSERT INTO nonce_updates (block_number, contract_address, nonce) VALUES (?, ?, ?)",
+        )
+        .context("Preparing nonce insert statement")?;
+
+    for (address, class_hash, nonce, state_hash) in contracts {
+        // The system contract has neither a class nor a nonce.
+        if *address != ContractAddress::ONE {
+            insert_contract
+                .execute(params![&block, address, class_hash])
+                .context("Inserting contract")?;
+        }
+        if *nonce != ContractNonce::ZERO {
+            insert_nonce
+                .execute(params![&block, address, nonce])
+                .context("Inserting nonce")?;
+        }
+        super::trie::insert_contract_state_hash(tx, block, *address, *state_hash)
+            .context("Inserting contract state hash")?;
+    }
+
+    Ok(())
+}
+
+/// Inserts the storage values of a contract as its state at `block`.
+pub(super) fn insert_snapshot_storage(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    contract: ContractAddress,
+    values: &[(StorageAddress, StorageValue)],
+) -> anyhow::Result<()> {
+    let mut insert_storage = tx
+        .inner()
+        .prepare_cached("INSERT INTO storage_updates (block_number, contract_address, storage_address, storage_value) VALUES (?, ?, ?, ?)")
+        .context("Preparing storage insert statement")?;
+
+    for (key, value) in values {
+        insert_storage
+            .execute(params![&block, &contract, key, value])
+            .context("Inserting storage value")?;
+    }
+
+    Ok(())
+}
+
+/// Marks the Sierra classes, whose definitions must already be stored, as declared at `block`
+/// and inserts their class commitment leaves.
+pub(super) fn insert_snapshot_classes(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    classes: &[(SierraHash, CasmHash, ClassCommitmentLeafHash)],
+) -> anyhow::Result<()> {
+    let mut update_class_defs = tx
+        .inner()
+        .prepare_cached(
+            "UPDATE class_definitions SET block_number=? WHERE hash=? AND block_number IS NULL",
+        )
+        .context("Preparing class definition block number update statement")?;
+
+    for (sierra_hash, casm_hash, leaf) in classes {
+        update_class_defs
+            .execute(params![&block, &ClassHash(sierra_hash.0)])
+            .context("Updating class definition block number")?;
+        super::class::insert_class_commitment_leaf(tx, block, leaf, casm_hash)
+            .context("Inserting class commitment leaf")?;
+    }
+
+    Ok(())
+}
+
+/// The classes of the contracts stored at `block` whose definitions are missing. Once the Sierra
+/// classes of the snapshot are stored, these are the Cairo 0 classes, which are not part of the
+/// classes trie.
+pub(super) fn snapshot_missing_classes(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+) -> anyhow::Result<Vec<ClassHash>> {
+    let mut stmt = tx
+        .inner()
+        .prepare_cached(
+            r"SELECT DISTINCT contract_updates.class_hash FROM contract_updates
+            LEFT JOIN class_definitions ON class_definitions.hash = contract_updates.class_hash
+            WHERE contract_updates.block_number = ? AND class_definitions.definition IS NULL",
+        )
+        .context("Preparing statement")?;
+
+    let classes = stmt
+        .query_map(params![&block], |row| row.get_class_hash(0))
+        .context("Querying missing classes")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Iterating over missing classes")?;
+
+    Ok(classes)
+}
+
+/// Inserts the definitions of Cairo 0 classes as declared at `block`.
+pub(super) fn insert_snapshot_cairo_classes(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    classes: &[(ClassHash, Vec<u8>)],
+) -> anyhow::Result<()> {
+    let mut update_class_defs = tx
+        .inner()
+        .prepare_cached(
+            "UPDATE class_definitions SET block_number=? WHERE hash=? AND block_number IS NULL",
+        )
+        .context("Preparing class definition block number update statement")?;
+
+    for (hash, definition) in classes {
+        super::class::insert_cairo_class(tx, *hash, definition)
+            .context("Inserting cairo definition")?;
+        update_class_defs
+            .execute(params![&block, hash])
+            .context("Updating class definition block number")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::BlockHeader;
+
+    use super::*;
+
+    #[test]
+    fn progress_roundtrip() {
+        let storage = crate::Storage::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let tx = connection.transaction().unwrap();
+
+        assert_eq!(tx.snapshot_progress().unwrap(), None);
+
+        let mut progress = SnapshotProgress::new(BlockNumber::new_or_panic(10));
+        tx.upsert_snapshot_progress(&progress).unwrap();
+        assert_eq!(tx.snapshot_progress().unwrap(), Some(progress.clone()));
+
+        progress.commitments = Some((storage_commitment!("0x1"), class_commitment!("0x2")));
+        progress.next_contract = None;
+        progress.storage_root_index = Some(3);
+        progress.next_class = Some(class_hash!("0x4"));
+        progress.next_body_block = BlockNumber::new_or_panic(5);
+        tx.upsert_snapshot_progress(&progress).unwrap();
+        assert_eq!(tx.snapshot_progress().unwrap(), Some(progress.clone()));
+
+        let pending = SnapshotStorage {
+            contract: contract_address!("0x1"),
+            root: contract_root!("0x2"),
+            next_key: storage_address!("0x3"),
+            root_index: None,
+        };
+        let done = SnapshotStorage {
+            contract: contract_address!("0x4"),
+            root: contract_root!("0x5"),
+            next_key: StorageAddress::ZERO,
+            root_index: Some(6),
+        };
+        tx.upsert_snapshot_storage(&pending).unwrap();
+        tx.upsert_snapshot_storage(&done).unwrap();
+        tx.delete_snapshot_storage(done.contract).unwrap();
+        assert_eq!(tx.snapshot_storage().unwrap(), vec![pending]);
+
+        tx.delete_snapshot_progress().unwrap();
+        assert_eq!(tx.snapshot_progress().unwrap(), None);
+        assert!(tx.snapshot_storage().unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_classes() {
+        let storage = crate::Storage::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let header = BlockHeader::builder().finalize_with_hash(block_hash!("0x1"));
+        tx.insert_block_header(&header).unwrap();
+        let contracts = [
+            (
+                contract_address!("0x1"),
+                class_hash!("0x11"),
+                ContractNonce::ZERO,
+                contract_state_hash!("0x21"),
+            ),
+            (
+                contract_address!("0x2"),
+                class_hash!("0x12"),
+                ContractNonce::ZERO,
+                contract_state_hash!("0x22"),
+            ),
+            (
+                contract_address!("0x3"),
+                class_hash!("0x12"),
+                ContractNonce::ZERO,
+                contract_state_hash!("0x23"),
+            ),
+        ];
+        tx.insert_snapshot_contracts(header.number, &contracts)
+            .unwrap();
+        assert_eq!(
+            tx.snapshot_missing_classes(header.number).unwrap(),
+            vec![class_hash!("0x11"), class_hash!("0x12")]
+        );
+
+        tx.insert_snapshot_cairo_classes(header.number, &[(class_hash!("0x12"), b"def".to_vec())])
+            .unwrap();
+        assert_eq!(
+            tx.snapshot_missing_classes(header.number).unwrap(),
+            vec![class_hash!("0x11")]
+        );
+        assert_eq!(
+            tx.class_definition_at(header.number.into(), class_hash!("0x12"))
+                .unwrap(),
+            Some(b"def".to_vec())
+        );
+    }
+}
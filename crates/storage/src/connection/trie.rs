@@ -42,6 +42,29 @@ pub(super) fn storage_root_index(
         .map_err(Into::into)
 }
 
+/// Returns the newest block which has a storage root i.e. whose state has been applied.
+pub(super) fn latest_storage_root_block(
+    tx: &Transaction<'_>,
+) -> anyhow::Result<Option<BlockNumber>> {
+    tx.inner()
+        .query_row("SELECT MAX(block_number) FROM storage_roots", [], |row| {
+            row.get_optional_block_number(0)
+        })
+        .map_err(Into::into)
+}
+
+/// Returns the oldest block which has a storage root. Nodes which synced a state snapshot have no
+/// state before the snapshot's block.
+pub(super) fn oldest_storage_root_block(
+    tx: &Transaction<'_>,
+) -> anyhow::Result<Option<BlockNumber>> {
+    tx.inner()
+        .query_row("SELECT MIN(block_number) FROM storage_roots", [], |row| {
+            row.get_optional_block_number(0)
+        })
+        .map_err(Into::into)
+}
+
 pub(super) fn contract_root_index(
     tx: &Transaction<'_>,
     block_number: BlockNumber,
@@ -520,6 +543,34 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[test]
+    fn latest_storage_root_block() {
+        let mut db = crate::Storage::in_memory().unwrap().connection().unwrap();
+        let tx = db.transaction().unwrap();
+
+        let result = super::latest_storage_root_block(&tx).unwrap();
+        assert_eq!(result, None);
+
+        insert_storage_root(&tx, BlockNumber::GENESIS, Some(123)).unwrap();
+        insert_storage_root(&tx, BlockNumber::GENESIS + 1, None).unwrap();
+        let result = super::latest_storage_root_block(&tx).unwrap();
+        assert_eq!(result, Some(BlockNumber::GENESIS + 1));
+    }
+
+    #[test]
+    fn oldest_storage_root_block() {
+        let mut db = crate::Storage::in_memory().unwrap().connection().unwrap();
+        let tx = db.transaction().unwrap();
+
+        let result = super::oldest_storage_root_block(&tx).unwrap();
+        assert_eq!(result, None);
+
+        insert_storage_root(&tx, BlockNumber::GENESIS + 5, Some(123)).unwrap();
+        insert_storage_root(&tx, BlockNumber::GENESIS + 6, None).unwrap();
+        let result = super::oldest_storage_root_block(&tx).unwrap();
+        assert_eq!(result, Some(BlockNumber::GENESIS + 5));
+    }
+
     #[test]
     fn contract_roots() {
         let mut db = crate::Storage::in_memory().unwrap().connection().unwrap();
@@ -0,0 +1,17 @@
+use anyhow::Context;
+
+/// Adds the queue of classes which sync still has to download and compile.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        r"
+CREATE TABLE class_download_queue (
+    class_hash BLOB PRIMARY KEY NOT NULL,
+    casm_hash BLOB,
+    starknet_version TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0
+);",
+    )
+    .context("Creating class_download_queue table")?;
+
+    Ok(())
+}
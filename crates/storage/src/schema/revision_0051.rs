@@ -0,0 +1,29 @@
+use anyhow::Context;
+
+/// Adds the progress of p2p state snapshot sync, so that an interrupted snapshot sync resumes
+/// where it left off instead of starting over.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tx.execute_batch(
+        r"
+CREATE TABLE snapshot_sync (
+    block_number INTEGER PRIMARY KEY NOT NULL,
+    storage_commitment BLOB,
+    class_commitment BLOB,
+    next_contract BLOB,
+    storage_root_index INTEGER,
+    next_class BLOB,
+    class_root_index INTEGER,
+    next_body_block INTEGER NOT NULL
+);
+
+CREATE TABLE snapshot_sync_storage (
+    contract_address BLOB PRIMARY KEY NOT NULL,
+    contract_root BLOB NOT NULL,
+    next_key BLOB NOT NULL,
+    root_index INTEGER
+);",
+    )
+    .context("Creating snapshot sync tables")?;
+
+    Ok(())
+}